@@ -0,0 +1,185 @@
+//! Spectral count based abundance estimates for label-free comparisons
+use super::*;
+#[cfg(feature = "serialization")]
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// Average mass of an amino acid residue (Da), used to estimate protein
+/// length from molecular weight
+const AVERAGE_RESIDUE_MASS: f64 = 110.0;
+
+/// Average number of residues per observable tryptic peptide, used to
+/// approximate the denominator of emPAI from protein length
+const RESIDUES_PER_OBSERVABLE: f64 = 12.0;
+
+/// Return the length of every protein in a FASTA file, by accession.
+///
+/// Each entry is recorded under its full identifier (the first word of the
+/// header line) and, for UniProt identifiers ("sp|P02768|ALBU_HUMAN"), under
+/// the bare accession
+pub fn fasta_lengths(input: &str) -> HashMap<String, usize> {
+    let mut lengths = HashMap::new();
+    let mut current: Option<(&str, usize)> = None;
+    let record = |entry: Option<(&str, usize)>, lengths: &mut HashMap<String, usize>| {
+        if let Some((id, len)) = entry {
+            if let Some(acc) = id.split('|').nth(1) {
+                lengths.insert(acc.to_string(), len);
+            }
+            lengths.insert(id.to_string(), len);
+        }
+    };
+    for line in input.lines() {
+        let line = line.trim_end();
+        if let Some(header) = line.strip_prefix('>') {
+            record(current.take(), &mut lengths);
+            let id = header.split_whitespace().next().unwrap_or("");
+            current = Some((id, 0));
+        } else if let Some((_, len)) = current.as_mut() {
+            *len += line.len();
+        }
+    }
+    record(current, &mut lengths);
+    lengths
+}
+
+#[cfg_attr(feature = "serialization", derive(Serialize))]
+#[derive(PartialEq, Clone, Debug)]
+/// Spectral count based abundance of a single protein
+pub struct SpectralAbundance {
+    /// Uniprot accession identifier
+    pub accession: String,
+    pub spectral_count: u16,
+    /// Protein length in residues, see `Dataset::spectral_abundance`
+    pub length: f64,
+    /// Spectral abundance factor: spectral count divided by length
+    pub saf: f64,
+    /// Normalized spectral abundance factor: SAF divided by the sum of the
+    /// SAFs of every protein in the dataset
+    pub nsaf: f64,
+    /// Approximate exponentially modified protein abundance index,
+    /// `10^(observed / observable) - 1`
+    pub empai: f64,
+}
+
+impl Protein {
+    /// Estimate the protein length from the sequence coverage and the
+    /// combined length of its distinct peptides, or failing that, from the
+    /// molecular weight. Overlapping peptides make coverage-based estimates
+    /// too long
+    pub fn estimated_length(&self) -> f64 {
+        let covered = self
+            .peptides
+            .iter()
+            .map(|pep| pep.stripped_sequence())
+            .collect::<HashSet<_>>()
+            .iter()
+            .map(|seq| seq.len())
+            .sum::<usize>() as f64;
+        if self.sequence_coverage > 0.0 && covered > 0.0 {
+            covered / (self.sequence_coverage as f64 / 100.0)
+        } else {
+            self.molecular_weight as f64 / AVERAGE_RESIDUE_MASS
+        }
+    }
+}
+
+impl Dataset {
+    /// Calculate spectral count based abundances (NSAF and emPAI) of each
+    /// protein, in protein order.
+    ///
+    /// Protein lengths are taken from `lengths` (see `fasta_lengths`) where
+    /// available, and are otherwise estimated with
+    /// `Protein::estimated_length`. emPAI uses the sequence count as the
+    /// number of observed peptides, and approximates the number of
+    /// observable peptides from the protein length
+    pub fn spectral_abundance(
+        &self,
+        lengths: Option<&HashMap<String, usize>>,
+    ) -> Vec<SpectralAbundance> {
+        let length = |prot: &Protein| {
+            lengths
+                .and_then(|map| {
+                    map.get(&prot.accession).or_else(|| {
+                        prot.accession
+                            .split('|')
+                            .nth(1)
+                            .and_then(|acc| map.get(acc))
+                    })
+                })
+                .map(|&len| len as f64)
+                .unwrap_or_else(|| prot.estimated_length())
+        };
+        let mut rows = self
+            .proteins
+            .iter()
+            .map(|prot| {
+                let length = length(prot);
+                let saf = if length > 0.0 {
+                    prot.spectral_count as f64 / length
+                } else {
+                    f64::NAN
+                };
+                let observable = (length / RESIDUES_PER_OBSERVABLE).max(1.0);
+                SpectralAbundance {
+                    accession: prot.accession.clone(),
+                    spectral_count: prot.spectral_count,
+                    length,
+                    saf,
+                    nsaf: f64::NAN,
+                    empai: 10f64.powf(prot.sequence_count as f64 / observable) - 1.0,
+                }
+            })
+            .collect::<Vec<_>>();
+        let total = util::sum(&rows.iter().map(|r| r.saf).collect::<Vec<_>>());
+        for row in rows.iter_mut() {
+            row.nsaf = row.saf / total;
+        }
+        rows
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn nsaf() {
+        let lengths =
+            fasta_lengths(">sp|P1|A_HUMAN Protein A\nMKKKK\nKKKKK\n>P2\nMKKKKKKKKKKKKKKKKKKK\n");
+        assert_eq!(lengths["P1"], 10);
+        assert_eq!(lengths["sp|P1|A_HUMAN"], 10);
+        assert_eq!(lengths["P2"], 20);
+
+        let data = Dataset {
+            proteins: vec![
+                Protein {
+                    accession: "sp|P1|A_HUMAN".into(),
+                    spectral_count: 4,
+                    sequence_count: 1,
+                    ..Protein::default()
+                },
+                Protein {
+                    accession: "P2".into(),
+                    spectral_count: 4,
+                    sequence_count: 1,
+                    ..Protein::default()
+                },
+                Protein {
+                    accession: "P3".into(),
+                    spectral_count: 11,
+                    molecular_weight: 11000,
+                    ..Protein::default()
+                },
+            ],
+            ..Dataset::default()
+        };
+        let rows = data.spectral_abundance(Some(&lengths));
+        assert_eq!(rows[0].saf, 0.4);
+        assert_eq!(rows[2].length, 100.0);
+        // SAFs are 0.4, 0.2 and 0.11
+        assert!((rows[0].nsaf - 0.4 / 0.71).abs() < 1e-12);
+        assert!((rows.iter().map(|r| r.nsaf).sum::<f64>() - 1.0).abs() < 1e-12);
+        assert!((rows[0].empai - 9.0).abs() < 1e-12);
+        assert_eq!(rows[2].empai, 0.0);
+    }
+}
@@ -0,0 +1,418 @@
+//! Programmatic construction of datasets, e.g. for tests and simulations
+use super::*;
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fmt;
+
+/// Error returned when a built dataset or protein would be inconsistent
+#[derive(Clone, Debug, PartialEq)]
+pub enum BuildError {
+    /// A peptide of protein `accession` has `found` intensity values, but
+    /// `expected` channels are required
+    ChannelMismatch {
+        accession: String,
+        expected: usize,
+        found: usize,
+    },
+    /// `found` channel annotations were supplied for `expected` channels
+    ChannelInfo { expected: usize, found: usize },
+    /// The peptides of protein `accession` have `found` intensity values,
+    /// more channels than fit in a `u8`
+    TooManyChannels { accession: String, found: usize },
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::ChannelMismatch {
+                accession,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Protein {} has a peptide with {} channels, expected {}",
+                accession, found, expected
+            ),
+            BuildError::ChannelInfo { expected, found } => write!(
+                f,
+                "{} channel annotations supplied for {} channels",
+                found, expected
+            ),
+            BuildError::TooManyChannels { accession, found } => write!(
+                f,
+                "Protein {} has peptides with {} channels, exceeding the maximum of {}",
+                accession,
+                found,
+                u8::MAX
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+/// Builder for a `Peptide`. Peptides are unique, with a purity of 1 and no
+/// intensity values, unless otherwise configured.
+///
+/// This follows the Builder pattern
+#[derive(Clone, Debug, PartialEq)]
+pub struct PeptideBuilder {
+    peptide: Peptide,
+}
+
+impl PeptideBuilder {
+    pub fn new<S: Into<String>>(sequence: S) -> PeptideBuilder {
+        PeptideBuilder {
+            peptide: Peptide {
+                sequence: sequence.into(),
+                unique: true,
+                purity: 1.0,
+                ..Peptide::default()
+            },
+        }
+    }
+
+    /// Set the intensity of each channel
+    pub fn values(mut self, values: Vec<f64>) -> Self {
+        self.peptide.values = values;
+        self
+    }
+
+    /// Set whether the peptide is unique to its protein
+    pub fn unique(mut self, unique: bool) -> Self {
+        self.peptide.unique = unique;
+        self
+    }
+
+    /// Set the precursor isolation purity
+    pub fn purity(mut self, purity: f32) -> Self {
+        self.peptide.purity = purity;
+        self
+    }
+
+    /// Set the scan number
+    pub fn scan(mut self, scan: usize) -> Self {
+        self.peptide.scan = scan;
+        self
+    }
+
+    /// Set the precursor charge state
+    pub fn charge(mut self, charge: u8) -> Self {
+        self.peptide.charge = charge;
+        self
+    }
+
+    /// Set the name of the spectrum file
+    pub fn file_name<S: Into<String>>(mut self, file_name: S) -> Self {
+        self.peptide.file_name = file_name.into();
+        self
+    }
+
+    /// Set the retention time, in minutes
+    pub fn retention_time(mut self, minutes: f32) -> Self {
+        self.peptide.retention_time = Some(minutes);
+        self
+    }
+
+    pub fn build(self) -> Peptide {
+        self.peptide
+    }
+}
+
+/// Builder for a `Protein`.
+///
+/// The number of channels is taken from the peptides, and the spectral and
+/// sequence counts are calculated from them unless set explicitly.
+///
+/// This follows the Builder pattern
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProteinBuilder {
+    protein: Protein,
+    spectral_count: Option<u16>,
+}
+
+impl ProteinBuilder {
+    pub fn new<S: Into<String>>(accession: S) -> ProteinBuilder {
+        ProteinBuilder {
+            protein: Protein {
+                accession: accession.into(),
+                ..Protein::default()
+            },
+            spectral_count: None,
+        }
+    }
+
+    /// Set the description, from which the gene and organism are parsed
+    /// (see `Protein::annotate`)
+    pub fn description<S: Into<String>>(mut self, description: S) -> Self {
+        self.protein.description = description.into();
+        self
+    }
+
+    /// Add a peptide to the protein
+    pub fn peptide(mut self, peptide: Peptide) -> Self {
+        self.protein.peptides.push(peptide);
+        self
+    }
+
+    /// Add several peptides to the protein
+    pub fn peptides<I: IntoIterator<Item = Peptide>>(mut self, peptides: I) -> Self {
+        self.protein.peptides.extend(peptides);
+        self
+    }
+
+    /// Set the spectral count, instead of counting the peptides
+    pub fn spectral_count(mut self, count: u16) -> Self {
+        self.spectral_count = Some(count);
+        self
+    }
+
+    /// Set the sequence coverage, in percent
+    pub fn sequence_coverage(mut self, coverage: f32) -> Self {
+        self.protein.sequence_coverage = coverage;
+        self
+    }
+
+    /// Set the molecular weight, in Da
+    pub fn molecular_weight(mut self, weight: u32) -> Self {
+        self.protein.molecular_weight = weight;
+        self
+    }
+
+    /// Build the protein, checking that every peptide has the same number
+    /// of intensity values
+    pub fn build(self) -> Result<Protein, BuildError> {
+        let mut protein = self.protein;
+        let channels = protein.peptides.first().map_or(0, |p| p.values.len());
+        if let Some(pep) = protein.peptides.iter().find(|p| p.values.len() != channels) {
+            return Err(BuildError::ChannelMismatch {
+                accession: protein.accession,
+                expected: channels,
+                found: pep.values.len(),
+            });
+        }
+        protein.channels = match u8::try_from(channels) {
+            Ok(channels) => channels,
+            Err(_) => {
+                return Err(BuildError::TooManyChannels {
+                    accession: protein.accession,
+                    found: channels,
+                })
+            }
+        };
+        let count = |n: usize| u16::try_from(n).unwrap_or(u16::MAX);
+        protein.spectral_count = self
+            .spectral_count
+            .unwrap_or_else(|| count(protein.peptides.len()));
+        // Distinct as by `Filter`, so that filtering does not change the
+        // count of a protein whose peptides all pass
+        protein.sequence_count = count(
+            protein
+                .peptides
+                .iter()
+                .map(|pep| &pep.sequence)
+                .collect::<HashSet<_>>()
+                .len(),
+        );
+        protein.annotate();
+        Ok(protein)
+    }
+}
+
+/// Builder for a `Dataset`.
+///
+/// The number of channels is taken from the first protein unless set
+/// explicitly, and every protein must agree with it.
+///
+/// This follows the Builder pattern
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DatasetBuilder {
+    channels: Option<u8>,
+    proteins: Vec<Protein>,
+    header: Header,
+    channel_info: Vec<ChannelInfo>,
+    scale: Scale,
+}
+
+impl DatasetBuilder {
+    pub fn new() -> DatasetBuilder {
+        DatasetBuilder::default()
+    }
+
+    /// Set the number of channels, instead of taking it from the first
+    /// protein
+    pub fn channels(mut self, channels: u8) -> Self {
+        self.channels = Some(channels);
+        self
+    }
+
+    /// Add a protein to the dataset
+    pub fn protein(mut self, protein: Protein) -> Self {
+        self.proteins.push(protein);
+        self
+    }
+
+    /// Add several proteins to the dataset
+    pub fn proteins<I: IntoIterator<Item = Protein>>(mut self, proteins: I) -> Self {
+        self.proteins.extend(proteins);
+        self
+    }
+
+    /// Set the header metadata
+    pub fn header(mut self, header: Header) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Set the channel annotations, one per channel
+    pub fn channel_info(mut self, info: Vec<ChannelInfo>) -> Self {
+        self.channel_info = info;
+        self
+    }
+
+    /// Set the scale of the intensity values. Defaults to linear
+    pub fn scale(mut self, scale: Scale) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Build the dataset, checking that every protein and peptide has the
+    /// same number of channels, and that any channel annotations match it
+    pub fn build(self) -> Result<Dataset, BuildError> {
+        let channels = self
+            .channels
+            .or_else(|| self.proteins.first().map(|p| p.channels))
+            .unwrap_or(0) as usize;
+        for prot in &self.proteins {
+            let found = std::iter::once(prot.channels as usize)
+                .chain(prot.peptides.iter().map(|p| p.values.len()))
+                .find(|&n| n != channels);
+            if let Some(found) = found {
+                return Err(BuildError::ChannelMismatch {
+                    accession: prot.accession.clone(),
+                    expected: channels,
+                    found,
+                });
+            }
+        }
+        if !self.channel_info.is_empty() && self.channel_info.len() != channels {
+            return Err(BuildError::ChannelInfo {
+                expected: channels,
+                found: self.channel_info.len(),
+            });
+        }
+        Ok(Dataset {
+            proteins: self.proteins,
+            channels: channels as u8,
+            header: self.header,
+            channel_info: self.channel_info,
+            scale: self.scale,
+            ..Dataset::default()
+        })
+    }
+}
+
+impl Dataset {
+    /// Return a `DatasetBuilder` for constructing a new dataset
+    pub fn builder() -> DatasetBuilder {
+        DatasetBuilder::new()
+    }
+}
+
+impl Protein {
+    /// Return a `ProteinBuilder` for constructing a new protein
+    pub fn builder<S: Into<String>>(accession: S) -> ProteinBuilder {
+        ProteinBuilder::new(accession)
+    }
+}
+
+impl Peptide {
+    /// Return a `PeptideBuilder` for constructing a new peptide
+    pub fn builder<S: Into<String>>(sequence: S) -> PeptideBuilder {
+        PeptideBuilder::new(sequence)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn build_dataset() {
+        let protein = Protein::builder("P02768")
+            .description("Serum albumin OS=Homo sapiens GN=ALB")
+            .peptide(
+                Peptide::builder("K.LVNEVTEFAK.T")
+                    .values(vec![1.0, 2.0])
+                    .build(),
+            )
+            .peptide(
+                Peptide::builder("K.LVNEVTEFAK.T")
+                    .values(vec![3.0, 4.0])
+                    .scan(2)
+                    .build(),
+            )
+            .build()
+            .unwrap();
+        assert_eq!(protein.channels, 2);
+        assert_eq!(protein.spectral_count, 2);
+        assert_eq!(protein.sequence_count, 1);
+        assert_eq!(protein.gene.as_deref(), Some("ALB"));
+        assert_eq!(protein.peptides[0].purity, 1.0);
+
+        // Modified forms count as distinct sequences
+        let modified = Protein::builder("P1")
+            .peptide(Peptide::builder("K.MEK.L").values(vec![1.0]).build())
+            .peptide(Peptide::builder("K.M*EK.L").values(vec![1.0]).build())
+            .build()
+            .unwrap();
+        assert_eq!(modified.sequence_count, 2);
+        let filtered = Filter::default()
+            .filter_protein(modified.clone(), &Filter::tryptic_regex())
+            .unwrap();
+        assert_eq!(filtered.sequence_count, modified.sequence_count);
+
+        let data = Dataset::builder().protein(protein.clone()).build().unwrap();
+        assert_eq!(data.channels, 2);
+        assert_eq!(data.get("P02768"), Some(&protein));
+
+        let err = Dataset::builder()
+            .channels(3)
+            .protein(protein.clone())
+            .build();
+        assert_eq!(
+            err.unwrap_err(),
+            BuildError::ChannelMismatch {
+                accession: "P02768".into(),
+                expected: 3,
+                found: 2
+            }
+        );
+        let err = Protein::builder("P1")
+            .peptide(Peptide::builder("AAK").values(vec![1.0]).build())
+            .peptide(Peptide::builder("CCK").build())
+            .build();
+        assert!(matches!(
+            err,
+            Err(BuildError::ChannelMismatch { found: 0, .. })
+        ));
+        let err = Protein::builder("P1")
+            .peptide(Peptide::builder("AAK").values(vec![1.0; 256]).build())
+            .build();
+        assert!(matches!(
+            err,
+            Err(BuildError::TooManyChannels { found: 256, .. })
+        ));
+        let err = Dataset::builder()
+            .protein(protein)
+            .channel_info(vec![ChannelInfo::default()])
+            .build();
+        assert_eq!(
+            err.unwrap_err(),
+            BuildError::ChannelInfo {
+                expected: 2,
+                found: 1
+            }
+        );
+    }
+}
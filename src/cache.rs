@@ -0,0 +1,138 @@
+//! Binary cache of a parsed `Dataset`, which loads much faster than
+//! re-parsing the original Census file
+//!
+//! A cache file is an 8 byte magic number and a little-endian `u32` format
+//! version, followed by the bincode encoding of the dataset. Caches written
+//! by a different format version are rejected rather than misread.
+use super::*;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"CENSUSDB";
+/// Format version, to be bumped whenever the serialized layout of `Dataset`
+/// or anything it contains changes. Version 2 added `Peptide::shared_with`
+const VERSION: u32 = 2;
+
+/// Error that may occur while loading a cached `Dataset`
+#[derive(Debug)]
+pub enum CacheError {
+    /// The file could not be opened or read
+    Io(io::Error),
+    /// The file is not a dataset cache
+    NotACache,
+    /// The cache was written with an incompatible format version
+    Version(u32),
+    /// The cache contents could not be decoded
+    Decode(String),
+}
+
+impl fmt::Display for CacheError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CacheError::Io(e) => write!(f, "Error reading cache: {}", e),
+            CacheError::NotACache => write!(f, "File is not a dataset cache"),
+            CacheError::Version(v) => write!(
+                f,
+                "Cache format version {} is not supported (expected {})",
+                v, VERSION
+            ),
+            CacheError::Decode(e) => write!(f, "Error decoding cache: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CacheError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CacheError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for CacheError {
+    fn from(e: io::Error) -> Self {
+        CacheError::Io(e)
+    }
+}
+
+impl Dataset {
+    /// Write the dataset to a binary cache file at `path`, which can be
+    /// read back in using `Dataset::load_cache`
+    pub fn save_cache<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        w.write_all(MAGIC)?;
+        w.write_all(&VERSION.to_le_bytes())?;
+        bincode::serialize_into(&mut w, self).map_err(io::Error::other)?;
+        w.flush()
+    }
+
+    /// Read a dataset from a binary cache file written by
+    /// `Dataset::save_cache`
+    pub fn load_cache<P: AsRef<Path>>(path: P) -> Result<Dataset, CacheError> {
+        let mut r = BufReader::new(File::open(path)?);
+        let mut header = [0u8; 12];
+        match r.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(CacheError::NotACache)
+            }
+            Err(e) => return Err(e.into()),
+        }
+        if &header[..8] != MAGIC {
+            return Err(CacheError::NotACache);
+        }
+        let version = u32::from_le_bytes([header[8], header[9], header[10], header[11]]);
+        if version != VERSION {
+            return Err(CacheError::Version(version));
+        }
+        bincode::deserialize_from(r).map_err(|e| CacheError::Decode(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cache_round_trip() {
+        let data = Dataset {
+            channels: 2,
+            proteins: vec![Protein {
+                accession: "P12345".into(),
+                channels: 2,
+                peptides: vec![Peptide {
+                    sequence: "K.LVNEVTEFAK.T".into(),
+                    values: vec![1000.0, 3000.0],
+                    retention_time: Some(45.1),
+                    ..Peptide::default()
+                }],
+                ..Protein::default()
+            }],
+            scale: Scale::Log2(1.0),
+            ..Dataset::default()
+        };
+        let path = std::env::temp_dir().join(format!("census-{}.cache", std::process::id()));
+        data.save_cache(&path).unwrap();
+        assert_eq!(Dataset::load_cache(&path).unwrap(), data);
+
+        std::fs::write(&path, b"CENSUSDB\x01\x00\x00\x00").unwrap();
+        assert!(matches!(
+            Dataset::load_cache(&path),
+            Err(CacheError::Version(1))
+        ));
+        std::fs::write(&path, b"CENSUSDB\x03\x00\x00\x00").unwrap();
+        assert!(matches!(
+            Dataset::load_cache(&path),
+            Err(CacheError::Version(3))
+        ));
+        std::fs::write(&path, b"H\tCensus").unwrap();
+        assert!(matches!(
+            Dataset::load_cache(&path),
+            Err(CacheError::NotACache)
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,249 @@
+//! Sample annotations of the quantified channels of a `Dataset`
+use super::*;
+#[cfg(feature = "serialization")]
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Clone, Debug, Default)]
+/// Sample annotation of a single quantified channel
+pub struct ChannelInfo {
+    /// Reporter ion label, e.g. "127N"
+    pub label: String,
+    /// Name of the sample in the channel
+    pub sample: Option<String>,
+    /// Experimental condition of the sample
+    pub condition: Option<String>,
+    /// Biological or technical replicate number
+    pub replicate: Option<u32>,
+    /// Plex (TMT batch) number, for multi-plex experiments
+    pub plex: Option<u32>,
+}
+
+impl ChannelInfo {
+    /// Parse a tab-delimited annotation table with one row per channel, in
+    /// channel order. The header row names the columns, in any order:
+    /// `channel` (or `label`) is required, while `sample`, `condition`,
+    /// `replicate` and `plex` are optional. Empty fields are `None`
+    pub fn from_tsv(input: &str) -> Result<Vec<ChannelInfo>, Error> {
+        let mut lines = input
+            .lines()
+            .enumerate()
+            .filter(|(_, l)| !l.trim().is_empty());
+        let names = match lines.next() {
+            Some((_, line)) => line.split('\t').map(str::trim).collect::<Vec<_>>(),
+            None => return Err(Error::new(ErrorKind::EOF, 1)),
+        };
+        let find = |keys: &[&str]| {
+            names
+                .iter()
+                .position(|n| keys.iter().any(|k| n.eq_ignore_ascii_case(k)))
+        };
+        let label = find(&["channel", "label"])
+            .ok_or_else(|| Error::new(ErrorKind::MissingColumn("channel".into()), 1))?;
+        let sample = find(&["sample"]);
+        let condition = find(&["condition"]);
+        let replicate = find(&["replicate"]);
+        let plex = find(&["plex"]);
+
+        lines
+            .map(|(n, line)| {
+                let fields = line.split('\t').map(str::trim).collect::<Vec<_>>();
+                let text = |idx: Option<usize>| {
+                    idx.and_then(|i| fields.get(i))
+                        .filter(|s| !s.is_empty())
+                        .map(|s| s.to_string())
+                };
+                let number = |idx: Option<usize>| {
+                    text(idx)
+                        .map(|s| {
+                            s.parse::<u32>().map_err(|_| {
+                                Error::new(ErrorKind::Conversion, n + 1).with_context(
+                                    ErrorContext {
+                                        column: idx.map(|i| i + 1),
+                                        field: idx.map(|i| names[i].to_string()),
+                                        token: Some(s),
+                                        text: line.into(),
+                                    },
+                                )
+                            })
+                        })
+                        .transpose()
+                };
+                Ok(ChannelInfo {
+                    label: text(Some(label)).unwrap_or_default(),
+                    sample: text(sample),
+                    condition: text(condition),
+                    replicate: number(replicate)?,
+                    plex: number(plex)?,
+                })
+            })
+            .collect()
+    }
+}
+
+impl Dataset {
+    /// Attach channel annotations parsed from a tab-delimited table, see
+    /// `ChannelInfo::from_tsv`. The table must have one row per channel
+    pub fn annotate_channels(&mut self, input: &str) -> Result<(), Error> {
+        let info = ChannelInfo::from_tsv(input)?;
+        if info.len() != self.channels as usize {
+            return Err(Error::new(ErrorKind::ChannelCount(info.len()), 1));
+        }
+        self.channel_info = info;
+        Ok(())
+    }
+
+    /// Return the index (0 indexed) of the channel whose label or sample
+    /// name is `name`. Note that `PeptideFilter` channels are 1 indexed
+    pub fn channel_index(&self, name: &str) -> Option<usize> {
+        self.channel_info
+            .iter()
+            .position(|c| c.label == name || c.sample.as_deref() == Some(name))
+    }
+
+    /// Return the name of each channel for use in exported column headers:
+    /// its label if every channel is annotated with a distinct label, and
+    /// otherwise `channel_1`, `channel_2`, ...
+    pub fn channel_names(&self) -> Vec<String> {
+        let labels = self
+            .channel_info
+            .iter()
+            .map(|c| c.label.as_str())
+            .filter(|l| !l.is_empty())
+            .collect::<std::collections::HashSet<_>>();
+        if self.channel_info.len() == self.channels as usize
+            && labels.len() == self.channel_info.len()
+        {
+            self.channel_info.iter().map(|c| c.label.clone()).collect()
+        } else {
+            (1..=self.channels)
+                .map(|i| format!("channel_{}", i))
+                .collect()
+        }
+    }
+
+    /// Keep only the given channels (0 indexed), in the given order, so
+    /// that channels can also be reordered or duplicated. Every peptide's
+    /// raw and Census-normalized intensities are sliced consistently, and
+    /// channel annotations are remapped. Annotations that do not match the
+    /// number of channels cannot be remapped, and are cleared.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any channel is out of range, or if more channels are
+    /// selected than fit in a `u8`
+    pub fn select_channels(&mut self, channels: &[usize]) {
+        let n = self.channels as usize;
+        if let Some(c) = channels.iter().find(|&&c| c >= n) {
+            panic!("channel {} is out of range for {} channels", c, n);
+        }
+        let selected = u8::try_from(channels.len()).unwrap_or_else(|_| {
+            panic!(
+                "{} channels exceed the maximum of {}",
+                channels.len(),
+                u8::MAX
+            )
+        });
+        let select = |values: &mut Vec<f64>| {
+            if !values.is_empty() {
+                *values = channels
+                    .iter()
+                    .map(|&c| values.get(c).copied().unwrap_or(f64::NAN))
+                    .collect();
+            }
+        };
+        for prot in self.proteins.iter_mut() {
+            prot.channels = selected;
+            for pep in prot.peptides.iter_mut() {
+                select(&mut pep.values);
+                select(&mut pep.norm_values);
+            }
+        }
+        if self.channel_info.len() == n {
+            self.channel_info = channels
+                .iter()
+                .map(|&c| self.channel_info[c].clone())
+                .collect();
+        } else {
+            self.channel_info.clear();
+        }
+        self.channels = selected;
+    }
+
+    /// Remove the given channels (0 indexed), keeping the remaining
+    /// channels in order, see `Dataset::select_channels`. Out of range
+    /// channels are ignored
+    pub fn drop_channels(&mut self, channels: &[usize]) {
+        let keep = (0..self.channels as usize)
+            .filter(|c| !channels.contains(c))
+            .collect::<Vec<_>>();
+        self.select_channels(&keep);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn channel_selection() {
+        let mut data = Dataset {
+            channels: 3,
+            proteins: vec![Protein {
+                channels: 3,
+                peptides: vec![Peptide {
+                    values: vec![1.0, 2.0, 3.0],
+                    norm_values: vec![0.1, 0.2, 0.3],
+                    ..Peptide::default()
+                }],
+                ..Protein::default()
+            }],
+            ..Dataset::default()
+        };
+        data.annotate_channels("channel\n126\n127\n128\n").unwrap();
+
+        data.drop_channels(&[1]);
+        assert_eq!(data.channels, 2);
+        assert_eq!(data.proteins[0].channels, 2);
+        assert_eq!(data.proteins[0].peptides[0].values, vec![1.0, 3.0]);
+        assert_eq!(data.channel_names(), vec!["126", "128"]);
+
+        data.select_channels(&[1, 0]);
+        assert_eq!(data.proteins[0].peptides[0].values, vec![3.0, 1.0]);
+        assert_eq!(data.proteins[0].peptides[0].norm_values, vec![0.3, 0.1]);
+        assert_eq!(data.channel_index("126"), Some(1));
+
+        // Annotations that do not match the channels are cleared
+        data.channel_info.pop();
+        data.select_channels(&[0, 0, 1]);
+        assert_eq!(data.channels, 3);
+        assert!(data.channel_info.is_empty());
+    }
+
+    #[test]
+    fn annotation_table() {
+        let mut data = Dataset {
+            channels: 3,
+            ..Dataset::default()
+        };
+        let table = "sample\tchannel\tcondition\treplicate
+ctrl_1\t126\tcontrol\t1
+treated_1\t127N\ttreated\t1
+treated_2\t127C\ttreated\t
+";
+        data.annotate_channels(table).unwrap();
+        assert_eq!(data.channel_info[1].label, "127N");
+        assert_eq!(data.channel_info[1].condition.as_deref(), Some("treated"));
+        assert_eq!(data.channel_info[2].replicate, None);
+        assert_eq!(data.channel_info[0].plex, None);
+        assert_eq!(data.channel_index("treated_2"), Some(2));
+        assert_eq!(data.channel_names(), vec!["126", "127N", "127C"]);
+
+        let err = data.annotate_channels("channel\n126\n").unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::ChannelCount(1));
+        let err = ChannelInfo::from_tsv("channel\treplicate\n126\tone\n").unwrap_err();
+        assert_eq!(err.line(), 2);
+        assert_eq!(err.context().unwrap().field.as_deref(), Some("replicate"));
+    }
+}
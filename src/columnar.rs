@@ -0,0 +1,328 @@
+//! Columnar export of a `Dataset` as Apache Arrow record batches, and as
+//! Apache Parquet
+//!
+//! Tables have typed columns, with one column per quantified channel, named
+//! as by `Dataset::channel_names`.
+//! Missing intensities (`NaN`) and absent optional values are written as
+//! nulls.
+use super::*;
+use arrow_array::{
+    ArrayRef, BooleanArray, Float32Array, Float64Array, RecordBatch, StringArray, UInt16Array,
+    UInt32Array, UInt64Array, UInt8Array,
+};
+use arrow_schema::{ArrowError, Field, Schema};
+#[cfg(feature = "parquet")]
+use parquet::{arrow::ArrowWriter, errors::ParquetError};
+#[cfg(feature = "parquet")]
+use std::io::Write;
+use std::sync::Arc;
+
+/// Arrow tables of a `Dataset`, see `Dataset::to_arrow`
+#[derive(Clone, Debug)]
+pub struct ArrowTables {
+    /// One row per protein, with the summed intensity of each channel
+    pub proteins: RecordBatch,
+    /// One row per peptide, keyed by protein accession, with the intensity
+    /// of each channel
+    pub peptides: RecordBatch,
+    /// Peptide-by-channel intensity matrix, with rows in the same order as
+    /// `peptides`. The channel arrays are shared with `peptides`
+    pub intensities: RecordBatch,
+}
+
+/// Build a record batch from named columns. Every column is declared
+/// nullable, so that the schema does not depend on the data
+fn batch(columns: Vec<(String, ArrayRef)>) -> Result<RecordBatch, ArrowError> {
+    let fields = columns
+        .iter()
+        .map(|(name, array)| Field::new(name, array.data_type().clone(), true))
+        .collect::<Vec<_>>();
+    let arrays = columns.into_iter().map(|(_, array)| array).collect();
+    RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays)
+}
+
+/// One column per channel, from the intensity vector of each row
+fn channel_columns(names: &[String], rows: &[Vec<f64>]) -> Vec<(String, ArrayRef)> {
+    names
+        .iter()
+        .enumerate()
+        .map(|(c, name)| {
+            let array: Float64Array = rows
+                .iter()
+                .map(|values| values.get(c).copied().filter(|v| !v.is_nan()))
+                .collect();
+            (name.clone(), Arc::new(array) as ArrayRef)
+        })
+        .collect()
+}
+
+impl Dataset {
+    /// Build a table with one row per protein, holding the summed channel
+    /// intensities of its peptides on the dataset's scale
+    fn protein_batch(&self) -> Result<RecordBatch, ArrowError> {
+        let prots = &self.proteins;
+        let mut columns: Vec<(String, ArrayRef)> = vec![
+            (
+                "accession".into(),
+                Arc::new(StringArray::from_iter_values(
+                    prots.iter().map(|p| &p.accession),
+                )),
+            ),
+            (
+                "description".into(),
+                Arc::new(StringArray::from_iter_values(
+                    prots.iter().map(|p| &p.description),
+                )),
+            ),
+            (
+                "gene".into(),
+                Arc::new(
+                    prots
+                        .iter()
+                        .map(|p| p.gene.as_deref())
+                        .collect::<StringArray>(),
+                ),
+            ),
+            (
+                "spectral_count".into(),
+                Arc::new(UInt16Array::from_iter_values(
+                    prots.iter().map(|p| p.spectral_count),
+                )),
+            ),
+            (
+                "sequence_count".into(),
+                Arc::new(UInt16Array::from_iter_values(
+                    prots.iter().map(|p| p.sequence_count),
+                )),
+            ),
+            (
+                "sequence_coverage".into(),
+                Arc::new(Float32Array::from_iter_values(
+                    prots.iter().map(|p| p.sequence_coverage),
+                )),
+            ),
+            (
+                "molecular_weight".into(),
+                Arc::new(UInt32Array::from_iter_values(
+                    prots.iter().map(|p| p.molecular_weight),
+                )),
+            ),
+        ];
+        let totals = prots
+            .iter()
+            .map(|p| p.quantify_scaled(Rollup::Sum, self.scale))
+            .collect::<Vec<_>>();
+        columns.extend(channel_columns(&self.channel_names(), &totals));
+        batch(columns)
+    }
+
+    /// Build the peptide table and the intensity matrix
+    fn peptide_batches(&self) -> Result<(RecordBatch, RecordBatch), ArrowError> {
+        let rows = self
+            .proteins
+            .iter()
+            .flat_map(|prot| prot.peptides.iter().map(move |pep| (prot, pep)))
+            .collect::<Vec<_>>();
+        let mut columns: Vec<(String, ArrayRef)> = vec![
+            (
+                "accession".into(),
+                Arc::new(StringArray::from_iter_values(
+                    rows.iter().map(|(p, _)| &p.accession),
+                )),
+            ),
+            (
+                "sequence".into(),
+                Arc::new(StringArray::from_iter_values(
+                    rows.iter().map(|(_, p)| &p.sequence),
+                )),
+            ),
+            (
+                "unique".into(),
+                Arc::new(
+                    rows.iter()
+                        .map(|(_, p)| Some(p.unique))
+                        .collect::<BooleanArray>(),
+                ),
+            ),
+            (
+                "redundant".into(),
+                Arc::new(
+                    rows.iter()
+                        .map(|(_, p)| Some(p.redundant))
+                        .collect::<BooleanArray>(),
+                ),
+            ),
+            (
+                "purity".into(),
+                Arc::new(Float32Array::from_iter_values(
+                    rows.iter().map(|(_, p)| p.purity),
+                )),
+            ),
+            (
+                "scan".into(),
+                Arc::new(UInt64Array::from_iter_values(
+                    rows.iter().map(|(_, p)| p.scan as u64),
+                )),
+            ),
+            (
+                "charge".into(),
+                Arc::new(UInt8Array::from_iter_values(
+                    rows.iter().map(|(_, p)| p.charge),
+                )),
+            ),
+            (
+                "xcorr".into(),
+                Arc::new(Float32Array::from_iter_values(
+                    rows.iter().map(|(_, p)| p.xcorr),
+                )),
+            ),
+            (
+                "delta_cn".into(),
+                Arc::new(Float32Array::from_iter_values(
+                    rows.iter().map(|(_, p)| p.delta_cn),
+                )),
+            ),
+            (
+                "file_name".into(),
+                Arc::new(StringArray::from_iter_values(
+                    rows.iter().map(|(_, p)| &p.file_name),
+                )),
+            ),
+            (
+                "retention_time".into(),
+                Arc::new(
+                    rows.iter()
+                        .map(|(_, p)| p.retention_time)
+                        .collect::<Float32Array>(),
+                ),
+            ),
+            (
+                "injection_time".into(),
+                Arc::new(
+                    rows.iter()
+                        .map(|(_, p)| p.injection_time)
+                        .collect::<Float32Array>(),
+                ),
+            ),
+        ];
+        let values = rows
+            .iter()
+            .map(|(_, p)| p.values.clone())
+            .collect::<Vec<_>>();
+        let intensities = channel_columns(&self.channel_names(), &values);
+        columns.extend(intensities.iter().cloned());
+        Ok((batch(columns)?, batch(intensities)?))
+    }
+
+    /// Convert the dataset into Arrow record batches: a protein table, a
+    /// peptide table and a peptide-by-channel intensity matrix
+    pub fn to_arrow(&self) -> Result<ArrowTables, ArrowError> {
+        let (peptides, intensities) = self.peptide_batches()?;
+        Ok(ArrowTables {
+            proteins: self.protein_batch()?,
+            peptides,
+            intensities,
+        })
+    }
+
+    /// Write the peptide-level or protein-level table of the dataset as a
+    /// Parquet file
+    #[cfg(feature = "parquet")]
+    pub fn to_parquet<W: Write + Send>(&self, w: W, layout: Layout) -> Result<(), ParquetError> {
+        let batch = match layout {
+            Layout::PeptideLevel => self.peptide_batches()?.0,
+            Layout::ProteinLevel => self.protein_batch()?,
+        };
+        let mut writer = ArrowWriter::try_new(w, batch.schema(), None)?;
+        writer.write(&batch)?;
+        writer.close()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[cfg(feature = "parquet")]
+    use arrow_array::Array;
+    use arrow_schema::DataType;
+    #[cfg(feature = "parquet")]
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    const SAMPLE: &str = "H\tPLINE\tLOCUS\tSPEC_COUNT\tSEQ_COUNT\tSEQ_COVERAGE\tMOLWT\tDESCRIPTION
+H\tSLINE\tUNIQUE\tSEQUENCE\tm/z_126_int\tnorm_m/z_126_int\tm/z_127_int\tnorm_m/z_127_int\tPURITY\tSCAN\tRETENTION_TIME
+P\tP12345\t2\t2\t12.5%\t69367\tSerum albumin OS=Homo sapiens GN=ALB
+S\tU\tK.LVNEVTEFAK.T\t1000\t0.25\t3000\t0.75\t0.95\t1234\t45.1
+S\t\tR.QNCELFEK.L\t500\t1.0\tNA\t\t0.8\t1301\t
+";
+
+    #[test]
+    fn arrow_tables() {
+        let tables = read_census(SAMPLE).unwrap().to_arrow().unwrap();
+        assert_eq!(tables.proteins.num_rows(), 1);
+        assert_eq!(tables.proteins.num_columns(), 9);
+        assert_eq!(
+            tables.proteins.schema().field(2).data_type(),
+            &DataType::Utf8
+        );
+        assert_eq!(tables.peptides.num_rows(), 2);
+        assert_eq!(tables.intensities.num_columns(), 2);
+        assert!(Arc::ptr_eq(
+            tables.intensities.column(1),
+            tables.peptides.column_by_name("channel_2").unwrap()
+        ));
+
+        let mut data = read_census(SAMPLE).unwrap();
+        data.log2_transform(1.0);
+        let tables = data.to_arrow().unwrap();
+        let total = |name| {
+            tables
+                .proteins
+                .column_by_name(name)
+                .unwrap()
+                .as_any()
+                .downcast_ref::<Float64Array>()
+                .unwrap()
+                .value(0)
+        };
+        assert!((total("channel_1") - 1501f64.log2()).abs() < 1e-9);
+        assert!((total("channel_2") - 3001f64.log2()).abs() < 1e-9);
+    }
+
+    #[test]
+    #[cfg(feature = "parquet")]
+    fn parquet_round_trip() {
+        let data = read_census(SAMPLE).unwrap();
+        let path = std::env::temp_dir().join(format!("census-{}.parquet", std::process::id()));
+        data.to_parquet(std::fs::File::create(&path).unwrap(), Layout::PeptideLevel)
+            .unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(std::fs::File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches = reader.collect::<Result<Vec<_>, _>>().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let batch = &batches[0];
+        assert_eq!(batch.num_rows(), 2);
+        let schema = batch.schema();
+        assert_eq!(
+            schema.field_with_name("scan").unwrap().data_type(),
+            &DataType::UInt64
+        );
+        let channel = batch
+            .column_by_name("channel_2")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        assert_eq!(channel.value(0), 3000.0);
+        assert!(channel.is_null(1));
+        assert!(batch.column_by_name("retention_time").unwrap().is_null(1));
+
+        let proteins = data.protein_batch().unwrap();
+        assert_eq!(proteins.num_rows(), 1);
+        assert_eq!(proteins.num_columns(), 9);
+    }
+}
@@ -0,0 +1,303 @@
+//! Resolve the positions of fields on protein and peptide lines from the
+//! column names declared on the `PLINE` and `SLINE` headers
+//!
+//! Files without column headers fall back to the conventional positional
+//! layout of Census isobaric output.
+
+/// Return the line position of the first column matching any of `names`.
+///
+/// Field 0 of a data line is the "P" or "S" tag, which has no column name,
+/// so header column `i` is found at field `i + 1`.
+fn find(columns: &[String], names: &[&str]) -> Option<usize> {
+    columns
+        .iter()
+        .position(|c| names.iter().any(|n| c.eq_ignore_ascii_case(n)))
+        .map(|i| i + 1)
+}
+
+/// Is this the column name of a raw reporter ion intensity?
+fn is_raw_channel(column: &str) -> bool {
+    column.contains("m/z") && !is_norm_channel(column)
+}
+
+/// Is this the column name of a Census-normalized reporter ion intensity?
+fn is_norm_channel(column: &str) -> bool {
+    column
+        .get(..4)
+        .is_some_and(|p| p.eq_ignore_ascii_case("norm"))
+}
+
+/// Field positions on protein ("P") lines
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ProteinColumns {
+    pub accession: usize,
+    pub spectral_count: Option<usize>,
+    pub sequence_count: Option<usize>,
+    pub sequence_coverage: Option<usize>,
+    pub molecular_weight: Option<usize>,
+    /// `None` indicates that the description is the last field of the line
+    pub description: Option<usize>,
+}
+
+impl Default for ProteinColumns {
+    /// Positional layout used when no `PLINE` header is present
+    fn default() -> Self {
+        ProteinColumns {
+            accession: 1,
+            spectral_count: Some(2),
+            sequence_count: Some(3),
+            sequence_coverage: Some(4),
+            molecular_weight: Some(5),
+            description: None,
+        }
+    }
+}
+
+impl ProteinColumns {
+    pub fn new(columns: &[String]) -> ProteinColumns {
+        if columns.is_empty() {
+            return ProteinColumns::default();
+        }
+        ProteinColumns {
+            accession: find(columns, &["LOCUS", "ACCESSION"]).unwrap_or(1),
+            spectral_count: find(columns, &["SPEC_COUNT", "SPECTRAL_COUNT"]),
+            sequence_count: find(columns, &["SEQ_COUNT", "SEQUENCE_COUNT"]),
+            sequence_coverage: find(columns, &["SEQ_COVERAGE", "COVERAGE"]),
+            molecular_weight: find(columns, &["MOLWT", "MOLECULAR_WEIGHT", "MW"]),
+            description: find(columns, &["DESCRIPTION"]),
+        }
+    }
+}
+
+/// Field positions on peptide ("S") lines
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct PeptideColumns {
+    pub unique: usize,
+    pub sequence: usize,
+    /// Raw intensity of each channel, in channel order
+    pub values: Vec<usize>,
+    /// Normalized intensity of each channel, in channel order
+    pub norm_values: Vec<usize>,
+    pub purity: Option<usize>,
+    pub scan: Option<usize>,
+    pub charge: Option<usize>,
+    pub xcorr: Option<usize>,
+    pub delta_cn: Option<usize>,
+    pub file_name: Option<usize>,
+    pub retention_time: Option<usize>,
+    pub injection_time: Option<usize>,
+}
+
+impl PeptideColumns {
+    /// Positional layout used when no `SLINE` header is present.
+    ///
+    /// Purity and scan number are located relative to the end of the
+    /// intensity columns, so they are not parsed from label-free
+    /// (zero-channel) files without a header
+    pub fn positional(channels: u8) -> PeptideColumns {
+        let channels = channels as usize;
+        let end = 3 + 2 * channels;
+        let anchored = |offset| Some(end + offset).filter(|_| channels > 0);
+        PeptideColumns {
+            unique: 1,
+            sequence: 2,
+            values: (0..channels).map(|i| 3 + 2 * i).collect(),
+            norm_values: (0..channels).map(|i| 4 + 2 * i).collect(),
+            purity: anchored(1),
+            scan: anchored(5),
+            charge: None,
+            xcorr: None,
+            delta_cn: None,
+            file_name: None,
+            retention_time: None,
+            injection_time: None,
+        }
+    }
+
+    pub fn new(columns: &[String]) -> PeptideColumns {
+        if columns.is_empty() {
+            return PeptideColumns::positional(0);
+        }
+        let channels = |pred: fn(&str) -> bool| {
+            columns
+                .iter()
+                .enumerate()
+                .filter(|(_, c)| pred(c))
+                .map(|(i, _)| i + 1)
+                .collect::<Vec<_>>()
+        };
+        PeptideColumns {
+            unique: find(columns, &["UNIQUE"]).unwrap_or(1),
+            sequence: find(columns, &["SEQUENCE"]).unwrap_or(2),
+            values: channels(is_raw_channel),
+            norm_values: channels(is_norm_channel),
+            purity: find(columns, &["PURITY"]),
+            scan: find(columns, &["SCAN", "SCANNUM", "SCAN_NUM"]),
+            charge: find(columns, &["CSTATE", "CS", "CHARGE"]),
+            xcorr: find(columns, &["XCORR"]),
+            delta_cn: find(columns, &["DELTACN", "DELTA_CN"]),
+            file_name: find(columns, &["FILE_NAME", "FILENAME"]),
+            retention_time: find(columns, &["RETENTION_TIME", "RETENTIONTIME", "RT"]),
+            injection_time: find(columns, &["ION_INJECTION_TIME", "INJECTION_TIME", "IIT"]),
+        }
+    }
+
+    /// Override the number of quantified channels, assuming alternating raw
+    /// and normalized intensity columns starting at the first declared
+    /// channel
+    pub fn force_channels(&mut self, channels: u8) {
+        let start = self.values.first().copied().unwrap_or(3);
+        let channels = channels as usize;
+        self.values = (0..channels).map(|i| start + 2 * i).collect();
+        self.norm_values = (0..channels).map(|i| start + 1 + 2 * i).collect();
+    }
+
+    /// Number of quantified channels
+    pub fn channels(&self) -> u8 {
+        self.values.len() as u8
+    }
+}
+
+/// Field positions on protein lines of ratio-format output
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct RatioProteinColumns {
+    pub accession: usize,
+    pub average_ratio: Option<usize>,
+    pub standard_deviation: Option<usize>,
+    pub composite_ratio: Option<usize>,
+    pub composite_ratio_standard_deviation: Option<usize>,
+    pub peptide_count: Option<usize>,
+    pub spectral_count: Option<usize>,
+    pub area_ratio: Option<usize>,
+    /// `None` indicates that the description is the last field of the line
+    pub description: Option<usize>,
+}
+
+impl RatioProteinColumns {
+    pub fn new(columns: &[String]) -> RatioProteinColumns {
+        if columns.is_empty() {
+            // Conventional layout of Census ratio output
+            return RatioProteinColumns {
+                accession: 1,
+                average_ratio: Some(2),
+                standard_deviation: Some(3),
+                composite_ratio: Some(4),
+                composite_ratio_standard_deviation: Some(5),
+                peptide_count: Some(6),
+                spectral_count: Some(7),
+                area_ratio: None,
+                description: None,
+            };
+        }
+        RatioProteinColumns {
+            accession: find(columns, &["LOCUS", "ACCESSION"]).unwrap_or(1),
+            average_ratio: find(columns, &["AVERAGE_RATIO"]),
+            standard_deviation: find(columns, &["STANDARD_DEVIATION"]),
+            composite_ratio: find(columns, &["COMPOSITE_RATIO"]),
+            composite_ratio_standard_deviation: find(
+                columns,
+                &["COMPOSITE_RATIO_STANDARD_DEVIATION"],
+            ),
+            peptide_count: find(columns, &["PEPTIDE_NUM"]),
+            spectral_count: find(columns, &["SPEC_COUNT", "SPECTRAL_COUNT"]),
+            area_ratio: find(columns, &["AREA_RATIO"]),
+            description: find(columns, &["DESCRIPTION"]),
+        }
+    }
+}
+
+/// Field positions on peptide lines of ratio-format output
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct RatioPeptideColumns {
+    pub unique: usize,
+    pub sequence: usize,
+    pub ratio: Option<usize>,
+    pub rev_slope_ratio: Option<usize>,
+    pub regression_factor: Option<usize>,
+    pub determinant_factor: Option<usize>,
+    pub sample_intensity: Option<usize>,
+    pub reference_intensity: Option<usize>,
+    pub area_ratio: Option<usize>,
+    pub profile_score: Option<usize>,
+    pub xcorr: Option<usize>,
+    pub delta_cn: Option<usize>,
+    pub scan: Option<usize>,
+    pub charge: Option<usize>,
+    pub file_name: Option<usize>,
+}
+
+impl RatioPeptideColumns {
+    pub fn new(columns: &[String]) -> RatioPeptideColumns {
+        if columns.is_empty() {
+            // Conventional layout of Census ratio output
+            return RatioPeptideColumns {
+                unique: 1,
+                sequence: 2,
+                ratio: Some(3),
+                rev_slope_ratio: Some(4),
+                regression_factor: Some(5),
+                determinant_factor: Some(6),
+                xcorr: Some(7),
+                delta_cn: Some(8),
+                sample_intensity: Some(9),
+                reference_intensity: Some(10),
+                area_ratio: Some(11),
+                profile_score: Some(12),
+                file_name: Some(13),
+                scan: Some(14),
+                charge: Some(15),
+            };
+        }
+        RatioPeptideColumns {
+            unique: find(columns, &["UNIQUE"]).unwrap_or(1),
+            sequence: find(columns, &["SEQUENCE"]).unwrap_or(2),
+            ratio: find(columns, &["RATIO"]),
+            rev_slope_ratio: find(columns, &["REV_SLOPE_RATIO"]),
+            regression_factor: find(columns, &["REGRESSION_FACTOR"]),
+            determinant_factor: find(columns, &["DETERMINANT_FACTOR"]),
+            sample_intensity: find(columns, &["SAM_INT", "SAMPLE_INTENSITY"]),
+            reference_intensity: find(columns, &["REF_INT", "REFERENCE_INTENSITY"]),
+            area_ratio: find(columns, &["AREA_RATIO"]),
+            profile_score: find(columns, &["PROFILE_SCORE"]),
+            xcorr: find(columns, &["XCORR"]),
+            delta_cn: find(columns, &["DELTACN", "DELTA_CN"]),
+            scan: find(columns, &["SCAN", "SCANNUM", "SCAN_NUM"]),
+            charge: find(columns, &["CSTATE", "CS", "CHARGE"]),
+            file_name: find(columns, &["FILE_NAME", "FILENAME"]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn names(s: &str) -> Vec<String> {
+        s.split('\t').map(String::from).collect()
+    }
+
+    #[test]
+    fn reordered_columns() {
+        let cols = PeptideColumns::new(&names(
+            "SEQUENCE\tUNIQUE\tRETENTION_TIME\tm/z_126_int\tm/z_127_int\tnorm_m/z_126_int\tnorm_m/z_127_int\tSCAN",
+        ));
+        assert_eq!(cols.sequence, 1);
+        assert_eq!(cols.unique, 2);
+        assert_eq!(cols.values, vec![4, 5]);
+        assert_eq!(cols.norm_values, vec![6, 7]);
+        assert_eq!(cols.scan, Some(8));
+        assert_eq!(cols.channels(), 2);
+        assert_eq!(cols.purity, None);
+        assert_eq!(cols.retention_time, Some(3));
+        assert_eq!(cols.injection_time, None);
+    }
+
+    #[test]
+    fn non_ascii_columns() {
+        assert!(is_norm_channel("NORM_m/z_126_int"));
+        assert!(!is_norm_channel("Nörm"));
+        assert!(is_raw_channel("m/zé_126_int"));
+        assert!(!is_norm_channel("m/z"));
+        assert!(is_raw_channel("m/z_126_int"));
+    }
+}
@@ -0,0 +1,254 @@
+//! Summarize protein intensities by experimental condition, using the
+//! channel annotations of a `Dataset`
+use super::*;
+#[cfg(feature = "serialization")]
+use serde::Serialize;
+
+#[cfg_attr(feature = "serialization", derive(Serialize))]
+#[derive(PartialEq, Clone, Debug)]
+/// Summary statistics of a protein's intensities in the channels of one
+/// condition. Missing channels are ignored
+pub struct ConditionStats {
+    /// Condition name, from `ChannelInfo::condition`
+    pub condition: String,
+    /// Number of channels with an intensity
+    pub n: usize,
+    pub mean: f64,
+    pub median: f64,
+    pub sum: f64,
+}
+
+#[cfg_attr(feature = "serialization", derive(Serialize))]
+#[derive(PartialEq, Clone, Debug)]
+/// Per-condition intensity statistics of a single protein
+pub struct ConditionSummary {
+    /// Uniprot accession identifier
+    pub accession: String,
+    /// Statistics of each condition, in order of first appearance in the
+    /// channel annotations
+    pub conditions: Vec<ConditionStats>,
+}
+
+impl ConditionSummary {
+    /// Return the statistics of the condition named `condition`
+    pub fn get(&self, condition: &str) -> Option<&ConditionStats> {
+        self.conditions.iter().find(|c| c.condition == condition)
+    }
+
+    /// Return the ratio of the mean intensities of conditions `a` and `b`,
+    /// or `None` if either condition is not annotated
+    pub fn fold_change(&self, a: &str, b: &str) -> Option<f64> {
+        Some(self.get(a)?.mean / self.get(b)?.mean)
+    }
+}
+
+/// Fraction of proteins below this CV is reported by `Dataset::cv_report`.
+/// CVs are calculated from the sample (n - 1) standard deviation, as
+/// replicate channels are a sample of the condition
+pub const CV_CUTOFF: f64 = 0.2;
+
+#[cfg_attr(feature = "serialization", derive(Serialize))]
+#[derive(PartialEq, Clone, Debug)]
+/// Distribution of protein CVs across the replicate channels of one
+/// condition
+pub struct ConditionCv {
+    /// Condition name, from `ChannelInfo::condition`
+    pub condition: String,
+    /// Number of proteins with a CV (quantified in at least two channels)
+    pub proteins: usize,
+    pub median_cv: f64,
+    pub mean_cv: f64,
+    /// Fraction of proteins with a CV below `CV_CUTOFF`
+    pub fraction_below_cutoff: f64,
+}
+
+#[cfg_attr(feature = "serialization", derive(Serialize))]
+#[derive(PartialEq, Clone, Debug)]
+/// Coefficients of variation of protein intensities within each condition
+pub struct CvReport {
+    /// Summary of each condition, in order of first appearance in the
+    /// channel annotations
+    pub conditions: Vec<ConditionCv>,
+    /// CV of each protein in each condition (in the order of
+    /// `conditions`), by accession. CVs of conditions in which the protein
+    /// is quantified in fewer than two channels are `NaN`
+    pub proteins: Vec<(String, Vec<f64>)>,
+}
+
+impl CvReport {
+    /// Return the summary of the condition named `condition`
+    pub fn get(&self, condition: &str) -> Option<&ConditionCv> {
+        self.conditions.iter().find(|c| c.condition == condition)
+    }
+}
+
+impl Dataset {
+    /// Return the names of the annotated conditions, in order of first
+    /// appearance, along with the channels (0 indexed) of each
+    pub fn conditions(&self) -> Vec<(String, Vec<usize>)> {
+        let mut groups: Vec<(String, Vec<usize>)> = Vec::new();
+        for (c, info) in self.channel_info.iter().enumerate() {
+            let name = match &info.condition {
+                Some(name) => name,
+                None => continue,
+            };
+            match groups.iter_mut().find(|(g, _)| g == name) {
+                Some((_, channels)) => channels.push(c),
+                None => groups.push((name.clone(), vec![c])),
+            }
+        }
+        groups
+    }
+
+    /// Summarize the summed intensities of each protein (see
+    /// `Protein::total`) by condition, as annotated in `channel_info`.
+    /// Channels without a condition are ignored. Intensities on log2 scale
+    /// are summed as linear intensities
+    pub fn summarize_by_condition(&self) -> Vec<ConditionSummary> {
+        let groups = self.conditions();
+        self.proteins
+            .iter()
+            .map(|prot| {
+                let total = prot.linear_total(self.scale);
+                let conditions = groups
+                    .iter()
+                    .map(|(name, channels)| {
+                        let values = channels
+                            .iter()
+                            .map(|&c| total.get(c).copied().unwrap_or(f64::NAN))
+                            .collect::<Vec<_>>();
+                        ConditionStats {
+                            condition: name.clone(),
+                            n: util::count(&values),
+                            mean: util::mean(&values),
+                            median: util::median(&values),
+                            sum: util::sum(&values),
+                        }
+                    })
+                    .collect();
+                ConditionSummary {
+                    accession: prot.accession.clone(),
+                    conditions,
+                }
+            })
+            .collect()
+    }
+
+    /// Calculate the coefficient of variation of the summed intensities of
+    /// each protein across the replicate channels of each condition, as a
+    /// quality control of replicate agreement. Unlike `util::cv`, the sample
+    /// standard deviation is used. Channels
+    /// without a condition are ignored. Intensities on log2 scale are
+    /// converted back to linear intensities first
+    pub fn cv_report(&self) -> CvReport {
+        let groups = self.conditions();
+        let proteins = self
+            .proteins
+            .iter()
+            .map(|prot| {
+                let total = prot.linear_total(self.scale);
+                let cvs = groups
+                    .iter()
+                    .map(|(_, channels)| {
+                        let values = channels
+                            .iter()
+                            .map(|&c| total.get(c).copied().unwrap_or(f64::NAN))
+                            .collect::<Vec<_>>();
+                        if util::count(&values) < 2 {
+                            f64::NAN
+                        } else {
+                            stats::variance(&values).sqrt() / util::mean(&values)
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                (prot.accession.clone(), cvs)
+            })
+            .collect::<Vec<_>>();
+
+        let conditions = groups
+            .into_iter()
+            .enumerate()
+            .map(|(i, (condition, _))| {
+                let cvs = proteins
+                    .iter()
+                    .map(|(_, cv)| cv[i])
+                    .filter(|cv| cv.is_finite())
+                    .collect::<Vec<_>>();
+                let below = cvs.iter().filter(|&&cv| cv < CV_CUTOFF).count();
+                ConditionCv {
+                    condition,
+                    proteins: cvs.len(),
+                    median_cv: util::median(&cvs),
+                    mean_cv: util::mean(&cvs),
+                    fraction_below_cutoff: below as f64 / cvs.len() as f64,
+                }
+            })
+            .collect();
+        CvReport {
+            conditions,
+            proteins,
+        }
+    }
+
+    /// Return the fold change of each protein between conditions `a` and
+    /// `b`, as the ratio of their mean intensities, in protein order.
+    /// Returns `None` if either condition is not annotated
+    pub fn fold_changes(&self, a: &str, b: &str) -> Option<Vec<(String, f64)>> {
+        self.summarize_by_condition()
+            .into_iter()
+            .map(|s| s.fold_change(a, b).map(|fc| (s.accession, fc)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn condition_summary() {
+        let mut data = Dataset {
+            channels: 4,
+            proteins: vec![Protein {
+                accession: "P1".into(),
+                channels: 4,
+                peptides: vec![Peptide {
+                    values: vec![100.0, 300.0, 400.0, f64::NAN],
+                    ..Peptide::default()
+                }],
+                ..Protein::default()
+            }],
+            ..Dataset::default()
+        };
+        data.annotate_channels(
+            "channel\tcondition\n126\tcontrol\n127N\tcontrol\n127C\ttreated\n128N\ttreated\n",
+        )
+        .unwrap();
+
+        let summary = data.summarize_by_condition();
+        let control = summary[0].get("control").unwrap();
+        assert_eq!(control.n, 2);
+        assert_eq!(control.mean, 200.0);
+        assert_eq!(control.sum, 400.0);
+        assert_eq!(summary[0].get("treated").unwrap().median, 400.0);
+
+        let fc = data.fold_changes("treated", "control").unwrap();
+        assert_eq!(fc, vec![("P1".to_string(), 2.0)]);
+        assert!(data.fold_changes("treated", "unknown").is_none());
+
+        // Sample standard deviation of 100 and 300 over their mean
+        let cv = 20000f64.sqrt() / 200.0;
+        let report = data.cv_report();
+        assert!((report.proteins[0].1[0] - cv).abs() < 1e-12);
+        assert!(report.proteins[0].1[1].is_nan());
+        let control = report.get("control").unwrap();
+        assert_eq!(control.proteins, 1);
+        assert!((control.median_cv - cv).abs() < 1e-12);
+        assert_eq!(control.fraction_below_cutoff, 0.0);
+        assert_eq!(report.get("treated").unwrap().proteins, 0);
+
+        data.log2_transform(1.0);
+        let report = data.cv_report();
+        assert!((report.proteins[0].1[0] - cv).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,166 @@
+//! Removal of common contaminant proteins (keratins, proteases, etc.)
+use super::*;
+use std::collections::HashSet;
+
+/// Prefixes that search databases commonly add to contaminant accessions
+const PREFIXES: [&str; 2] = ["CON__", "contaminant_"];
+
+/// Set of contaminant protein accessions.
+///
+/// Accessions are matched exactly, as the bare accession of a UniProt
+/// identifier ("sp|P00761|TRYP_PIG" matches "P00761"), and after removing a
+/// "CON__" or "contaminant_" prefix
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ContaminantList {
+    accessions: HashSet<String>,
+}
+
+/// Does `accession` carry one of the contaminant prefixes added by search
+/// databases?
+fn is_tagged(accession: &str) -> bool {
+    PREFIXES.iter().any(|p| accession.starts_with(p))
+}
+
+/// Is `protein`, or any other member of its protein group, a contaminant?
+/// Accessions are contaminants if they carry a contaminant prefix, or with
+/// the `crap` feature, if they are on the bundled cRAP list. See
+/// `ProteinFilter::ExcludeContaminants`
+pub(crate) fn is_contaminant(protein: &Protein) -> bool {
+    #[cfg(feature = "crap")]
+    let crap = {
+        static CRAP: std::sync::OnceLock<ContaminantList> = std::sync::OnceLock::new();
+        CRAP.get_or_init(ContaminantList::crap)
+    };
+    std::iter::once(&protein.accession)
+        .chain(&protein.alternatives)
+        .any(|accession| {
+            #[cfg(feature = "crap")]
+            if crap.contains(accession) {
+                return true;
+            }
+            is_tagged(accession)
+        })
+}
+
+/// Return the forms of `accession` that are compared against the list
+fn forms(accession: &str) -> impl Iterator<Item = &str> {
+    let bare = PREFIXES
+        .iter()
+        .find_map(|p| accession.strip_prefix(p))
+        .unwrap_or(accession);
+    std::iter::once(accession)
+        .chain(std::iter::once(bare))
+        .chain(bare.split('|').nth(1))
+}
+
+impl ContaminantList {
+    /// Build a list from an iterator of accessions
+    pub fn new<I: IntoIterator<Item = S>, S: Into<String>>(accessions: I) -> ContaminantList {
+        let mut list = ContaminantList::default();
+        list.extend(accessions);
+        list
+    }
+
+    /// Parse a list with one accession per line. Anything after the first
+    /// whitespace is ignored, as are empty lines and lines starting with
+    /// '#'
+    pub fn from_accessions(input: &str) -> ContaminantList {
+        ContaminantList::new(
+            input
+                .lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                .filter_map(|l| l.split_whitespace().next()),
+        )
+    }
+
+    /// Read the identifier of every entry in a FASTA file
+    pub fn from_fasta(input: &str) -> ContaminantList {
+        ContaminantList::new(
+            input
+                .lines()
+                .filter_map(|l| l.strip_prefix('>'))
+                .filter_map(|l| l.split_whitespace().next()),
+        )
+    }
+
+    /// The bundled list of common laboratory contaminants, a subset of the
+    /// cRAP database
+    #[cfg(feature = "crap")]
+    pub fn crap() -> ContaminantList {
+        ContaminantList::from_accessions(include_str!("crap.txt"))
+    }
+
+    /// Add accessions to the list
+    pub fn extend<I: IntoIterator<Item = S>, S: Into<String>>(&mut self, accessions: I) {
+        for accession in accessions {
+            let accession = accession.into();
+            self.accessions.extend(forms(&accession).map(String::from));
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.accessions.is_empty()
+    }
+
+    /// Is `accession` on the list?
+    pub fn contains(&self, accession: &str) -> bool {
+        forms(accession).any(|a| self.accessions.contains(a))
+    }
+}
+
+impl Dataset {
+    /// Remove every protein whose accession, or the accession of any other
+    /// member of its protein group, is on the contaminant list. Returns the
+    /// number of proteins removed
+    pub fn remove_contaminants(&mut self, list: &ContaminantList) -> usize {
+        let before = self.proteins.len();
+        self.retain(|prot| {
+            !list.contains(&prot.accession) && !prot.alternatives.iter().any(|a| list.contains(a))
+        });
+        before - self.proteins.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn contaminants() {
+        let list = ContaminantList::from_fasta(
+            ">sp|P00761|TRYP_PIG Trypsin\nFPTDDDDK\n>P04264 Keratin\nMSRQ\n",
+        );
+        assert!(list.contains("P00761"));
+        assert!(list.contains("CON__P04264"));
+        assert!(list.contains("sp|P04264|K2C1_HUMAN"));
+        assert!(!list.contains("P02768"));
+
+        let list = ContaminantList::from_accessions("# keratins\nP04264\tK2C1_HUMAN\n\nP35908\n");
+        assert!(!list.contains("#"));
+
+        let protein = |acc: &str, alternatives: Vec<String>| Protein {
+            accession: acc.into(),
+            alternatives,
+            ..Protein::default()
+        };
+        let mut data = Dataset {
+            proteins: vec![
+                protein("P02768", vec![]),
+                protein("P12345", vec!["P35908".into()]),
+                protein("CON__P04264", vec![]),
+            ],
+            ..Dataset::default()
+        };
+        assert_eq!(data.remove_contaminants(&list), 2);
+        assert_eq!(data.proteins[0].accession, "P02768");
+    }
+
+    #[cfg(feature = "crap")]
+    #[test]
+    fn bundled_list() {
+        let list = ContaminantList::crap();
+        assert!(list.contains("sp|P00761|TRYP_PIG"));
+        assert!(list.contains("P04264"));
+    }
+}
@@ -3,16 +3,144 @@ use super::*;
 #[cfg(feature = "serialization")]
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt;
+use std::sync::OnceLock;
 
-#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Clone, Debug, Default)]
 /// Container for proteomics data read from a Census version file
 pub struct Dataset {
     /// TMT data for each protein in the dataset
     pub proteins: Vec<Protein>,
     /// Number of TMT channels in the dataset
     pub channels: u8,
+    /// Header information from the Census file the dataset was read from
+    pub header: Header,
+    /// Sample annotation of each channel, in channel order. Empty if the
+    /// channels have not been annotated, see `Dataset::annotate_channels`
+    pub channel_info: Vec<ChannelInfo>,
+    /// Scale of the peptide intensities, see `Dataset::log2_transform`
+    pub scale: Scale,
+    /// Accession lookup table used by `Dataset::get`, built on first use
+    #[cfg_attr(feature = "serialization", serde(skip))]
+    pub(crate) index: AccessionIndex,
 }
 
+/// Lazily built map from accession to position in `Dataset::proteins`,
+/// along with the number of proteins it was built from.
+///
+/// The index is a cache, and is ignored when comparing datasets. Methods of
+/// `Dataset` that add, remove or reorder proteins discard it. Lookups still
+/// detect an out of date index when the number of proteins has changed or
+/// a protein has moved, since `Dataset::proteins` can also be modified
+/// directly, in which case `Dataset::get_mut` rebuilds it and
+/// `Dataset::get` falls back to a linear search. Changing the accession of
+/// a protein in place requires a call to `Dataset::reindex`
+#[derive(Clone, Debug, Default)]
+pub(crate) struct AccessionIndex(OnceLock<(usize, HashMap<String, usize>)>);
+
+impl PartialEq for AccessionIndex {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl AccessionIndex {
+    fn build(&self, proteins: &[Protein]) -> &(usize, HashMap<String, usize>) {
+        self.0.get_or_init(|| {
+            let mut map = HashMap::with_capacity(proteins.len());
+            for (i, prot) in proteins.iter().enumerate() {
+                map.entry(prot.accession.clone()).or_insert(i);
+            }
+            (proteins.len(), map)
+        })
+    }
+
+    /// Position of `accession`, or `Err` if the index is out of date
+    fn find(&self, proteins: &[Protein], accession: &str) -> Result<Option<usize>, ()> {
+        let (len, map) = self.build(proteins);
+        if *len != proteins.len() {
+            return Err(());
+        }
+        match map.get(accession) {
+            Some(&i) if proteins[i].accession == accession => Ok(Some(i)),
+            Some(_) => Err(()),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Copy, Clone, Debug, Default)]
+/// Scale of the intensity values of a `Dataset`
+pub enum Scale {
+    /// Intensities as quantified
+    #[default]
+    Linear,
+    /// log2 of the intensities plus a pseudocount
+    Log2(f64),
+}
+
+impl Scale {
+    /// Convert an intensity on this scale back to a linear intensity
+    pub(crate) fn linear(self, x: f64) -> f64 {
+        match self {
+            Scale::Linear => x,
+            Scale::Log2(pseudocount) => x.exp2() - pseudocount,
+        }
+    }
+}
+
+/// Error returned by operations combining or transforming datasets
+#[derive(Debug, Clone, PartialEq)]
+pub enum DatasetError {
+    /// The datasets to be merged do not all have the same `Scale`
+    MixedScales,
+    /// The merged dataset would have more channels than fit in a `u8`
+    TooManyChannels(usize),
+    /// ChannelOutOfRange(channel, channels)
+    ///
+    /// A channel index (0 indexed) is not among the channels of the dataset
+    ChannelOutOfRange(usize, u8),
+    /// ChannelMismatch(channels, other)
+    ///
+    /// The datasets to be combined have different numbers of channels
+    ChannelMismatch(u8, u8),
+}
+
+impl fmt::Display for DatasetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DatasetError::MixedScales => {
+                write!(
+                    f,
+                    "datasets with different intensity scales cannot be merged"
+                )
+            }
+            DatasetError::TooManyChannels(n) => {
+                write!(f, "{} channels exceed the maximum of {}", n, u8::MAX)
+            }
+            DatasetError::ChannelOutOfRange(channel, channels) => {
+                write!(
+                    f,
+                    "channel {} is out of range for a dataset of {} channels",
+                    channel, channels
+                )
+            }
+            DatasetError::ChannelMismatch(channels, other) => {
+                write!(
+                    f,
+                    "datasets of {} and {} channels cannot be combined",
+                    channels, other
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for DatasetError {}
+
 impl Dataset {
     /// Return a set of all UniProt KB accession ID's present in the
     /// `Dataset`
@@ -32,7 +160,645 @@ impl Dataset {
             .collect()
     }
 
+    /// Return the protein with the given accession, using the accession
+    /// index. If several proteins share an accession, the first is returned
+    pub fn get(&self, accession: &str) -> Option<&Protein> {
+        match self.index.find(&self.proteins, accession) {
+            Ok(i) => i.map(|i| &self.proteins[i]),
+            Err(()) => self.proteins.iter().find(|p| p.accession == accession),
+        }
+    }
+
+    /// Return a mutable reference to the protein with the given accession,
+    /// rebuilding the accession index if it is out of date
+    pub fn get_mut(&mut self, accession: &str) -> Option<&mut Protein> {
+        let i = match self.index.find(&self.proteins, accession) {
+            Ok(i) => i,
+            Err(()) => {
+                self.reindex();
+                self.index
+                    .find(&self.proteins, accession)
+                    .unwrap_or_else(|_| self.proteins.iter().position(|p| p.accession == accession))
+            }
+        };
+        i.map(move |i| &mut self.proteins[i])
+    }
+
+    /// Discard the accession index, so that it is rebuilt on the next lookup
+    pub fn reindex(&mut self) {
+        self.index = AccessionIndex::default();
+    }
+
+    /// Return true if the dataset has no quantified channels, as is the case
+    /// for spectral counting (label-free) Census output
+    pub fn is_label_free(&self) -> bool {
+        self.channels == 0
+    }
+
+    /// Transform every peptide intensity `x` into `log2(x + pseudocount)`.
+    /// Values that are not positive after adding the pseudocount become
+    /// missing (`NaN`). Does nothing if the dataset is already on log2
+    /// scale
+    pub fn log2_transform(&mut self, pseudocount: f64) {
+        if self.scale != Scale::Linear {
+            return;
+        }
+        self.map_values(|x| {
+            let x = x + pseudocount;
+            if x > 0.0 {
+                x.log2()
+            } else {
+                f64::NAN
+            }
+        });
+        self.scale = Scale::Log2(pseudocount);
+    }
+
+    /// Reverse `log2_transform`, transforming every peptide intensity `x`
+    /// into `2^x - pseudocount`. Values that became missing when
+    /// log-transformed remain missing. Does nothing if the dataset is
+    /// already on linear scale
+    pub fn expm2(&mut self) {
+        if let Scale::Log2(pseudocount) = self.scale {
+            self.map_values(|x| x.exp2() - pseudocount);
+            self.scale = Scale::Linear;
+        }
+    }
+
+    /// Apply `f` to every peptide intensity
+    fn map_values<F: Fn(f64) -> f64>(&mut self, f: F) {
+        for prot in self.proteins.iter_mut() {
+            for pep in prot.peptides.iter_mut() {
+                pep.values.iter_mut().for_each(|x| *x = f(*x));
+            }
+        }
+    }
+
+    /// Sort proteins with the comparator `f`. The sort is stable
+    pub fn sort_by<F: FnMut(&Protein, &Protein) -> std::cmp::Ordering>(&mut self, f: F) {
+        self.proteins.sort_by(f);
+        self.reindex();
+    }
+
+    /// Sort proteins by accession
+    pub fn sort_by_accession(&mut self) {
+        self.sort_by(|a, b| a.accession.cmp(&b.accession));
+    }
+
+    /// Sort proteins by their summed linear intensity across all channels
+    /// (see `Protein::total`), from highest to lowest
+    pub fn sort_by_total_intensity(&mut self) {
+        let scale = self.scale;
+        let mut keyed = std::mem::take(&mut self.proteins)
+            .into_iter()
+            .map(|prot| (util::sum(&prot.linear_total(scale)), prot))
+            .collect::<Vec<_>>();
+        keyed.sort_by(|a, b| b.0.total_cmp(&a.0));
+        self.proteins = keyed.into_iter().map(|(_, prot)| prot).collect();
+        self.reindex();
+    }
+
+    /// Keep only the proteins for which `f` returns true
+    pub fn retain<F: FnMut(&Protein) -> bool>(&mut self, f: F) {
+        self.proteins.retain(f);
+        self.reindex();
+    }
+
+    /// Keep only the peptides for which `f`, called with each peptide and
+    /// the protein it belongs to, returns true. As with `Filter`, proteins
+    /// left without any peptides are removed
+    pub fn retain_peptides<F: FnMut(&Protein, &Peptide) -> bool>(&mut self, mut f: F) {
+        for prot in self.proteins.iter_mut() {
+            let keep = prot
+                .peptides
+                .iter()
+                .map(|pep| f(prot, pep))
+                .collect::<Vec<_>>();
+            let mut keep = keep.into_iter();
+            prot.peptides.retain(|_| keep.next().unwrap_or(false));
+        }
+        self.retain(|prot| !prot.peptides.is_empty());
+    }
+
     pub fn filter(self, filter: &Filter) -> Self {
         filter.filter_dataset(self)
     }
+
+    /// Apply `filter` in place, as `Filter::filter_dataset` does, without
+    /// moving or cloning the dataset
+    pub fn apply_filter_in_place(&mut self, filter: &Filter) {
+        let reg = Filter::tryptic_regex();
+        let context = filter::Context::new(self);
+        self.proteins
+            .retain_mut(|prot| filter.retain_protein(prot, &reg, &context, None));
+        self.reindex();
+    }
+
+    /// Merge datasets from several TMT plexes into a single dataset, with
+    /// the channels of each plex placed in consecutive blocks.
+    ///
+    /// Proteins are combined by accession. Each peptide keeps its intensities
+    /// in its own plex's block of channels, and is missing (`NaN`) in every
+    /// other block. The channel range of plex `n` (1-based) is recorded in the
+    /// merged header as the `plex[n]` parameter, e.g. "1-10".
+    ///
+    /// Fails with `DatasetError::MixedScales` unless every plex has the same
+    /// `Scale`, and with `DatasetError::TooManyChannels` if the plexes have
+    /// more than 255 channels between them
+    pub fn merge(plexes: Vec<Dataset>) -> Result<Dataset, DatasetError> {
+        if plexes.windows(2).any(|w| w[0].scale != w[1].scale) {
+            return Err(DatasetError::MixedScales);
+        }
+        let channels = plexes.iter().map(|d| d.channels as usize).sum::<usize>();
+        let channels =
+            u8::try_from(channels).map_err(|_| DatasetError::TooManyChannels(channels))?;
+        let mut merged = Dataset {
+            proteins: Vec::new(),
+            channels,
+            header: plexes.first().map(|d| d.header.clone()).unwrap_or_default(),
+            channel_info: Vec::new(),
+            scale: plexes.first().map(|d| d.scale).unwrap_or_default(),
+            ..Dataset::default()
+        };
+        let annotated = plexes
+            .iter()
+            .all(|d| d.channel_info.len() == d.channels as usize);
+        let mut index: HashMap<String, usize> = HashMap::new();
+        let mut offset = 0;
+
+        for (plex, data) in plexes.into_iter().enumerate() {
+            let n = data.channels as usize;
+            merged.header.parameters.push((
+                format!("plex[{}]", plex + 1),
+                format!("{}-{}", offset + 1, offset + n),
+            ));
+            if annotated {
+                merged
+                    .channel_info
+                    .extend(data.channel_info.into_iter().map(|info| ChannelInfo {
+                        plex: Some(plex as u32 + 1),
+                        ..info
+                    }));
+            }
+            for prot in data.proteins {
+                let idx = *index.entry(prot.accession.clone()).or_insert_with(|| {
+                    merged.proteins.push(Protein {
+                        accession: prot.accession.clone(),
+                        description: prot.description.clone(),
+                        alternatives: prot.alternatives.clone(),
+                        entry_name: prot.entry_name.clone(),
+                        protein_name: prot.protein_name.clone(),
+                        organism: prot.organism.clone(),
+                        gene: prot.gene.clone(),
+                        molecular_weight: prot.molecular_weight,
+                        channels,
+                        ..Protein::default()
+                    });
+                    merged.proteins.len() - 1
+                });
+                let entry = &mut merged.proteins[idx];
+                entry.spectral_count = entry.spectral_count.saturating_add(prot.spectral_count);
+                entry.sequence_coverage = entry.sequence_coverage.max(prot.sequence_coverage);
+                entry
+                    .peptides
+                    .extend(prot.peptides.into_iter().map(|mut pep| {
+                        pep.values = pad(&pep.values, offset, channels as usize);
+                        if !pep.norm_values.is_empty() {
+                            pep.norm_values = pad(&pep.norm_values, offset, channels as usize);
+                        }
+                        pep
+                    }));
+            }
+            offset += n;
+        }
+
+        for prot in merged.proteins.iter_mut() {
+            let sequences = prot
+                .peptides
+                .iter()
+                .map(|pep| pep.sequence.as_str())
+                .collect::<HashSet<_>>()
+                .len();
+            prot.sequence_count = u16::try_from(sequences).unwrap_or(u16::MAX);
+        }
+        Ok(merged)
+    }
+
+    /// Recalculate which peptides are unique to a single protein (group)
+    /// from the proteins currently in the dataset, since the flags read from
+    /// a Census file become stale after filtering or merging.
+    ///
+    /// Peptides are compared by stripped sequence, ignoring modifications
+    /// and flanking residues. Every peptide's `unique` flag and
+    /// `shared_with` list are updated. Returns the number of peptides whose
+    /// `unique` flag changed
+    pub fn recompute_uniqueness(&mut self) -> usize {
+        let mut owners: HashMap<String, Vec<usize>> = HashMap::new();
+        for (i, prot) in self.proteins.iter().enumerate() {
+            for pep in &prot.peptides {
+                let entry = owners.entry(pep.stripped_sequence()).or_default();
+                if entry.last() != Some(&i) {
+                    entry.push(i);
+                }
+            }
+        }
+
+        let accessions = self
+            .proteins
+            .iter()
+            .map(|p| p.accession.clone())
+            .collect::<Vec<_>>();
+        let mut changed = 0;
+        for (i, prot) in self.proteins.iter_mut().enumerate() {
+            for pep in prot.peptides.iter_mut() {
+                pep.shared_with = owners[&pep.stripped_sequence()]
+                    .iter()
+                    .filter(|&&j| j != i)
+                    .map(|&j| accessions[j].clone())
+                    .collect();
+                let unique = pep.shared_with.is_empty();
+                if pep.unique != unique {
+                    pep.unique = unique;
+                    changed += 1;
+                }
+            }
+        }
+        changed
+    }
+
+    /// Return a copy of the dataset with its proteins replaced by `proteins`
+    pub(crate) fn with_proteins(&self, proteins: Vec<Protein>) -> Dataset {
+        Dataset {
+            proteins,
+            channels: self.channels,
+            header: self.header.clone(),
+            channel_info: self.channel_info.clone(),
+            scale: self.scale,
+            ..Dataset::default()
+        }
+    }
+
+    /// Return the proteins of this dataset whose accessions are also
+    /// present in `other`
+    pub fn intersect(&self, other: &Dataset) -> Dataset {
+        let accessions = other.accessions();
+        self.with_proteins(
+            self.proteins
+                .iter()
+                .filter(|p| accessions.contains(p.accession.as_str()))
+                .cloned()
+                .collect(),
+        )
+    }
+
+    /// Return the proteins of this dataset whose accessions are not present
+    /// in `other`
+    pub fn difference(&self, other: &Dataset) -> Dataset {
+        let accessions = other.accessions();
+        self.with_proteins(
+            self.proteins
+                .iter()
+                .filter(|p| !accessions.contains(p.accession.as_str()))
+                .cloned()
+                .collect(),
+        )
+    }
+
+    /// Return every protein of this dataset, followed by the proteins of
+    /// `other` whose accessions this dataset does not contain.
+    ///
+    /// A protein present in both keeps the metadata of this dataset, and
+    /// gains the peptides of `other` that it does not already contain (by
+    /// sequence, scan and file name). Unlike `Dataset::merge`, both datasets
+    /// must share the same channels.
+    ///
+    /// Fails with `DatasetError::ChannelMismatch` if the datasets have
+    /// different numbers of channels, or `DatasetError::MixedScales` if they
+    /// are not on the same `Scale`
+    pub fn union_merge(&self, other: &Dataset) -> Result<Dataset, DatasetError> {
+        if self.channels != other.channels {
+            return Err(DatasetError::ChannelMismatch(self.channels, other.channels));
+        }
+        if self.scale != other.scale {
+            return Err(DatasetError::MixedScales);
+        }
+        let mut merged = self.clone();
+        merged.reindex();
+        let mut index: HashMap<&str, usize> = HashMap::with_capacity(self.proteins.len());
+        for (i, prot) in self.proteins.iter().enumerate() {
+            index.entry(prot.accession.as_str()).or_insert(i);
+        }
+        for prot in &other.proteins {
+            let entry = match index.get(prot.accession.as_str()) {
+                Some(&i) => &mut merged.proteins[i],
+                None => {
+                    index.insert(prot.accession.as_str(), merged.proteins.len());
+                    merged.proteins.push(prot.clone());
+                    continue;
+                }
+            };
+            let seen = entry
+                .peptides
+                .iter()
+                .map(|pep| (pep.sequence.as_str(), pep.scan, pep.file_name.as_str()))
+                .collect::<HashSet<_>>();
+            let added = prot
+                .peptides
+                .iter()
+                .filter(|pep| {
+                    !seen.contains(&(pep.sequence.as_str(), pep.scan, pep.file_name.as_str()))
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+            let count = u16::try_from(added.len()).unwrap_or(u16::MAX);
+            entry.spectral_count = entry.spectral_count.saturating_add(count);
+            entry.peptides.extend(added);
+            let sequences = entry
+                .peptides
+                .iter()
+                .map(|pep| pep.sequence.as_str())
+                .collect::<HashSet<_>>()
+                .len();
+            entry.sequence_count = u16::try_from(sequences).unwrap_or(u16::MAX);
+        }
+        Ok(merged)
+    }
+}
+
+/// Place `values` at `offset` within a vector of `len` missing values
+fn pad(values: &[f64], offset: usize, len: usize) -> Vec<f64> {
+    let mut v = vec![f64::NAN; len];
+    v[offset..offset + values.len()].copy_from_slice(values);
+    v
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn set_operations() {
+        let protein = |acc: &str, seqs: &[&str]| Protein {
+            accession: acc.into(),
+            channels: 1,
+            peptides: seqs
+                .iter()
+                .map(|s| Peptide {
+                    sequence: s.to_string(),
+                    values: vec![1.0],
+                    ..Peptide::default()
+                })
+                .collect(),
+            ..Protein::default()
+        };
+        let a = Dataset {
+            channels: 1,
+            proteins: vec![protein("P1", &["AAK"]), protein("P2", &["CCK"])],
+            ..Dataset::default()
+        };
+        let b = Dataset {
+            channels: 1,
+            proteins: vec![protein("P2", &["CCK", "DDK"]), protein("P3", &["EEK"])],
+            ..Dataset::default()
+        };
+        let names = |d: Dataset| {
+            d.proteins
+                .into_iter()
+                .map(|p| p.accession)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(a.intersect(&b)), vec!["P2"]);
+        assert_eq!(names(a.difference(&b)), vec!["P1"]);
+
+        let union = a.union_merge(&b).unwrap();
+        let p2 = union.get("P2").unwrap();
+        assert_eq!(p2.peptides.len(), 2);
+        assert_eq!(p2.sequence_count, 2);
+        assert_eq!(p2.spectral_count, 1);
+        assert_eq!(names(union), vec!["P1", "P2", "P3"]);
+
+        let mut full = a.clone();
+        full.proteins[1].spectral_count = u16::MAX;
+        let union = full.union_merge(&b).unwrap();
+        assert_eq!(union.proteins[1].spectral_count, u16::MAX);
+
+        let mut log = b.clone();
+        log.log2_transform(1.0);
+        assert_eq!(a.union_merge(&log), Err(DatasetError::MixedScales));
+        let wide = Dataset {
+            channels: 2,
+            ..Dataset::default()
+        };
+        assert_eq!(
+            a.union_merge(&wide),
+            Err(DatasetError::ChannelMismatch(1, 2))
+        );
+    }
+
+    #[test]
+    fn peptide_uniqueness() {
+        let protein = |acc: &str, seqs: &[&str]| Protein {
+            accession: acc.into(),
+            peptides: seqs
+                .iter()
+                .map(|s| Peptide {
+                    sequence: s.to_string(),
+                    unique: true,
+                    ..Peptide::default()
+                })
+                .collect(),
+            ..Protein::default()
+        };
+        let mut data = Dataset {
+            proteins: vec![
+                protein("P1", &["K.AAK.L", "R.CCK.D"]),
+                protein("P2", &["R.AAK.-", "K.EEK.R"]),
+            ],
+            ..Dataset::default()
+        };
+        assert_eq!(data.recompute_uniqueness(), 2);
+        assert!(!data.proteins[0].peptides[0].unique);
+        assert_eq!(data.proteins[1].peptides[0].shared_with, vec!["P1"]);
+        assert!(data.proteins[0].peptides[1].unique);
+
+        data.proteins.remove(0);
+        assert_eq!(data.recompute_uniqueness(), 1);
+        assert!(data.proteins[0].peptides[0].shared_with.is_empty());
+    }
+
+    #[test]
+    fn closure_retention() {
+        let protein = |acc: &str, seqs: &[&str]| {
+            Protein::builder(acc)
+                .peptides(seqs.iter().map(|s| Peptide::builder(*s).build()))
+                .build()
+                .unwrap()
+        };
+        let mut data = Dataset::builder()
+            .proteins(vec![
+                protein("P1", &["AAK", "CCK"]),
+                protein("P2", &["DDK"]),
+                protein("Reverse_P3", &["EEK"]),
+            ])
+            .build()
+            .unwrap();
+        data.retain(|prot| !prot.accession.starts_with("Reverse"));
+        assert_eq!(data.proteins.len(), 2);
+
+        data.retain_peptides(|prot, pep| prot.accession == "P1" && pep.sequence != "CCK");
+        assert_eq!(data.proteins.len(), 1);
+        assert_eq!(data.proteins[0].peptides.len(), 1);
+        assert!(data.get("P2").is_none());
+    }
+
+    #[test]
+    fn protein_order() {
+        let protein = |acc: &str, values: Vec<f64>| {
+            Protein::builder(acc)
+                .peptide(Peptide::builder("AAK").values(values).build())
+                .build()
+                .unwrap()
+        };
+        let mut data = Dataset::builder()
+            .proteins(vec![
+                protein("P2", vec![1.0, 2.0]),
+                protein("P3", vec![f64::NAN, f64::NAN]),
+                protein("P1", vec![10.0, f64::NAN]),
+            ])
+            .build()
+            .unwrap();
+        let order = |d: &Dataset| {
+            d.proteins
+                .iter()
+                .map(|p| p.accession.clone())
+                .collect::<Vec<_>>()
+        };
+        data.sort_by_accession();
+        assert_eq!(order(&data), vec!["P1", "P2", "P3"]);
+        data.sort_by_total_intensity();
+        assert_eq!(order(&data), vec!["P1", "P2", "P3"]);
+        data.sort_by(|a, b| b.accession.cmp(&a.accession));
+        assert_eq!(order(&data), vec!["P3", "P2", "P1"]);
+        assert_eq!(data.get("P1").unwrap().total()[0], 10.0);
+
+        // Ranked by linear intensity: 64 + 1 > 16 + 16, although 6 + 0 < 4 + 4
+        let mut data = Dataset::builder()
+            .proteins(vec![
+                protein("P1", vec![16.0, 16.0]),
+                protein("P2", vec![64.0, 1.0]),
+            ])
+            .build()
+            .unwrap();
+        data.log2_transform(0.0);
+        data.sort_by_total_intensity();
+        assert_eq!(order(&data), vec!["P2", "P1"]);
+    }
+
+    #[test]
+    fn accession_index() {
+        let protein = |acc: &str| Protein {
+            accession: acc.into(),
+            ..Protein::default()
+        };
+        let mut data = Dataset {
+            proteins: vec![protein("P1"), protein("P2")],
+            ..Dataset::default()
+        };
+        assert_eq!(data.get("P2").map(|p| p.accession.as_str()), Some("P2"));
+        assert!(data.get("P3").is_none());
+
+        // The index is stale after each of these changes
+        data.proteins.push(protein("P3"));
+        assert!(data.get("P3").is_some());
+        data.get_mut("P3").unwrap().spectral_count = 2;
+        assert_eq!(data.get("P3").unwrap().spectral_count, 2);
+        data.proteins.remove(0);
+        assert!(data.get("P1").is_none());
+        assert!(data.get_mut("P2").is_some());
+        assert_eq!(data, data.clone());
+
+        // Duplicate accessions do not make the index look out of date
+        data.proteins.push(protein("P2"));
+        data.reindex();
+        assert_eq!(data.index.find(&data.proteins, "P2"), Ok(Some(0)));
+        assert_eq!(data.index.find(&data.proteins, "P1"), Ok(None));
+    }
+
+    fn plex(accessions: &[&str], values: Vec<f64>) -> Dataset {
+        Dataset {
+            channels: values.len() as u8,
+            proteins: accessions
+                .iter()
+                .map(|acc| Protein {
+                    accession: acc.to_string(),
+                    spectral_count: 1,
+                    channels: values.len() as u8,
+                    peptides: vec![Peptide {
+                        sequence: format!("K.{}.R", acc),
+                        values: values.clone(),
+                        ..Peptide::default()
+                    }],
+                    ..Protein::default()
+                })
+                .collect(),
+            header: Header::default(),
+            channel_info: Vec::new(),
+            scale: Scale::Linear,
+            ..Dataset::default()
+        }
+    }
+
+    #[test]
+    fn merge_plexes() {
+        let merged = Dataset::merge(vec![
+            plex(&["P1", "P2"], vec![1.0, 2.0]),
+            plex(&["P2", "P3"], vec![3.0, 4.0, 5.0]),
+        ])
+        .unwrap();
+        assert_eq!(merged.channels, 5);
+        assert_eq!(merged.accessions().len(), 3);
+        assert_eq!(merged.header.parameter("plex[2]"), Some("3-5"));
+
+        let p2 = merged.map()["P2"];
+        assert_eq!(p2.spectral_count, 2);
+        assert_eq!(p2.sequence_count, 1);
+        assert_eq!(p2.peptides.len(), 2);
+        assert_eq!(&p2.peptides[0].values[..2], &[1.0, 2.0]);
+        assert!(p2.peptides[0].is_missing(2));
+        assert_eq!(&p2.peptides[1].values[2..], &[3.0, 4.0, 5.0]);
+        assert_eq!(p2.total(), vec![1.0, 2.0, 3.0, 4.0, 5.0]);
+
+        let mut log = plex(&["P1"], vec![1.0]);
+        log.log2_transform(1.0);
+        assert_eq!(
+            Dataset::merge(vec![plex(&["P1"], vec![1.0]), log]),
+            Err(DatasetError::MixedScales)
+        );
+
+        let wide = || plex(&["P1"], vec![1.0; 128]);
+        assert_eq!(
+            Dataset::merge(vec![wide(), wide()]),
+            Err(DatasetError::TooManyChannels(256))
+        );
+
+        let mut a = plex(&["P1"], vec![1.0]);
+        a.proteins[0].spectral_count = u16::MAX;
+        let merged = Dataset::merge(vec![a, plex(&["P1"], vec![1.0])]).unwrap();
+        assert_eq!(merged.proteins[0].spectral_count, u16::MAX);
+    }
+
+    #[test]
+    fn log_transform() {
+        let mut data = plex(&["P1"], vec![0.0, 3.0, f64::NAN]);
+        data.log2_transform(1.0);
+        assert_eq!(data.scale, Scale::Log2(1.0));
+        assert_eq!(&data.proteins[0].peptides[0].values[..2], &[0.0, 2.0]);
+        data.log2_transform(1.0);
+        assert_eq!(data.proteins[0].peptides[0].values[1], 2.0);
+
+        data.expm2();
+        assert_eq!(data.scale, Scale::Linear);
+        assert_eq!(&data.proteins[0].peptides[0].values[..2], &[0.0, 3.0]);
+        assert!(data.proteins[0].peptides[0].is_missing(2));
+    }
 }
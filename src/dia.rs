@@ -0,0 +1,284 @@
+//! Import long-format DIA reports (DIA-NN, Spectronaut), where each row
+//! reports the quantity of one precursor in one run
+//!
+//! Reports are pivoted into the `Protein`/`Peptide` model, with each run
+//! becoming a channel, so that the protein rollup and channel-based filters
+//! can be reused for DIA experiments. Run names are recorded in the dataset
+//! header as `run[n]` parameters, in channel order, and are used as the
+//! channel labels.
+//!
+//! DIA reports do not carry flanking residues, so peptide sequences are the
+//! (modified) precursor sequences as reported.
+use super::*;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+/// Names of the report columns used to build a `Dataset`
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiaColumns {
+    /// Run (raw file) name, which becomes a channel
+    pub run: String,
+    /// Protein group accession(s)
+    pub protein: String,
+    /// Modified precursor sequence
+    pub sequence: String,
+    /// Precursor charge state
+    pub charge: String,
+    /// Precursor quantity
+    pub quantity: String,
+    /// Optional protein description or name column
+    pub description: Option<String>,
+    /// Optional proteotypicity column, used for `Peptide::unique`
+    pub proteotypic: Option<String>,
+}
+
+impl DiaColumns {
+    /// Column names of a DIA-NN `report.tsv`
+    pub fn diann() -> DiaColumns {
+        DiaColumns {
+            run: "Run".into(),
+            protein: "Protein.Group".into(),
+            sequence: "Modified.Sequence".into(),
+            charge: "Precursor.Charge".into(),
+            quantity: "Precursor.Quantity".into(),
+            description: Some("Protein.Names".into()),
+            proteotypic: Some("Proteotypic".into()),
+        }
+    }
+
+    /// Column names of a Spectronaut precursor-level report
+    pub fn spectronaut() -> DiaColumns {
+        DiaColumns {
+            run: "R.FileName".into(),
+            protein: "PG.ProteinAccessions".into(),
+            sequence: "EG.ModifiedSequence".into(),
+            charge: "FG.Charge".into(),
+            quantity: "FG.Quantity".into(),
+            description: Some("PG.ProteinDescriptions".into()),
+            proteotypic: Some("PEP.IsProteotypic".into()),
+        }
+    }
+}
+
+/// Parse a long-format DIA report, detecting whether it was produced by
+/// DIA-NN or Spectronaut from its column names
+pub fn from_long_format(input: &str) -> Result<Dataset, Error> {
+    let first = input.lines().next().unwrap_or("");
+    let columns = if first.split('\t').any(|c| c == "R.FileName") {
+        DiaColumns::spectronaut()
+    } else {
+        DiaColumns::diann()
+    };
+    from_long_format_with(input, &columns)
+}
+
+/// Parse a tab-delimited long-format DIA report using the given column names
+pub fn from_long_format_with(input: &str, columns: &DiaColumns) -> Result<Dataset, Error> {
+    let mut lines = input.lines();
+    let names = lines
+        .next()
+        .ok_or_else(|| Error::new(ErrorKind::EOF, 1))?
+        .split('\t')
+        .collect::<Vec<_>>();
+    let find = |name: &str| names.iter().position(|n| *n == name);
+    let require =
+        |name: &str| find(name).ok_or_else(|| Error::new(ErrorKind::MissingColumn(name.into()), 1));
+    let run = require(&columns.run)?;
+    let protein = require(&columns.protein)?;
+    let sequence = require(&columns.sequence)?;
+    let charge = require(&columns.charge)?;
+    let quantity = require(&columns.quantity)?;
+    let description = columns.description.as_deref().and_then(find);
+    let proteotypic = columns.proteotypic.as_deref().and_then(find);
+
+    let rows = lines
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(n, line)| (n + 2, line.split('\t').collect::<Vec<_>>()))
+        .collect::<Vec<_>>();
+
+    // First pass: runs, in order of first appearance, become channels
+    let mut runs: Vec<&str> = Vec::new();
+    for (line, fields) in &rows {
+        let name = *fields
+            .get(run)
+            .ok_or_else(|| Error::new(ErrorKind::EOF, *line))?;
+        if !runs.contains(&name) {
+            runs.push(name);
+            // Channels are indexed by `u8`, so `channels as u8` below is exact
+            if u8::try_from(runs.len()).is_err() {
+                return Err(Error::new(ErrorKind::ChannelCount(runs.len()), *line));
+            }
+        }
+    }
+    let channels = runs.len();
+
+    let mut proteins: Vec<Protein> = Vec::new();
+    let mut protein_index: HashMap<&str, usize> = HashMap::new();
+    let mut precursor_index: HashMap<(&str, &str, &str), usize> = HashMap::new();
+
+    for (line, fields) in &rows {
+        let field = |idx: usize| {
+            fields
+                .get(idx)
+                .copied()
+                .ok_or_else(|| Error::new(ErrorKind::EOF, *line))
+        };
+        let group = field(protein)?;
+        let seq = field(sequence)?.trim_matches('_');
+        let z = field(charge)?;
+        let name = field(run)?;
+        let channel = runs.iter().position(|r| *r == name).unwrap_or(0);
+        let value = match field(quantity)? {
+            "" | "NA" | "NaN" => f64::NAN,
+            s => s.parse::<f64>().map_err(|_| {
+                Error::new(ErrorKind::Conversion, *line).with_context(ErrorContext {
+                    column: Some(quantity + 1),
+                    field: Some(columns.quantity.clone()),
+                    token: Some(s.into()),
+                    text: fields.join("\t"),
+                })
+            })?,
+        };
+
+        let p = *protein_index.entry(group).or_insert_with(|| {
+            proteins.push(Protein {
+                accession: group.into(),
+                description: description
+                    .and_then(|i| fields.get(i))
+                    .copied()
+                    .unwrap_or("")
+                    .into(),
+                channels: channels as u8,
+                ..Protein::default()
+            });
+            proteins.len() - 1
+        });
+
+        let peptides = &mut proteins[p].peptides;
+        let idx = *precursor_index.entry((group, seq, z)).or_insert_with(|| {
+            let unique = match proteotypic.and_then(|i| fields.get(i)) {
+                Some(s) => matches!(*s, "1" | "True" | "TRUE" | "true"),
+                None => !group.contains(';'),
+            };
+            peptides.push(Peptide {
+                sequence: seq.into(),
+                values: vec![f64::NAN; channels],
+                unique,
+                purity: 1.0,
+                charge: z.parse().unwrap_or(0),
+                ..Peptide::default()
+            });
+            peptides.len() - 1
+        });
+
+        // Keep the first quantity reported for each precursor and run
+        let slot = &mut peptides[idx].values[channel];
+        if slot.is_nan() {
+            *slot = value;
+        }
+    }
+
+    for prot in proteins.iter_mut() {
+        prot.annotate();
+        prot.spectral_count = prot.peptides.len() as u16;
+        let mut seqs = prot
+            .peptides
+            .iter()
+            .map(|p| &p.sequence)
+            .collect::<Vec<_>>();
+        seqs.sort_unstable();
+        seqs.dedup();
+        prot.sequence_count = seqs.len() as u16;
+    }
+
+    let header = Header {
+        parameters: runs
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (format!("run[{}]", i + 1), name.to_string()))
+            .collect(),
+        ..Header::default()
+    };
+    let channel_info = runs
+        .iter()
+        .map(|name| ChannelInfo {
+            label: name.to_string(),
+            ..ChannelInfo::default()
+        })
+        .collect();
+
+    Ok(Dataset {
+        proteins,
+        channels: channels as u8,
+        header,
+        channel_info,
+        scale: Scale::Linear,
+        ..Dataset::default()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn diann_report() {
+        let input = "File.Name\tRun\tProtein.Group\tProtein.Names\tModified.Sequence\tPrecursor.Charge\tPrecursor.Quantity\tProteotypic
+a.raw\tA\tP12345\tALBU_HUMAN\tLVNEVTEFAK\t2\t1000\t1
+b.raw\tB\tP12345\tALBU_HUMAN\tLVNEVTEFAK\t2\t1500\t1
+a.raw\tA\tP12345\tALBU_HUMAN\tQNC(UniMod:4)ELFEK\t2\t300\t1
+b.raw\tB\tP02768;P12345\tALBU\tAAAAAK\t3\t50\t0
+";
+        let data = from_long_format(input).unwrap();
+        assert_eq!(data.channels, 2);
+        assert_eq!(data.header.parameter("run[2]"), Some("B"));
+        assert_eq!(data.channel_names(), vec!["A", "B"]);
+        assert_eq!(data.proteins.len(), 2);
+
+        let prot = &data.proteins[0];
+        assert_eq!(prot.description, "ALBU_HUMAN");
+        assert_eq!(prot.spectral_count, 2);
+        assert_eq!(prot.peptides[0].values, vec![1000.0, 1500.0]);
+        assert_eq!(prot.peptides[0].charge, 2);
+        assert!(prot.peptides[1].values[1].is_nan());
+        assert!(!data.proteins[1].peptides[0].unique);
+    }
+
+    #[test]
+    fn spectronaut_report() {
+        let input = "R.FileName\tPG.ProteinAccessions\tEG.ModifiedSequence\tFG.Charge\tFG.Quantity
+A\tP12345\t_LVNEVTEFAK_\t2\t1000
+B\tP12345\t_LVNEVTEFAK_\t2\tNaN
+";
+        let data = from_long_format(input).unwrap();
+        assert_eq!(data.channels, 2);
+        let pep = &data.proteins[0].peptides[0];
+        assert_eq!(pep.sequence, "LVNEVTEFAK");
+        assert_eq!(pep.values[0], 1000.0);
+        assert!(pep.values[1].is_nan());
+        assert!(pep.unique);
+    }
+
+    #[test]
+    fn too_many_runs() {
+        let mut input = String::from(
+            "Run\tProtein.Group\tModified.Sequence\tPrecursor.Charge\tPrecursor.Quantity\n",
+        );
+        for i in 0..300 {
+            input.push_str(&format!("run{}\tP12345\tLVNEVTEFAK\t2\t1000\n", i));
+        }
+        let err = from_long_format(&input).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::ChannelCount(256));
+        assert_eq!(err.line(), 257);
+    }
+
+    #[test]
+    fn missing_column() {
+        let err = from_long_format("Run\tProtein.Group\n").unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::MissingColumn("Modified.Sequence".into())
+        );
+    }
+}
@@ -0,0 +1,167 @@
+//! Comparison of two datasets, e.g. before and after reprocessing
+use super::*;
+#[cfg(feature = "serialization")]
+use serde::Serialize;
+use std::collections::HashMap;
+
+#[cfg_attr(feature = "serialization", derive(Serialize))]
+#[derive(PartialEq, Clone, Debug)]
+/// Change in the spectral count of a protein
+pub struct CountChange {
+    pub accession: String,
+    pub before: u16,
+    pub after: u16,
+}
+
+#[cfg_attr(feature = "serialization", derive(Serialize))]
+#[derive(PartialEq, Clone, Debug)]
+/// Change in the summed intensities of a protein
+pub struct IntensityShift {
+    pub accession: String,
+    /// log2 ratio of the new to the old summed intensity of each channel
+    /// (see `Protein::total`), on linear scale
+    pub log2_ratios: Vec<f64>,
+}
+
+#[cfg_attr(feature = "serialization", derive(Serialize))]
+#[derive(PartialEq, Clone, Debug, Default)]
+/// Differences between two datasets, see `Dataset::diff`
+pub struct DatasetDiff {
+    /// Accessions only present in the new dataset
+    pub gained: Vec<String>,
+    /// Accessions only present in the old dataset
+    pub lost: Vec<String>,
+    pub spectral_counts: Vec<CountChange>,
+    /// Proteins with at least one channel shifted beyond the threshold
+    pub intensity_shifts: Vec<IntensityShift>,
+}
+
+impl DatasetDiff {
+    /// Return true if no differences were found
+    pub fn is_empty(&self) -> bool {
+        self.gained.is_empty()
+            && self.lost.is_empty()
+            && self.spectral_counts.is_empty()
+            && self.intensity_shifts.is_empty()
+    }
+}
+
+impl Dataset {
+    /// Compare this dataset with a newer version of it, `other`. Proteins
+    /// are matched by accession, and a protein's intensities are reported
+    /// as shifted if the absolute log2 ratio of any channel exceeds
+    /// `log2_threshold`, or if a channel is missing in only one dataset.
+    /// Intensities are compared on linear scale, so a log2 transformed
+    /// dataset can be compared with a linear one
+    pub fn diff(&self, other: &Dataset, log2_threshold: f64) -> DatasetDiff {
+        let (old_index, new_index) = (by_accession(self), by_accession(other));
+        let mut diff = DatasetDiff {
+            gained: other
+                .proteins
+                .iter()
+                .filter(|p| !old_index.contains_key(p.accession.as_str()))
+                .map(|p| p.accession.clone())
+                .collect(),
+            ..DatasetDiff::default()
+        };
+        for old in &self.proteins {
+            let new = match new_index.get(old.accession.as_str()) {
+                Some(new) => new,
+                None => {
+                    diff.lost.push(old.accession.clone());
+                    continue;
+                }
+            };
+            if old.spectral_count != new.spectral_count {
+                diff.spectral_counts.push(CountChange {
+                    accession: old.accession.clone(),
+                    before: old.spectral_count,
+                    after: new.spectral_count,
+                });
+            }
+            let (before, after) = (old.linear_total(self.scale), new.linear_total(other.scale));
+            let log2_ratios = before
+                .iter()
+                .zip(&after)
+                .map(|(a, b)| (b / a).log2())
+                .collect::<Vec<_>>();
+            let shifted = old.channels != new.channels
+                || before
+                    .iter()
+                    .zip(&after)
+                    .zip(&log2_ratios)
+                    .any(|((a, b), r)| a.is_nan() != b.is_nan() || r.abs() > log2_threshold);
+            if shifted {
+                diff.intensity_shifts.push(IntensityShift {
+                    accession: old.accession.clone(),
+                    log2_ratios,
+                });
+            }
+        }
+        diff
+    }
+}
+
+/// Map from accession to the first protein with that accession. Built once
+/// per diff rather than using `Dataset::get`, which searches linearly if
+/// the dataset's accession index is out of date
+fn by_accession(data: &Dataset) -> HashMap<&str, &Protein> {
+    let mut map = HashMap::with_capacity(data.proteins.len());
+    for prot in &data.proteins {
+        map.entry(prot.accession.as_str()).or_insert(prot);
+    }
+    map
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn dataset_diff() {
+        let protein = |acc: &str, values: Vec<f64>| {
+            Protein::builder(acc)
+                .peptide(Peptide::builder("AAK").values(values).build())
+                .build()
+                .unwrap()
+        };
+        let old = Dataset::builder()
+            .proteins(vec![
+                protein("P1", vec![100.0, 100.0]),
+                protein("P2", vec![100.0, 100.0]),
+                protein("P3", vec![100.0, 100.0]),
+            ])
+            .build()
+            .unwrap();
+        let mut new = Dataset::builder()
+            .proteins(vec![
+                protein("P1", vec![100.0, 110.0]),
+                protein("P2", vec![100.0, 400.0]),
+                protein("P4", vec![1.0, 1.0]),
+            ])
+            .build()
+            .unwrap();
+        new.proteins[0].spectral_count = 3;
+
+        let diff = old.diff(&new, 0.5);
+        assert_eq!(diff.gained, vec!["P4"]);
+        assert_eq!(diff.lost, vec!["P3"]);
+        assert_eq!(
+            diff.spectral_counts,
+            vec![CountChange {
+                accession: "P1".into(),
+                before: 1,
+                after: 3
+            }]
+        );
+        assert_eq!(diff.intensity_shifts.len(), 1);
+        assert_eq!(diff.intensity_shifts[0].log2_ratios, vec![0.0, 2.0]);
+        assert!(old.diff(&old, 0.5).is_empty());
+
+        let mut log = old.clone();
+        log.log2_transform(0.0);
+        assert!(old.diff(&log, 0.5).is_empty());
+        assert!(log.diff(&old, 0.5).is_empty());
+        assert!(log.diff(&log, 0.5).is_empty());
+    }
+}
@@ -0,0 +1,148 @@
+//! Protease cleavage rules, for checking the termini and missed cleavages
+//! of peptides
+use super::*;
+#[cfg(feature = "serialization")]
+use serde::{Deserialize, Serialize};
+
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+#[derive(PartialEq, PartialOrd, Clone, Debug)]
+/// A protease, described by the residues it cleaves at and the residues
+/// that block cleavage
+pub enum Enzyme {
+    /// Cleaves after K or R, except before P
+    Trypsin,
+    /// Cleaves after K or R, including before P
+    TrypsinP,
+    /// Cleaves after K, except before P
+    LysC,
+    /// Cleaves after F, W or Y, except before P
+    Chymotrypsin,
+    /// Cleaves after E, except before P
+    GluC,
+    /// Cleaves after any of `residues`, or before them if `n_term` is set,
+    /// unless the residue on the other side of the site is in `restrict`.
+    /// For example, Asp-N cleaves before D with no restriction
+    Custom {
+        residues: String,
+        restrict: String,
+        n_term: bool,
+    },
+}
+
+impl Enzyme {
+    /// Residues cleaved at, residues blocking cleavage, and whether
+    /// cleavage is N-terminal to the residues
+    fn rule(&self) -> (&str, &str, bool) {
+        match self {
+            Enzyme::Trypsin => ("KR", "P", false),
+            Enzyme::TrypsinP => ("KR", "", false),
+            Enzyme::LysC => ("K", "P", false),
+            Enzyme::Chymotrypsin => ("FWY", "P", false),
+            Enzyme::GluC => ("E", "P", false),
+            Enzyme::Custom {
+                residues,
+                restrict,
+                n_term,
+            } => (residues, restrict, *n_term),
+        }
+    }
+
+    /// Does the enzyme cleave between residues `n` and `c`?
+    pub fn cleaves(&self, n: char, c: char) -> bool {
+        let (residues, restrict, n_term) = self.rule();
+        if n_term {
+            residues.contains(c) && !restrict.contains(n)
+        } else {
+            residues.contains(n) && !restrict.contains(c)
+        }
+    }
+
+    /// Return the number of enzymatic termini (0, 1 or 2) of a peptide,
+    /// judged from its flanking residues, e.g. "K.LVNEVTEFAK.T". Protein
+    /// termini, written as "-", and sequences without flanking residues
+    /// count as enzymatic
+    pub fn termini(&self, peptide: &Peptide) -> usize {
+        let (prev, core, next) = modification::split_flanks(&peptide.sequence);
+        let seq = modification::parse(core).0;
+        let site = |n: Option<char>, c: Option<char>| match (n, c) {
+            (Some('-'), _) | (_, Some('-')) => true,
+            (Some(n), Some(c)) => self.cleaves(n, c),
+            _ => false,
+        };
+        usize::from(site(prev.chars().next(), seq.chars().next()))
+            + usize::from(site(seq.chars().last(), next.chars().next()))
+    }
+
+    /// Return the number of missed cleavages: internal sites of the
+    /// stripped peptide sequence that the enzyme cleaves
+    pub fn missed_cleavages(&self, peptide: &Peptide) -> usize {
+        let seq = peptide.stripped_sequence().chars().collect::<Vec<_>>();
+        seq.windows(2).filter(|&w| self.cleaves(w[0], w[1])).count()
+    }
+}
+
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+#[derive(PartialEq, PartialOrd, Clone, Debug)]
+/// Required enzymatic termini of a peptide, see `Enzyme::termini`
+pub enum Termini {
+    /// Both termini are enzymatic
+    Full(Enzyme),
+    /// At least one terminus is enzymatic
+    Semi(Enzyme),
+}
+
+impl Termini {
+    /// Does the peptide have the required enzymatic termini?
+    pub fn matches(&self, peptide: &Peptide) -> bool {
+        match self {
+            Termini::Full(enzyme) => enzyme.termini(peptide) == 2,
+            Termini::Semi(enzyme) => enzyme.termini(peptide) >= 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pep(sequence: &str) -> Peptide {
+        Peptide {
+            sequence: sequence.into(),
+            ..Peptide::default()
+        }
+    }
+
+    #[test]
+    fn termini() {
+        assert_eq!(Enzyme::Trypsin.termini(&pep("K.LVNEVTEFAK.T")), 2);
+        assert_eq!(Enzyme::Trypsin.termini(&pep("K.LVNEVTEFAK.P")), 1);
+        assert_eq!(Enzyme::TrypsinP.termini(&pep("K.LVNEVTEFAK.P")), 2);
+        assert_eq!(Enzyme::Trypsin.termini(&pep("-.MDKDT.A")), 1);
+        assert_eq!(Enzyme::Trypsin.termini(&pep("S.KMDKDT.A")), 0);
+        assert_eq!(Enzyme::LysC.termini(&pep("R.AAK(42.0106).T")), 1);
+        assert_eq!(Enzyme::Chymotrypsin.termini(&pep("W.AAAY.G")), 2);
+        assert_eq!(Enzyme::GluC.termini(&pep("E.AAAE.-")), 2);
+
+        let asp_n = Enzyme::Custom {
+            residues: "D".into(),
+            restrict: String::new(),
+            n_term: true,
+        };
+        assert_eq!(asp_n.termini(&pep("A.DAAAK.D")), 2);
+        assert_eq!(asp_n.termini(&pep("D.AAAK.L")), 0);
+
+        assert!(Termini::Full(Enzyme::Trypsin).matches(&pep("R.AAK.L")));
+        assert!(!Termini::Full(Enzyme::Trypsin).matches(&pep("R.AAK.P")));
+        assert!(Termini::Semi(Enzyme::Trypsin).matches(&pep("R.AAK.P")));
+        assert!(!Termini::Semi(Enzyme::LysC).matches(&pep("R.AAR.L")));
+    }
+
+    #[test]
+    fn missed_cleavages() {
+        let p = pep("K.AKPLVRK(42.0106)EFK.T");
+        assert_eq!(Enzyme::Trypsin.missed_cleavages(&p), 2);
+        assert_eq!(Enzyme::TrypsinP.missed_cleavages(&p), 3);
+        assert_eq!(Enzyme::LysC.missed_cleavages(&p), 1);
+        assert_eq!(Enzyme::Chymotrypsin.missed_cleavages(&p), 1);
+    }
+}
@@ -0,0 +1,178 @@
+//! Target-decoy estimation of the protein-level false discovery rate
+use super::*;
+#[cfg(feature = "serialization")]
+use serde::{Deserialize, Serialize};
+
+/// Identification of decoy proteins by accession
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum DecoyPattern {
+    /// Accession starts with the given prefix, e.g. "rev_"
+    Prefix(String),
+    /// Accession contains the given text, e.g. "Reverse" (as in
+    /// `ProteinFilter::ExcludeReverse`)
+    Contains(String),
+}
+
+impl Default for DecoyPattern {
+    fn default() -> Self {
+        DecoyPattern::Contains("Reverse".into())
+    }
+}
+
+impl DecoyPattern {
+    pub fn is_decoy(&self, accession: &str) -> bool {
+        match self {
+            DecoyPattern::Prefix(p) => accession.starts_with(p.as_str()),
+            DecoyPattern::Contains(s) => accession.contains(s.as_str()),
+        }
+    }
+}
+
+/// Score used to rank proteins, where higher is better
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Default)]
+pub enum ProteinScore {
+    #[default]
+    SpectralCount,
+    SequenceCount,
+    /// Highest SEQUEST XCorr of the protein's peptides
+    MaxXcorr,
+    /// Sum of the SEQUEST XCorr of the protein's peptides
+    SumXcorr,
+}
+
+impl ProteinScore {
+    pub fn score(&self, protein: &Protein) -> f64 {
+        let xcorr = protein.peptides.iter().map(|pep| pep.xcorr as f64);
+        match self {
+            ProteinScore::SpectralCount => protein.spectral_count as f64,
+            ProteinScore::SequenceCount => protein.sequence_count as f64,
+            ProteinScore::MaxXcorr => xcorr.fold(0.0, f64::max),
+            ProteinScore::SumXcorr => xcorr.sum(),
+        }
+    }
+}
+
+#[cfg_attr(feature = "serialization", derive(Serialize))]
+#[derive(PartialEq, Clone, Debug)]
+/// FDR estimate of a single protein, see `Dataset::protein_fdr`
+pub struct ProteinFdr {
+    /// Uniprot accession identifier
+    pub accession: String,
+    pub score: f64,
+    pub decoy: bool,
+    /// Lowest FDR at which the protein is accepted
+    pub q_value: f64,
+}
+
+impl Dataset {
+    /// Estimate the protein-level FDR by target-decoy competition. Proteins
+    /// are ranked by `score`, and the FDR at each score cutoff is the
+    /// number of decoys divided by the number of targets scoring at least
+    /// as high. Results are in order of decreasing score
+    pub fn protein_fdr(&self, decoys: &DecoyPattern, score: ProteinScore) -> Vec<ProteinFdr> {
+        let mut rows = self
+            .proteins
+            .iter()
+            .map(|prot| ProteinFdr {
+                accession: prot.accession.clone(),
+                score: score.score(prot),
+                decoy: decoys.is_decoy(&prot.accession),
+                q_value: f64::NAN,
+            })
+            .collect::<Vec<_>>();
+        rows.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+        let (mut targets, mut decoy_count) = (0usize, 0usize);
+        let mut start = 0;
+        for i in 0..rows.len() {
+            if rows[i].decoy {
+                decoy_count += 1;
+            } else {
+                targets += 1;
+            }
+            // Proteins with tied scores share the FDR of their cutoff
+            if rows
+                .get(i + 1)
+                .is_none_or(|next| next.score != rows[i].score)
+            {
+                let fdr = (decoy_count as f64 / targets.max(1) as f64).min(1.0);
+                rows[start..=i].iter_mut().for_each(|r| r.q_value = fdr);
+                start = i + 1;
+            }
+        }
+        let mut min = 1.0f64;
+        for row in rows.iter_mut().rev() {
+            min = min.min(row.q_value);
+            row.q_value = min;
+        }
+        rows
+    }
+
+    /// Remove decoy proteins, and target proteins with a q-value above
+    /// `threshold` (e.g. 0.01), see `Dataset::protein_fdr`. Returns the
+    /// lowest score that was accepted, or `None` if no target passes
+    pub fn filter_to_fdr(
+        &mut self,
+        decoys: &DecoyPattern,
+        score: ProteinScore,
+        threshold: f64,
+    ) -> Option<f64> {
+        let accepted = self
+            .protein_fdr(decoys, score)
+            .into_iter()
+            .filter(|r| !r.decoy && r.q_value <= threshold)
+            .collect::<Vec<_>>();
+        let cutoff = accepted.last().map(|r| r.score);
+        let accessions = accepted
+            .into_iter()
+            .map(|r| r.accession)
+            .collect::<std::collections::HashSet<_>>();
+        self.retain(|prot| accessions.contains(&prot.accession));
+        cutoff
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn target_decoy() {
+        let protein = |acc: &str, spectral_count| Protein {
+            accession: acc.into(),
+            spectral_count,
+            ..Protein::default()
+        };
+        let mut data = Dataset {
+            proteins: vec![
+                protein("P1", 10),
+                protein("P2", 8),
+                protein("Reverse_P9", 7),
+                protein("P3", 6),
+                protein("P4", 5),
+                protein("Reverse_P8", 2),
+                protein("P5", 2),
+            ],
+            ..Dataset::default()
+        };
+        let decoys = DecoyPattern::default();
+        let fdr = data.protein_fdr(&decoys, ProteinScore::SpectralCount);
+        assert_eq!(fdr[0].q_value, 0.0);
+        // One decoy among four targets at a score of 5
+        assert_eq!(fdr[4].accession, "P4");
+        assert_eq!(fdr[2].q_value, 0.25);
+        assert_eq!(fdr[4].q_value, 0.25);
+        // Tied scores share a cutoff: two decoys, five targets
+        assert_eq!(fdr[5].q_value, 0.4);
+        assert_eq!(fdr[6].q_value, 0.4);
+
+        assert_eq!(
+            data.filter_to_fdr(&decoys, ProteinScore::SpectralCount, 0.3),
+            Some(5.0)
+        );
+        assert_eq!(data.proteins.len(), 4);
+        assert!(DecoyPattern::Prefix("rev_".into()).is_decoy("rev_P1"));
+    }
+}
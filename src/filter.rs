@@ -3,19 +3,110 @@
 use super::*;
 #[cfg(feature = "serialization")]
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::iter::FromIterator;
+
+mod compile;
+#[cfg(any(feature = "toml", feature = "yaml"))]
+mod config;
+mod parse;
+mod presets;
+mod validate;
+
+pub use compile::CompiledFilter;
+#[cfg(any(feature = "toml", feature = "yaml"))]
+pub use config::ConfigError;
+pub use validate::FilterError;
 
 /// Protein-level filter
-#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
-pub enum ProteinFilter {
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub enum ProteinFilter<'a> {
     /// Include only proteins that have spectral counts >= N
     SpectralCounts(u16),
     /// Include only proteins that have sequence counts >= N
     SequenceCounts(u16),
+    /// Include only proteins with at least N distinct sequences among their
+    /// unique peptides, after peptide filtering
+    UniquePeptides(u16),
     /// Include only proteins that do not have "Reverse" in their
     /// UniProt accession
     ExcludeReverse,
+    /// Include only proteins that are not contaminants. A protein is a
+    /// contaminant if its accession, or that of any other member of its
+    /// protein group, starts with a "CON__" or "contaminant_" prefix, or
+    /// with the `crap` feature, is on the bundled cRAP list (see
+    /// `ContaminantList::crap`). See `Dataset::remove_contaminants` to match
+    /// against another list of accessions
+    ExcludeContaminants,
+    /// Include only proteins with a nonzero, non-missing summed intensity
+    /// (see `Protein::total`) in at least N channels, after peptide
+    /// filtering
+    MinQuantifiedChannels(usize),
+    /// Include only proteins quantified in at least N plexes of a merged
+    /// multi-plex dataset, after peptide filtering. A protein is quantified
+    /// in a plex if its summed intensity is nonzero and non-missing in any
+    /// channel of the plex. Plexes are read from the `ChannelInfo::plex`
+    /// annotations, and channels without one form a single plex together
+    MinQuantifiedPlexes(usize),
+    /// Keep only the N peptides with the highest total linear intensity in
+    /// each protein. Applied last, so that the other rules count every
+    /// peptide that passed peptide filtering. Ties keep the earlier peptide
+    TopNPeptides(usize),
+    /// Include only proteins with a sequence coverage (percent) >= N
+    MinCoverage(f32),
+    /// MolecularWeightRange(min, max)
+    ///
+    /// Include only proteins with a molecular weight (Da) within [min, max]
+    MolecularWeightRange(u32, u32),
+    /// Include only proteins with a description containing the pattern,
+    /// ignoring case
+    DescriptionMatches(Cow<'a, str>),
+    /// Include only proteins with a description NOT containing the
+    /// pattern, ignoring case, e.g. "Keratin" or "Trypsin" to remove common
+    /// contaminants
+    DescriptionExcludes(Cow<'a, str>),
+}
+
+impl ProteinFilter<'_> {
+    /// Convert into a filter that owns its patterns
+    pub fn into_owned(self) -> ProteinFilter<'static> {
+        match self {
+            ProteinFilter::SpectralCounts(n) => ProteinFilter::SpectralCounts(n),
+            ProteinFilter::SequenceCounts(n) => ProteinFilter::SequenceCounts(n),
+            ProteinFilter::ExcludeReverse => ProteinFilter::ExcludeReverse,
+            ProteinFilter::ExcludeContaminants => ProteinFilter::ExcludeContaminants,
+            ProteinFilter::UniquePeptides(n) => ProteinFilter::UniquePeptides(n),
+            ProteinFilter::MinQuantifiedChannels(n) => ProteinFilter::MinQuantifiedChannels(n),
+            ProteinFilter::MinQuantifiedPlexes(n) => ProteinFilter::MinQuantifiedPlexes(n),
+            ProteinFilter::TopNPeptides(n) => ProteinFilter::TopNPeptides(n),
+            ProteinFilter::MinCoverage(n) => ProteinFilter::MinCoverage(n),
+            ProteinFilter::MolecularWeightRange(min, max) => {
+                ProteinFilter::MolecularWeightRange(min, max)
+            }
+            ProteinFilter::DescriptionMatches(pat) => {
+                ProteinFilter::DescriptionMatches(pat.into_owned().into())
+            }
+            ProteinFilter::DescriptionExcludes(pat) => {
+                ProteinFilter::DescriptionExcludes(pat.into_owned().into())
+            }
+        }
+    }
+
+    /// Does `other` set the same threshold as this filter, so that only one
+    /// of them should apply? Pattern rules can be combined, and never
+    /// conflict
+    fn conflicts(&self, other: &ProteinFilter<'_>) -> bool {
+        match self {
+            ProteinFilter::ExcludeReverse
+            | ProteinFilter::ExcludeContaminants
+            | ProteinFilter::DescriptionMatches(_)
+            | ProteinFilter::DescriptionExcludes(_) => false,
+            _ => std::mem::discriminant(self) == std::mem::discriminant(other),
+        }
+    }
 }
 
 /// Peptide-level filter
@@ -26,48 +117,382 @@ pub enum ProteinFilter {
 ///
 /// Peptide can also be filtered based on whether they have 2 tryptic ends,
 /// or if they are unique.
-#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub enum PeptideFilter<'a> {
     /// Include only peptides that have a sequence matching the pattern
-    SequenceMatch(&'a str),
+    SequenceMatch(Cow<'a, str>),
     /// Include only peptides that do NOT have a sequence matching the pattern
-    SequenceExclude(&'a str),
+    SequenceExclude(Cow<'a, str>),
+    /// Include only peptides that have a sequence matching the regular
+    /// expression, e.g. "N[^P][ST]" for N-glycosylation motifs. The
+    /// expression is matched against the sequence without its flanking
+    /// residues, but with any modifications. An invalid expression matches
+    /// no peptides, and is rejected by `Filter::parse` and `Filter::validate`
+    SequenceRegex(Cow<'a, str>),
+    /// Include only peptides that do NOT have a sequence matching the
+    /// regular expression, as by `SequenceRegex`
+    SequenceRegexExclude(Cow<'a, str>),
+    /// Length(min, max)
+    ///
+    /// Include only peptides whose stripped sequence (without flanking
+    /// residues or modifications) has a length within [min, max]
+    Length(usize, usize),
+
+    /// Include only peptides with at most N missed cleavages, see
+    /// `Peptide::missed_cleavages`
+    MaxMissedCleavages(usize),
+
+    /// Include only peptides with a nonzero, non-missing intensity in at
+    /// least N channels
+    MinQuantifiedChannels(usize),
+
     /// Include only peptides that have a total ion itensity >= N
-    TotalIntensity(u32),
+    TotalIntensity(f64),
 
     /// Include only peptides where the total intensity in a set of channels
     /// >= N
-    TotalIntensityChannels(Vec<usize>, u32),
+    TotalIntensityChannels(Vec<usize>, f64),
 
     /// ChannelCV(channels, N)
     ///
     /// Include only peptides where the coeff. of variance is < N between
-    /// the specified channels
+    /// the specified channels. Missing intensities are ignored, and
+    /// peptides whose CV is undefined (e.g. with no intensity in any of the
+    /// channels) pass
     ChannelCV(Vec<usize>, f64),
 
+    /// ChannelCVQuantified(channels, N, min)
+    ///
+    /// Like `ChannelCV`, but zero intensities are ignored as well as
+    /// missing ones, so that unquantified channels do not dominate the CV.
+    /// Peptides with fewer than `min` quantified channels among the
+    /// specified channels are not subject to the rule, and pass
+    ChannelCVQuantified(Vec<usize>, f64, usize),
+
+    /// ChannelRatio(numerator, denominator, min, max)
+    ///
+    /// Include only peptides where the ratio of the mean intensity of the
+    /// numerator channels to that of the denominator channels is within
+    /// [min, max]. Missing intensities are ignored, and peptides whose
+    /// ratio cannot be computed are excluded
+    ChannelRatio(Vec<usize>, Vec<usize>, f64, f64),
+
     /// ChannelIntensity(channel, cutoff)
     ///
     /// Include only peptides that have an ion intensity >= N
-    /// in the specified channel
-    ChannelIntensity(usize, u32),
+    /// in the specified channel, excluding those where it is missing
+    ChannelIntensity(usize, f64),
 
-    /// TMT purity
+    /// Include only peptides with a precursor isolation purity >= N, to
+    /// exclude PSMs contaminated by co-isolated ions. Purity is read from
+    /// the `PURITY` column when present, and is 1.0 otherwise
     Purity(f32),
 
-    /// Include only tryptic peptides
+    /// RetentionTime(min, max)
+    ///
+    /// Include only peptides eluting within the retention time window
+    /// [min, max]. Peptides without a retention time are excluded
+    RetentionTime(f32, f32),
+
+    /// ScanRange(min, max)
+    ///
+    /// Include only peptides identified from a scan number within
+    /// [min, max]
+    ScanRange(usize, usize),
+
+    /// Include only peptides identified in a spectrum file whose name
+    /// contains the pattern, e.g. "F3" for a single fraction. Combine with
+    /// `PeptideFilter::Not` to exclude files instead
+    SourceFile(Cow<'a, str>),
+
+    /// Include only tryptic peptides, with K, R or a protein terminus
+    /// before and at the end of the peptide. See `PeptideFilter::Termini`
+    /// for other enzymes
     Tryptic,
+    /// Include only peptides with fully or semi enzymatic termini, see
+    /// `Enzyme::termini`
+    Termini(Termini),
     /// Include only unique peptides
     Unique,
+
+    /// Include only peptides passing any of the filters
+    Or(Vec<PeptideFilter<'a>>),
+    /// Include only peptides failing the filter
+    Not(Box<PeptideFilter<'a>>),
+    /// AnyOf(n, filters)
+    ///
+    /// Include only peptides passing at least N of the filters
+    AnyOf(usize, Vec<PeptideFilter<'a>>),
+}
+
+impl<'a> PeptideFilter<'a> {
+    /// Convert into a filter that owns its patterns
+    pub fn into_owned(self) -> PeptideFilter<'static> {
+        let owned = |pat: Cow<'_, str>| Cow::Owned(pat.into_owned());
+        let all = |filters: Vec<PeptideFilter<'_>>| {
+            filters.into_iter().map(PeptideFilter::into_owned).collect()
+        };
+        match self {
+            PeptideFilter::SequenceMatch(pat) => PeptideFilter::SequenceMatch(owned(pat)),
+            PeptideFilter::SequenceExclude(pat) => PeptideFilter::SequenceExclude(owned(pat)),
+            PeptideFilter::SequenceRegex(pat) => PeptideFilter::SequenceRegex(owned(pat)),
+            PeptideFilter::SequenceRegexExclude(pat) => {
+                PeptideFilter::SequenceRegexExclude(owned(pat))
+            }
+            PeptideFilter::Length(min, max) => PeptideFilter::Length(min, max),
+            PeptideFilter::MaxMissedCleavages(n) => PeptideFilter::MaxMissedCleavages(n),
+            PeptideFilter::MinQuantifiedChannels(n) => PeptideFilter::MinQuantifiedChannels(n),
+            PeptideFilter::TotalIntensity(n) => PeptideFilter::TotalIntensity(n),
+            PeptideFilter::TotalIntensityChannels(c, n) => {
+                PeptideFilter::TotalIntensityChannels(c, n)
+            }
+            PeptideFilter::ChannelCV(c, n) => PeptideFilter::ChannelCV(c, n),
+            PeptideFilter::ChannelCVQuantified(c, n, min) => {
+                PeptideFilter::ChannelCVQuantified(c, n, min)
+            }
+            PeptideFilter::ChannelRatio(num, den, min, max) => {
+                PeptideFilter::ChannelRatio(num, den, min, max)
+            }
+            PeptideFilter::ChannelIntensity(c, n) => PeptideFilter::ChannelIntensity(c, n),
+            PeptideFilter::Purity(n) => PeptideFilter::Purity(n),
+            PeptideFilter::RetentionTime(min, max) => PeptideFilter::RetentionTime(min, max),
+            PeptideFilter::ScanRange(min, max) => PeptideFilter::ScanRange(min, max),
+            PeptideFilter::SourceFile(pat) => PeptideFilter::SourceFile(owned(pat)),
+            PeptideFilter::Tryptic => PeptideFilter::Tryptic,
+            PeptideFilter::Termini(t) => PeptideFilter::Termini(t),
+            PeptideFilter::Unique => PeptideFilter::Unique,
+            PeptideFilter::Or(filters) => PeptideFilter::Or(all(filters)),
+            PeptideFilter::Not(filter) => PeptideFilter::Not(Box::new(filter.into_owned())),
+            PeptideFilter::AnyOf(n, filters) => PeptideFilter::AnyOf(n, all(filters)),
+        }
+    }
+
+    /// Does `other` set the same threshold as this filter, so that only one
+    /// of them should apply? Channel rules only conflict if they are on the
+    /// same channels, and pattern rules and combinators can be combined, and
+    /// never conflict
+    fn conflicts(&self, other: &PeptideFilter<'_>) -> bool {
+        use PeptideFilter::*;
+        match (self, other) {
+            (TotalIntensityChannels(a, _), TotalIntensityChannels(b, _))
+            | (ChannelCV(a, _), ChannelCV(b, _))
+            | (ChannelCVQuantified(a, _, _), ChannelCVQuantified(b, _, _)) => a == b,
+            (ChannelRatio(num_a, den_a, _, _), ChannelRatio(num_b, den_b, _, _)) => {
+                num_a == num_b && den_a == den_b
+            }
+            (ChannelIntensity(a, _), ChannelIntensity(b, _)) => a == b,
+            (Length(..), Length(..))
+            | (MaxMissedCleavages(_), MaxMissedCleavages(_))
+            | (MinQuantifiedChannels(_), MinQuantifiedChannels(_))
+            | (TotalIntensity(_), TotalIntensity(_))
+            | (Purity(_), Purity(_))
+            | (RetentionTime(..), RetentionTime(..))
+            | (ScanRange(..), ScanRange(..))
+            | (Termini(_), Termini(_)) => true,
+            _ => false,
+        }
+    }
+
+    /// Does the peptide pass this filter? `tryptic_regex` is used by
+    /// `PeptideFilter::Tryptic`, see `Filter::tryptic_regex`.
+    ///
+    /// Intensities are compared as they are. `Filter::filter_dataset`
+    /// instead compares the linear intensities of a log2 transformed
+    /// dataset
+    pub fn passes(&self, peptide: &Peptide, tryptic_regex: &regex::Regex) -> bool {
+        self.passes_values(peptide, &peptide.values, tryptic_regex)
+    }
+
+    /// Does the peptide pass this filter, with intensity rules applied to
+    /// `values` rather than the peptide's own values?
+    pub(crate) fn passes_values(
+        &self,
+        peptide: &Peptide,
+        values: &[f64],
+        tryptic_regex: &regex::Regex,
+    ) -> bool {
+        match self {
+            PeptideFilter::SequenceExclude(pat) => !peptide.sequence.contains(pat.as_ref()),
+            PeptideFilter::SequenceMatch(pat) => peptide.sequence.contains(pat.as_ref()),
+            PeptideFilter::SequenceRegex(pat) => regex_match(pat, core(peptide)),
+            PeptideFilter::SequenceRegexExclude(pat) => !regex_match(pat, core(peptide)),
+            PeptideFilter::Length(min, max) => {
+                let len = peptide.stripped_sequence().len();
+                len >= *min && len <= *max
+            }
+            PeptideFilter::MaxMissedCleavages(n) => peptide.missed_cleavages() <= *n,
+            PeptideFilter::MinQuantifiedChannels(n) => quantified(values) >= *n,
+            PeptideFilter::TotalIntensity(n) => util::sum(values) >= *n,
+            PeptideFilter::Tryptic => tryptic_regex.is_match(&peptide.sequence),
+            PeptideFilter::Termini(termini) => termini.matches(peptide),
+            PeptideFilter::Unique => peptide.unique,
+            PeptideFilter::Purity(cutoff) => peptide.purity >= *cutoff,
+            PeptideFilter::RetentionTime(min, max) => peptide
+                .retention_time
+                .is_some_and(|rt| rt >= *min && rt <= *max),
+            PeptideFilter::ScanRange(min, max) => peptide.scan >= *min && peptide.scan <= *max,
+            PeptideFilter::SourceFile(pat) => peptide.file_name.contains(pat.as_ref()),
+            // Channels outside of the peptide are ignored, see
+            // `Filter::validate`
+            PeptideFilter::ChannelCV(channels, cutoff) => {
+                let v = channels
+                    .iter()
+                    .filter_map(|&c| channel_value(values, c))
+                    .collect::<Vec<_>>();
+                cv_below(util::cv(&v), *cutoff)
+            }
+            PeptideFilter::ChannelCVQuantified(channels, cutoff, min) => {
+                let v = channels
+                    .iter()
+                    .filter_map(|&c| channel_value(values, c))
+                    .filter(|&v| v != 0.0 && !v.is_nan())
+                    .collect::<Vec<_>>();
+                v.len() < *min || cv_below(util::cv(&v), *cutoff)
+            }
+            PeptideFilter::ChannelRatio(num, den, min, max) => {
+                let mean = |channels: &[usize]| {
+                    let v = channels
+                        .iter()
+                        .filter_map(|&c| channel_value(values, c))
+                        .collect::<Vec<_>>();
+                    util::mean(&v)
+                };
+                let ratio = mean(num) / mean(den);
+                ratio.is_finite() && ratio >= *min && ratio <= *max
+            }
+            PeptideFilter::ChannelIntensity(channel, cutoff) => {
+                // A missing intensity never passes the cutoff
+                match channel_index(values, *channel) {
+                    Some(c) => values[c] >= *cutoff,
+                    None => true,
+                }
+            }
+            PeptideFilter::TotalIntensityChannels(channels, cutoff) => {
+                let sum = channels
+                    .iter()
+                    .filter_map(|&c| channel_index(values, c))
+                    .map(|c| values[c])
+                    .filter(|v| !v.is_nan())
+                    .sum::<f64>();
+                sum >= *cutoff
+            }
+            PeptideFilter::Or(filters) => filters
+                .iter()
+                .any(|f| f.passes_values(peptide, values, tryptic_regex)),
+            PeptideFilter::Not(filter) => !filter.passes_values(peptide, values, tryptic_regex),
+            PeptideFilter::AnyOf(n, filters) => {
+                filters
+                    .iter()
+                    .filter(|f| f.passes_values(peptide, values, tryptic_regex))
+                    .count()
+                    >= *n
+            }
+        }
+    }
+}
+
+thread_local! {
+    /// Compiled `SequenceRegex` patterns, so that each is compiled only once
+    static REGEXES: RefCell<HashMap<String, Option<regex::Regex>>> = RefCell::new(HashMap::new());
+}
+
+/// Does `text` match the regular expression `pattern`? Invalid patterns
+/// never match
+fn regex_match(pattern: &str, text: &str) -> bool {
+    REGEXES.with(|cache| {
+        if let Some(re) = cache.borrow().get(pattern) {
+            return re.as_ref().is_some_and(|re| re.is_match(text));
+        }
+        let re = regex::Regex::new(pattern).ok();
+        let matched = re.as_ref().is_some_and(|re| re.is_match(text));
+        cache.borrow_mut().insert(pattern.to_string(), re);
+        matched
+    })
+}
+
+/// Sequence of `peptide` without its flanking residues, which
+/// `SequenceRegex` patterns are matched against
+fn core(peptide: &Peptide) -> &str {
+    modification::split_flanks(&peptide.sequence).1
+}
+
+/// Does a CV pass the `ChannelCV` `cutoff`? An undefined CV does
+fn cv_below(cv: f64, cutoff: f64) -> bool {
+    cv.is_nan() || cv < cutoff
+}
+
+/// Does `text` contain `pattern`, ignoring case?
+fn contains_ignore_case(text: &str, pattern: &str) -> bool {
+    text.to_lowercase().contains(&pattern.to_lowercase())
+}
+
+/// Count the plexes with a nonzero, non-missing intensity in any channel,
+/// where `plexes` holds the plex of each channel
+fn quantified_plexes(values: &[f64], plexes: &[Option<u32>]) -> usize {
+    values
+        .iter()
+        .enumerate()
+        .filter(|(_, &v)| v != 0.0 && !v.is_nan())
+        .map(|(c, _)| plexes.get(c).copied().flatten())
+        .collect::<HashSet<_>>()
+        .len()
+}
+
+/// The dataset a protein is filtered in, as far as the rules need to know
+#[derive(Default)]
+pub(crate) struct Context {
+    /// Plex annotation of each channel, see
+    /// `ProteinFilter::MinQuantifiedPlexes`
+    pub(crate) plexes: Vec<Option<u32>>,
+    /// Intensity rules compare linear intensities, so values on log2 scale
+    /// are converted back first
+    scale: Scale,
+}
+
+impl Context {
+    pub(crate) fn new(dataset: &Dataset) -> Context {
+        Context {
+            plexes: dataset.channel_info.iter().map(|info| info.plex).collect(),
+            scale: dataset.scale,
+        }
+    }
+
+    /// Linear intensities of `peptide`
+    pub(crate) fn values<'p>(&self, peptide: &'p Peptide) -> Cow<'p, [f64]> {
+        match self.scale {
+            Scale::Linear => Cow::Borrowed(&peptide.values),
+            scale => peptide.values.iter().map(|&v| scale.linear(v)).collect(),
+        }
+    }
+}
+
+/// Index into `values` of the 1-based `channel`, if there is such a channel
+fn channel_index(values: &[f64], channel: usize) -> Option<usize> {
+    channel.checked_sub(1).filter(|&c| c < values.len())
+}
+
+/// Value of the 1-based `channel`, if there is such a channel
+fn channel_value(values: &[f64], channel: usize) -> Option<f64> {
+    channel_index(values, channel).map(|c| values[c])
+}
+
+/// Count the channels with a nonzero, non-missing intensity
+fn quantified(values: &[f64]) -> usize {
+    values.iter().filter(|&&v| v != 0.0 && !v.is_nan()).count()
 }
 
 /// Provides filtering functionality on datasets and proteins
-#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+///
+/// Text patterns are stored as `Cow<str>`, so a filter can either borrow
+/// them, e.g. `PeptideFilter::SequenceMatch("M".into())`, or own them. A
+/// deserialized `Filter<'static>` always owns its patterns, so it can be
+/// loaded from a configuration file and returned from a function
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub struct Filter<'a> {
-    #[cfg_attr(feature = "serde", serde(borrow))]
     peptide_filters: Vec<PeptideFilter<'a>>,
-    protein_filters: Vec<ProteinFilter>,
+    protein_filters: Vec<ProteinFilter<'a>>,
 }
 
 impl<'a> Default for Filter<'a> {
@@ -81,10 +506,27 @@ impl<'a> Default for Filter<'a> {
 }
 
 impl<'a> Filter<'a> {
+    /// Convert into a filter that owns its patterns, so that it can outlive
+    /// the text it was built or deserialized from
+    pub fn into_owned(self) -> Filter<'static> {
+        Filter {
+            peptide_filters: self
+                .peptide_filters
+                .into_iter()
+                .map(PeptideFilter::into_owned)
+                .collect(),
+            protein_filters: self
+                .protein_filters
+                .into_iter()
+                .map(ProteinFilter::into_owned)
+                .collect(),
+        }
+    }
+
     /// Add a new `ProteinFilter` to the `Filter` object.
     ///
     /// This follows the Builder pattern
-    pub fn add_protein_filter(mut self, filter: ProteinFilter) -> Self {
+    pub fn add_protein_filter(mut self, filter: ProteinFilter<'a>) -> Self {
         self.protein_filters.push(filter);
         self
     }
@@ -97,6 +539,48 @@ impl<'a> Filter<'a> {
         self
     }
 
+    /// Combine two filters, e.g. a lab default and experiment-specific
+    /// rules, into one applying the rules of both. Rules of `other` that
+    /// are already in `self` are dropped, so merging a filter with itself
+    /// leaves it unchanged.
+    ///
+    /// A rule of `other` that sets a different threshold for the same kind
+    /// of rule as one in `self`, e.g. `Purity(0.9)` against `Purity(0.7)`,
+    /// or `ChannelCV` on the same channels, overrides it in place. Pattern
+    /// rules (`SequenceMatch`, `DescriptionExcludes`, ...) and combinators
+    /// are kept side by side, as all of them apply.
+    ///
+    /// This follows the Builder pattern
+    pub fn merge(mut self, other: Filter<'a>) -> Self {
+        for filter in other.protein_filters {
+            match self
+                .protein_filters
+                .iter()
+                .position(|f| f.conflicts(&filter))
+            {
+                Some(i) => self.protein_filters[i] = filter,
+                None if !self.protein_filters.contains(&filter) => {
+                    self.protein_filters.push(filter)
+                }
+                None => {}
+            }
+        }
+        for filter in other.peptide_filters {
+            match self
+                .peptide_filters
+                .iter()
+                .position(|f| f.conflicts(&filter))
+            {
+                Some(i) => self.peptide_filters[i] = filter,
+                None if !self.peptide_filters.contains(&filter) => {
+                    self.peptide_filters.push(filter)
+                }
+                None => {}
+            }
+        }
+        self
+    }
+
     pub fn tryptic_regex() -> regex::Regex {
         regex::RegexBuilder::new(r#"(R|K|-)\..*((R|K)\..|.-)"#)
             .build()
@@ -106,13 +590,60 @@ impl<'a> Filter<'a> {
     /// Return a new `Dataset` that only contains filtered `Protein`'s
     pub fn filter_dataset(&self, dataset: Dataset) -> Dataset {
         let reg = Self::tryptic_regex();
+        let context = Context::new(&dataset);
         Dataset {
             channels: dataset.channels,
+            header: dataset.header,
+            channel_info: dataset.channel_info,
+            scale: dataset.scale,
             proteins: dataset
                 .proteins
                 .into_iter()
-                .filter_map(|prot| self.filter_protein(prot, &reg))
+                .filter_map(|prot| self.filter_protein_counted(prot, &reg, &context, None))
+                .collect(),
+            ..Dataset::default()
+        }
+    }
+
+    /// Return a new `Dataset` that only contains filtered `Protein`'s,
+    /// filtering the proteins in parallel. The result is identical to
+    /// `Filter::filter_dataset`, including the order of the proteins
+    #[cfg(feature = "rayon")]
+    pub fn par_filter_dataset(&self, dataset: Dataset) -> Dataset {
+        use rayon::prelude::*;
+        let reg = Self::tryptic_regex();
+        let context = Context::new(&dataset);
+        Dataset {
+            channels: dataset.channels,
+            header: dataset.header,
+            channel_info: dataset.channel_info,
+            scale: dataset.scale,
+            proteins: dataset
+                .proteins
+                .into_par_iter()
+                .filter_map(|prot| self.filter_protein_counted(prot, &reg, &context, None))
+                .collect(),
+            ..Dataset::default()
+        }
+    }
+
+    /// Return a new `Dataset` that only contains filtered `Protein`'s,
+    /// leaving `dataset` untouched. Only the proteins and peptides that
+    /// pass are cloned
+    pub fn filter_dataset_ref(&self, dataset: &Dataset) -> Dataset {
+        let reg = Self::tryptic_regex();
+        let context = Context::new(dataset);
+        Dataset {
+            channels: dataset.channels,
+            header: dataset.header.clone(),
+            channel_info: dataset.channel_info.clone(),
+            scale: dataset.scale,
+            proteins: dataset
+                .proteins
+                .iter()
+                .filter_map(|prot| self.filter_protein_ref(prot, &reg, &context))
                 .collect(),
+            ..Dataset::default()
         }
     }
 
@@ -122,153 +653,399 @@ impl<'a> Filter<'a> {
     ///
     /// The peptides associated with the returned `Protein` object aree those
     /// that passed any given `PeptideFilter`s.
+    ///
+    /// The plexes of the channels are unknown, so all channels are treated
+    /// as a single plex by `ProteinFilter::MinQuantifiedPlexes`
     pub fn filter_protein(
+        &self,
+        protein: Protein,
+        tryptic_regex: &regex::Regex,
+    ) -> Option<Protein> {
+        self.filter_protein_counted(protein, tryptic_regex, &Context::default(), None)
+    }
+
+    /// Return a new `Dataset` that only contains filtered `Protein`'s, and a
+    /// report of how many proteins and peptides each rule removed
+    pub fn filter_dataset_with_report(&self, dataset: Dataset) -> (Dataset, FilterReport) {
+        let reg = Self::tryptic_regex();
+        let context = Context::new(&dataset);
+        let mut report = FilterReport {
+            proteins_before: dataset.proteins.len(),
+            peptides_before: dataset.proteins.iter().map(|p| p.peptides.len()).sum(),
+            protein_rules: self
+                .protein_filters
+                .iter()
+                .map(|f| (f.to_string(), 0))
+                .collect(),
+            peptide_rules: self
+                .peptide_filters
+                .iter()
+                .map(|f| (f.to_string(), 0))
+                .collect(),
+            ..FilterReport::default()
+        };
+        let proteins = dataset
+            .proteins
+            .into_iter()
+            .filter_map(|prot| self.filter_protein_counted(prot, &reg, &context, Some(&mut report)))
+            .collect::<Vec<_>>();
+        report.proteins_after = proteins.len();
+        report.peptides_after = proteins.iter().map(|p| p.peptides.len()).sum();
+        let dataset = Dataset {
+            channels: dataset.channels,
+            header: dataset.header,
+            channel_info: dataset.channel_info,
+            scale: dataset.scale,
+            proteins,
+            ..Dataset::default()
+        };
+        (dataset, report)
+    }
+
+    /// Return the peptide filters that each peptide of `protein`, a protein
+    /// of `dataset`, fails, in peptide order. Passing peptides have an empty
+    /// list. Intensity rules are applied as by `Filter::filter_dataset`
+    pub fn explain(&self, dataset: &Dataset, protein: &Protein) -> Vec<Vec<&PeptideFilter<'a>>> {
+        let reg = Self::tryptic_regex();
+        let context = Context::new(dataset);
+        protein
+            .peptides
+            .iter()
+            .map(|pep| {
+                let values = context.values(pep);
+                self.failed_peptide_rules(pep, &values, &reg)
+                    .map(|i| &self.peptide_filters[i])
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Filter a `Protein`, attributing each removed protein or peptide to
+    /// the first rule it fails in `report`
+    fn filter_protein_counted(
         &self,
         mut protein: Protein,
         tryptic_regex: &regex::Regex,
+        context: &Context,
+        report: Option<&mut FilterReport>,
     ) -> Option<Protein> {
-        // First run through any protein level filters
-        for filter in &self.protein_filters {
-            match filter {
-                ProteinFilter::SequenceCounts(n) => {
-                    if protein.sequence_count < *n {
-                        return None;
-                    }
-                }
-                ProteinFilter::SpectralCounts(n) => {
-                    if protein.spectral_count < *n {
-                        return None;
-                    }
-                }
-                ProteinFilter::ExcludeReverse => {
-                    if protein.accession.contains("Reverse") {
-                        return None;
-                    }
-                }
-            }
+        if self.retain_protein(&mut protein, tryptic_regex, context, report) {
+            Some(protein)
+        } else {
+            None
         }
+    }
+
+    /// Filter a `Protein` in place, removing the peptides that fail any
+    /// `PeptideFilter`. Returns false if the protein should be removed, see
+    /// `Filter::filter_protein`
+    pub(crate) fn retain_protein(
+        &self,
+        protein: &mut Protein,
+        tryptic_regex: &regex::Regex,
+        context: &Context,
+        mut report: Option<&mut FilterReport>,
+    ) -> bool {
+        let reject = |report: Option<&mut FilterReport>, rule: usize, peptides: usize| {
+            if let Some(report) = report {
+                report.protein_rules[rule].1 += 1;
+                report.peptides_in_removed_proteins += peptides;
+            }
+            false
+        };
 
-        let mut filtered = Vec::new();
+        // First run through any protein level filters
+        if let Some(i) = self.failed_protein_rule(protein) {
+            return reject(report, i, protein.peptides.len());
+        }
 
         // Iterate through all of the peptides in the protein container,
         // applying relevant filters as we go.
-        for peptide in protein.peptides {
-            let mut pass = true;
-            for filter in &self.peptide_filters {
-                match filter {
-                    PeptideFilter::SequenceExclude(pat) => {
-                        if peptide.sequence.contains(pat) {
-                            pass = false;
-                            break;
-                        }
-                    }
-                    PeptideFilter::SequenceMatch(pat) => {
-                        if !peptide.sequence.contains(pat) {
-                            pass = false;
-                            break;
-                        }
-                    }
-                    PeptideFilter::TotalIntensity(n) => {
-                        if peptide.values.iter().sum::<u32>() < *n {
-                            pass = false;
-                            break;
-                        }
-                    }
-                    PeptideFilter::Tryptic => {
-                        if !tryptic_regex.is_match(&peptide.sequence) {
-                            pass = false;
-                            break;
-                        }
-                    }
-                    PeptideFilter::Unique => {
-                        if !peptide.unique {
-                            pass = false;
-                            break;
-                        }
-                    }
-                    PeptideFilter::Purity(cutoff) => {
-                        if peptide.purity < *cutoff {
-                            pass = false;
-                            break;
-                        }
-                    }
-                    PeptideFilter::ChannelCV(channels, cutoff) => {
-                        let mut v = Vec::new();
-                        for chan in channels.iter() {
-                            if chan - 1 < peptide.values.len() {
-                                v.push(peptide.values[chan - 1]);
-                            }
-                        }
-                        if util::cv(&v) >= *cutoff {
-                            pass = false;
-                            break;
-                        }
-                    }
-                    PeptideFilter::ChannelIntensity(channel, cutoff) => {
-                        // Ignore incorrect channel values
-                        if channel - 1 < peptide.values.len()
-                            && peptide.values[channel - 1] < *cutoff
-                        {
-                            pass = false;
-                            break;
-                        }
-                    }
-                    PeptideFilter::TotalIntensityChannels(chan, cutoff) => {
-                        let mut sum = 0;
-                        for c in chan {
-                            if c - 1 < peptide.values.len() {
-                                sum += peptide.values[*c - 1];
-                            }
-                        }
-                        if sum < *cutoff {
-                            pass = false;
-                            break;
-                        }
+        protein.peptides.retain(|peptide| {
+            match self.failed_peptide_rule(peptide, tryptic_regex, context) {
+                None => true,
+                Some(i) => {
+                    if let Some(report) = report.as_deref_mut() {
+                        report.peptide_rules[i].1 += 1;
                     }
+                    false
                 }
             }
+        });
+        // We must have at least a single peptide...
+        if protein.peptides.is_empty() {
+            if let Some(report) = report {
+                report.no_peptides += 1;
+            }
+            return false;
+        }
 
-            if pass {
-                filtered.push(peptide)
+        if let Some(i) = self.failed_recount_rule(protein, context) {
+            return reject(report, i, protein.peptides.len());
+        }
+        // Only trim the peptides of proteins that are kept, so that the
+        // rules above count every passing peptide
+        if let Some(n) = self.top_n_peptides() {
+            let before = protein.peptides.len();
+            keep_top_peptides(&mut protein.peptides, n, context);
+            recount(protein);
+            if let Some(report) = report {
+                report.not_top_n += before - protein.peptides.len();
             }
         }
-        // We must have at least a single peptide...
-        if filtered.len() == 0 {
+        true
+    }
+
+    /// Filter a borrowed `Protein`, cloning only its metadata and the
+    /// peptides that pass
+    fn filter_protein_ref(
+        &self,
+        protein: &Protein,
+        tryptic_regex: &regex::Regex,
+        context: &Context,
+    ) -> Option<Protein> {
+        if self.failed_protein_rule(protein).is_some() {
+            return None;
+        }
+        let peptides = protein
+            .peptides
+            .iter()
+            .filter(|peptide| {
+                self.failed_peptide_rule(peptide, tryptic_regex, context)
+                    .is_none()
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        if peptides.is_empty() {
+            return None;
+        }
+        let mut protein = protein.with_peptides(peptides);
+        if self.failed_recount_rule(&mut protein, context).is_some() {
             return None;
         }
+        self.keep_top_peptides(&mut protein, context);
+        Some(protein)
+    }
 
-        protein.peptides = filtered;
+    /// Index of the first protein filter that `protein` fails before its
+    /// peptides are filtered
+    fn failed_protein_rule(&self, protein: &Protein) -> Option<usize> {
+        self.protein_filters.iter().position(|filter| {
+            !match filter {
+                ProteinFilter::SequenceCounts(n) => protein.sequence_count >= *n,
+                ProteinFilter::SpectralCounts(n) => protein.spectral_count >= *n,
+                ProteinFilter::ExcludeReverse => !protein.accession.contains("Reverse"),
+                ProteinFilter::ExcludeContaminants => !contaminant::is_contaminant(protein),
+                ProteinFilter::MinCoverage(n) => protein.sequence_coverage >= *n,
+                ProteinFilter::MolecularWeightRange(min, max) => {
+                    protein.molecular_weight >= *min && protein.molecular_weight <= *max
+                }
+                ProteinFilter::DescriptionMatches(pat) => {
+                    contains_ignore_case(&protein.description, pat)
+                }
+                ProteinFilter::DescriptionExcludes(pat) => {
+                    !contains_ignore_case(&protein.description, pat)
+                }
+                // Applied after peptide filtering, see `failed_recount_rule`
+                ProteinFilter::MinQuantifiedChannels(_)
+                | ProteinFilter::MinQuantifiedPlexes(_)
+                | ProteinFilter::UniquePeptides(_) => true,
+                // Applied last, see `retain_protein`
+                ProteinFilter::TopNPeptides(_) => true,
+            }
+        })
+    }
 
-        let spec = protein.peptides.len() as u16;
-        let seq = protein
-            .peptides
+    /// Apply `ProteinFilter::TopNPeptides` to a protein that passed every
+    /// other rule, and update its counts
+    pub(crate) fn keep_top_peptides(&self, protein: &mut Protein, context: &Context) {
+        if let Some(n) = self.top_n_peptides() {
+            keep_top_peptides(&mut protein.peptides, n, context);
+            recount(protein);
+        }
+    }
+
+    /// Number of peptides to keep per protein, if limited by
+    /// `ProteinFilter::TopNPeptides`
+    fn top_n_peptides(&self) -> Option<usize> {
+        self.protein_filters
+            .iter()
+            .filter_map(|filter| match filter {
+                ProteinFilter::TopNPeptides(n) => Some(*n),
+                _ => None,
+            })
+            .min()
+    }
+
+    /// Index of the first peptide filter that `peptide` fails
+    fn failed_peptide_rule(
+        &self,
+        peptide: &Peptide,
+        tryptic_regex: &regex::Regex,
+        context: &Context,
+    ) -> Option<usize> {
+        let values = context.values(peptide);
+        let failed = self
+            .failed_peptide_rules(peptide, &values, tryptic_regex)
+            .next();
+        failed
+    }
+
+    /// Indices of the peptide filters that `peptide` fails, with intensity
+    /// rules applied to `values`
+    fn failed_peptide_rules<'s>(
+        &'s self,
+        peptide: &'s Peptide,
+        values: &'s [f64],
+        tryptic_regex: &'s regex::Regex,
+    ) -> impl Iterator<Item = usize> + 's {
+        self.peptide_filters
             .iter()
-            .map(|pep| &pep.sequence)
-            .collect::<HashSet<_>>()
-            .len() as u16;
+            .enumerate()
+            .filter(move |(_, filter)| !filter.passes_values(peptide, values, tryptic_regex))
+            .map(|(i, _)| i)
+    }
 
-        protein.spectral_count = spec;
-        protein.sequence_count = seq;
+    /// Update the counts of a protein whose peptides have been filtered,
+    /// and return the index of the first protein filter it now fails
+    fn failed_recount_rule(&self, protein: &mut Protein, context: &Context) -> Option<usize> {
+        recount(protein);
+        let (spec, seq) = (protein.spectral_count, protein.sequence_count);
 
         // Second pass through protein filters, in case we no longer have
         // enough filtered peptides
-        for filter in &self.protein_filters {
-            match filter {
-                ProteinFilter::SequenceCounts(n) => {
-                    if seq < *n {
-                        return None;
-                    }
-                }
-                ProteinFilter::SpectralCounts(n) => {
-                    if spec < *n {
-                        return None;
-                    }
-                }
-                _ => {}
+        self.protein_filters.iter().position(|filter| match filter {
+            ProteinFilter::SequenceCounts(n) => seq < *n,
+            ProteinFilter::SpectralCounts(n) => spec < *n,
+            ProteinFilter::MinQuantifiedChannels(n) => {
+                quantified(&protein.linear_total(context.scale)) < *n
             }
-        }
+            ProteinFilter::MinQuantifiedPlexes(n) => {
+                quantified_plexes(&protein.linear_total(context.scale), &context.plexes) < *n
+            }
+            ProteinFilter::UniquePeptides(n) => {
+                let unique = protein
+                    .peptides
+                    .iter()
+                    .filter(|pep| pep.unique)
+                    .map(|pep| &pep.sequence)
+                    .collect::<HashSet<_>>()
+                    .len();
+                unique < *n as usize
+            }
+            _ => false,
+        })
+    }
+}
 
-        Some(protein)
+/// Set the spectral and sequence counts of a protein from its peptides
+pub(crate) fn recount(protein: &mut Protein) {
+    protein.spectral_count = protein.peptides.len() as u16;
+    protein.sequence_count = protein
+        .peptides
+        .iter()
+        .map(|pep| &pep.sequence)
+        .collect::<HashSet<_>>()
+        .len() as u16;
+}
+
+/// Keep the `n` peptides with the highest total linear intensity, in their
+/// original order
+fn keep_top_peptides(peptides: &mut Vec<Peptide>, n: usize, context: &Context) {
+    if peptides.len() <= n {
+        return;
+    }
+    let totals = peptides
+        .iter()
+        .map(|pep| util::sum(&context.values(pep)))
+        .collect::<Vec<_>>();
+    let mut order = (0..peptides.len()).collect::<Vec<_>>();
+    order.sort_by(|&a, &b| totals[b].total_cmp(&totals[a]));
+    let mut keep = vec![false; peptides.len()];
+    for &i in &order[..n] {
+        keep[i] = true;
+    }
+    let mut keep = keep.into_iter();
+    peptides.retain(|_| keep.next().unwrap_or(false));
+}
+
+/// Merge filters in order, see `Filter::merge`
+impl<'a> FromIterator<Filter<'a>> for Filter<'a> {
+    fn from_iter<I: IntoIterator<Item = Filter<'a>>>(iter: I) -> Self {
+        iter.into_iter().fold(Filter::default(), Filter::merge)
+    }
+}
+
+impl<'a> FromIterator<ProteinFilter<'a>> for Filter<'a> {
+    fn from_iter<I: IntoIterator<Item = ProteinFilter<'a>>>(iter: I) -> Self {
+        iter.into_iter()
+            .fold(Filter::default(), Filter::add_protein_filter)
+    }
+}
+
+impl<'a> FromIterator<PeptideFilter<'a>> for Filter<'a> {
+    fn from_iter<I: IntoIterator<Item = PeptideFilter<'a>>>(iter: I) -> Self {
+        iter.into_iter()
+            .fold(Filter::default(), Filter::add_peptide_filter)
+    }
+}
+
+/// Number of proteins and peptides removed by each rule of a `Filter`, see
+/// `Filter::filter_dataset_with_report`.
+///
+/// Each removed protein or peptide is attributed to the first rule it
+/// failed. Peptides that are removed along with their protein, rather than
+/// by a peptide rule, are counted in `peptides_in_removed_proteins`, so that
+/// the counts add up to the total number of proteins and peptides removed
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FilterReport {
+    pub proteins_before: usize,
+    pub proteins_after: usize,
+    pub peptides_before: usize,
+    pub peptides_after: usize,
+    /// Each protein filter, in the order it was added and in the text
+    /// format of `Filter::parse`, and the number of proteins it removed
+    pub protein_rules: Vec<(String, usize)>,
+    /// Each peptide filter, in the order it was added and in the text
+    /// format of `Filter::parse`, and the number of peptides it removed
+    pub peptide_rules: Vec<(String, usize)>,
+    /// Number of proteins removed because none of their peptides passed
+    pub no_peptides: usize,
+    /// Number of passing peptides removed by `ProteinFilter::TopNPeptides`
+    pub not_top_n: usize,
+    /// Number of peptides not removed by any peptide rule, but along with
+    /// their protein by a protein rule
+    pub peptides_in_removed_proteins: usize,
+}
+
+impl std::fmt::Display for FilterReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Proteins: {} -> {}",
+            self.proteins_before, self.proteins_after
+        )?;
+        for (rule, n) in &self.protein_rules {
+            writeln!(f, "  {:<8} {}", n, rule)?;
+        }
+        writeln!(f, "  {:<8} no passing peptides", self.no_peptides)?;
+        writeln!(
+            f,
+            "Peptides: {} -> {}",
+            self.peptides_before, self.peptides_after
+        )?;
+        for (rule, n) in &self.peptide_rules {
+            writeln!(f, "  {:<8} {}", n, rule)?;
+        }
+        if self.not_top_n > 0 {
+            writeln!(f, "  {:<8} not among the most intense", self.not_top_n)?;
+        }
+        writeln!(
+            f,
+            "  {:<8} in removed proteins",
+            self.peptides_in_removed_proteins
+        )?;
+        Ok(())
     }
 }
 
@@ -280,25 +1057,34 @@ mod test {
     fn total_intensity_channels() {
         let p1 = Peptide {
             sequence: "aa".into(),
-            values: vec![1, 2998, 5000, 84, 4738, 9384],
+            values: vec![1.0, 2998.0, 5000.0, 84.0, 4738.0, 9384.0],
+            norm_values: Vec::new(),
             unique: true,
+            redundant: false,
             scan: 0,
             purity: 1.0,
+            ..Peptide::default()
         };
         let p2 = Peptide {
             sequence: "aaa".into(),
-            values: vec![10000, 0, 433, 61346, 41, 5555],
+            values: vec![10000.0, 0.0, 433.0, 61346.0, 41.0, 5555.0],
+            norm_values: Vec::new(),
             unique: true,
+            redundant: false,
             scan: 0,
             purity: 1.0,
+            ..Peptide::default()
         };
 
         let p3 = Peptide {
             sequence: "aaaa".into(),
-            values: vec![1, 2999, 0, 0, 0, 0],
+            values: vec![1.0, 2999.0, 0.0, 0.0, 0.0, 0.0],
+            norm_values: Vec::new(),
             unique: true,
+            redundant: false,
             scan: 0,
             purity: 1.0,
+            ..Peptide::default()
         };
 
         let prot = Protein {
@@ -310,6 +1096,7 @@ mod test {
             molecular_weight: 10,
             peptides: vec![p1.clone(), p2.clone(), p3.clone()],
             channels: 6,
+            ..Protein::default()
         };
 
         let mut fil = Filter {
@@ -317,10 +1104,755 @@ mod test {
             protein_filters: Vec::new(),
         };
 
-        fil = fil.add_peptide_filter(PeptideFilter::TotalIntensityChannels(vec![1, 2], 3000));
+        fil = fil.add_peptide_filter(PeptideFilter::TotalIntensityChannels(vec![1, 2], 3000.0));
         let p = fil.filter_protein(prot, &Filter::tryptic_regex()).unwrap();
         assert_eq!(p.peptides.len(), 2);
         assert_eq!(p.sequence_count, 2);
         assert_eq!(p.peptides, vec![p2.clone(), p3.clone()]);
     }
+
+    #[test]
+    fn purity() {
+        let pep = |purity| Peptide {
+            values: vec![1.0],
+            purity,
+            ..Peptide::default()
+        };
+        let prot = Protein {
+            peptides: vec![pep(0.95), pep(0.5), pep(0.9)],
+            channels: 1,
+            ..Protein::default()
+        };
+        let fil = Filter::default().add_peptide_filter(PeptideFilter::Purity(0.9));
+        let p = fil.filter_protein(prot, &Filter::tryptic_regex()).unwrap();
+        assert_eq!(p.peptides, vec![pep(0.95), pep(0.9)]);
+    }
+
+    #[test]
+    fn sequence_regex() {
+        let pep = |seq: &str| Peptide {
+            sequence: seq.into(),
+            values: vec![1.0],
+            ..Peptide::default()
+        };
+        let prot = Protein {
+            peptides: vec![pep("K.LNGTK.A"), pep("K.LNPSK.A"), pep("R.AAK.L")],
+            channels: 1,
+            ..Protein::default()
+        };
+        let fil =
+            Filter::default().add_peptide_filter(PeptideFilter::SequenceRegex("N[^P][ST]".into()));
+        let p = fil
+            .filter_protein(prot.clone(), &Filter::tryptic_regex())
+            .unwrap();
+        assert_eq!(p.peptides, vec![pep("K.LNGTK.A")]);
+
+        let fil = Filter::default()
+            .add_peptide_filter(PeptideFilter::SequenceRegexExclude("N[^P][ST]".into()));
+        let p = fil
+            .filter_protein(prot.clone(), &Filter::tryptic_regex())
+            .unwrap();
+        assert_eq!(p.peptides.len(), 2);
+
+        // Flanking residues are not part of the matched sequence
+        let fil = Filter::default().add_peptide_filter(PeptideFilter::SequenceRegex("^K".into()));
+        assert!(fil
+            .filter_protein(prot.clone(), &Filter::tryptic_regex())
+            .is_none());
+
+        let fil = Filter::default().add_peptide_filter(PeptideFilter::SequenceRegex("([".into()));
+        assert!(fil.filter_protein(prot, &Filter::tryptic_regex()).is_none());
+    }
+
+    #[test]
+    fn peptide_length() {
+        let pep = |seq: &str| Peptide {
+            sequence: seq.into(),
+            values: vec![1.0],
+            ..Peptide::default()
+        };
+        let prot = Protein {
+            peptides: vec![
+                pep("K.AAK.L"),
+                pep("K.S(79.9663)EMPEK.R"),
+                pep("R.AAAAAAAAAK.L"),
+            ],
+            channels: 1,
+            ..Protein::default()
+        };
+        let fil = Filter::default().add_peptide_filter(PeptideFilter::Length(6, 9));
+        let p = fil.filter_protein(prot, &Filter::tryptic_regex()).unwrap();
+        assert_eq!(p.peptides, vec![pep("K.S(79.9663)EMPEK.R")]);
+    }
+
+    #[test]
+    fn missed_cleavages() {
+        let pep = |seq: &str| Peptide {
+            sequence: seq.into(),
+            values: vec![1.0],
+            ..Peptide::default()
+        };
+        let prot = Protein {
+            peptides: vec![pep("K.AAK.L"), pep("K.AKPAK.L"), pep("K.AKAKAK.L")],
+            channels: 1,
+            ..Protein::default()
+        };
+        let fil = Filter::default().add_peptide_filter(PeptideFilter::MaxMissedCleavages(1));
+        let p = fil.filter_protein(prot, &Filter::tryptic_regex()).unwrap();
+        assert_eq!(p.peptides, vec![pep("K.AAK.L"), pep("K.AKPAK.L")]);
+    }
+
+    #[test]
+    fn quantified_channels() {
+        let pep = |values: Vec<f64>| Peptide {
+            values,
+            ..Peptide::default()
+        };
+        let prot = Protein {
+            peptides: vec![
+                pep(vec![1.0, 2.0, 0.0]),
+                pep(vec![1.0, f64::NAN, 0.0]),
+                pep(vec![1.0, 2.0, 3.0]),
+            ],
+            channels: 3,
+            ..Protein::default()
+        };
+        let fil = Filter::default().add_peptide_filter(PeptideFilter::MinQuantifiedChannels(2));
+        let p = fil
+            .filter_protein(prot.clone(), &Filter::tryptic_regex())
+            .unwrap();
+        assert_eq!(p.peptides.len(), 2);
+
+        // Totals are quantified in the first two channels only
+        let partial = Protein {
+            peptides: prot.peptides[..2].to_vec(),
+            ..prot.clone()
+        };
+        let fil = Filter::default().add_protein_filter(ProteinFilter::MinQuantifiedChannels(3));
+        assert!(fil
+            .filter_protein(partial, &Filter::tryptic_regex())
+            .is_none());
+        assert!(fil.filter_protein(prot, &Filter::tryptic_regex()).is_some());
+    }
+
+    #[test]
+    fn combinators() {
+        let pep = |unique, total| Peptide {
+            values: vec![total],
+            unique,
+            ..Peptide::default()
+        };
+        let prot = Protein {
+            peptides: vec![pep(true, 10.0), pep(false, 1e6), pep(false, 10.0)],
+            channels: 1,
+            ..Protein::default()
+        };
+        let unique_or_intense = PeptideFilter::Or(vec![
+            PeptideFilter::Unique,
+            PeptideFilter::TotalIntensity(1e5),
+        ]);
+        let fil = Filter::default().add_peptide_filter(unique_or_intense.clone());
+        let p = fil
+            .filter_protein(prot.clone(), &Filter::tryptic_regex())
+            .unwrap();
+        assert_eq!(p.peptides, vec![pep(true, 10.0), pep(false, 1e6)]);
+
+        let fil =
+            Filter::default().add_peptide_filter(PeptideFilter::Not(Box::new(unique_or_intense)));
+        let p = fil
+            .filter_protein(prot.clone(), &Filter::tryptic_regex())
+            .unwrap();
+        assert_eq!(p.peptides, vec![pep(false, 10.0)]);
+
+        let fil = Filter::default().add_peptide_filter(PeptideFilter::AnyOf(
+            2,
+            vec![
+                PeptideFilter::Unique,
+                PeptideFilter::TotalIntensity(1e5),
+                PeptideFilter::TotalIntensity(5.0),
+            ],
+        ));
+        let p = fil.filter_protein(prot, &Filter::tryptic_regex()).unwrap();
+        assert_eq!(p.peptides, vec![pep(true, 10.0), pep(false, 1e6)]);
+    }
+
+    #[test]
+    fn coverage_and_weight() {
+        let prot = |coverage, weight| Protein {
+            sequence_coverage: coverage,
+            molecular_weight: weight,
+            peptides: vec![Peptide {
+                values: vec![1.0],
+                ..Peptide::default()
+            }],
+            channels: 1,
+            ..Protein::default()
+        };
+        let fil = Filter::default()
+            .add_protein_filter(ProteinFilter::MinCoverage(10.0))
+            .add_protein_filter(ProteinFilter::MolecularWeightRange(10_000, 200_000));
+        let reg = Filter::tryptic_regex();
+        assert!(fil.filter_protein(prot(25.0, 66_472), &reg).is_some());
+        assert!(fil.filter_protein(prot(5.0, 66_472), &reg).is_none());
+        assert!(fil.filter_protein(prot(25.0, 5_000), &reg).is_none());
+        assert!(fil.filter_protein(prot(25.0, 500_000), &reg).is_none());
+    }
+
+    #[test]
+    fn description() {
+        let prot = |desc: &str| Protein {
+            description: desc.into(),
+            peptides: vec![Peptide {
+                values: vec![1.0],
+                ..Peptide::default()
+            }],
+            channels: 1,
+            ..Protein::default()
+        };
+        let reg = Filter::tryptic_regex();
+        let fil = Filter::default()
+            .add_protein_filter(ProteinFilter::DescriptionExcludes("keratin".into()));
+        assert!(fil
+            .filter_protein(prot("Keratin, type II cytoskeletal 1"), &reg)
+            .is_none());
+        assert!(fil.filter_protein(prot("Serum albumin"), &reg).is_some());
+
+        let fil = Filter::default()
+            .add_protein_filter(ProteinFilter::DescriptionMatches("Kinase".into()));
+        assert!(fil
+            .filter_protein(prot("Mitogen-activated protein kinase 1"), &reg)
+            .is_some());
+        assert!(fil.filter_protein(prot("Serum albumin"), &reg).is_none());
+    }
+
+    #[test]
+    fn rejection_report() {
+        let pep = |seq: &str, total| Peptide {
+            sequence: seq.into(),
+            values: vec![total],
+            ..Peptide::default()
+        };
+        let prot = |acc: &str, peptides: Vec<Peptide>| Protein {
+            accession: acc.into(),
+            sequence_count: peptides.len() as u16,
+            peptides,
+            channels: 1,
+            ..Protein::default()
+        };
+        let dataset = Dataset::default().with_proteins(vec![
+            prot("P1", vec![pep("K.AAK.L", 100.0), pep("K.CCK.L", 1.0)]),
+            prot("Reverse_P2", vec![pep("K.DDK.L", 100.0)]),
+            prot("P3", vec![pep("K.EEK.L", 1.0), pep("K.MMK.L", 100.0)]),
+            prot("P4", vec![pep("K.FFK.L", 100.0), pep("K.GGK.L", 100.0)]),
+        ]);
+        let fil = Filter::default()
+            .add_protein_filter(ProteinFilter::ExcludeReverse)
+            .add_protein_filter(ProteinFilter::SequenceCounts(2))
+            .add_peptide_filter(PeptideFilter::TotalIntensity(10.0))
+            .add_peptide_filter(PeptideFilter::SequenceExclude("M".into()));
+        let (filtered, report) = fil.filter_dataset_with_report(dataset);
+
+        assert_eq!(filtered.proteins.len(), 1);
+        assert_eq!(report.proteins_before, 4);
+        assert_eq!(report.proteins_after, 1);
+        assert_eq!(report.peptides_before, 7);
+        assert_eq!(report.peptides_after, 2);
+        let counts = |rules: &[(String, usize)]| rules.iter().map(|r| r.1).collect::<Vec<_>>();
+        assert_eq!(counts(&report.protein_rules), vec![1, 1]);
+        assert_eq!(counts(&report.peptide_rules), vec![2, 1]);
+        assert_eq!(report.no_peptides, 1);
+        assert_eq!(report.peptides_in_removed_proteins, 2);
+        assert_eq!(
+            report.peptide_rules.iter().map(|r| r.1).sum::<usize>()
+                + report.not_top_n
+                + report.peptides_in_removed_proteins,
+            report.peptides_before - report.peptides_after
+        );
+        assert_eq!(report.protein_rules[0].0, "exclude_reverse");
+    }
+
+    #[test]
+    fn explain() {
+        let pep = |seq: &str, values| Peptide {
+            sequence: seq.into(),
+            values,
+            unique: true,
+            ..Peptide::default()
+        };
+        let prot = Protein {
+            peptides: vec![
+                pep("K.AAK.L", vec![100.0, 50.0]),
+                pep("K.MMK.L", vec![1.0, f64::NAN]),
+            ],
+            channels: 2,
+            ..Protein::default()
+        };
+        let fil = Filter::default()
+            .add_peptide_filter(PeptideFilter::Unique)
+            .add_peptide_filter(PeptideFilter::SequenceExclude("M".into()))
+            .add_peptide_filter(PeptideFilter::ChannelIntensity(2, 10.0));
+        let mut dataset = Dataset::default().with_proteins(vec![prot]);
+        let reasons = fil.explain(&dataset, &dataset.proteins[0]);
+        assert!(reasons[0].is_empty());
+        assert_eq!(
+            reasons[1],
+            vec![
+                &PeptideFilter::SequenceExclude("M".into()),
+                &PeptideFilter::ChannelIntensity(2, 10.0)
+            ]
+        );
+
+        // Intensities are compared on linear scale: log2(100) < 10 < 100
+        dataset.log2_transform(0.0);
+        let fil = Filter::default().add_peptide_filter(PeptideFilter::ChannelIntensity(1, 10.0));
+        let reasons = fil.explain(&dataset, &dataset.proteins[0]);
+        assert!(reasons[0].is_empty());
+        assert_eq!(reasons[1], vec![&PeptideFilter::ChannelIntensity(1, 10.0)]);
+    }
+
+    #[cfg(feature = "serialization")]
+    fn load_filter(path: &std::path::Path) -> Filter<'static> {
+        let text = std::fs::read_to_string(path).unwrap();
+        serde_json::from_str(&text).unwrap()
+    }
+
+    #[cfg(feature = "serialization")]
+    #[test]
+    fn owned_filter() {
+        let path = std::env::temp_dir().join(format!("census-filter-{}.json", std::process::id()));
+        let pattern = String::from("M");
+        let fil = Filter::default()
+            .add_peptide_filter(PeptideFilter::Not(Box::new(PeptideFilter::SequenceMatch(
+                pattern.as_str().into(),
+            ))))
+            .add_protein_filter(ProteinFilter::DescriptionExcludes("Keratin".into()));
+        std::fs::write(&path, serde_json::to_string(&fil).unwrap()).unwrap();
+        let loaded = load_filter(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, fil.clone().into_owned());
+        assert_eq!(loaded, fil);
+    }
+
+    #[test]
+    fn retention_time() {
+        let pep = |rt| Peptide {
+            values: vec![1.0],
+            retention_time: rt,
+            ..Peptide::default()
+        };
+        let prot = Protein {
+            peptides: vec![pep(Some(10.0)), pep(Some(25.5)), pep(None)],
+            channels: 1,
+            ..Protein::default()
+        };
+        let fil = Filter::default().add_peptide_filter(PeptideFilter::RetentionTime(20.0, 30.0));
+        let p = fil.filter_protein(prot, &Filter::tryptic_regex()).unwrap();
+        assert_eq!(p.peptides, vec![pep(Some(25.5))]);
+    }
+
+    #[test]
+    fn channel_cv_quantified() {
+        let pep = |values: Vec<f64>| Peptide {
+            values,
+            ..Peptide::default()
+        };
+        let reg = Filter::tryptic_regex();
+        let cv = PeptideFilter::ChannelCV(vec![1, 2, 3, 4], 0.2);
+        let quant = PeptideFilter::ChannelCVQuantified(vec![1, 2, 3, 4], 0.2, 2);
+
+        let zeros = pep(vec![100.0, 0.0, 105.0, f64::NAN]);
+        assert!(!cv.passes(&zeros, &reg));
+        assert!(quant.passes(&zeros, &reg));
+
+        let noisy = pep(vec![100.0, 0.0, 300.0, 0.0]);
+        assert!(!quant.passes(&noisy, &reg));
+
+        let single = pep(vec![100.0, 0.0, 0.0, f64::NAN]);
+        assert!(quant.passes(&single, &reg));
+
+        // An undefined CV passes
+        let quant = PeptideFilter::ChannelCVQuantified(vec![1, 2, 3, 4], 0.2, 0);
+        let missing = pep(vec![f64::NAN; 4]);
+        assert!(cv.passes(&missing, &reg));
+        assert!(quant.passes(&missing, &reg));
+        assert!(cv.passes(&pep(vec![0.0; 4]), &reg));
+        let fil = Filter::default()
+            .add_peptide_filter(cv)
+            .add_peptide_filter(quant)
+            .compile();
+        assert!(fil.passes(&missing));
+        assert!(!fil.passes(&zeros));
+    }
+
+    #[test]
+    fn channel_ratio() {
+        let pep = |values: Vec<f64>| Peptide {
+            values,
+            ..Peptide::default()
+        };
+        let reg = Filter::tryptic_regex();
+        let fil = PeptideFilter::ChannelRatio(vec![1, 2], vec![3, 4], 0.5, 2.0);
+        assert!(fil.passes(&pep(vec![100.0, 120.0, 100.0, 100.0]), &reg));
+        assert!(fil.passes(&pep(vec![100.0, f64::NAN, 200.0, f64::NAN]), &reg));
+        assert!(!fil.passes(&pep(vec![1000.0, 1200.0, 100.0, 100.0]), &reg));
+        assert!(!fil.passes(&pep(vec![10.0, 12.0, 100.0, 100.0]), &reg));
+        assert!(!fil.passes(&pep(vec![100.0, 100.0, 0.0, 0.0]), &reg));
+        assert!(!fil.passes(&pep(vec![100.0, 100.0, f64::NAN, f64::NAN]), &reg));
+    }
+
+    #[test]
+    fn top_n_peptides() {
+        let pep = |seq: &str, total| Peptide {
+            sequence: seq.into(),
+            values: vec![total],
+            ..Peptide::default()
+        };
+        let prot = Protein {
+            accession: "P1".into(),
+            peptides: vec![
+                pep("K.AAK.L", 10.0),
+                pep("K.CCK.L", 300.0),
+                pep("K.MMK.L", 500.0),
+                pep("K.DDK.L", 200.0),
+                pep("K.EEK.L", 300.0),
+            ],
+            channels: 1,
+            spectral_count: 5,
+            ..Protein::default()
+        };
+        let dataset = Dataset::default().with_proteins(vec![prot]);
+        let fil = Filter::default()
+            .add_protein_filter(ProteinFilter::TopNPeptides(2))
+            .add_peptide_filter(PeptideFilter::SequenceExclude("M".into()));
+
+        let (filtered, report) = fil.filter_dataset_with_report(dataset.clone());
+        let p = &filtered.proteins[0];
+        let seqs = p
+            .peptides
+            .iter()
+            .map(|p| &p.sequence[..])
+            .collect::<Vec<_>>();
+        assert_eq!(seqs, vec!["K.CCK.L", "K.EEK.L"]);
+        assert_eq!(p.spectral_count, 2);
+        assert_eq!(report.peptide_rules[0].1, 1);
+        assert_eq!(report.not_top_n, 2);
+        assert_eq!(fil.filter_dataset_ref(&dataset), filtered);
+
+        // Count rules see every passing peptide, not just the top N
+        let fil = fil.add_protein_filter(ProteinFilter::SpectralCounts(3));
+        let filtered = fil.filter_dataset(dataset.clone());
+        assert_eq!(filtered.proteins[0].spectral_count, 2);
+        assert_eq!(fil.filter_dataset_ref(&dataset), filtered);
+        assert_eq!(fil.compile().filter_dataset(dataset), filtered);
+
+        // Ranked by linear intensity: 64 + 1 > 16 + 16, although 6 + 0 < 4 + 4
+        let pep = |seq: &str, values| Peptide {
+            sequence: seq.into(),
+            values,
+            ..Peptide::default()
+        };
+        let prot = Protein {
+            accession: "P1".into(),
+            peptides: vec![
+                pep("K.AAK.L", vec![16.0, 16.0]),
+                pep("K.CCK.L", vec![64.0, 1.0]),
+            ],
+            channels: 2,
+            ..Protein::default()
+        };
+        let mut dataset = Dataset::default().with_proteins(vec![prot]);
+        dataset.log2_transform(0.0);
+        let fil = Filter::default().add_protein_filter(ProteinFilter::TopNPeptides(1));
+        let filtered = fil.filter_dataset(dataset.clone());
+        assert_eq!(filtered.proteins[0].peptides[0].sequence, "K.CCK.L");
+        assert_eq!(fil.filter_dataset_ref(&dataset), filtered);
+        assert_eq!(fil.compile().filter_dataset(dataset), filtered);
+    }
+
+    #[test]
+    fn scan_range_and_source_file() {
+        let pep = |scan, file: &str| Peptide {
+            scan,
+            file_name: file.into(),
+            values: vec![1.0],
+            ..Peptide::default()
+        };
+        let prot = Protein {
+            peptides: vec![
+                pep(500, "sample_F1.raw"),
+                pep(1500, "sample_F1.raw"),
+                pep(1500, "sample_F2.raw"),
+                pep(9000, "sample_F2.raw"),
+            ],
+            channels: 1,
+            ..Protein::default()
+        };
+        let reg = Filter::tryptic_regex();
+        let fil = Filter::default()
+            .add_peptide_filter(PeptideFilter::ScanRange(1000, 5000))
+            .add_peptide_filter(PeptideFilter::Not(Box::new(PeptideFilter::SourceFile(
+                "_F2".into(),
+            ))));
+        let p = fil.filter_protein(prot, &reg).unwrap();
+        assert_eq!(p.peptides, vec![pep(1500, "sample_F1.raw")]);
+    }
+
+    #[test]
+    fn unique_peptides() {
+        let pep = |seq: &str, unique| Peptide {
+            sequence: seq.into(),
+            unique,
+            values: vec![1.0],
+            ..Peptide::default()
+        };
+        let prot = |peptides| Protein {
+            peptides,
+            channels: 1,
+            ..Protein::default()
+        };
+        let reg = Filter::tryptic_regex();
+        let fil = Filter::default()
+            .add_protein_filter(ProteinFilter::UniquePeptides(2))
+            .add_peptide_filter(PeptideFilter::SequenceExclude("M".into()));
+
+        let two = prot(vec![pep("K.AAK.L", true), pep("K.CCK.L", true)]);
+        assert!(fil.filter_protein(two, &reg).is_some());
+        let repeated = prot(vec![
+            pep("K.AAK.L", true),
+            pep("K.AAK.L", true),
+            pep("K.CCK.L", false),
+        ]);
+        assert!(fil.filter_protein(repeated, &reg).is_none());
+        let filtered = prot(vec![pep("K.AAK.L", true), pep("K.MMK.L", true)]);
+        assert!(fil.filter_protein(filtered, &reg).is_none());
+    }
+
+    #[test]
+    fn min_quantified_plexes() {
+        let plex = |accessions: &[&str], values: Vec<f64>| {
+            Dataset {
+                channels: values.len() as u8,
+                channel_info: vec![ChannelInfo::default(); values.len()],
+                ..Dataset::default()
+            }
+            .with_proteins(
+                accessions
+                    .iter()
+                    .map(|acc| Protein {
+                        accession: acc.to_string(),
+                        channels: values.len() as u8,
+                        peptides: vec![Peptide {
+                            sequence: format!("K.{}.R", acc),
+                            values: values.clone(),
+                            ..Peptide::default()
+                        }],
+                        ..Protein::default()
+                    })
+                    .collect(),
+            )
+        };
+        let merged = Dataset::merge(vec![
+            plex(&["P1", "P2"], vec![1.0, 2.0]),
+            plex(&["P2", "P3"], vec![0.0, 4.0]),
+            plex(&["P3"], vec![0.0, 0.0]),
+        ])
+        .unwrap();
+        let fil = Filter::default().add_protein_filter(ProteinFilter::MinQuantifiedPlexes(2));
+        assert_eq!(fil.validate(&merged), Ok(()));
+        assert_eq!(
+            fil.filter_dataset_ref(&merged).accessions(),
+            HashSet::from(["P2"])
+        );
+        assert_eq!(
+            fil.compile().filter_dataset(merged.clone()).proteins.len(),
+            1
+        );
+
+        let mut data = merged.clone();
+        data.apply_filter_in_place(&fil);
+        assert_eq!(data.proteins.len(), 1);
+
+        // Without plex annotations, every channel is in a single plex
+        let p2 = merged.get("P2").unwrap().clone();
+        assert!(fil.filter_protein(p2, &Filter::tryptic_regex()).is_none());
+        let fil = Filter::default().add_protein_filter(ProteinFilter::MinQuantifiedPlexes(4));
+        assert!(matches!(
+            fil.validate(&merged),
+            Err(FilterError::Invalid { .. })
+        ));
+    }
+
+    #[test]
+    fn merge() {
+        let lab = Filter::default()
+            .add_protein_filter(ProteinFilter::ExcludeReverse)
+            .add_peptide_filter(PeptideFilter::Unique)
+            .add_peptide_filter(PeptideFilter::Purity(0.7));
+        let experiment = Filter::default()
+            .add_protein_filter(ProteinFilter::ExcludeReverse)
+            .add_protein_filter(ProteinFilter::UniquePeptides(2))
+            .add_peptide_filter(PeptideFilter::Purity(0.7))
+            .add_peptide_filter(PeptideFilter::SequenceExclude("M".into()));
+        let merged = lab.clone().merge(experiment.clone());
+        assert_eq!(
+            merged,
+            Filter::default()
+                .add_protein_filter(ProteinFilter::ExcludeReverse)
+                .add_protein_filter(ProteinFilter::UniquePeptides(2))
+                .add_peptide_filter(PeptideFilter::Unique)
+                .add_peptide_filter(PeptideFilter::Purity(0.7))
+                .add_peptide_filter(PeptideFilter::SequenceExclude("M".into()))
+        );
+        assert_eq!(merged.clone().merge(merged.clone()), merged);
+        assert_eq!(
+            vec![lab, experiment].into_iter().collect::<Filter>(),
+            merged
+        );
+
+        // Conflicting thresholds are overridden by the later filter, while
+        // pattern rules and rules on other channels accumulate
+        let strict = Filter::default()
+            .add_protein_filter(ProteinFilter::UniquePeptides(3))
+            .add_peptide_filter(PeptideFilter::Purity(0.9))
+            .add_peptide_filter(PeptideFilter::SequenceExclude("C".into()))
+            .add_peptide_filter(PeptideFilter::ChannelCV(vec![1, 2], 0.2));
+        let lenient = merged
+            .clone()
+            .add_peptide_filter(PeptideFilter::ChannelCV(vec![3, 4], 0.5));
+        assert_eq!(
+            lenient.merge(strict),
+            Filter::default()
+                .add_protein_filter(ProteinFilter::ExcludeReverse)
+                .add_protein_filter(ProteinFilter::UniquePeptides(3))
+                .add_peptide_filter(PeptideFilter::Unique)
+                .add_peptide_filter(PeptideFilter::Purity(0.9))
+                .add_peptide_filter(PeptideFilter::SequenceExclude("M".into()))
+                .add_peptide_filter(PeptideFilter::ChannelCV(vec![3, 4], 0.5))
+                .add_peptide_filter(PeptideFilter::SequenceExclude("C".into()))
+                .add_peptide_filter(PeptideFilter::ChannelCV(vec![1, 2], 0.2))
+        );
+
+        let fil = vec![PeptideFilter::Unique, PeptideFilter::Tryptic]
+            .into_iter()
+            .collect::<Filter>();
+        assert_eq!(
+            fil,
+            Filter::default()
+                .add_peptide_filter(PeptideFilter::Unique)
+                .add_peptide_filter(PeptideFilter::Tryptic)
+        );
+        let fil = Some(ProteinFilter::ExcludeReverse)
+            .into_iter()
+            .collect::<Filter>();
+        assert_eq!(
+            fil,
+            Filter::default().add_protein_filter(ProteinFilter::ExcludeReverse)
+        );
+    }
+
+    #[test]
+    fn filter_dataset_ref() {
+        let pep = |seq: &str, values| Peptide {
+            sequence: seq.into(),
+            values,
+            ..Peptide::default()
+        };
+        let prot = |acc: &str, peptides: Vec<Peptide>| Protein {
+            accession: acc.into(),
+            sequence_count: peptides.len() as u16,
+            peptides,
+            channels: 2,
+            ..Protein::default()
+        };
+        let dataset = Dataset::default().with_proteins(vec![
+            prot(
+                "P1",
+                vec![
+                    pep("K.AAK.L", vec![10.0, 10.0]),
+                    pep("K.MMK.L", vec![10.0, 10.0]),
+                ],
+            ),
+            prot("Reverse_P2", vec![pep("K.DDK.L", vec![10.0, 10.0])]),
+            prot("P3", vec![pep("K.EEK.L", vec![10.0, 0.0])]),
+            prot("P4", vec![pep("K.FFK.L", vec![1.0, 1.0])]),
+        ]);
+        let fil = Filter::default()
+            .add_protein_filter(ProteinFilter::ExcludeReverse)
+            .add_protein_filter(ProteinFilter::MinQuantifiedChannels(2))
+            .add_peptide_filter(PeptideFilter::TotalIntensity(10.0))
+            .add_peptide_filter(PeptideFilter::SequenceExclude("M".into()));
+
+        let filtered = fil.filter_dataset_ref(&dataset);
+        assert_eq!(filtered.proteins.len(), 1);
+        assert_eq!(filtered.proteins[0].spectral_count, 1);
+        assert_eq!(filtered.proteins[0].peptides[0].sequence, "K.AAK.L");
+        assert_eq!(dataset.proteins.len(), 4);
+        assert_eq!(filtered, fil.filter_dataset(dataset.clone()));
+
+        let mut in_place = dataset.clone();
+        in_place.apply_filter_in_place(&fil);
+        assert_eq!(in_place, filtered);
+        assert!(in_place.get("P1").is_some());
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn par_filter_dataset() {
+        let prot = |acc: usize| Protein {
+            accession: format!("P{}", acc),
+            peptides: (0..4)
+                .map(|i| Peptide {
+                    sequence: if i % 2 == 0 { "K.AAK.L" } else { "K.MMK.L" }.into(),
+                    values: vec![(acc * i) as f64, 1.0],
+                    ..Peptide::default()
+                })
+                .collect(),
+            channels: 2,
+            spectral_count: 4,
+            ..Protein::default()
+        };
+        let dataset = Dataset::default().with_proteins((0..200).map(prot).collect());
+        let fil = Filter::default()
+            .add_protein_filter(ProteinFilter::SpectralCounts(1))
+            .add_peptide_filter(PeptideFilter::SequenceExclude("M".into()))
+            .add_peptide_filter(PeptideFilter::ChannelCV(vec![1, 2], 0.5));
+        let filtered = fil.par_filter_dataset(dataset.clone());
+        assert!(!filtered.proteins.is_empty());
+        assert!(filtered.proteins.len() < 200);
+        assert_eq!(filtered, fil.filter_dataset(dataset));
+    }
+
+    #[test]
+    fn log2_dataset() {
+        let prot = |values: Vec<f64>| Protein {
+            accession: "P1".into(),
+            peptides: vec![Peptide {
+                sequence: "K.AAK.L".into(),
+                values,
+                ..Peptide::default()
+            }],
+            channels: 4,
+            spectral_count: 1,
+            ..Protein::default()
+        };
+        let mut dataset = Dataset::default().with_proteins(vec![
+            prot(vec![0.0, 1000.0, f64::NAN, 50.0]),
+            prot(vec![0.0, 10.0, f64::NAN, 0.0]),
+        ]);
+        let fil = Filter::default()
+            .add_protein_filter(ProteinFilter::MinQuantifiedChannels(2))
+            .add_peptide_filter(PeptideFilter::MinQuantifiedChannels(2))
+            .add_peptide_filter(PeptideFilter::TotalIntensity(1000.0))
+            .add_peptide_filter(PeptideFilter::ChannelIntensity(4, 40.0));
+        let linear = fil.filter_dataset(dataset.clone());
+        assert_eq!(linear.proteins.len(), 1);
+
+        dataset.log2_transform(1.0);
+        let filtered = fil.filter_dataset(dataset.clone());
+        assert_eq!(filtered.proteins.len(), 1);
+        assert_eq!(filtered.proteins[0].peptides[0].values[1], 1001f64.log2());
+        let compiled = fil.compile().filter_dataset(dataset);
+        assert_eq!(compiled.proteins.len(), 1);
+        assert_eq!(compiled.proteins[0].peptides[0].values[1], 1001f64.log2());
+    }
 }
@@ -0,0 +1,315 @@
+//! Filters compiled for repeated use, see `Filter::compile`
+use super::*;
+
+/// A peptide rule, with its regular expression compiled and its channels
+/// converted to 0-based indices
+#[derive(Debug, Clone)]
+enum Rule<'a> {
+    /// Evaluated by `PeptideFilter::passes`
+    Plain(PeptideFilter<'a>),
+    /// Compiled `SequenceRegex`, or `SequenceRegexExclude` if `exclude` is
+    /// set. An invalid expression matches no peptides
+    Regex {
+        regex: Option<regex::Regex>,
+        exclude: bool,
+    },
+    TotalIntensityChannels(Vec<usize>, f64),
+    ChannelCV(Vec<usize>, f64),
+    ChannelCVQuantified(Vec<usize>, f64, usize),
+    ChannelRatio(Vec<usize>, Vec<usize>, f64, f64),
+    ChannelIntensity(Option<usize>, f64),
+    Or(Vec<Rule<'a>>),
+    Not(Box<Rule<'a>>),
+    AnyOf(usize, Vec<Rule<'a>>),
+}
+
+/// Convert 1-based channels to indices, dropping channel 0
+fn indices(channels: &[usize]) -> Vec<usize> {
+    channels.iter().filter_map(|c| c.checked_sub(1)).collect()
+}
+
+/// `values` at `indices`, skipping those out of range
+fn at<'p>(values: &'p [f64], indices: &'p [usize]) -> impl Iterator<Item = f64> + Clone + 'p {
+    indices.iter().filter_map(move |&i| values.get(i).copied())
+}
+
+/// Coefficient of variation, as by `util::cv`, without collecting the
+/// values
+fn cv<I: Iterator<Item = f64> + Clone>(values: I) -> f64 {
+    let values = values.filter(|v| !v.is_nan());
+    let (n, sum) = values
+        .clone()
+        .fold((0usize, 0.0f64), |(n, sum), x| (n + 1, sum + x));
+    let mean = sum / n as f64;
+    let var = values.fold(0.0f64, |acc, x| acc + (x - mean).powi(2)) / n as f64;
+    var.sqrt() / mean
+}
+
+/// Mean of the values, ignoring missing ones, as by `util::mean`
+fn mean<I: Iterator<Item = f64>>(values: I) -> f64 {
+    let (n, sum) = values
+        .filter(|v| !v.is_nan())
+        .fold((0usize, 0.0f64), |(n, sum), x| (n + 1, sum + x));
+    sum / n as f64
+}
+
+/// Relative cost of evaluating a rule: flags and numbers first, then
+/// sequence checks, then regular expressions and channel statistics
+fn cost(filter: &PeptideFilter<'_>) -> u8 {
+    match filter {
+        PeptideFilter::Unique
+        | PeptideFilter::Purity(_)
+        | PeptideFilter::RetentionTime(..)
+        | PeptideFilter::ScanRange(..)
+        | PeptideFilter::ChannelIntensity(..) => 0,
+        PeptideFilter::SequenceMatch(_)
+        | PeptideFilter::SequenceExclude(_)
+        | PeptideFilter::SourceFile(_)
+        | PeptideFilter::TotalIntensity(_)
+        | PeptideFilter::TotalIntensityChannels(..)
+        | PeptideFilter::MinQuantifiedChannels(_) => 1,
+        PeptideFilter::Length(..)
+        | PeptideFilter::MaxMissedCleavages(_)
+        | PeptideFilter::Termini(_)
+        | PeptideFilter::Tryptic
+        | PeptideFilter::SequenceRegex(_)
+        | PeptideFilter::SequenceRegexExclude(_) => 2,
+        PeptideFilter::ChannelCV(..)
+        | PeptideFilter::ChannelCVQuantified(..)
+        | PeptideFilter::ChannelRatio(..) => 3,
+        PeptideFilter::Or(filters) | PeptideFilter::AnyOf(_, filters) => {
+            filters.iter().map(cost).max().unwrap_or(0)
+        }
+        PeptideFilter::Not(filter) => cost(filter),
+    }
+}
+
+impl<'a> Rule<'a> {
+    fn new(filter: &PeptideFilter<'a>) -> Rule<'a> {
+        let all = |filters: &[PeptideFilter<'a>]| filters.iter().map(Rule::new).collect();
+        match filter {
+            PeptideFilter::SequenceRegex(pat) => Rule::Regex {
+                regex: regex::Regex::new(pat).ok(),
+                exclude: false,
+            },
+            PeptideFilter::SequenceRegexExclude(pat) => Rule::Regex {
+                regex: regex::Regex::new(pat).ok(),
+                exclude: true,
+            },
+            PeptideFilter::TotalIntensityChannels(channels, n) => {
+                Rule::TotalIntensityChannels(indices(channels), *n)
+            }
+            PeptideFilter::ChannelCV(channels, n) => Rule::ChannelCV(indices(channels), *n),
+            PeptideFilter::ChannelCVQuantified(channels, n, min) => {
+                Rule::ChannelCVQuantified(indices(channels), *n, *min)
+            }
+            PeptideFilter::ChannelRatio(num, den, min, max) => {
+                Rule::ChannelRatio(indices(num), indices(den), *min, *max)
+            }
+            PeptideFilter::ChannelIntensity(channel, n) => {
+                Rule::ChannelIntensity(channel.checked_sub(1), *n)
+            }
+            PeptideFilter::Or(filters) => Rule::Or(all(filters)),
+            PeptideFilter::Not(filter) => Rule::Not(Box::new(Rule::new(filter))),
+            PeptideFilter::AnyOf(n, filters) => Rule::AnyOf(*n, all(filters)),
+            filter => Rule::Plain(filter.clone()),
+        }
+    }
+
+    fn passes(&self, peptide: &Peptide, values: &[f64], tryptic_regex: &regex::Regex) -> bool {
+        match self {
+            Rule::Plain(filter) => filter.passes_values(peptide, values, tryptic_regex),
+            Rule::Regex { regex, exclude } => {
+                regex.as_ref().is_some_and(|re| re.is_match(core(peptide))) != *exclude
+            }
+            Rule::TotalIntensityChannels(indices, cutoff) => {
+                at(values, indices).filter(|v| !v.is_nan()).sum::<f64>() >= *cutoff
+            }
+            Rule::ChannelCV(indices, cutoff) => cv_below(cv(at(values, indices)), *cutoff),
+            Rule::ChannelCVQuantified(indices, cutoff, min) => {
+                let quantified = at(values, indices).filter(|&v| v != 0.0 && !v.is_nan());
+                quantified.clone().count() < *min || cv_below(cv(quantified), *cutoff)
+            }
+            Rule::ChannelRatio(num, den, min, max) => {
+                let ratio = mean(at(values, num)) / mean(at(values, den));
+                ratio.is_finite() && ratio >= *min && ratio <= *max
+            }
+            Rule::ChannelIntensity(index, cutoff) => match index.filter(|&i| i < values.len()) {
+                Some(i) => values[i] >= *cutoff,
+                None => true,
+            },
+            Rule::Or(rules) => rules
+                .iter()
+                .any(|r| r.passes(peptide, values, tryptic_regex)),
+            Rule::Not(rule) => !rule.passes(peptide, values, tryptic_regex),
+            Rule::AnyOf(n, rules) => {
+                rules
+                    .iter()
+                    .filter(|r| r.passes(peptide, values, tryptic_regex))
+                    .count()
+                    >= *n
+            }
+        }
+    }
+}
+
+/// A `Filter` prepared for repeated use, see `Filter::compile`
+#[derive(Debug, Clone)]
+pub struct CompiledFilter<'a> {
+    /// The protein filters, with no peptide filters
+    proteins: Filter<'a>,
+    /// The peptide filters, cheapest first
+    peptides: Vec<Rule<'a>>,
+    tryptic_regex: regex::Regex,
+}
+
+impl<'a> Filter<'a> {
+    /// Prepare the filter for repeated use in hot loops: peptide rules are
+    /// reordered so that cheap checks run before channel statistics,
+    /// regular expressions are compiled once, and channel lists are
+    /// converted to indices so that channel rules do not allocate per
+    /// peptide. `Length`, `MaxMissedCleavages` and `Termini` still strip the
+    /// modifications of each peptide's sequence into a new string, see
+    /// `Peptide::stripped_sequence`.
+    ///
+    /// The compiled filter keeps and removes the same peptides and proteins
+    /// as the filter, but cannot produce a `FilterReport`, since the first
+    /// failing rule depends on the order rules are checked in
+    pub fn compile(&self) -> CompiledFilter<'a> {
+        let mut peptides = self.peptide_filters.iter().collect::<Vec<_>>();
+        peptides.sort_by_key(|filter| cost(filter));
+        CompiledFilter {
+            proteins: Filter {
+                protein_filters: self.protein_filters.clone(),
+                peptide_filters: Vec::new(),
+            },
+            peptides: peptides.into_iter().map(Rule::new).collect(),
+            tryptic_regex: Filter::tryptic_regex(),
+        }
+    }
+}
+
+impl<'a> CompiledFilter<'a> {
+    /// Does the peptide pass every peptide filter?
+    pub fn passes(&self, peptide: &Peptide) -> bool {
+        self.peptides
+            .iter()
+            .all(|rule| rule.passes(peptide, &peptide.values, &self.tryptic_regex))
+    }
+
+    /// Filter a `Protein`, as by `Filter::filter_protein`
+    pub fn filter_protein(&self, protein: Protein) -> Option<Protein> {
+        self.filter_protein_in(protein, &Context::default())
+    }
+
+    /// Filter a `Protein` of the dataset described by `context`
+    fn filter_protein_in(&self, mut protein: Protein, context: &Context) -> Option<Protein> {
+        if self.proteins.failed_protein_rule(&protein).is_some() {
+            return None;
+        }
+        protein.peptides.retain(|peptide| {
+            let values = context.values(peptide);
+            self.peptides
+                .iter()
+                .all(|rule| rule.passes(peptide, &values, &self.tryptic_regex))
+        });
+        if protein.peptides.is_empty() {
+            return None;
+        }
+        if self
+            .proteins
+            .failed_recount_rule(&mut protein, context)
+            .is_some()
+        {
+            return None;
+        }
+        self.proteins.keep_top_peptides(&mut protein, context);
+        Some(protein)
+    }
+
+    /// Return a new `Dataset` that only contains filtered `Protein`'s, as
+    /// by `Filter::filter_dataset`
+    pub fn filter_dataset(&self, dataset: Dataset) -> Dataset {
+        let context = Context::new(&dataset);
+        Dataset {
+            channels: dataset.channels,
+            header: dataset.header,
+            channel_info: dataset.channel_info,
+            scale: dataset.scale,
+            proteins: dataset
+                .proteins
+                .into_iter()
+                .filter_map(|prot| self.filter_protein_in(prot, &context))
+                .collect(),
+            ..Dataset::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn compiled_filter() {
+        let fil = Filter::default()
+            .add_protein_filter(ProteinFilter::SpectralCounts(2))
+            .add_peptide_filter(PeptideFilter::ChannelCV(vec![1, 2, 3], 0.3))
+            .add_peptide_filter(PeptideFilter::ChannelCVQuantified(vec![1, 2, 3, 4], 0.3, 2))
+            .add_peptide_filter(PeptideFilter::Or(vec![
+                PeptideFilter::SequenceRegex("^A".into()),
+                PeptideFilter::ChannelRatio(vec![1], vec![2, 0, 9], 0.5, 2.0),
+            ]))
+            .add_peptide_filter(PeptideFilter::Not(Box::new(PeptideFilter::SequenceRegex(
+                "(".into(),
+            ))))
+            .add_peptide_filter(PeptideFilter::TotalIntensityChannels(vec![1, 2], 100.0))
+            .add_peptide_filter(PeptideFilter::ChannelIntensity(3, 10.0))
+            .add_peptide_filter(PeptideFilter::SequenceExclude("M".into()));
+        let compiled = fil.compile();
+        assert!(matches!(
+            compiled.peptides[0],
+            Rule::ChannelIntensity(Some(2), _)
+        ));
+
+        let pep = |seq: &str, values: Vec<f64>| Peptide {
+            sequence: seq.into(),
+            values,
+            ..Peptide::default()
+        };
+        let peptides = vec![
+            pep("K.AAK.L", vec![100.0, 110.0, 90.0, 0.0]),
+            pep("K.CCK.L", vec![100.0, 110.0, 90.0, f64::NAN]),
+            pep("K.DDK.L", vec![100.0, 300.0, 90.0, 0.0]),
+            pep("K.AMK.L", vec![100.0, 110.0, 90.0, 95.0]),
+            pep("K.AEK.L", vec![100.0, 110.0, f64::NAN, 0.0]),
+            pep("K.AFK.L", vec![10.0, 11.0, 12.0, 0.0]),
+            pep("K.AGK.L", vec![100.0, 120.0, 0.0, 0.0]),
+        ];
+        let reg = Filter::tryptic_regex();
+        for p in &peptides {
+            let expected = fil.peptide_filters.iter().all(|f| f.passes(p, &reg));
+            assert_eq!(compiled.passes(p), expected, "{}", p.sequence);
+        }
+
+        let prot = |acc: &str, peptides: Vec<Peptide>| Protein {
+            accession: acc.into(),
+            spectral_count: peptides.len() as u16,
+            peptides,
+            channels: 4,
+            ..Protein::default()
+        };
+        let dataset = Dataset::default().with_proteins(vec![
+            prot("P1", peptides.clone()),
+            prot("P2", peptides[2..4].to_vec()),
+            prot("P3", peptides[..1].to_vec()),
+        ]);
+        let filtered = compiled.filter_dataset(dataset.clone());
+        assert_eq!(filtered.proteins.len(), 1);
+        // Compare the debug output, as missing values are not equal
+        assert_eq!(
+            format!("{:?}", filtered),
+            format!("{:?}", fil.filter_dataset(dataset))
+        );
+    }
+}
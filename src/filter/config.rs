@@ -0,0 +1,179 @@
+//! Loading filters from TOML and YAML configuration files
+//!
+//! Configuration files use the same layout as the serialized `Filter`,
+//! with optional `protein_filters` and `peptide_filters` lists (or
+//! `protein` and `peptide`), and rules named by their variant. In TOML:
+//!
+//! ```toml
+//! protein_filters = ["ExcludeReverse", { SpectralCounts = 2 }]
+//! peptide_filters = [
+//!     "Unique",
+//!     { ChannelCV = [[1, 2, 3], 0.2] },
+//!     { SequenceExclude = "M" },
+//! ]
+//! ```
+//!
+//! Unknown sections or rules are rejected, and the rules are checked for
+//! values that can never be valid, see `ConfigError::Invalid`. This is
+//! stricter than deserializing a `Filter` directly, e.g. from JSON, which
+//! ignores unknown fields and requires both lists.
+//!
+//! YAML is read with `serde_norway`, the maintained fork of the deprecated
+//! `serde_yaml`, which accepts the same documents
+use super::*;
+use serde::Deserialize;
+use std::fmt;
+
+/// Error that may occur while loading a filter configuration
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The file is not valid TOML, or does not describe a filter
+    #[cfg(feature = "toml")]
+    Toml(toml::de::Error),
+    /// The file is not valid YAML, or does not describe a filter
+    #[cfg(feature = "yaml")]
+    Yaml(serde_norway::Error),
+    /// A rule has a value that can never be valid: a channel number of 0
+    /// (channels are numbered from 1), a range with its minimum above its
+    /// maximum, a `TopNPeptides` of 0, an invalid regular expression, or
+    /// an `AnyOf` or `ChannelCVQuantified` requiring more rules or channels
+    /// than it contains
+    Invalid {
+        /// The offending rule, in the text format of `Filter::parse`
+        rule: String,
+        reason: String,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "toml")]
+            ConfigError::Toml(e) => write!(f, "invalid TOML filter configuration: {}", e),
+            #[cfg(feature = "yaml")]
+            ConfigError::Yaml(e) => write!(f, "invalid YAML filter configuration: {}", e),
+            ConfigError::Invalid { rule, reason } => {
+                write!(f, "invalid filter rule `{}`: {}", rule, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Layout of a configuration file
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct Config {
+    #[serde(alias = "peptide")]
+    peptide_filters: Vec<PeptideFilter<'static>>,
+    #[serde(alias = "protein")]
+    protein_filters: Vec<ProteinFilter<'static>>,
+}
+
+fn check(config: Config) -> Result<Filter<'static>, ConfigError> {
+    let filter = Filter {
+        peptide_filters: config.peptide_filters,
+        protein_filters: config.protein_filters,
+    };
+    match filter.check(None) {
+        Ok(()) => Ok(filter),
+        Err(FilterError::ChannelOutOfRange { rule, .. }) => Err(ConfigError::Invalid {
+            rule,
+            reason: "channels are numbered from 1".into(),
+        }),
+        Err(FilterError::Invalid { rule, reason }) => Err(ConfigError::Invalid { rule, reason }),
+    }
+}
+
+impl Filter<'static> {
+    /// Load a filter from a TOML configuration, see the module
+    /// documentation for the layout
+    #[cfg(feature = "toml")]
+    pub fn from_toml(input: &str) -> Result<Filter<'static>, ConfigError> {
+        check(toml::from_str(input).map_err(ConfigError::Toml)?)
+    }
+
+    /// Load a filter from a YAML configuration. Rules with values are
+    /// written as YAML tags, e.g.
+    ///
+    /// ```yaml
+    /// protein_filters: [ExcludeReverse, !SpectralCounts 2]
+    /// peptide_filters:
+    ///   - Unique
+    ///   - !ChannelCV [[1, 2, 3], 0.2]
+    /// ```
+    #[cfg(feature = "yaml")]
+    pub fn from_yaml(input: &str) -> Result<Filter<'static>, ConfigError> {
+        check(serde_norway::from_str(input).map_err(ConfigError::Yaml)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn expected() -> Filter<'static> {
+        Filter::default()
+            .add_protein_filter(ProteinFilter::ExcludeReverse)
+            .add_protein_filter(ProteinFilter::SpectralCounts(2))
+            .add_peptide_filter(PeptideFilter::Unique)
+            .add_peptide_filter(PeptideFilter::ChannelCV(vec![1, 2, 3], 0.2))
+            .add_peptide_filter(PeptideFilter::SequenceExclude("M".into()))
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn toml_config() {
+        let filter = Filter::from_toml(
+            r#"
+protein = ["ExcludeReverse", { SpectralCounts = 2 }]
+peptide_filters = [
+    "Unique",
+    { ChannelCV = [[1, 2, 3], 0.2] },
+    { SequenceExclude = "M" },
+]
+"#,
+        )
+        .unwrap();
+        assert_eq!(filter, expected());
+
+        let err = Filter::from_toml("peptides = [\"Unique\"]").unwrap_err();
+        assert!(matches!(err, ConfigError::Toml(_)));
+        assert!(err.to_string().contains("peptides"));
+
+        // Only configuration files are strict about unknown fields
+        let json = r#"{"peptide_filters": [], "protein_filters": [], "note": "lab"}"#;
+        assert_eq!(
+            serde_json::from_str::<Filter>(json).unwrap(),
+            Filter::default()
+        );
+
+        let err = Filter::from_toml("peptide = [{ ChannelIntensity = [0, 10.0] }]").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid filter rule `channel_intensity(0) >= 10`: channels are numbered from 1"
+        );
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn yaml_config() {
+        let filter = Filter::from_yaml(
+            "
+protein_filters: [ExcludeReverse, !SpectralCounts 2]
+peptide:
+  - Unique
+  - !ChannelCV [[1, 2, 3], 0.2]
+  - !SequenceExclude M
+",
+        )
+        .unwrap();
+        assert_eq!(filter, expected());
+
+        let err = Filter::from_yaml("peptide: [!Length [30, 7]]").unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid { .. }));
+        let err = Filter::from_yaml("peptide: [Uniqe]").unwrap_err();
+        assert!(matches!(err, ConfigError::Yaml(_)));
+    }
+}
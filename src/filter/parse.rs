@@ -0,0 +1,978 @@
+//! Text format for filters, see `Filter::parse`
+use super::*;
+use crate::parser::{Error, ErrorKind};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(String),
+    Str(String),
+    LParen,
+    RParen,
+    Comma,
+    Colon,
+    /// Comparison operator: ">=", ">", "<" or "<="
+    Op(&'static str),
+}
+
+/// A token, with its (1-based) column and text on the line
+struct Lexeme {
+    token: Token,
+    column: usize,
+    text: String,
+}
+
+/// Names of the protein rules, for suggestions
+const PROTEIN_RULES: [&str; 12] = [
+    "spectral_counts",
+    "sequence_counts",
+    "unique_peptides",
+    "exclude_reverse",
+    "exclude_contaminants",
+    "min_quantified_channels",
+    "min_quantified_plexes",
+    "top_n_peptides",
+    "min_coverage",
+    "molecular_weight",
+    "description_matches",
+    "description_excludes",
+];
+
+/// Names of the peptide rules, for suggestions
+const PEPTIDE_RULES: [&str; 23] = [
+    "sequence_match",
+    "sequence_exclude",
+    "sequence_regex",
+    "sequence_regex_exclude",
+    "length",
+    "max_missed_cleavages",
+    "min_quantified_channels",
+    "total_intensity",
+    "total_intensity_channels",
+    "channel_cv",
+    "channel_cv_quantified",
+    "channel_ratio",
+    "channel_intensity",
+    "purity",
+    "retention_time",
+    "scan_range",
+    "source_file",
+    "tryptic",
+    "termini",
+    "unique",
+    "or",
+    "not",
+    "any_of",
+];
+
+/// Names of the enzymes, for suggestions
+const ENZYMES: [&str; 7] = [
+    "trypsin",
+    "trypsin_p",
+    "lys_c",
+    "chymotrypsin",
+    "glu_c",
+    "custom",
+    "custom_n",
+];
+
+/// Edit distance between two strings
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diag = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let next = (diag + usize::from(ca != *cb))
+                .min(row[j] + 1)
+                .min(row[j + 1] + 1);
+            diag = row[j + 1];
+            row[j + 1] = next;
+        }
+    }
+    row[b.len()]
+}
+
+/// Return the closest name to a misspelled `name`, if any is close enough
+fn suggest<'n>(name: &str, names: &[&'n str]) -> Option<&'n str> {
+    names
+        .iter()
+        .map(|n| (levenshtein(name, n), *n))
+        .filter(|(d, n)| *d <= 2.max(n.len() / 4))
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, n)| n)
+}
+
+/// Build an error pointing at `column` of the line
+fn error(msg: String, line: usize, text: &str, column: usize, token: Option<&str>) -> Error {
+    Error::new(ErrorKind::InvalidFilter(msg), line).with_context(ErrorContext {
+        column: Some(column),
+        field: None,
+        token: token.map(String::from),
+        text: text.into(),
+    })
+}
+
+/// Split a line into tokens, stopping at a comment
+fn tokenize(text: &str, line: usize) -> Result<Vec<Lexeme>, Error> {
+    let chars = text.chars().collect::<Vec<_>>();
+    let mut lexemes = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let start = i;
+        let c = chars[i];
+        i += 1;
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '#' => break,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            ',' => Token::Comma,
+            ':' => Token::Colon,
+            '<' | '>' => {
+                let eq = chars.get(i) == Some(&'=');
+                if eq {
+                    i += 1;
+                }
+                Token::Op(match (c, eq) {
+                    ('<', false) => "<",
+                    ('<', true) => "<=",
+                    ('>', false) => ">",
+                    _ => ">=",
+                })
+            }
+            '"' => {
+                let mut s = String::new();
+                loop {
+                    match chars.get(i) {
+                        Some('"') => break,
+                        Some('\\') if i + 1 < chars.len() => {
+                            s.push(chars[i + 1]);
+                            i += 1;
+                        }
+                        Some(&c) => s.push(c),
+                        None => {
+                            let rest = chars[start..].iter().collect::<String>();
+                            let msg = "unterminated string".into();
+                            return Err(error(msg, line, text, start + 1, Some(&rest)));
+                        }
+                    }
+                    i += 1;
+                }
+                i += 1;
+                Token::Str(s)
+            }
+            c if c.is_ascii_digit() || c == '-' || c == '.' => {
+                while i < chars.len()
+                    && (chars[i].is_ascii_alphanumeric() || matches!(chars[i], '-' | '+' | '.'))
+                {
+                    i += 1;
+                }
+                Token::Number(chars[start..i].iter().collect())
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                let word = chars[start..i].iter().collect::<String>();
+                // Non-finite values, as written by `Display`
+                match word.as_str() {
+                    "inf" | "NaN" => Token::Number(word),
+                    _ => Token::Ident(word),
+                }
+            }
+            c => {
+                let msg = "unexpected character".into();
+                return Err(error(msg, line, text, start + 1, Some(&c.to_string())));
+            }
+        };
+        lexemes.push(Lexeme {
+            token,
+            column: start + 1,
+            text: chars[start..i].iter().collect(),
+        });
+    }
+    Ok(lexemes)
+}
+
+/// Recursive descent parser over the tokens of a single rule
+struct Rule<'t> {
+    lexemes: &'t [Lexeme],
+    pos: usize,
+    line: usize,
+    text: &'t str,
+}
+
+impl<'t> Rule<'t> {
+    /// Fail at the most recently consumed token, or at the end of the line
+    /// if every token has been consumed
+    fn err<T>(&self, msg: String) -> Result<T, Error> {
+        let (column, token) = match self.lexemes.get(self.pos.saturating_sub(1)) {
+            Some(lex) if self.pos <= self.lexemes.len() => (lex.column, Some(lex.text.as_str())),
+            _ => (self.text.chars().count() + 1, None),
+        };
+        Err(error(msg, self.line, self.text, column, token))
+    }
+
+    fn next(&mut self) -> Option<&'t Token> {
+        let tok = self.lexemes.get(self.pos).map(|lex| &lex.token);
+        self.pos += 1;
+        tok
+    }
+
+    fn peek(&self) -> Option<&'t Token> {
+        self.lexemes.get(self.pos).map(|lex| &lex.token)
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), Error> {
+        let name = match &expected {
+            Token::LParen => "\"(\"",
+            Token::RParen => "\")\"",
+            Token::Comma => "\",\"",
+            _ => "a token",
+        };
+        match self.next() {
+            Some(tok) if *tok == expected => Ok(()),
+            _ => self.err(format!("expected {}", name)),
+        }
+    }
+
+    fn ident(&mut self) -> Result<&'t str, Error> {
+        match self.next() {
+            Some(Token::Ident(s)) => Ok(s),
+            _ => self.err("expected a rule name".into()),
+        }
+    }
+
+    fn keyword(&mut self, word: &str) -> Result<(), Error> {
+        match self.next() {
+            Some(Token::Ident(s)) if s == word => Ok(()),
+            _ => self.err(format!("expected {:?}", word)),
+        }
+    }
+
+    fn number<T: FromStr>(&mut self) -> Result<T, Error> {
+        match self.next() {
+            Some(Token::Number(s)) => match s.parse() {
+                Ok(n) => Ok(n),
+                Err(_) => self.err("invalid number".into()),
+            },
+            _ => self.err("expected a number".into()),
+        }
+    }
+
+    /// Parse a threshold, optionally preceded by the comparison operator
+    /// `op` that the rule applies. Other operators are rejected, so that
+    /// the direction of a threshold is never ambiguous
+    fn threshold<T: FromStr>(&mut self, op: &'static str) -> Result<T, Error> {
+        if let Some(Token::Op(found)) = self.peek() {
+            self.next();
+            if *found != op {
+                return self.err(format!("this rule only supports {:?}", op));
+            }
+        }
+        self.number()
+    }
+
+    fn string(&mut self) -> Result<Cow<'static, str>, Error> {
+        match self.next() {
+            Some(Token::Str(s)) => Ok(s.clone().into()),
+            _ => self.err("expected a quoted pattern".into()),
+        }
+    }
+
+    /// Parse a quoted regular expression, failing if it does not compile
+    fn regex(&mut self) -> Result<Cow<'static, str>, Error> {
+        let pattern = self.string()?;
+        match regex::Regex::new(&pattern) {
+            Ok(_) => Ok(pattern),
+            // Syntax errors end with the reason, after the pattern and a caret
+            Err(e) => {
+                let e = e.to_string();
+                let reason = e
+                    .lines()
+                    .last()
+                    .unwrap_or_default()
+                    .trim_start_matches("error: ");
+                self.err(format!("invalid regular expression: {}", reason))
+            }
+        }
+    }
+
+    /// Parse an enzyme name, or a `custom("KR", "P")` or `custom_n("D", "")`
+    /// cleavage rule
+    fn enzyme(&mut self) -> Result<Enzyme, Error> {
+        let name = self.ident()?;
+        Ok(match name {
+            "trypsin" => Enzyme::Trypsin,
+            "trypsin_p" => Enzyme::TrypsinP,
+            "lys_c" => Enzyme::LysC,
+            "chymotrypsin" => Enzyme::Chymotrypsin,
+            "glu_c" => Enzyme::GluC,
+            "custom" | "custom_n" => {
+                self.expect(Token::LParen)?;
+                let residues = self.string()?.into_owned();
+                self.expect(Token::Comma)?;
+                let restrict = self.string()?.into_owned();
+                self.expect(Token::RParen)?;
+                Enzyme::Custom {
+                    residues,
+                    restrict,
+                    n_term: name == "custom_n",
+                }
+            }
+            _ => {
+                let msg = match suggest(name, &ENZYMES) {
+                    Some(s) => format!("unknown enzyme, did you mean {:?}?", s),
+                    None => "unknown enzyme".into(),
+                };
+                return self.err(msg);
+            }
+        })
+    }
+
+    /// Parse a parenthesized, comma separated list of channels
+    fn channels(&mut self) -> Result<Vec<usize>, Error> {
+        self.expect(Token::LParen)?;
+        let mut channels = vec![self.number()?];
+        while self.peek() == Some(&Token::Comma) {
+            self.next();
+            channels.push(self.number()?);
+        }
+        self.expect(Token::RParen)?;
+        Ok(channels)
+    }
+
+    /// Parse a parenthesized, comma separated list of peptide rules
+    fn peptide_list(&mut self) -> Result<Vec<PeptideFilter<'static>>, Error> {
+        let mut rules = vec![self.peptide()?];
+        while self.peek() == Some(&Token::Comma) {
+            self.next();
+            rules.push(self.peptide()?);
+        }
+        self.expect(Token::RParen)?;
+        Ok(rules)
+    }
+
+    fn end(&mut self) -> Result<(), Error> {
+        match self.next() {
+            None => Ok(()),
+            Some(_) => self.err("unexpected token after rule".into()),
+        }
+    }
+
+    /// Fail on an unknown rule name, suggesting the closest rule of the
+    /// section, or the section that `name` belongs to
+    fn unknown<T>(
+        &self,
+        name: &str,
+        names: &[&str],
+        other: &[&str],
+        other_section: &str,
+    ) -> Result<T, Error> {
+        let msg = if other.contains(&name) {
+            format!("{:?} is a {} rule", name, other_section)
+        } else if let Some(s) = suggest(name, names) {
+            format!("unknown rule, did you mean {:?}?", s)
+        } else {
+            "unknown rule".into()
+        };
+        self.err(msg)
+    }
+
+    fn peptide(&mut self) -> Result<PeptideFilter<'static>, Error> {
+        let name = self.ident()?;
+        Ok(match name {
+            "sequence_match" => PeptideFilter::SequenceMatch(self.string()?),
+            "sequence_exclude" => PeptideFilter::SequenceExclude(self.string()?),
+            "sequence_regex" => PeptideFilter::SequenceRegex(self.regex()?),
+            "sequence_regex_exclude" => PeptideFilter::SequenceRegexExclude(self.regex()?),
+            "length" => PeptideFilter::Length(self.number()?, self.number()?),
+            "max_missed_cleavages" => PeptideFilter::MaxMissedCleavages(self.threshold("<=")?),
+            "min_quantified_channels" => {
+                PeptideFilter::MinQuantifiedChannels(self.threshold(">=")?)
+            }
+            "total_intensity" => PeptideFilter::TotalIntensity(self.threshold(">=")?),
+            "total_intensity_channels" => {
+                PeptideFilter::TotalIntensityChannels(self.channels()?, self.threshold(">=")?)
+            }
+            "channel_cv" => PeptideFilter::ChannelCV(self.channels()?, self.threshold("<")?),
+            "channel_cv_quantified" => {
+                let channels = self.channels()?;
+                let cutoff = self.threshold("<")?;
+                self.keyword("min")?;
+                PeptideFilter::ChannelCVQuantified(channels, cutoff, self.number()?)
+            }
+            "channel_ratio" => PeptideFilter::ChannelRatio(
+                self.channels()?,
+                self.channels()?,
+                self.number()?,
+                self.number()?,
+            ),
+            "channel_intensity" => {
+                self.expect(Token::LParen)?;
+                let channel = self.number()?;
+                self.expect(Token::RParen)?;
+                PeptideFilter::ChannelIntensity(channel, self.threshold(">=")?)
+            }
+            "purity" => PeptideFilter::Purity(self.threshold(">=")?),
+            "retention_time" => PeptideFilter::RetentionTime(self.number()?, self.number()?),
+            "scan_range" => PeptideFilter::ScanRange(self.number()?, self.number()?),
+            "source_file" => PeptideFilter::SourceFile(self.string()?),
+            "tryptic" => PeptideFilter::Tryptic,
+            "termini" => match self.ident()? {
+                "full" => PeptideFilter::Termini(Termini::Full(self.enzyme()?)),
+                "semi" => PeptideFilter::Termini(Termini::Semi(self.enzyme()?)),
+                _ => return self.err("expected \"full\" or \"semi\"".into()),
+            },
+            "unique" => PeptideFilter::Unique,
+            "or" => {
+                self.expect(Token::LParen)?;
+                PeptideFilter::Or(self.peptide_list()?)
+            }
+            "not" => {
+                self.expect(Token::LParen)?;
+                let rule = self.peptide()?;
+                self.expect(Token::RParen)?;
+                PeptideFilter::Not(Box::new(rule))
+            }
+            "any_of" => {
+                self.expect(Token::LParen)?;
+                let n = self.number()?;
+                self.expect(Token::Comma)?;
+                PeptideFilter::AnyOf(n, self.peptide_list()?)
+            }
+            _ => return self.unknown(name, &PEPTIDE_RULES, &PROTEIN_RULES, "protein"),
+        })
+    }
+
+    fn protein(&mut self) -> Result<ProteinFilter<'static>, Error> {
+        let name = self.ident()?;
+        Ok(match name {
+            "spectral_counts" => ProteinFilter::SpectralCounts(self.threshold(">=")?),
+            "sequence_counts" => ProteinFilter::SequenceCounts(self.threshold(">=")?),
+            "unique_peptides" => ProteinFilter::UniquePeptides(self.threshold(">=")?),
+            "exclude_reverse" => ProteinFilter::ExcludeReverse,
+            "exclude_contaminants" => ProteinFilter::ExcludeContaminants,
+            "min_quantified_channels" => {
+                ProteinFilter::MinQuantifiedChannels(self.threshold(">=")?)
+            }
+            "min_quantified_plexes" => ProteinFilter::MinQuantifiedPlexes(self.threshold(">=")?),
+            "top_n_peptides" => ProteinFilter::TopNPeptides(self.number()?),
+            "min_coverage" => ProteinFilter::MinCoverage(self.threshold(">=")?),
+            "molecular_weight" => {
+                ProteinFilter::MolecularWeightRange(self.number()?, self.number()?)
+            }
+            "description_matches" => ProteinFilter::DescriptionMatches(self.string()?),
+            "description_excludes" => ProteinFilter::DescriptionExcludes(self.string()?),
+            _ => return self.unknown(name, &PROTEIN_RULES, &PEPTIDE_RULES, "peptide"),
+        })
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Section {
+    Protein,
+    Peptide,
+}
+
+impl Filter<'static> {
+    /// Parse a filter from its text format, with one rule per line under a
+    /// `protein:` or `peptide:` section header, e.g.
+    ///
+    /// ```text
+    /// protein:
+    ///     exclude_reverse
+    ///     spectral_counts 2
+    /// peptide:
+    ///     unique
+    ///     channel_cv(1, 2, 3) < 0.2  # drop noisy PSMs
+    ///     or(tryptic, total_intensity >= 1e5)
+    /// ```
+    ///
+    /// Rules are named after their variant in snake case, followed by
+    /// their arguments: numbers, and double-quoted patterns. Channel lists
+    /// are given in parentheses after the rule name, as in
+    /// `total_intensity_channels(1, 2) 5000` and `channel_intensity(1) 1000`.
+    /// Thresholds may be preceded by the comparison the rule applies, e.g.
+    /// `total_intensity >= 5000`, `channel_cv(1, 2, 3) < 0.2` and
+    /// `max_missed_cleavages <= 2`; any other operator is an error.
+    /// Unbounded values are written `inf` and `-inf`. Text from a `#` to the
+    /// end of the line is a comment.
+    /// The `or(...)`, `not(...)` and `any_of(n, ...)` combinators take
+    /// comma separated peptide rules.
+    ///
+    /// Protein rules are `spectral_counts`, `sequence_counts`,
+    /// `unique_peptides`, `exclude_reverse`, `exclude_contaminants`,
+    /// `min_quantified_channels`, `min_quantified_plexes`, `top_n_peptides`,
+    /// `min_coverage`, `molecular_weight min max`, `description_matches` and
+    /// `description_excludes`. Peptide rules are `sequence_match`,
+    /// `sequence_exclude`, `sequence_regex`, `sequence_regex_exclude`,
+    /// `length min max`, `max_missed_cleavages`, `min_quantified_channels`,
+    /// `total_intensity`, `total_intensity_channels`, `channel_cv`,
+    /// `channel_cv_quantified(channels) < N min M`,
+    /// `channel_ratio(numerator) (denominator) min max`, `channel_intensity`,
+    /// `purity`, `retention_time min max`, `scan_range min max`,
+    /// `source_file`, `tryptic`, `termini` and `unique`.
+    ///
+    /// `termini` is followed by `full` or `semi` and an enzyme: `trypsin`,
+    /// `trypsin_p`, `lys_c`, `chymotrypsin`, `glu_c`, or a custom rule
+    /// `custom("KR", "P")` cleaving after the first residues unless
+    /// followed by the second, or `custom_n("D", "")` cleaving before them
+    pub fn parse(input: &str) -> Result<Filter<'static>, Error> {
+        let mut filter = Filter::default();
+        let mut section = None;
+        for (idx, text) in input.lines().enumerate() {
+            let line = idx + 1;
+            let lexemes = tokenize(text, line)?;
+            if lexemes.is_empty() {
+                continue;
+            }
+            let mut rule = Rule {
+                lexemes: &lexemes,
+                pos: 0,
+                line,
+                text,
+            };
+            if lexemes.len() == 2 && lexemes[1].token == Token::Colon {
+                rule.pos = 1;
+                section = match &lexemes[0].token {
+                    Token::Ident(s) if s == "protein" => Some(Section::Protein),
+                    Token::Ident(s) if s == "peptide" => Some(Section::Peptide),
+                    Token::Ident(s) => {
+                        let msg = match suggest(s, &["protein", "peptide"]) {
+                            Some(s) => format!("unknown section, did you mean {:?}?", s),
+                            None => "unknown section".into(),
+                        };
+                        return rule.err(msg);
+                    }
+                    _ => return rule.err("unknown section".into()),
+                };
+                continue;
+            }
+            match section {
+                Some(Section::Protein) => filter.protein_filters.push(rule.protein()?),
+                Some(Section::Peptide) => filter.peptide_filters.push(rule.peptide()?),
+                None => return rule.err("rule outside of a protein: or peptide: section".into()),
+            }
+            rule.end()?;
+        }
+        Ok(filter)
+    }
+}
+
+/// Write a double-quoted pattern, escaping quotes and backslashes
+fn quoted(f: &mut fmt::Formatter<'_>, pattern: &str) -> fmt::Result {
+    write!(
+        f,
+        "\"{}\"",
+        pattern.replace('\\', "\\\\").replace('"', "\\\"")
+    )
+}
+
+/// Enzymes are written in the text format read by `Filter::parse`
+impl fmt::Display for Enzyme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Enzyme::Trypsin => write!(f, "trypsin"),
+            Enzyme::TrypsinP => write!(f, "trypsin_p"),
+            Enzyme::LysC => write!(f, "lys_c"),
+            Enzyme::Chymotrypsin => write!(f, "chymotrypsin"),
+            Enzyme::GluC => write!(f, "glu_c"),
+            Enzyme::Custom {
+                residues,
+                restrict,
+                n_term,
+            } => {
+                write!(f, "{}(", if *n_term { "custom_n" } else { "custom" })?;
+                quoted(f, residues)?;
+                write!(f, ", ")?;
+                quoted(f, restrict)?;
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+/// Write a comma separated list
+fn list<T: fmt::Display>(f: &mut fmt::Formatter<'_>, items: &[T]) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", item)?;
+    }
+    Ok(())
+}
+
+/// Rules are written in the text format read by `Filter::parse`
+impl fmt::Display for ProteinFilter<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProteinFilter::SpectralCounts(n) => write!(f, "spectral_counts >= {}", n),
+            ProteinFilter::SequenceCounts(n) => write!(f, "sequence_counts >= {}", n),
+            ProteinFilter::UniquePeptides(n) => write!(f, "unique_peptides >= {}", n),
+            ProteinFilter::ExcludeReverse => write!(f, "exclude_reverse"),
+            ProteinFilter::ExcludeContaminants => write!(f, "exclude_contaminants"),
+            ProteinFilter::MinQuantifiedChannels(n) => {
+                write!(f, "min_quantified_channels >= {}", n)
+            }
+            ProteinFilter::MinQuantifiedPlexes(n) => write!(f, "min_quantified_plexes >= {}", n),
+            ProteinFilter::TopNPeptides(n) => write!(f, "top_n_peptides {}", n),
+            ProteinFilter::MinCoverage(n) => write!(f, "min_coverage >= {}", n),
+            ProteinFilter::MolecularWeightRange(min, max) => {
+                write!(f, "molecular_weight {} {}", min, max)
+            }
+            ProteinFilter::DescriptionMatches(pat) => {
+                write!(f, "description_matches ")?;
+                quoted(f, pat)
+            }
+            ProteinFilter::DescriptionExcludes(pat) => {
+                write!(f, "description_excludes ")?;
+                quoted(f, pat)
+            }
+        }
+    }
+}
+
+/// Rules are written in the text format read by `Filter::parse`
+impl fmt::Display for PeptideFilter<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PeptideFilter::SequenceMatch(pat) => {
+                write!(f, "sequence_match ")?;
+                quoted(f, pat)
+            }
+            PeptideFilter::SequenceExclude(pat) => {
+                write!(f, "sequence_exclude ")?;
+                quoted(f, pat)
+            }
+            PeptideFilter::SequenceRegex(pat) => {
+                write!(f, "sequence_regex ")?;
+                quoted(f, pat)
+            }
+            PeptideFilter::SequenceRegexExclude(pat) => {
+                write!(f, "sequence_regex_exclude ")?;
+                quoted(f, pat)
+            }
+            PeptideFilter::Length(min, max) => write!(f, "length {} {}", min, max),
+            PeptideFilter::MaxMissedCleavages(n) => write!(f, "max_missed_cleavages <= {}", n),
+            PeptideFilter::MinQuantifiedChannels(n) => {
+                write!(f, "min_quantified_channels >= {}", n)
+            }
+            PeptideFilter::TotalIntensity(n) => write!(f, "total_intensity >= {}", n),
+            PeptideFilter::TotalIntensityChannels(channels, n) => {
+                write!(f, "total_intensity_channels(")?;
+                list(f, channels)?;
+                write!(f, ") >= {}", n)
+            }
+            PeptideFilter::ChannelCV(channels, n) => {
+                write!(f, "channel_cv(")?;
+                list(f, channels)?;
+                write!(f, ") < {}", n)
+            }
+            PeptideFilter::ChannelCVQuantified(channels, n, min) => {
+                write!(f, "channel_cv_quantified(")?;
+                list(f, channels)?;
+                write!(f, ") < {} min {}", n, min)
+            }
+            PeptideFilter::ChannelRatio(num, den, min, max) => {
+                write!(f, "channel_ratio(")?;
+                list(f, num)?;
+                write!(f, ") (")?;
+                list(f, den)?;
+                write!(f, ") {} {}", min, max)
+            }
+            PeptideFilter::ChannelIntensity(channel, n) => {
+                write!(f, "channel_intensity({}) >= {}", channel, n)
+            }
+            PeptideFilter::Purity(n) => write!(f, "purity >= {}", n),
+            PeptideFilter::RetentionTime(min, max) => write!(f, "retention_time {} {}", min, max),
+            PeptideFilter::ScanRange(min, max) => write!(f, "scan_range {} {}", min, max),
+            PeptideFilter::SourceFile(pat) => {
+                write!(f, "source_file ")?;
+                quoted(f, pat)
+            }
+            PeptideFilter::Tryptic => write!(f, "tryptic"),
+            PeptideFilter::Termini(Termini::Full(enzyme)) => write!(f, "termini full {}", enzyme),
+            PeptideFilter::Termini(Termini::Semi(enzyme)) => write!(f, "termini semi {}", enzyme),
+            PeptideFilter::Unique => write!(f, "unique"),
+            PeptideFilter::Or(filters) => {
+                write!(f, "or(")?;
+                list(f, filters)?;
+                write!(f, ")")
+            }
+            PeptideFilter::Not(filter) => write!(f, "not({})", filter),
+            PeptideFilter::AnyOf(n, filters) => {
+                write!(f, "any_of({}, ", n)?;
+                list(f, filters)?;
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+/// A filter is written in the text format read by `Filter::parse`, with a
+/// section for each non-empty set of rules
+impl fmt::Display for Filter<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.protein_filters.is_empty() {
+            writeln!(f, "protein:")?;
+            for filter in &self.protein_filters {
+                writeln!(f, "    {}", filter)?;
+            }
+        }
+        if !self.peptide_filters.is_empty() {
+            writeln!(f, "peptide:")?;
+            for filter in &self.peptide_filters {
+                writeln!(f, "    {}", filter)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_every_rule() {
+        let text = r#"
+protein:
+    spectral_counts 2
+    sequence_counts 1
+    unique_peptides >= 2
+    exclude_reverse
+    exclude_contaminants
+    min_quantified_channels 3
+    min_quantified_plexes >= 2
+    top_n_peptides 5
+    min_coverage 12.5
+    molecular_weight 10000 200000
+    description_matches "kinase"
+    description_excludes "Keratin, \"type\""
+peptide:
+    sequence_match "M"
+    sequence_exclude "C"
+    sequence_regex "N[^P][ST]"
+    sequence_regex_exclude "^K"
+    length 7 30
+    max_missed_cleavages 2
+    min_quantified_channels 4
+    total_intensity 5e3
+    total_intensity_channels(1, 2) 1000000
+    channel_cv(1,2,3) 0.2
+    channel_cv_quantified(1, 2, 3, 4) < 0.3 min 2
+    channel_ratio(1, 2) (3) 0.1 10
+    channel_intensity(1) 1000
+    purity 0.9
+    retention_time 10 90.5
+    scan_range 1000 25000
+    not(source_file "_F12")
+    tryptic
+    termini full trypsin
+    termini semi custom_n("D", "")
+    unique
+    or(unique, total_intensity 1e5)
+    not(sequence_match "M")
+    any_of(2, unique, tryptic, not(purity 0.5))
+"#;
+        let filter = Filter::parse(text).unwrap();
+        let expected = Filter::default()
+            .add_protein_filter(ProteinFilter::SpectralCounts(2))
+            .add_protein_filter(ProteinFilter::SequenceCounts(1))
+            .add_protein_filter(ProteinFilter::UniquePeptides(2))
+            .add_protein_filter(ProteinFilter::ExcludeReverse)
+            .add_protein_filter(ProteinFilter::ExcludeContaminants)
+            .add_protein_filter(ProteinFilter::MinQuantifiedChannels(3))
+            .add_protein_filter(ProteinFilter::MinQuantifiedPlexes(2))
+            .add_protein_filter(ProteinFilter::TopNPeptides(5))
+            .add_protein_filter(ProteinFilter::MinCoverage(12.5))
+            .add_protein_filter(ProteinFilter::MolecularWeightRange(10000, 200000))
+            .add_protein_filter(ProteinFilter::DescriptionMatches("kinase".into()))
+            .add_protein_filter(ProteinFilter::DescriptionExcludes(
+                "Keratin, \"type\"".into(),
+            ))
+            .add_peptide_filter(PeptideFilter::SequenceMatch("M".into()))
+            .add_peptide_filter(PeptideFilter::SequenceExclude("C".into()))
+            .add_peptide_filter(PeptideFilter::SequenceRegex("N[^P][ST]".into()))
+            .add_peptide_filter(PeptideFilter::SequenceRegexExclude("^K".into()))
+            .add_peptide_filter(PeptideFilter::Length(7, 30))
+            .add_peptide_filter(PeptideFilter::MaxMissedCleavages(2))
+            .add_peptide_filter(PeptideFilter::MinQuantifiedChannels(4))
+            .add_peptide_filter(PeptideFilter::TotalIntensity(5000.0))
+            .add_peptide_filter(PeptideFilter::TotalIntensityChannels(vec![1, 2], 1000000.0))
+            .add_peptide_filter(PeptideFilter::ChannelCV(vec![1, 2, 3], 0.2))
+            .add_peptide_filter(PeptideFilter::ChannelCVQuantified(vec![1, 2, 3, 4], 0.3, 2))
+            .add_peptide_filter(PeptideFilter::ChannelRatio(vec![1, 2], vec![3], 0.1, 10.0))
+            .add_peptide_filter(PeptideFilter::ChannelIntensity(1, 1000.0))
+            .add_peptide_filter(PeptideFilter::Purity(0.9))
+            .add_peptide_filter(PeptideFilter::RetentionTime(10.0, 90.5))
+            .add_peptide_filter(PeptideFilter::ScanRange(1000, 25000))
+            .add_peptide_filter(PeptideFilter::Not(Box::new(PeptideFilter::SourceFile(
+                "_F12".into(),
+            ))))
+            .add_peptide_filter(PeptideFilter::Tryptic)
+            .add_peptide_filter(PeptideFilter::Termini(Termini::Full(Enzyme::Trypsin)))
+            .add_peptide_filter(PeptideFilter::Termini(Termini::Semi(Enzyme::Custom {
+                residues: "D".into(),
+                restrict: String::new(),
+                n_term: true,
+            })))
+            .add_peptide_filter(PeptideFilter::Unique)
+            .add_peptide_filter(PeptideFilter::Or(vec![
+                PeptideFilter::Unique,
+                PeptideFilter::TotalIntensity(1e5),
+            ]))
+            .add_peptide_filter(PeptideFilter::Not(Box::new(PeptideFilter::SequenceMatch(
+                "M".into(),
+            ))))
+            .add_peptide_filter(PeptideFilter::AnyOf(
+                2,
+                vec![
+                    PeptideFilter::Unique,
+                    PeptideFilter::Tryptic,
+                    PeptideFilter::Not(Box::new(PeptideFilter::Purity(0.5))),
+                ],
+            ));
+        assert_eq!(filter, expected);
+        assert_eq!(Filter::parse(&filter.to_string()).unwrap(), filter);
+    }
+
+    #[test]
+    fn display() {
+        let filter = Filter::default()
+            .add_protein_filter(ProteinFilter::DescriptionExcludes("a \"b\" \\".into()))
+            .add_peptide_filter(PeptideFilter::ChannelCV(vec![1, 2], 0.25))
+            .add_peptide_filter(PeptideFilter::AnyOf(
+                1,
+                vec![PeptideFilter::Unique, PeptideFilter::TotalIntensity(1e5)],
+            ));
+        let text = filter.to_string();
+        assert_eq!(
+            text,
+            "protein:\n    description_excludes \"a \\\"b\\\" \\\\\"\n\
+             peptide:\n    channel_cv(1, 2) < 0.25\n    any_of(1, unique, total_intensity >= 100000)\n"
+        );
+        assert_eq!(Filter::parse(&text).unwrap(), filter);
+        assert_eq!(Filter::default().to_string(), "");
+
+        // Non-finite values round-trip too
+        let filter = Filter::default()
+            .add_peptide_filter(PeptideFilter::ChannelRatio(
+                vec![1],
+                vec![2],
+                2.0,
+                f64::INFINITY,
+            ))
+            .add_peptide_filter(PeptideFilter::RetentionTime(f32::NEG_INFINITY, 60.0))
+            .add_peptide_filter(PeptideFilter::TotalIntensity(f64::NAN));
+        let text = filter.to_string();
+        assert_eq!(
+            text,
+            "peptide:\n    channel_ratio(1) (2) 2 inf\n    retention_time -inf 60\n    \
+             total_intensity >= NaN\n"
+        );
+        assert_eq!(Filter::parse(&text).unwrap().to_string(), text);
+        assert!(Filter::parse("peptide:\n  purity inf").is_ok());
+        assert!(Filter::parse("protein:\n  spectral_counts inf").is_err());
+    }
+
+    #[test]
+    fn comments_and_operators() {
+        let text = "
+# Keep well-quantified PSMs
+peptide: # section comment
+    total_intensity >= 5000
+    channel_cv(1,2,3) < 0.2  # noisy
+    max_missed_cleavages <= 1
+    sequence_exclude \"#\"
+    purity 0.5
+";
+        let filter = Filter::parse(text).unwrap();
+        let expected = Filter::default()
+            .add_peptide_filter(PeptideFilter::TotalIntensity(5000.0))
+            .add_peptide_filter(PeptideFilter::ChannelCV(vec![1, 2, 3], 0.2))
+            .add_peptide_filter(PeptideFilter::MaxMissedCleavages(1))
+            .add_peptide_filter(PeptideFilter::SequenceExclude("#".into()))
+            .add_peptide_filter(PeptideFilter::Purity(0.5));
+        assert_eq!(filter, expected);
+
+        assert!(Filter::parse("peptide:\n total_intensity < 5000").is_err());
+        assert!(Filter::parse("peptide:\n channel_cv(1, 2) >= 0.2").is_err());
+        assert!(Filter::parse("peptide:\n total_intensity > 5000").is_err());
+    }
+
+    #[test]
+    fn parse_errors() {
+        let line = |text: &str| Filter::parse(text).unwrap_err().line();
+        assert_eq!(line("unique"), 1);
+        assert_eq!(line("peptide:\n  unique\n  purity"), 3);
+        assert_eq!(line("peptide:\n  exclude_reverse"), 2);
+        assert_eq!(line("protein:\n  spectral_counts 2 3"), 2);
+        assert_eq!(line("peptide:\n  sequence_match \"M"), 2);
+        assert_eq!(line("proteins:"), 1);
+        assert!(matches!(
+            Filter::parse("peptide:\n  total_intensity x")
+                .unwrap_err()
+                .kind(),
+            ErrorKind::InvalidFilter(_)
+        ));
+    }
+
+    #[test]
+    fn error_context() {
+        let err = Filter::parse("peptide:\n  purity >= 0.5\n  uniqe").unwrap_err();
+        assert_eq!(err.line(), 3);
+        let ctx = err.context().unwrap();
+        assert_eq!(ctx.column, Some(3));
+        assert_eq!(ctx.token.as_deref(), Some("uniqe"));
+        assert_eq!(ctx.text, "  uniqe");
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::InvalidFilter("unknown rule, did you mean \"unique\"?".into())
+        );
+
+        let err = Filter::parse("peptide:\n  exclude_reverse").unwrap_err();
+        assert!(err.to_string().contains("is a protein rule"));
+
+        let err = Filter::parse("proteins:").unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::InvalidFilter("unknown section, did you mean \"protein\"?".into())
+        );
+
+        let err = Filter::parse("peptide:\n  total_intensity >= x").unwrap_err();
+        let ctx = err.context().unwrap();
+        assert_eq!((ctx.column, ctx.token.as_deref()), (Some(22), Some("x")));
+
+        let err = Filter::parse("peptide:\n  purity <= 0.5").unwrap_err();
+        let ctx = err.context().unwrap();
+        assert_eq!((ctx.column, ctx.token.as_deref()), (Some(10), Some("<=")));
+
+        let err = Filter::parse("peptide:\n  length 7").unwrap_err();
+        let ctx = err.context().unwrap();
+        assert_eq!((ctx.column, ctx.token.as_deref()), (Some(11), None));
+
+        let err = Filter::parse("peptide:\n  sequence_match \"M").unwrap_err();
+        let ctx = err.context().unwrap();
+        assert_eq!((ctx.column, ctx.token.as_deref()), (Some(18), Some("\"M")));
+
+        let err = Filter::parse("peptide:\n  sequence_regex \"([\"").unwrap_err();
+        let ctx = err.context().unwrap();
+        assert_eq!(
+            (ctx.column, ctx.token.as_deref()),
+            (Some(18), Some("\"([\""))
+        );
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::InvalidFilter(
+                "invalid regular expression: unclosed character class".into()
+            )
+        );
+    }
+}
@@ -0,0 +1,114 @@
+//! Named filters for common TMT quality control policies
+use super::*;
+
+impl Filter<'static> {
+    /// Standard TMT protein quantification filter:
+    ///
+    /// ```text
+    /// protein:
+    ///     exclude_reverse
+    ///     exclude_contaminants
+    ///     unique_peptides >= 2
+    /// peptide:
+    ///     unique
+    ///     tryptic
+    ///     purity >= 0.7
+    ///     total_intensity >= 10000
+    /// ```
+    ///
+    /// Peptides shared between proteins, non-tryptic peptides, PSMs with
+    /// more than 30% co-isolation interference, and low signal PSMs are
+    /// removed, and only proteins with two or more unique peptides are kept.
+    ///
+    /// Contaminants are only recognized by their accession prefix unless the
+    /// `crap` feature is enabled, see `ProteinFilter::ExcludeContaminants`
+    pub fn standard_tmt() -> Filter<'static> {
+        Filter::default()
+            .add_protein_filter(ProteinFilter::ExcludeReverse)
+            .add_protein_filter(ProteinFilter::ExcludeContaminants)
+            .add_protein_filter(ProteinFilter::UniquePeptides(2))
+            .add_peptide_filter(PeptideFilter::Unique)
+            .add_peptide_filter(PeptideFilter::Tryptic)
+            .add_peptide_filter(PeptideFilter::Purity(0.7))
+            .add_peptide_filter(PeptideFilter::TotalIntensity(10_000.0))
+    }
+
+    /// TMT phosphopeptide filter:
+    ///
+    /// ```text
+    /// protein:
+    ///     exclude_reverse
+    ///     exclude_contaminants
+    /// peptide:
+    ///     sequence_regex "[STY][(\[]79\.96"
+    ///     unique
+    ///     tryptic
+    ///     purity >= 0.7
+    ///     total_intensity >= 10000
+    /// ```
+    ///
+    /// Only peptides with a phosphorylated S, T or Y are kept, see
+    /// `Peptide::modifications`. A phosphosite is usually seen on a single
+    /// peptide, so unlike `Filter::standard_tmt` there is no minimum
+    /// number of peptides per protein
+    pub fn phospho_preset() -> Filter<'static> {
+        Filter::default()
+            .add_protein_filter(ProteinFilter::ExcludeReverse)
+            .add_protein_filter(ProteinFilter::ExcludeContaminants)
+            .add_peptide_filter(PeptideFilter::SequenceRegex(r"[STY][(\[]79\.96".into()))
+            .add_peptide_filter(PeptideFilter::Unique)
+            .add_peptide_filter(PeptideFilter::Tryptic)
+            .add_peptide_filter(PeptideFilter::Purity(0.7))
+            .add_peptide_filter(PeptideFilter::TotalIntensity(10_000.0))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn presets() {
+        for filter in [Filter::standard_tmt(), Filter::phospho_preset()] {
+            assert_eq!(filter.check(None), Ok(()));
+            assert_eq!(Filter::parse(&filter.to_string()).unwrap(), filter);
+        }
+
+        let pep = |seq: &str| Peptide {
+            sequence: seq.into(),
+            unique: true,
+            purity: 0.9,
+            values: vec![20_000.0],
+            ..Peptide::default()
+        };
+        let prot = |acc: &str| Protein {
+            accession: acc.into(),
+            peptides: vec![
+                pep("K.AAS(79.9663)K.L"),
+                pep("K.CCK.L"),
+                pep("K.DDT[79.9663]EK.L"),
+            ],
+            channels: 1,
+            ..Protein::default()
+        };
+        let reg = Filter::tryptic_regex();
+
+        let p = Filter::phospho_preset()
+            .filter_protein(prot("P1"), &reg)
+            .unwrap();
+        assert_eq!(p.peptides.len(), 2);
+        assert!(Filter::phospho_preset()
+            .filter_protein(prot("CON__P1"), &reg)
+            .is_none());
+        let trypsin = Filter::phospho_preset().filter_protein(prot("sp|P00761|TRYP_PIG"), &reg);
+        assert_eq!(trypsin.is_none(), cfg!(feature = "crap"));
+
+        let p = Filter::standard_tmt()
+            .filter_protein(prot("P1"), &reg)
+            .unwrap();
+        assert_eq!(p.peptides.len(), 3);
+        assert!(Filter::standard_tmt()
+            .filter_protein(prot("Reverse_P1"), &reg)
+            .is_none());
+    }
+}
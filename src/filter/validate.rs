@@ -0,0 +1,227 @@
+//! Checking filter rules for values that can never be valid, or that do not
+//! fit a dataset
+use super::*;
+use std::fmt;
+
+/// Error returned by `Filter::validate`
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterError {
+    /// A rule refers to a channel outside of the dataset. Channels are
+    /// numbered from 1 to the number of channels of the dataset
+    ChannelOutOfRange {
+        /// The offending rule, in the text format of `Filter::parse`
+        rule: String,
+        channel: usize,
+        channels: u8,
+    },
+    /// A rule has a value that can never be valid: a range with its minimum
+    /// above its maximum, a `TopNPeptides` of 0, an invalid regular expression, or an `AnyOf` or
+    /// `ChannelCVQuantified` requiring more rules or channels than it
+    /// contains. `Filter::validate` also rejects a `MinQuantifiedPlexes`
+    /// requiring more plexes than the dataset has
+    Invalid {
+        /// The offending rule, in the text format of `Filter::parse`
+        rule: String,
+        reason: String,
+    },
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterError::ChannelOutOfRange {
+                rule,
+                channel,
+                channels,
+            } => write!(
+                f,
+                "filter rule `{}` refers to channel {}, but the dataset has channels 1 to {}",
+                rule, channel, channels
+            ),
+            FilterError::Invalid { rule, reason } => {
+                write!(f, "invalid filter rule `{}`: {}", rule, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FilterError {}
+
+fn invalid<T: fmt::Display>(rule: &T, reason: &str) -> FilterError {
+    FilterError::Invalid {
+        rule: rule.to_string(),
+        reason: reason.into(),
+    }
+}
+
+/// Check a peptide filter, and its channels against `channels` if given
+fn check_peptide(filter: &PeptideFilter<'_>, channels: Option<u8>) -> Result<(), FilterError> {
+    let check_channel = |channel: usize| match channels {
+        _ if channel == 0 => Err(FilterError::ChannelOutOfRange {
+            rule: filter.to_string(),
+            channel,
+            channels: channels.unwrap_or(0),
+        }),
+        Some(n) if channel > n as usize => Err(FilterError::ChannelOutOfRange {
+            rule: filter.to_string(),
+            channel,
+            channels: n,
+        }),
+        _ => Ok(()),
+    };
+    match filter {
+        PeptideFilter::ChannelCVQuantified(list, _, min) if *min > list.len() => {
+            Err(invalid(filter, "requires more channels than it contains"))
+        }
+        PeptideFilter::TotalIntensityChannels(list, _)
+        | PeptideFilter::ChannelCV(list, _)
+        | PeptideFilter::ChannelCVQuantified(list, _, _) => {
+            list.iter().try_for_each(|&c| check_channel(c))
+        }
+        PeptideFilter::ChannelRatio(_, _, min, max) if min > max => {
+            Err(invalid(filter, "minimum is above maximum"))
+        }
+        PeptideFilter::ChannelRatio(num, den, _, _) => num
+            .iter()
+            .chain(den.iter())
+            .try_for_each(|&c| check_channel(c)),
+        PeptideFilter::ChannelIntensity(c, _) => check_channel(*c),
+        PeptideFilter::Termini(Termini::Full(Enzyme::Custom { residues, .. }))
+        | PeptideFilter::Termini(Termini::Semi(Enzyme::Custom { residues, .. }))
+            if residues.is_empty() =>
+        {
+            Err(invalid(filter, "enzyme cleaves at no residues"))
+        }
+        PeptideFilter::Length(min, max) if min > max => {
+            Err(invalid(filter, "minimum is above maximum"))
+        }
+        PeptideFilter::RetentionTime(min, max) if min > max => {
+            Err(invalid(filter, "minimum is above maximum"))
+        }
+        PeptideFilter::ScanRange(min, max) if min > max => {
+            Err(invalid(filter, "minimum is above maximum"))
+        }
+        PeptideFilter::SequenceRegex(pat) | PeptideFilter::SequenceRegexExclude(pat) => {
+            match regex::Regex::new(pat) {
+                Ok(_) => Ok(()),
+                Err(e) => Err(invalid(filter, &e.to_string())),
+            }
+        }
+        PeptideFilter::AnyOf(n, filters) if *n > filters.len() => {
+            Err(invalid(filter, "requires more rules than it contains"))
+        }
+        PeptideFilter::Or(filters) | PeptideFilter::AnyOf(_, filters) => {
+            filters.iter().try_for_each(|f| check_peptide(f, channels))
+        }
+        PeptideFilter::Not(filter) => check_peptide(filter, channels),
+        _ => Ok(()),
+    }
+}
+
+impl<'a> Filter<'a> {
+    /// Check the rules, and their channels against `channels` if given
+    pub(crate) fn check(&self, channels: Option<u8>) -> Result<(), FilterError> {
+        for rule in &self.protein_filters {
+            match rule {
+                ProteinFilter::MolecularWeightRange(min, max) if min > max => {
+                    return Err(invalid(rule, "minimum is above maximum"));
+                }
+                ProteinFilter::TopNPeptides(0) => {
+                    return Err(invalid(rule, "keeps no peptides"));
+                }
+                _ => {}
+            }
+        }
+        self.peptide_filters
+            .iter()
+            .try_for_each(|f| check_peptide(f, channels))
+    }
+
+    /// Check that every rule can be applied to `dataset`.
+    ///
+    /// Filtering never fails: a rule referring to a channel outside of the
+    /// dataset treats that channel as missing, so `ChannelIntensity`
+    /// passes every peptide and the channel is left out of `ChannelCV` and
+    /// `TotalIntensityChannels`. A rule with an invalid value, such as an
+    /// empty range, either removes everything or nothing. Validate a filter
+    /// first to catch these mistakes
+    pub fn validate(&self, dataset: &Dataset) -> Result<(), FilterError> {
+        self.check(Some(dataset.channels))?;
+        let plexes = Context::new(dataset)
+            .plexes
+            .into_iter()
+            .collect::<HashSet<_>>()
+            .len();
+        for rule in &self.protein_filters {
+            if let ProteinFilter::MinQuantifiedPlexes(n) = rule {
+                if *n > plexes.max(1) {
+                    return Err(invalid(rule, "requires more plexes than the dataset has"));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn validate() {
+        let dataset = Dataset {
+            channels: 6,
+            ..Dataset::default()
+        };
+        let fil = Filter::default()
+            .add_peptide_filter(PeptideFilter::ChannelCV(vec![1, 2, 6], 0.2))
+            .add_peptide_filter(PeptideFilter::ChannelIntensity(3, 100.0));
+        assert_eq!(fil.validate(&dataset), Ok(()));
+
+        let fil = fil.add_peptide_filter(PeptideFilter::Not(Box::new(
+            PeptideFilter::TotalIntensityChannels(vec![1, 11], 100.0),
+        )));
+        assert_eq!(
+            fil.validate(&dataset),
+            Err(FilterError::ChannelOutOfRange {
+                rule: "total_intensity_channels(1, 11) >= 100".into(),
+                channel: 11,
+                channels: 6,
+            })
+        );
+
+        let fil = Filter::default().add_peptide_filter(PeptideFilter::ChannelIntensity(0, 1.0));
+        assert!(matches!(
+            fil.validate(&dataset),
+            Err(FilterError::ChannelOutOfRange { channel: 0, .. })
+        ));
+
+        let fil = Filter::default().add_protein_filter(ProteinFilter::MolecularWeightRange(9, 1));
+        assert!(matches!(
+            fil.validate(&dataset),
+            Err(FilterError::Invalid { .. })
+        ));
+    }
+
+    #[test]
+    fn out_of_range_channels() {
+        let prot = Protein {
+            peptides: vec![Peptide {
+                values: vec![100.0, 110.0],
+                ..Peptide::default()
+            }],
+            channels: 2,
+            ..Protein::default()
+        };
+        let reg = Filter::tryptic_regex();
+        for rule in [
+            PeptideFilter::ChannelIntensity(0, 1000.0),
+            PeptideFilter::ChannelIntensity(3, 1000.0),
+            PeptideFilter::ChannelCV(vec![0, 1, 2, 3], 0.2),
+            PeptideFilter::TotalIntensityChannels(vec![0, 1, 3], 100.0),
+        ] {
+            let fil = Filter::default().add_peptide_filter(rule);
+            assert!(fil.filter_protein(prot.clone(), &reg).is_some());
+        }
+    }
+}
@@ -0,0 +1,167 @@
+//! Conversion of a `Dataset` into polars `DataFrame`s
+//!
+//! Two shapes are provided: a wide frame with one row per peptide and one
+//! column per channel, and a long (tidy) frame with one row per peptide and
+//! channel. Missing intensities (`NaN`) become nulls in both.
+use crate::Dataset;
+use polars::prelude::*;
+
+/// Peptide-level identifier columns, repeated `times` for each peptide
+fn peptide_columns(data: &Dataset, times: usize) -> Vec<Column> {
+    let rows = data
+        .proteins
+        .iter()
+        .flat_map(|prot| prot.peptides.iter().map(move |pep| (prot, pep)))
+        .flat_map(|row| std::iter::repeat_n(row, times))
+        .collect::<Vec<_>>();
+    vec![
+        Column::new(
+            "accession".into(),
+            rows.iter()
+                .map(|(p, _)| p.accession.as_str())
+                .collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "sequence".into(),
+            rows.iter()
+                .map(|(_, p)| p.sequence.as_str())
+                .collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "unique".into(),
+            rows.iter().map(|(_, p)| p.unique).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "purity".into(),
+            rows.iter().map(|(_, p)| p.purity).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "scan".into(),
+            rows.iter().map(|(_, p)| p.scan as u64).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "charge".into(),
+            rows.iter()
+                .map(|(_, p)| p.charge as u32)
+                .collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "file_name".into(),
+            rows.iter()
+                .map(|(_, p)| p.file_name.as_str())
+                .collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "retention_time".into(),
+            rows.iter()
+                .map(|(_, p)| p.retention_time)
+                .collect::<Vec<_>>(),
+        ),
+    ]
+}
+
+/// Convert an intensity to an optional value, treating `NaN` as missing
+fn present(value: f64) -> Option<f64> {
+    Some(value).filter(|v| !v.is_nan())
+}
+
+impl Dataset {
+    /// Return a wide `DataFrame`, with one row per peptide and an intensity
+    /// column for each channel, named as by `Dataset::channel_names`
+    pub fn to_wide_frame(&self) -> PolarsResult<DataFrame> {
+        let mut columns = peptide_columns(self, 1);
+        for (c, name) in self.channel_names().into_iter().enumerate() {
+            let values = self
+                .proteins
+                .iter()
+                .flat_map(|prot| prot.peptides.iter())
+                .map(|pep| pep.values.get(c).copied().and_then(present))
+                .collect::<Vec<_>>();
+            columns.push(Column::new(name.into(), values));
+        }
+        DataFrame::new(columns)
+    }
+
+    /// Return a long (tidy) `DataFrame`, with one row per peptide and
+    /// channel. Channels are numbered from 1 in the `channel` column, and
+    /// their intensities are given in the `intensity` column
+    pub fn to_long_frame(&self) -> PolarsResult<DataFrame> {
+        let channels = self.channels as usize;
+        let mut columns = peptide_columns(self, channels);
+        let peptides = self.proteins.iter().flat_map(|prot| prot.peptides.iter());
+        let (channel, intensity): (Vec<u32>, Vec<Option<f64>>) = peptides
+            .flat_map(|pep| {
+                (0..channels).map(move |c| {
+                    let value = pep.values.get(c).copied().and_then(present);
+                    (c as u32 + 1, value)
+                })
+            })
+            .unzip();
+        columns.push(Column::new("channel".into(), channel));
+        columns.push(Column::new("intensity".into(), intensity));
+        DataFrame::new(columns)
+    }
+}
+
+impl From<&Dataset> for DataFrame {
+    /// Convert into the wide form, see `Dataset::to_wide_frame`
+    fn from(data: &Dataset) -> DataFrame {
+        // Every column has one value per peptide, and column names are
+        // distinct, so construction cannot fail
+        data.to_wide_frame()
+            .expect("wide frame columns have equal lengths and distinct names")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Dataset, Header, Peptide, Protein, Scale};
+
+    fn dataset() -> Dataset {
+        Dataset {
+            channels: 2,
+            proteins: vec![Protein {
+                accession: "P12345".into(),
+                channels: 2,
+                peptides: vec![
+                    Peptide {
+                        sequence: "K.LVNEVTEFAK.T".into(),
+                        values: vec![1000.0, 3000.0],
+                        ..Peptide::default()
+                    },
+                    Peptide {
+                        sequence: "R.QNCELFEK.L".into(),
+                        values: vec![500.0, f64::NAN],
+                        ..Peptide::default()
+                    },
+                ],
+                ..Protein::default()
+            }],
+            header: Header::default(),
+            channel_info: Vec::new(),
+            scale: Scale::Linear,
+            ..Dataset::default()
+        }
+    }
+
+    #[test]
+    fn wide_frame() {
+        let df = DataFrame::from(&dataset());
+        assert_eq!(df.shape(), (2, 10));
+        let channel = df.column("channel_2").unwrap().f64().unwrap();
+        assert_eq!(channel.get(0), Some(3000.0));
+        assert_eq!(channel.get(1), None);
+    }
+
+    #[test]
+    fn long_frame() {
+        let df = dataset().to_long_frame().unwrap();
+        assert_eq!(df.shape(), (4, 10));
+        let channel = df.column("channel").unwrap().u32().unwrap();
+        assert_eq!(channel.get(3), Some(2));
+        let intensity = df.column("intensity").unwrap().f64().unwrap();
+        assert_eq!(intensity.get(2), Some(500.0));
+        assert_eq!(intensity.null_count(), 1);
+    }
+}
@@ -0,0 +1,174 @@
+//! Export of protein or gene intensity matrices in the GCT 1.3 format read
+//! by GSEA and Morpheus
+use super::*;
+use std::io::{self, Write};
+
+/// Row metadata columns, following the row id column
+const ROW_META: [&str; 2] = ["description", "gene"];
+
+/// Replace the tabs and line breaks in a field, which cannot be escaped in
+/// GCT files
+fn clean(field: &str) -> String {
+    field.replace(['\t', '\n', '\r'], " ")
+}
+
+impl Dataset {
+    /// Write a GCT 1.3 matrix with one row per protein, identified by its
+    /// accession, and one column per channel, named as by
+    /// `Dataset::channel_names`.
+    ///
+    /// Values are the summed peptide intensities (see `Protein::total`),
+    /// or the mean of the peptide log2 intensities if the dataset has been
+    /// log2 transformed. Missing values are left blank. Each protein's
+    /// description and gene are written as row metadata, and the sample,
+    /// condition, replicate and plex of the channels as column metadata,
+    /// skipping annotations that no channel has
+    pub fn write_gct<W: Write>(&self, w: W) -> io::Result<()> {
+        self.write_gct_values(w, |prot| match self.scale {
+            Scale::Linear => prot.total(),
+            Scale::Log2(_) => prot.quantify(Rollup::Mean),
+        })
+    }
+
+    /// Write a GCT 1.3 matrix with one row per gene, identified by the gene
+    /// name. Proteins are combined with `Dataset::aggregate_by_gene`, and
+    /// their peptides summarized with `rollup`, see `Dataset::write_gct`
+    /// for the layout
+    pub fn write_gene_gct<W: Write>(&self, w: W, rollup: Rollup) -> io::Result<()> {
+        self.aggregate_by_gene()
+            .write_gct_values(w, |prot| prot.quantify_scaled(rollup, self.scale))
+    }
+
+    /// Write a GCT 1.3 matrix with the row values given by `values`
+    fn write_gct_values<W: Write, F: Fn(&Protein) -> Vec<f64>>(
+        &self,
+        mut w: W,
+        values: F,
+    ) -> io::Result<()> {
+        let channels = self.channel_names();
+        let info = |c: usize| self.channel_info.get(c);
+        let col_meta: Vec<(&str, Vec<Option<String>>)> = vec![
+            (
+                "sample",
+                (0..channels.len())
+                    .map(|c| info(c).and_then(|i| i.sample.clone()))
+                    .collect(),
+            ),
+            (
+                "condition",
+                (0..channels.len())
+                    .map(|c| info(c).and_then(|i| i.condition.clone()))
+                    .collect(),
+            ),
+            (
+                "replicate",
+                (0..channels.len())
+                    .map(|c| info(c).and_then(|i| i.replicate.map(|r| r.to_string())))
+                    .collect(),
+            ),
+            (
+                "plex",
+                (0..channels.len())
+                    .map(|c| info(c).and_then(|i| i.plex.map(|p| p.to_string())))
+                    .collect(),
+            ),
+        ];
+        let col_meta = col_meta
+            .into_iter()
+            .filter(|(_, values)| values.iter().any(Option::is_some))
+            .collect::<Vec<_>>();
+
+        writeln!(w, "#1.3")?;
+        writeln!(
+            w,
+            "{}\t{}\t{}\t{}",
+            self.proteins.len(),
+            channels.len(),
+            ROW_META.len(),
+            col_meta.len()
+        )?;
+        writeln!(
+            w,
+            "id\t{}\t{}",
+            ROW_META.join("\t"),
+            channels
+                .iter()
+                .map(|c| clean(c))
+                .collect::<Vec<_>>()
+                .join("\t")
+        )?;
+        for (name, values) in &col_meta {
+            write!(w, "{}", name)?;
+            for _ in ROW_META.iter() {
+                write!(w, "\tna")?;
+            }
+            for value in values {
+                write!(w, "\t{}", value.as_deref().map(clean).unwrap_or_default())?;
+            }
+            writeln!(w)?;
+        }
+
+        for prot in &self.proteins {
+            write!(
+                w,
+                "{}\t{}\t{}",
+                clean(&prot.accession),
+                clean(&prot.description),
+                prot.gene.as_deref().map(clean).unwrap_or_default()
+            )?;
+            for value in values(prot) {
+                if value.is_nan() {
+                    write!(w, "\t")?;
+                } else {
+                    write!(w, "\t{}", value)?;
+                }
+            }
+            writeln!(w)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn gct_matrix() {
+        let mut dataset = Dataset::builder()
+            .protein(
+                Protein::builder("P02768")
+                    .description("Serum albumin OS=Homo sapiens GN=ALB PE=1 SV=2")
+                    .peptide(
+                        Peptide::builder("K.AAK.L")
+                            .values(vec![1.0, f64::NAN])
+                            .build(),
+                    )
+                    .peptide(Peptide::builder("K.CCK.L").values(vec![2.0, 3.0]).build())
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap();
+        dataset
+            .annotate_channels("channel\tcondition\n126\tctrl\n127\ttreated\n")
+            .unwrap();
+
+        let mut buf = Vec::new();
+        dataset.write_gct(&mut buf).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        assert_eq!(
+            text,
+            "#1.3\n\
+             1\t2\t2\t1\n\
+             id\tdescription\tgene\t126\t127\n\
+             condition\tna\tna\tctrl\ttreated\n\
+             P02768\tSerum albumin OS=Homo sapiens GN=ALB PE=1 SV=2\tALB\t3\t3\n"
+        );
+
+        let mut buf = Vec::new();
+        dataset.write_gene_gct(&mut buf, Rollup::Sum).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        assert!(text.lines().last().unwrap().starts_with("ALB\t"));
+    }
+}
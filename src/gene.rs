@@ -0,0 +1,178 @@
+//! Gene-level aggregation of protein quantification data
+use super::*;
+use std::collections::{HashMap, HashSet};
+
+impl Dataset {
+    /// Assign gene names to proteins from a tab-delimited table. The header
+    /// row must name an `accession` (or `protein`) column and a `gene`
+    /// column. Proteins that are not listed keep their current gene, if
+    /// any. Returns the number of proteins assigned a gene
+    pub fn assign_genes(&mut self, input: &str) -> Result<usize, Error> {
+        let mut lines = input
+            .lines()
+            .enumerate()
+            .filter(|(_, l)| !l.trim().is_empty());
+        let names = match lines.next() {
+            Some((_, line)) => line.split('\t').map(str::trim).collect::<Vec<_>>(),
+            None => return Err(Error::new(ErrorKind::EOF, 1)),
+        };
+        let find = |keys: &[&str], name: &str| {
+            names
+                .iter()
+                .position(|n| keys.iter().any(|k| n.eq_ignore_ascii_case(k)))
+                .ok_or_else(|| Error::new(ErrorKind::MissingColumn(name.into()), 1))
+        };
+        let accession = find(&["accession", "protein"], "accession")?;
+        let gene = find(&["gene"], "gene")?;
+
+        let mut genes = HashMap::new();
+        for (_, line) in lines {
+            let fields = line.split('\t').map(str::trim).collect::<Vec<_>>();
+            if let (Some(a), Some(g)) = (fields.get(accession), fields.get(gene)) {
+                if !g.is_empty() {
+                    genes.insert(*a, *g);
+                }
+            }
+        }
+
+        let mut assigned = 0;
+        for prot in self.proteins.iter_mut() {
+            if let Some(g) = genes.get(prot.accession.as_str()) {
+                prot.gene = Some(g.to_string());
+                assigned += 1;
+            }
+        }
+        Ok(assigned)
+    }
+
+    /// Combine the proteins (isoforms, fragments, protein groups) of each
+    /// gene into a single entry, producing a gene-keyed dataset.
+    ///
+    /// Each gene is represented by a protein whose accession is the gene
+    /// name, with the member accessions as `alternatives`. The peptides of
+    /// the members are deduplicated (by stripped sequence, scan and file),
+    /// and the spectral and sequence counts recomputed from them. Use
+    /// `Protein::quantify` for gene-level intensities.
+    /// Proteins without a gene (see `Protein::gene`) are kept under their
+    /// own accession, and never combined with a gene of the same name.
+    /// Genes are in order of first appearance
+    pub fn aggregate_by_gene(&self) -> Dataset {
+        let mut groups: Vec<(String, Vec<&Protein>)> = Vec::new();
+        // Keyed by gene name, or by accession for proteins without a gene
+        let mut index: HashMap<(bool, &str), usize> = HashMap::new();
+        for prot in &self.proteins {
+            let key = match prot.gene.as_deref() {
+                Some(gene) => (true, gene),
+                None => (false, prot.accession.as_str()),
+            };
+            match index.get(&key) {
+                Some(&i) => groups[i].1.push(prot),
+                None => {
+                    index.insert(key, groups.len());
+                    groups.push((key.1.to_string(), vec![prot]));
+                }
+            }
+        }
+
+        let proteins: Vec<Protein> = groups
+            .into_iter()
+            .map(|(key, members)| {
+                let mut seen = HashSet::new();
+                let peptides = members
+                    .iter()
+                    .flat_map(|prot| prot.peptides.iter())
+                    .filter(|pep| seen.insert((pep.stripped_sequence(), pep.scan, &pep.file_name)))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                let sequence_count = peptides
+                    .iter()
+                    .map(|pep| pep.sequence.as_str())
+                    .collect::<HashSet<_>>()
+                    .len();
+                let first = members[0];
+                Protein {
+                    accession: key,
+                    description: first.description.clone(),
+                    alternatives: members.iter().map(|p| p.accession.clone()).collect(),
+                    organism: first.organism.clone(),
+                    gene: first.gene.clone(),
+                    spectral_count: peptides.len().min(u16::MAX as usize) as u16,
+                    sequence_count: sequence_count.min(u16::MAX as usize) as u16,
+                    sequence_coverage: members
+                        .iter()
+                        .map(|p| p.sequence_coverage)
+                        .fold(0.0, f32::max),
+                    molecular_weight: first.molecular_weight,
+                    peptides,
+                    channels: self.channels,
+                    ..Protein::default()
+                }
+            })
+            .collect();
+
+        self.with_proteins(proteins)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn gene_aggregation() {
+        let peptide = |sequence: &str, scan: usize, values: Vec<f64>| Peptide {
+            sequence: sequence.into(),
+            scan,
+            values,
+            ..Peptide::default()
+        };
+        let protein = |accession: &str, peptides: Vec<Peptide>| Protein {
+            accession: accession.into(),
+            channels: 2,
+            peptides,
+            ..Protein::default()
+        };
+        let mut data = Dataset {
+            channels: 2,
+            proteins: vec![
+                protein("P1", vec![peptide("AAK", 1, vec![100.0, 200.0])]),
+                protein(
+                    "P1-2",
+                    vec![
+                        peptide("AAK", 1, vec![100.0, 200.0]),
+                        peptide("CCK", 2, vec![10.0, 20.0]),
+                    ],
+                ),
+                protein("P2", vec![peptide("DDK", 3, vec![1.0, 2.0])]),
+                protein("P3", vec![peptide("EEK", 4, vec![5.0, 5.0])]),
+            ],
+            ..Dataset::default()
+        };
+        let assigned = data
+            .assign_genes("Accession\tGene\nP1\tALB\nP1-2\tALB\nP3\tP2\nP9\tXYZ\n")
+            .unwrap();
+        assert_eq!(assigned, 3);
+
+        let genes = data.aggregate_by_gene();
+        assert_eq!(genes.proteins.len(), 3);
+        let alb = genes.get("ALB").unwrap();
+        assert_eq!(alb.alternatives, vec!["P1", "P1-2"]);
+        assert_eq!(alb.spectral_count, 2);
+        assert_eq!(alb.peptides.len(), 2);
+        assert_eq!(alb.peptides[1].sequence, "CCK");
+        assert_eq!(alb.total(), vec![110.0, 220.0]);
+        assert_eq!(alb.quantify(Rollup::Mean), vec![55.0, 110.0]);
+        // The gene named "P2" is not combined with the protein P2
+        assert_eq!(genes.proteins[1].total(), vec![1.0, 2.0]);
+        assert_eq!(genes.proteins[2].alternatives, vec!["P3"]);
+
+        // Gene entries keep real peptides, so peptide filters still apply
+        let fil = Filter::default().add_peptide_filter(PeptideFilter::Length(3, 3));
+        let filtered = fil.filter_dataset(genes);
+        assert_eq!(filtered.proteins.len(), 3);
+        assert_eq!(filtered.get("ALB").unwrap().spectral_count, 2);
+
+        let err = data.assign_genes("Accession\tName\n").unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::MissingColumn("gene".into()));
+    }
+}
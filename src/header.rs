@@ -0,0 +1,69 @@
+//! Metadata recovered from the 'H' lines of a Census file
+#[cfg(feature = "serialization")]
+use serde::{Deserialize, Serialize};
+
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Clone, Debug, Default)]
+/// Provenance information stored in the header section of a Census file
+pub struct Header {
+    /// Census version that produced the file, if declared
+    pub version: Option<String>,
+    /// Key-value search and quantification parameters, in file order
+    pub parameters: Vec<(String, String)>,
+    /// Column names declared on the `PLINE` header
+    pub protein_columns: Vec<String>,
+    /// Column names declared on the `SLINE` header
+    pub peptide_columns: Vec<String>,
+    /// Every header line, verbatim
+    pub lines: Vec<String>,
+}
+
+impl Header {
+    /// Record a single raw 'H' line, extracting any structured information
+    /// that it contains
+    pub(crate) fn push_line(&mut self, line: &str, delimiter: char) {
+        self.lines.push(line.into());
+
+        let mut fields = line.split(delimiter);
+        if fields.next() != Some("H") {
+            return;
+        }
+        let fields = fields.collect::<Vec<_>>();
+        let first = match fields.first() {
+            Some(f) => f.trim(),
+            None => return,
+        };
+
+        match first {
+            "PLINE" => self.protein_columns = columns(&fields[1..]),
+            "SLINE" => self.peptide_columns = columns(&fields[1..]),
+            _ => {
+                let lower = first.to_ascii_lowercase();
+                if let Some(idx) = lower.find("census version") {
+                    let version = first[idx + "census version".len()..].trim();
+                    if !version.is_empty() {
+                        self.version = Some(version.into());
+                    }
+                } else if fields.len() > 1 {
+                    let value = fields[1..].join(&delimiter.to_string());
+                    self.parameters.push((first.into(), value.trim().into()));
+                } else if let Some(idx) = first.find(':') {
+                    self.parameters
+                        .push((first[..idx].trim().into(), first[idx + 1..].trim().into()));
+                }
+            }
+        }
+    }
+
+    /// Return the value of the first parameter matching `key`
+    pub fn parameter(&self, key: &str) -> Option<&str> {
+        self.parameters
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_ref())
+    }
+}
+
+fn columns(fields: &[&str]) -> Vec<String> {
+    fields.iter().map(|s| s.trim().into()).collect()
+}
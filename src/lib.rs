@@ -1,18 +1,107 @@
 //! A high-performance Rust library for parsing, filtering, and manipulating
 //! multiplexed isobaric data that has been quantified using the Census
 //! algorithm
+mod abundance;
+mod builder;
+#[cfg(feature = "cache")]
+mod cache;
+mod channels;
+#[cfg(feature = "arrow")]
+mod columnar;
+mod columns;
+mod condition;
+mod contaminant;
 mod dataset;
+pub mod dia;
+mod diff;
+mod enzyme;
+mod fast;
+mod fdr;
 mod filter;
+#[cfg(feature = "polars")]
+mod frame;
+mod gct;
+mod gene;
+mod header;
+mod lines;
+mod modification;
+mod msstats;
+pub mod mztab;
+#[cfg(feature = "serialization")]
+mod nan;
+pub mod normalize;
+#[cfg(feature = "openms")]
+pub mod openms;
 mod parser;
+#[cfg(feature = "pca")]
+mod pca;
 mod protein;
+mod ratio;
+pub mod reader;
+mod rollup;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+pub mod stats;
+#[cfg(feature = "async")]
+pub mod stream;
+mod summary;
+mod table;
+#[cfg(feature = "tpp")]
+pub mod tpp;
+mod uniprot;
 pub mod util;
+mod validate;
+mod writer;
+#[cfg(feature = "xlsx")]
+mod xlsx;
+#[cfg(any(feature = "tpp", feature = "openms"))]
+mod xml;
 
-pub use dataset::Dataset;
-pub use filter::{Filter, PeptideFilter, ProteinFilter};
-pub use parser::{Error, Parser};
+pub use abundance::{fasta_lengths, SpectralAbundance};
+pub use builder::{BuildError, DatasetBuilder, PeptideBuilder, ProteinBuilder};
+#[cfg(feature = "cache")]
+pub use cache::CacheError;
+pub use channels::ChannelInfo;
+#[cfg(feature = "arrow")]
+pub use columnar::ArrowTables;
+pub use condition::{ConditionCv, ConditionStats, ConditionSummary, CvReport, CV_CUTOFF};
+pub use contaminant::ContaminantList;
+pub use dataset::{Dataset, DatasetError, Scale};
+pub use diff::{CountChange, DatasetDiff, IntensityShift};
+pub use enzyme::{Enzyme, Termini};
+pub use fdr::{DecoyPattern, ProteinFdr, ProteinScore};
+#[cfg(any(feature = "toml", feature = "yaml"))]
+pub use filter::ConfigError;
+pub use filter::{CompiledFilter, Filter, FilterError, FilterReport, PeptideFilter, ProteinFilter};
+pub use header::Header;
+pub use modification::Modification;
+pub use parser::{
+    DuplicatePolicy, Error, ErrorContext, ErrorKind, Parser, ParserBuilder, ProgressEvent,
+    Proteins, Warning,
+};
+#[cfg(feature = "pca")]
+pub use pca::Pca;
 pub use protein::{Peptide, Protein};
+pub use ratio::{RatioDataset, RatioPeptide, RatioProtein};
+pub use reader::{read_census_path, ReadError};
+pub use rollup::Rollup;
+pub use summary::{ChannelSummary, Summary};
+pub use table::{Column, Layout, LongRow, TableFormat};
+pub use validate::{ValidationIssue, ValidationReport};
 
 /// Parse a string containing a complete census file into a `Dataset`
 pub fn read_census(input: &str) -> Result<Dataset, Error> {
     Parser::new(input).parse()
 }
+
+/// Parse a string containing a complete ratio-format (e.g. SILAC or 15N)
+/// census file into a `RatioDataset`
+pub fn read_census_ratios(input: &str) -> Result<RatioDataset, Error> {
+    Parser::new(input).parse_ratios()
+}
+
+/// Parse several census files, e.g. one per TMT plex or fraction, into a
+/// `Dataset` each. Plexes can then be combined using `Dataset::merge`
+pub fn read_census_multi(inputs: &[&str]) -> Result<Vec<Dataset>, Error> {
+    inputs.iter().map(|input| read_census(input)).collect()
+}
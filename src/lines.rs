@@ -0,0 +1,82 @@
+//! Line iteration over string and byte-slice input
+
+use memchr::memchr;
+use std::str::Lines as StrLines;
+
+/// Placeholder yielded for lines of byte input that are not valid UTF-8.
+/// These lines are rejected by the parser as `ErrorKind::Invalid`
+pub(crate) const INVALID_LINE: &str = "\u{FFFD}";
+
+/// Source of input lines for a `Parser`
+pub(crate) enum Lines<'s> {
+    Str(StrLines<'s>),
+    Bytes(ByteLines<'s>),
+}
+
+impl<'s> Iterator for Lines<'s> {
+    type Item = &'s str;
+
+    fn next(&mut self) -> Option<&'s str> {
+        match self {
+            Lines::Str(iter) => iter.next(),
+            Lines::Bytes(iter) => iter.next(),
+        }
+    }
+}
+
+/// Split byte input into lines using `memchr`, with the same line ending
+/// semantics as `str::lines`.
+///
+/// The input as a whole is never validated as UTF-8: lines consisting only
+/// of ASCII, as Census output almost always does, are converted directly
+pub(crate) struct ByteLines<'s> {
+    bytes: &'s [u8],
+}
+
+impl<'s> ByteLines<'s> {
+    pub fn new(bytes: &'s [u8]) -> ByteLines<'s> {
+        ByteLines { bytes }
+    }
+}
+
+impl<'s> Iterator for ByteLines<'s> {
+    type Item = &'s str;
+
+    fn next(&mut self) -> Option<&'s str> {
+        if self.bytes.is_empty() {
+            return None;
+        }
+        let mut line = match memchr(b'\n', self.bytes) {
+            Some(end) => {
+                let line = &self.bytes[..end];
+                self.bytes = &self.bytes[end + 1..];
+                line
+            }
+            None => std::mem::take(&mut self.bytes),
+        };
+        if let Some(stripped) = line.strip_suffix(b"\r") {
+            line = stripped;
+        }
+        if line.is_ascii() {
+            // SAFETY: ASCII is always valid UTF-8
+            Some(unsafe { std::str::from_utf8_unchecked(line) })
+        } else {
+            Some(std::str::from_utf8(line).unwrap_or(INVALID_LINE))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn byte_lines() {
+        let input = "H\ta\r\nP\tb\n\nS\tc\u{e9}\n";
+        let lines = ByteLines::new(input.as_bytes()).collect::<Vec<_>>();
+        assert_eq!(lines, input.lines().collect::<Vec<_>>());
+
+        let lines = ByteLines::new(b"P\t\xff\nS").collect::<Vec<_>>();
+        assert_eq!(lines, vec![INVALID_LINE, "S"]);
+    }
+}
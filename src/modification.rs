@@ -0,0 +1,107 @@
+//! Peptide modification annotations, e.g. "K.S(79.9663)EM(15.9949)PK.R"
+#[cfg(feature = "serialization")]
+use serde::{Deserialize, Serialize};
+
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+#[derive(PartialEq, PartialOrd, Clone, Debug)]
+/// A modification annotated on a peptide sequence
+pub struct Modification {
+    /// 1-based position of the modified residue in the stripped sequence.
+    /// Position 0 denotes an N-terminal modification
+    pub position: usize,
+    /// Modified residue, or `None` for an N-terminal modification
+    pub residue: Option<char>,
+    /// Mass shift of the modification. `NaN` if the annotation is a name
+    /// rather than a mass
+    #[cfg_attr(feature = "serialization", serde(with = "crate::nan"))]
+    pub delta: f64,
+    /// Name of the modification, if the annotation is not a mass (e.g.
+    /// "M(Oxidation)")
+    pub name: Option<String>,
+}
+
+/// Split a sequence into its flanking residues and the core sequence, e.g.
+/// "K.PEPTIDEK.R" into ("K", "PEPTIDEK", "R"). Sequences without flanking
+/// residues have "-" for both
+pub(crate) fn split_flanks(sequence: &str) -> (&str, &str, &str) {
+    // Flanking residues are single characters, but modification masses may
+    // also contain periods
+    let b = sequence.as_bytes();
+    match b.len() {
+        n if n >= 4 && b[1] == b'.' && b[n - 2] == b'.' => {
+            (&sequence[..1], &sequence[2..n - 2], &sequence[n - 1..])
+        }
+        _ => ("-", sequence, "-"),
+    }
+}
+
+/// Differential modification markers written by SEQUEST/ProLuCID and Census
+/// after a modified residue, e.g. "M*". Their masses depend on the search
+pub(crate) const MARKERS: [char; 6] = ['*', '#', '@', '^', '~', '$'];
+
+/// Parse a (core) sequence into its bare amino acid sequence and the
+/// modifications annotated on it, in either parentheses or square brackets.
+/// A marker such as "*" is a modification named by the marker, see `MARKERS`
+pub(crate) fn parse(core: &str) -> (String, Vec<Modification>) {
+    let mut bare = String::new();
+    let mut mods = Vec::new();
+    let mut chars = core.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '(' | '[' => {
+                let close = if c == '(' { ')' } else { ']' };
+                let text = chars
+                    .by_ref()
+                    .take_while(|&c| c != close)
+                    .collect::<String>();
+                let (delta, name) = match text.trim_start_matches('+').parse::<f64>() {
+                    Ok(delta) => (delta, None),
+                    Err(_) => (f64::NAN, Some(text)),
+                };
+                mods.push(Modification {
+                    position: bare.len(),
+                    residue: bare.chars().last(),
+                    delta,
+                    name,
+                });
+            }
+            c if MARKERS.contains(&c) => mods.push(Modification {
+                position: bare.len(),
+                residue: bare.chars().last(),
+                delta: f64::NAN,
+                name: Some(c.to_string()),
+            }),
+            c if c.is_ascii_alphabetic() => bare.push(c),
+            _ => {}
+        }
+    }
+    (bare, mods)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_modifications() {
+        let (_, core, _) = split_flanks("K.(42.0106)S(79.9663)EM[Oxidation]PK.R");
+        let (bare, mods) = parse(core);
+        assert_eq!(bare, "SEMPK");
+        assert_eq!(mods.len(), 3);
+        assert_eq!(mods[0].position, 0);
+        assert_eq!(mods[0].residue, None);
+        assert_eq!(mods[1].position, 1);
+        assert_eq!(mods[1].residue, Some('S'));
+        assert_eq!(mods[1].delta, 79.9663);
+        assert_eq!(mods[2].residue, Some('M'));
+        assert_eq!(mods[2].name.as_deref(), Some("Oxidation"));
+        assert!(mods[2].delta.is_nan());
+
+        let (bare, mods) = parse("AM*S#K");
+        assert_eq!(bare, "AMSK");
+        assert_eq!(mods[0].position, 2);
+        assert_eq!(mods[0].name.as_deref(), Some("*"));
+        assert_eq!(mods[1].residue, Some('S'));
+        assert_eq!(mods[1].name.as_deref(), Some("#"));
+    }
+}
@@ -0,0 +1,165 @@
+//! Export PSM-level intensities in the long format expected by the
+//! MSstatsTMT R package
+use super::*;
+use std::io::{self, Write};
+
+/// Column names of the MSstatsTMT input table
+const COLUMNS: [&str; 11] = [
+    "ProteinName",
+    "PeptideSequence",
+    "Charge",
+    "PSM",
+    "Mixture",
+    "TechRepMixture",
+    "Run",
+    "Channel",
+    "Condition",
+    "BioReplicate",
+    "Intensity",
+];
+
+impl Dataset {
+    /// Write a comma-delimited MSstatsTMT input table, with one row per
+    /// PSM and channel. Missing intensities are written as "NA".
+    ///
+    /// Columns are filled from the channel annotations (see
+    /// `Dataset::annotate_channels`):
+    /// * Mixture is the channel's plex, or 1 if unannotated
+    /// * Run is the spectrum file name of the PSM, or the mixture if the
+    ///   file name is unknown. TechRepMixture is always 1
+    /// * Channel is the channel's reporter ion label, which MSstatsTMT
+    ///   matches across mixtures, or the channel name if it has no label
+    ///   (see `Dataset::channel_names`)
+    /// * Condition is the channel's condition, or "NA"
+    /// * BioReplicate is the channel's sample name, or failing that, its
+    ///   condition and replicate number joined by "_", or "NA"
+    ///
+    /// For merged multi-plex datasets, each PSM is only written for the
+    /// channels of the plex it was quantified in
+    pub fn write_msstats_tmt<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let format = TableFormat::new(Layout::PeptideLevel).delimiter(',');
+        format.write_row(
+            &mut w,
+            &COLUMNS.iter().map(|c| c.to_string()).collect::<Vec<_>>(),
+        )?;
+
+        let names = self.channel_names();
+        let info = |c: usize| self.channel_info.get(c);
+        let plex = |c: usize| info(c).and_then(|i| i.plex).unwrap_or(1);
+        let channel = |c: usize| match info(c) {
+            Some(i) if !i.label.is_empty() => i.label.clone(),
+            _ => names.get(c).cloned().unwrap_or_default(),
+        };
+        let condition = |c: usize| {
+            info(c)
+                .and_then(|i| i.condition.clone())
+                .unwrap_or_else(|| "NA".into())
+        };
+        let replicate = |c: usize| match info(c) {
+            Some(ChannelInfo {
+                sample: Some(sample),
+                ..
+            }) => sample.clone(),
+            Some(ChannelInfo {
+                condition: Some(cond),
+                replicate: Some(rep),
+                ..
+            }) => format!("{}_{}", cond, rep),
+            _ => "NA".into(),
+        };
+
+        for prot in &self.proteins {
+            for pep in &prot.peptides {
+                let (_, sequence, _) = modification::split_flanks(&pep.sequence);
+                let psm = format!("{}_{}_{}", sequence, pep.charge, pep.scan);
+                let mixture = (0..pep.values.len())
+                    .find(|&c| !pep.is_missing(c))
+                    .map(plex)
+                    .unwrap_or(1);
+                for (c, value) in pep.values.iter().enumerate() {
+                    if plex(c) != mixture {
+                        continue;
+                    }
+                    let run = if pep.file_name.is_empty() {
+                        mixture.to_string()
+                    } else {
+                        pep.file_name.clone()
+                    };
+                    let row = [
+                        prot.accession.clone(),
+                        sequence.to_string(),
+                        pep.charge.to_string(),
+                        psm.clone(),
+                        mixture.to_string(),
+                        "1".into(),
+                        run,
+                        channel(c),
+                        condition(c),
+                        replicate(c),
+                        if value.is_nan() {
+                            "NA".into()
+                        } else {
+                            value.to_string()
+                        },
+                    ];
+                    format.write_row(&mut w, &row)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn msstats_tmt() {
+        let plex = |values: Vec<f64>| {
+            Dataset::builder()
+                .protein(
+                    Protein::builder("P1")
+                        .peptide(
+                            Peptide::builder("K.AAK.L")
+                                .values(values)
+                                .charge(2)
+                                .scan(10)
+                                .file_name("run1")
+                                .build(),
+                        )
+                        .build()
+                        .unwrap(),
+                )
+                .build()
+                .unwrap()
+        };
+        let mut a = plex(vec![100.0, f64::NAN]);
+        a.annotate_channels("channel\tcondition\treplicate\n126\tctrl\t1\n127\ttreated\t1\n")
+            .unwrap();
+        let mut b = plex(vec![300.0, 400.0]);
+        b.annotate_channels("channel\tsample\tcondition\n126\tS3\tctrl\n127\tS4\ttreated\n")
+            .unwrap();
+        let mut merged = Dataset::merge(vec![a, b]).unwrap();
+
+        let mut buf = Vec::new();
+        merged.write_msstats_tmt(&mut buf).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        let lines = text.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], COLUMNS.join(","));
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[1], "P1,AAK,2,AAK_2_10,1,1,run1,126,ctrl,ctrl_1,100");
+        assert_eq!(
+            lines[2],
+            "P1,AAK,2,AAK_2_10,1,1,run1,127,treated,treated_1,NA"
+        );
+        assert_eq!(lines[3], "P1,AAK,2,AAK_2_10,2,1,run1,126,ctrl,S3,300");
+        assert_eq!(lines[4], "P1,AAK,2,AAK_2_10,2,1,run1,127,treated,S4,400");
+
+        merged.channel_info[3].label.clear();
+        let mut buf = Vec::new();
+        merged.write_msstats_tmt(&mut buf).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        assert!(text.ends_with(",run1,channel_4,treated,S4,400\n"));
+    }
+}
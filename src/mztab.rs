@@ -0,0 +1,801 @@
+//! Import and export of the HUPO-PSI mzTab (version 1.0) format
+//!
+//! Proteins are written to the PRT section, with the summed reporter ion
+//! intensities of each channel reported as `protein_abundance_assay[n]`,
+//! on the same scale as the dataset (see `Rollup::Sum`).
+//! Peptides are written to the PSM section, carrying their reporter ion
+//! intensities in `opt_assay[n]_reporter_intensity` columns so that no
+//! PSM-level quantification is lost.
+//!
+//! The metadata section carries the fields mzTab 1.0 requires of a Summary
+//! Quantification file, and each channel is reported as its own study
+//! variable.
+//!
+//! On import, PSM rows are attached to the protein matching their accession.
+//! If a file has no PSM rows, peptide-level quantification is read from the
+//! PEP section instead.
+use super::*;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::{self, Write};
+
+/// Column indices of a PRH, PEH or PSH header line
+struct Columns(HashMap<String, usize>);
+
+impl Columns {
+    fn new(fields: &[&str]) -> Columns {
+        Columns(
+            fields
+                .iter()
+                .enumerate()
+                .map(|(i, f)| (f.to_string(), i))
+                .collect(),
+        )
+    }
+
+    /// Return the value of `column`, treating "null" as absent
+    fn get<'a>(&self, fields: &[&'a str], column: &str) -> Option<&'a str> {
+        self.0
+            .get(column)
+            .and_then(|&i| fields.get(i))
+            .copied()
+            .filter(|s| *s != "null" && !s.is_empty())
+    }
+
+    /// Return the positions of `{prefix}{n}{suffix}` columns, ordered by n
+    fn assays(&self, prefix: &str, suffix: &str) -> Vec<usize> {
+        let mut assays = self
+            .0
+            .iter()
+            .filter_map(|(name, &i)| {
+                name.strip_prefix(prefix)?
+                    .strip_suffix(suffix)?
+                    .parse::<usize>()
+                    .ok()
+                    .map(|n| (n, i))
+            })
+            .collect::<Vec<_>>();
+        assays.sort_unstable();
+        assays.into_iter().map(|(_, i)| i).collect()
+    }
+}
+
+/// Unimod accessions of modifications annotated by name, e.g. "M[Oxidation]"
+const UNIMOD: [(&str, u32); 9] = [
+    ("Acetyl", 1),
+    ("Carbamidomethyl", 4),
+    ("Deamidated", 7),
+    ("Phospho", 21),
+    ("Methyl", 34),
+    ("Oxidation", 35),
+    ("GlyGly", 121),
+    ("TMT6plex", 737),
+    ("TMTpro", 2016),
+];
+
+/// Return the mzTab identifier of a modification: its Unimod accession if
+/// it is annotated by a known name, or otherwise its mass shift. Markers
+/// such as "*" are given a mass by `markers`
+fn modification_id(m: &Modification, markers: &[(char, f64)]) -> io::Result<String> {
+    let name = match &m.name {
+        None => return Ok(format!("CHEMMOD:{}", m.delta)),
+        Some(name) => name,
+    };
+    if let Some((_, delta)) = markers.iter().find(|(c, _)| name.starts_with(*c)) {
+        return Ok(format!("CHEMMOD:{}", delta));
+    }
+    match UNIMOD.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)) {
+        Some((_, id)) => Ok(format!("UNIMOD:{}", id)),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("no mass or Unimod accession for modification \"{}\"", name),
+        )),
+    }
+}
+
+/// Convert a Census-style sequence ("K.S(79.9663)EMPK.R") into its bare
+/// amino acid sequence, flanking residues, and mzTab modifications string
+fn split_sequence<'s>(
+    sequence: &'s str,
+    markers: &[(char, f64)],
+) -> io::Result<(String, &'s str, &'s str, String)> {
+    let (pre, core, post) = modification::split_flanks(sequence);
+    let (bare, mods) = modification::parse(core);
+    let mods = if mods.is_empty() {
+        "null".into()
+    } else {
+        // Position 0 denotes an N-terminal modification
+        mods.iter()
+            .map(|m| Ok(format!("{}-{}", m.position, modification_id(m, markers)?)))
+            .collect::<io::Result<Vec<_>>>()?
+            .join(",")
+    };
+    Ok((bare, pre, post, mods))
+}
+
+/// Rebuild a Census-style sequence from mzTab sequence, flanking residue
+/// and modification columns
+fn join_sequence(
+    sequence: &str,
+    pre: Option<&str>,
+    post: Option<&str>,
+    mods: Option<&str>,
+) -> String {
+    let mut deltas = mods
+        .unwrap_or("")
+        .split(',')
+        .filter_map(|m| {
+            let (pos, rest) = m.split_once('-')?;
+            let annotation = match rest.strip_prefix("CHEMMOD:") {
+                Some(delta) => format!("({})", delta),
+                None => {
+                    let id = rest.strip_prefix("UNIMOD:")?.parse::<u32>().ok()?;
+                    let (name, _) = UNIMOD.iter().find(|(_, i)| *i == id)?;
+                    format!("[{}]", name)
+                }
+            };
+            Some((pos.parse::<usize>().ok()?, annotation))
+        })
+        .collect::<Vec<_>>();
+    deltas.sort_by_key(|(pos, _)| *pos);
+
+    let mut core = String::new();
+    for (_, annotation) in deltas.iter().filter(|(pos, _)| *pos == 0) {
+        core.push_str(annotation);
+    }
+    for (i, c) in sequence.chars().enumerate() {
+        core.push(c);
+        for (_, annotation) in deltas.iter().filter(|(pos, _)| *pos == i + 1) {
+            core.push_str(annotation);
+        }
+    }
+    format!("{}.{}.{}", pre.unwrap_or("-"), core, post.unwrap_or("-"))
+}
+
+/// Parse an mzTab file into a `Dataset`, treating each assay as a channel.
+/// Files with more than 255 assays fail with `ErrorKind::ChannelCount`
+pub fn from_mztab(input: &str) -> Result<Dataset, Error> {
+    let mut runs: HashMap<String, String> = HashMap::new();
+    let mut proteins: Vec<Protein> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    let mut prh = None;
+    let mut psh = None;
+    let mut peh = None;
+    let mut psms = Vec::new();
+    let mut peps = Vec::new();
+    let mut header = Header::default();
+
+    for (n, line) in input.lines().enumerate() {
+        let fields = line.split('\t').collect::<Vec<_>>();
+        let err = |kind| Error::new(kind, n + 1);
+        match fields[0] {
+            "MTD" => {
+                header.lines.push(line.into());
+                if let (Some(key), Some(value)) = (fields.get(1), fields.get(2)) {
+                    if let Some(run) = key.strip_suffix("-location") {
+                        let name = value.rsplit(['/', '\\']).next().unwrap_or(value);
+                        runs.insert(run.into(), name.into());
+                    }
+                    if *key == "mzTab-version" {
+                        header.version = Some(value.to_string());
+                    }
+                    header.parameters.push((key.to_string(), value.to_string()));
+                }
+            }
+            "PRH" => prh = Some(Columns::new(&fields)),
+            "PSH" => psh = Some((n + 1, Columns::new(&fields))),
+            "PEH" => peh = Some((n + 1, Columns::new(&fields))),
+            "PRT" => {
+                let cols = prh
+                    .as_ref()
+                    .ok_or_else(|| err(ErrorKind::MissingColumn("PRH".into())))?;
+                let accession = cols
+                    .get(&fields, "accession")
+                    .ok_or_else(|| err(ErrorKind::MissingColumn("accession".into())))?;
+                let number = |column| -> Result<Option<f64>, Error> {
+                    cols.get(&fields, column)
+                        .map(|s| s.parse::<f64>().map_err(|_| err(ErrorKind::Conversion)))
+                        .transpose()
+                };
+                index.insert(accession.into(), proteins.len());
+                proteins.push(Protein {
+                    accession: accession.into(),
+                    description: cols.get(&fields, "description").unwrap_or("").into(),
+                    alternatives: cols
+                        .get(&fields, "ambiguity_members")
+                        .map(|m| m.split(',').map(|s| s.trim().to_string()).collect())
+                        .unwrap_or_default(),
+                    sequence_coverage: number("protein_coverage")?.unwrap_or(0.0) as f32 * 100.0,
+                    spectral_count: number("num_psms_ms_run[1]")?.unwrap_or(0.0) as u16,
+                    sequence_count: number("num_peptides_distinct_ms_run[1]")?.unwrap_or(0.0)
+                        as u16,
+                    molecular_weight: number("opt_global_molecular_weight")?.unwrap_or(0.0) as u32,
+                    ..Protein::default()
+                });
+            }
+            "PSM" => psms.push((n + 1, fields)),
+            "PEP" => peps.push((n + 1, fields)),
+            _ => {}
+        }
+    }
+
+    // Prefer PSM-level quantification when it is available
+    let (line, cols, rows, prefix, suffix) = match (psh, peh) {
+        (Some((line, cols)), _) if !psms.is_empty() => {
+            (line, cols, psms, "opt_assay[", "]_reporter_intensity")
+        }
+        (_, Some((line, cols))) => (line, cols, peps, "peptide_abundance_assay[", "]"),
+        _ => (0, Columns(HashMap::new()), Vec::new(), "", ""),
+    };
+    let assays = cols.assays(prefix, suffix);
+    let channels = u8::try_from(assays.len())
+        .map_err(|_| Error::new(ErrorKind::ChannelCount(assays.len()), line))?;
+
+    for (line, fields) in rows {
+        let err = |kind| Error::new(kind, line);
+        let sequence = cols
+            .get(&fields, "sequence")
+            .ok_or_else(|| err(ErrorKind::MissingColumn("sequence".into())))?;
+        let accession = cols
+            .get(&fields, "accession")
+            .ok_or_else(|| err(ErrorKind::MissingColumn("accession".into())))?;
+        let values = assays
+            .iter()
+            .map(|&i| match fields.get(i) {
+                Some(&"null") | Some(&"NaN") | None => Ok(f64::NAN),
+                Some(s) => s.parse::<f64>().map_err(|_| err(ErrorKind::Conversion)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // spectra_ref is formatted as "ms_run[1]:scan=1234"
+        let spectra_ref = cols.get(&fields, "spectra_ref").unwrap_or("");
+        let (run, scan) = spectra_ref.split_once(':').unwrap_or(("", spectra_ref));
+        let scan = scan
+            .rsplit('=')
+            .next()
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap_or(0);
+
+        let peptide = Peptide {
+            sequence: join_sequence(
+                sequence,
+                cols.get(&fields, "pre"),
+                cols.get(&fields, "post"),
+                cols.get(&fields, "modifications"),
+            ),
+            values,
+            unique: cols.get(&fields, "unique") == Some("1"),
+            purity: cols
+                .get(&fields, "opt_global_purity")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(1.0),
+            scan,
+            charge: cols
+                .get(&fields, "charge")
+                .and_then(|s| s.parse::<f64>().ok())
+                .unwrap_or(0.0) as u8,
+            xcorr: cols
+                .get(&fields, "search_engine_score[1]")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(0.0),
+            file_name: runs.get(run).cloned().unwrap_or_default(),
+            ..Peptide::default()
+        };
+
+        let idx = *index.entry(accession.into()).or_insert_with(|| {
+            proteins.push(Protein {
+                accession: accession.into(),
+                ..Protein::default()
+            });
+            proteins.len() - 1
+        });
+        proteins[idx].peptides.push(peptide);
+    }
+
+    for protein in proteins.iter_mut() {
+        protein.channels = channels;
+        protein.annotate();
+        if protein.spectral_count == 0 {
+            protein.spectral_count = protein.peptides.len() as u16;
+        }
+        if protein.sequence_count == 0 {
+            let mut seqs = protein
+                .peptides
+                .iter()
+                .map(|p| &p.sequence)
+                .collect::<Vec<_>>();
+            seqs.sort_unstable();
+            seqs.dedup();
+            protein.sequence_count = seqs.len() as u16;
+        }
+    }
+
+    Ok(Dataset {
+        proteins,
+        channels,
+        header,
+        channel_info: Vec::new(),
+        scale: Scale::Linear,
+        ..Dataset::default()
+    })
+}
+
+impl Dataset {
+    /// Write the dataset in mzTab 1.0 "Summary" quantification format.
+    ///
+    /// Modifications annotated by a marker, such as "M*", have no mass in
+    /// the sequence and fail with `io::ErrorKind::InvalidData`; give their
+    /// masses with `Dataset::to_mztab_with_markers`
+    pub fn to_mztab<W: Write>(&self, w: W) -> io::Result<()> {
+        self.to_mztab_with_markers(w, &[])
+    }
+
+    /// Write the dataset in mzTab 1.0 "Summary" quantification format, with
+    /// the mass shift of each modification marker given by `markers`, e.g.
+    /// `[('*', 15.9949), ('#', 79.9663)]`
+    pub fn to_mztab_with_markers<W: Write>(
+        &self,
+        mut w: W,
+        markers: &[(char, f64)],
+    ) -> io::Result<()> {
+        let channels = self.channels as usize;
+
+        // Each distinct source file is reported as a separate MS run
+        let mut runs: Vec<&str> = Vec::new();
+        for pep in self.proteins.iter().flat_map(|p| &p.peptides) {
+            if !pep.file_name.is_empty() && !runs.contains(&pep.file_name.as_str()) {
+                runs.push(&pep.file_name);
+            }
+        }
+        let run_index = |name: &str| runs.iter().position(|r| *r == name).unwrap_or(0) + 1;
+
+        // Modifications are checked before anything is written
+        let mut sequences = HashMap::new();
+        let mut variable_mods: Vec<String> = Vec::new();
+        for pep in self.proteins.iter().flat_map(|p| &p.peptides) {
+            if sequences.contains_key(pep.sequence.as_str()) {
+                continue;
+            }
+            let split = split_sequence(&pep.sequence, markers)?;
+            for id in split.3.split(',').filter(|id| *id != "null") {
+                let id = id.split_once('-').map_or(id, |(_, id)| id);
+                if !variable_mods.iter().any(|m| m == id) {
+                    variable_mods.push(id.into());
+                }
+            }
+            sequences.insert(pep.sequence.as_str(), split);
+        }
+
+        writeln!(w, "MTD\tmzTab-version\t1.0.0")?;
+        writeln!(w, "MTD\tmzTab-mode\tSummary")?;
+        writeln!(w, "MTD\tmzTab-type\tQuantification")?;
+        writeln!(w, "MTD\tdescription\tCensus isobaric quantification")?;
+        if runs.is_empty() {
+            writeln!(w, "MTD\tms_run[1]-location\tnull")?;
+        }
+        for (i, run) in runs.iter().enumerate() {
+            writeln!(w, "MTD\tms_run[{}]-location\tfile://{}", i + 1, run)?;
+        }
+        writeln!(
+            w,
+            "MTD\tprotein_search_engine_score[1]\t[MS, MS:1001153, search engine specific score, ]"
+        )?;
+        writeln!(
+            w,
+            "MTD\tpsm_search_engine_score[1]\t[MS, MS:1001155, SEQUEST:xcorr, ]"
+        )?;
+        writeln!(
+            w,
+            "MTD\tsoftware[1]\t[MS, MS:1001456, analysis software, census-proteomics {}]",
+            env!("CARGO_PKG_VERSION")
+        )?;
+        writeln!(
+            w,
+            "MTD\tfixed_mod[1]\t[MS, MS:1002453, No fixed modifications searched, ]"
+        )?;
+        if variable_mods.is_empty() {
+            writeln!(
+                w,
+                "MTD\tvariable_mod[1]\t[MS, MS:1002454, No variable modifications searched, ]"
+            )?;
+        }
+        for (i, id) in variable_mods.iter().enumerate() {
+            match id.strip_prefix("CHEMMOD:") {
+                Some(delta) => writeln!(
+                    w,
+                    "MTD\tvariable_mod[{}]\t[MS, MS:1001460, unknown modification, {}]",
+                    i + 1,
+                    delta
+                )?,
+                None => {
+                    let name = id
+                        .strip_prefix("UNIMOD:")
+                        .and_then(|n| n.parse::<u32>().ok())
+                        .and_then(|n| UNIMOD.iter().find(|(_, i)| *i == n))
+                        .map_or("", |(name, _)| name);
+                    writeln!(
+                        w,
+                        "MTD\tvariable_mod[{}]\t[UNIMOD, {}, {}, ]",
+                        i + 1,
+                        id,
+                        name
+                    )?
+                }
+            }
+        }
+        writeln!(
+            w,
+            "MTD\tquantification_method\t[MS, MS:1002010, TMT quantitation analysis, ]"
+        )?;
+        writeln!(
+            w,
+            "MTD\tprotein-quantification_unit\t[PRIDE, PRIDE:0000393, Relative quantification unit, ]"
+        )?;
+        let run_refs = (1..=runs.len().max(1))
+            .map(|i| format!("ms_run[{}]", i))
+            .collect::<Vec<_>>()
+            .join(",");
+        let labels = self.channel_names();
+        for (i, label) in (1..).zip(&labels) {
+            writeln!(
+                w,
+                "MTD\tassay[{}]-quantification_reagent\t[MS, MS:1002616, TMT reagent, {}]",
+                i, label
+            )?;
+            writeln!(w, "MTD\tassay[{}]-ms_run_ref\t{}", i, run_refs)?;
+        }
+        for (i, label) in (1..).zip(&labels) {
+            let info = self.channel_info.get(i - 1);
+            let description = info
+                .and_then(|c| c.sample.as_deref().or(c.condition.as_deref()))
+                .unwrap_or(label);
+            writeln!(w, "MTD\tstudy_variable[{}]-assay_refs\tassay[{}]", i, i)?;
+            writeln!(w, "MTD\tstudy_variable[{}]-description\t{}", i, description)?;
+        }
+        writeln!(w)?;
+
+        let assays = |prefix: &str, suffix: &str| {
+            (1..=channels)
+                .map(|i| format!("\t{}{}{}", prefix, i, suffix))
+                .collect::<String>()
+        };
+        let values = |values: &[f64]| {
+            values
+                .iter()
+                .map(|v| {
+                    if v.is_nan() {
+                        "\tnull".into()
+                    } else {
+                        format!("\t{}", v)
+                    }
+                })
+                .collect::<String>()
+        };
+        let nulls = "\tnull".repeat(channels);
+
+        writeln!(
+            w,
+            "PRH\taccession\tdescription\ttaxid\tspecies\tdatabase\tdatabase_version\t\
+             search_engine\tbest_search_engine_score[1]\tambiguity_members\tmodifications\t\
+             protein_coverage\tnum_psms_ms_run[1]\tnum_peptides_distinct_ms_run[1]{}{}{}{}\t\
+             opt_global_molecular_weight",
+            assays("protein_abundance_assay[", "]"),
+            assays("protein_abundance_study_variable[", "]"),
+            assays("protein_abundance_stdev_study_variable[", "]"),
+            assays("protein_abundance_std_error_study_variable[", "]"),
+        )?;
+        for prot in &self.proteins {
+            let total = values(&prot.quantify_scaled(Rollup::Sum, self.scale));
+            writeln!(
+                w,
+                "PRT\t{}\t{}\tnull\tnull\tnull\tnull\tnull\tnull\t{}\tnull\t{}\t{}\t{}{}{}{}{}\t{}",
+                prot.accession,
+                prot.description,
+                if prot.alternatives.is_empty() {
+                    "null".into()
+                } else {
+                    prot.alternatives.join(",")
+                },
+                prot.sequence_coverage / 100.0,
+                prot.spectral_count,
+                prot.sequence_count,
+                total,
+                total,
+                nulls,
+                nulls,
+                prot.molecular_weight
+            )?;
+        }
+        writeln!(w)?;
+
+        writeln!(
+            w,
+            "PSH\tsequence\tPSM_ID\taccession\tunique\tdatabase\tdatabase_version\t\
+             search_engine\tsearch_engine_score[1]\tmodifications\tretention_time\tcharge\t\
+             exp_mass_to_charge\tcalc_mass_to_charge\tspectra_ref\tpre\tpost\tstart\tend{}\t\
+             opt_global_purity",
+            assays("opt_assay[", "]_reporter_intensity")
+        )?;
+        let mut id = 0;
+        for prot in &self.proteins {
+            for pep in &prot.peptides {
+                id += 1;
+                let (bare, pre, post, mods) = &sequences[pep.sequence.as_str()];
+                writeln!(
+                    w,
+                    "PSM\t{}\t{}\t{}\t{}\tnull\tnull\tnull\t{}\t{}\tnull\t{}\tnull\tnull\t\
+                     ms_run[{}]:scan={}\t{}\t{}\tnull\tnull{}\t{}",
+                    bare,
+                    id,
+                    prot.accession,
+                    pep.unique as u8,
+                    pep.xcorr,
+                    mods,
+                    pep.charge,
+                    run_index(&pep.file_name),
+                    pep.scan,
+                    pre,
+                    post,
+                    values(&pep.values),
+                    pep.purity
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sequence_conversion() {
+        let (bare, pre, post, mods) = split_sequence("K.S(79.9663)EM(15.9949)PK.R", &[]).unwrap();
+        assert_eq!(bare, "SEMPK");
+        assert_eq!((pre, post), ("K", "R"));
+        assert_eq!(mods, "1-CHEMMOD:79.9663,3-CHEMMOD:15.9949");
+        assert_eq!(
+            join_sequence(&bare, Some(pre), Some(post), Some(&mods)),
+            "K.S(79.9663)EM(15.9949)PK.R"
+        );
+
+        let markers = [('*', 15.9949)];
+        let (bare, _, _, mods) = split_sequence("R.AM*S[Phospho]K.L", &markers).unwrap();
+        assert_eq!(mods, "2-CHEMMOD:15.9949,3-UNIMOD:21");
+        assert_eq!(
+            join_sequence(&bare, None, None, Some(&mods)),
+            "-.AM(15.9949)S[Phospho]K.-"
+        );
+        assert!(split_sequence("R.AM*SK.L", &[]).is_err());
+    }
+
+    #[test]
+    fn required_fields() {
+        let dataset = Dataset {
+            channels: 2,
+            channel_info: vec![
+                ChannelInfo {
+                    label: "126".into(),
+                    sample: Some("ctrl".into()),
+                    ..ChannelInfo::default()
+                },
+                ChannelInfo {
+                    label: "127N".into(),
+                    ..ChannelInfo::default()
+                },
+            ],
+            ..Dataset::default()
+        }
+        .with_proteins(vec![Protein {
+            accession: "P12345".into(),
+            channels: 2,
+            peptides: vec![Peptide {
+                sequence: "K.LVNEM(15.9949)TEFAK.T".into(),
+                values: vec![1.0, 2.0],
+                ..Peptide::default()
+            }],
+            ..Protein::default()
+        }]);
+        let mut buf = Vec::new();
+        dataset.to_mztab(&mut buf).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+
+        let metadata = text
+            .lines()
+            .filter_map(|l| l.strip_prefix("MTD\t"))
+            .filter_map(|l| l.split_once('\t'))
+            .collect::<HashMap<_, _>>();
+        for key in [
+            "mzTab-version",
+            "mzTab-mode",
+            "mzTab-type",
+            "description",
+            "ms_run[1]-location",
+            "protein_search_engine_score[1]",
+            "psm_search_engine_score[1]",
+            "software[1]",
+            "fixed_mod[1]",
+            "variable_mod[1]",
+            "quantification_method",
+            "protein-quantification_unit",
+            "assay[2]-quantification_reagent",
+            "assay[2]-ms_run_ref",
+            "study_variable[2]-assay_refs",
+            "study_variable[2]-description",
+        ] {
+            assert!(metadata.contains_key(key), "missing {}", key);
+        }
+        assert_eq!(metadata["study_variable[1]-description"], "ctrl");
+        assert_eq!(metadata["study_variable[2]-description"], "127N");
+        assert_eq!(
+            metadata["variable_mod[1]"],
+            "[MS, MS:1001460, unknown modification, 15.9949]"
+        );
+        // Every parameter names its controlled vocabulary and accession
+        for value in metadata.values().filter(|v| v.starts_with('[')) {
+            let fields = value
+                .trim_matches(['[', ']'])
+                .split(',')
+                .collect::<Vec<_>>();
+            assert_eq!(fields.len(), 4, "{}", value);
+            assert!(
+                fields[..3].iter().all(|f| !f.trim().is_empty()),
+                "{}",
+                value
+            );
+        }
+
+        let section = |header: &str, row: &str| {
+            let columns = text
+                .lines()
+                .find(|l| l.starts_with(header))
+                .unwrap()
+                .split('\t')
+                .collect::<Vec<_>>();
+            for line in text.lines().filter(|l| l.starts_with(row)) {
+                assert_eq!(line.split('\t').count(), columns.len());
+            }
+            columns
+        };
+        let prh = section("PRH", "PRT");
+        for column in [
+            "accession",
+            "description",
+            "taxid",
+            "species",
+            "database",
+            "database_version",
+            "search_engine",
+            "best_search_engine_score[1]",
+            "ambiguity_members",
+            "modifications",
+            "protein_abundance_study_variable[2]",
+            "protein_abundance_stdev_study_variable[2]",
+            "protein_abundance_std_error_study_variable[2]",
+        ] {
+            assert!(prh.contains(&column), "missing {}", column);
+        }
+        let psh = section("PSH", "PSM");
+        for column in [
+            "sequence",
+            "PSM_ID",
+            "accession",
+            "unique",
+            "search_engine_score[1]",
+            "modifications",
+            "retention_time",
+            "charge",
+            "exp_mass_to_charge",
+            "calc_mass_to_charge",
+            "spectra_ref",
+            "pre",
+            "post",
+            "start",
+            "end",
+        ] {
+            assert!(psh.contains(&column), "missing {}", column);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let pep = |seq: &str, values: Vec<f64>, scan| Peptide {
+            sequence: seq.into(),
+            values,
+            unique: true,
+            purity: 0.9,
+            scan,
+            charge: 2,
+            xcorr: 3.5,
+            file_name: "run1.ms2".into(),
+            ..Peptide::default()
+        };
+        let dataset = Dataset {
+            channels: 2,
+            proteins: vec![Protein {
+                accession: "P12345".into(),
+                description: "Serum albumin".into(),
+                alternatives: vec!["P12345-2".into()],
+                spectral_count: 2,
+                sequence_count: 2,
+                sequence_coverage: 12.5,
+                molecular_weight: 69367,
+                channels: 2,
+                peptides: vec![
+                    pep("K.LVNEVTEFAK.T", vec![1000.0, 3000.0], 1234),
+                    pep("R.M(15.9949)ELFEK.L", vec![10.5, f64::NAN], 1301),
+                ],
+                ..Protein::default()
+            }],
+            ..Dataset::default()
+        };
+
+        let mut buf = Vec::new();
+        dataset.to_mztab(&mut buf).unwrap();
+        let parsed = from_mztab(std::str::from_utf8(&buf).unwrap()).unwrap();
+
+        assert_eq!(parsed.channels, 2);
+        assert_eq!(parsed.header.version.as_deref(), Some("1.0.0"));
+        let prot = &parsed.proteins[0];
+        assert_eq!(prot.accession, "P12345");
+        assert_eq!(prot.description, "Serum albumin");
+        assert_eq!(prot.alternatives, vec!["P12345-2"]);
+        assert_eq!(prot.spectral_count, 2);
+        assert_eq!(prot.molecular_weight, 69367);
+        assert_eq!(prot.peptides.len(), 2);
+        assert_eq!(prot.peptides[0], dataset.proteins[0].peptides[0]);
+        assert_eq!(prot.peptides[1].sequence, "R.M(15.9949)ELFEK.L");
+        assert!(prot.peptides[1].values[1].is_nan());
+    }
+
+    #[test]
+    fn too_many_assays() {
+        let assays = (1..=300)
+            .map(|i| format!("\tpeptide_abundance_assay[{}]", i))
+            .collect::<String>();
+        let input = format!(
+            "MTD\tmzTab-version\t1.0.0\nPEH\tsequence\taccession{}\nPEP\tAAK\tP1\n",
+            assays
+        );
+        let err = from_mztab(&input).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::ChannelCount(300));
+        assert_eq!(err.line(), 2);
+    }
+
+    #[test]
+    fn log2_protein_abundance() {
+        let pep = |values| Peptide {
+            sequence: "K.LVNEVTEFAK.T".into(),
+            values,
+            ..Peptide::default()
+        };
+        let mut dataset = Dataset {
+            channels: 2,
+            proteins: vec![Protein {
+                accession: "P12345".into(),
+                channels: 2,
+                peptides: vec![pep(vec![1000.0, 3000.0]), pep(vec![500.0, f64::NAN])],
+                ..Protein::default()
+            }],
+            ..Dataset::default()
+        };
+        dataset.log2_transform(1.0);
+
+        let mut buf = Vec::new();
+        dataset.to_mztab(&mut buf).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        let header = text.lines().find(|l| l.starts_with("PRH")).unwrap();
+        let row = text.lines().find(|l| l.starts_with("PRT")).unwrap();
+        let column = |name| header.split('\t').position(|h| h == name).unwrap();
+        let field = |name| {
+            row.split('\t')
+                .nth(column(name))
+                .unwrap()
+                .parse::<f64>()
+                .unwrap()
+        };
+        assert!((field("protein_abundance_assay[1]") - 1501f64.log2()).abs() < 1e-9);
+        assert!((field("protein_abundance_assay[2]") - 3001f64.log2()).abs() < 1e-9);
+    }
+}
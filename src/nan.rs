@@ -0,0 +1,70 @@
+//! Serde helpers for floating point fields that use `NaN` for missing
+//! values.
+//!
+//! `NaN` is written as `None`, which self-describing formats such as JSON
+//! represent as `null` (serde_json writes `NaN` as `null`, but cannot read it
+//! back as a float). Binary formats see an `Option<f64>` in both directions.
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+fn to_option(x: f64) -> Option<f64> {
+    Some(x).filter(|x| !x.is_nan())
+}
+
+pub fn serialize<S: Serializer>(x: &f64, s: S) -> Result<S::Ok, S::Error> {
+    to_option(*x).serialize(s)
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<f64, D::Error> {
+    Ok(Option::<f64>::deserialize(d)?.unwrap_or(f64::NAN))
+}
+
+/// As the parent module, for `Vec<f64>` fields
+pub mod vec {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(values: &[f64], s: S) -> Result<S::Ok, S::Error> {
+        s.collect_seq(values.iter().map(|x| to_option(*x)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<f64>, D::Error> {
+        Ok(Vec::<Option<f64>>::deserialize(d)?
+            .into_iter()
+            .map(|x| x.unwrap_or(f64::NAN))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::*;
+
+    #[test]
+    fn json_round_trip() {
+        let mut data = Dataset {
+            channels: 2,
+            proteins: vec![Protein {
+                accession: "P12345".into(),
+                description: "Serum albumin OS=Homo sapiens GN=ALB".into(),
+                channels: 2,
+                peptides: vec![Peptide {
+                    sequence: "K.LVNEVTEFAK.T".into(),
+                    values: vec![1000.0, f64::NAN],
+                    retention_time: Some(45.1),
+                    ..Peptide::default()
+                }],
+                ..Protein::default()
+            }],
+            ..Dataset::default()
+        };
+        data.proteins[0].annotate();
+        let json = serde_json::to_string(&data).unwrap();
+        assert!(json.contains("[1000.0,null]"));
+
+        let parsed: Dataset = serde_json::from_str(&json).unwrap();
+        let values = &parsed.proteins[0].peptides[0].values;
+        assert_eq!(values[0], 1000.0);
+        assert!(values[1].is_nan());
+        assert_eq!(parsed.proteins[0].gene.as_deref(), Some("ALB"));
+        assert_eq!(parsed.get("P12345").unwrap().peptides.len(), 1);
+    }
+}
@@ -0,0 +1,558 @@
+//! Normalization of reporter ion intensities
+//!
+//! Channels are 0 indexed, as in `Peptide::values`. Missing intensities
+//! (`NaN`) are ignored when calculating scaling factors, and remain missing
+//! after scaling.
+use super::*;
+use std::collections::HashMap;
+
+/// Multiply every peptide intensity of `channel` by `factor`
+fn scale_channel(prot: &mut Protein, channel: usize, factor: f64) {
+    for pep in prot.peptides.iter_mut() {
+        if let Some(v) = pep.values.get_mut(channel) {
+            *v *= factor;
+        }
+    }
+}
+
+/// Internal Reference Scaling (IRS) of several TMT plexes that share one or
+/// more bridge (reference) channels, as described by Plubell et al. (2017).
+///
+/// `references[i]` lists the reference channels of `plexes[i]`. For each
+/// protein, the mean of its summed intensity in the reference channels of a
+/// plex is compared to the geometric mean of that value across all plexes,
+/// and every channel of the protein in that plex is scaled by the ratio of
+/// the two. Afterwards, the reference channels of each protein agree across
+/// plexes, and the remaining channels are comparable.
+///
+/// Only proteins quantified in the reference channels of every plex can be
+/// scaled. Other proteins are left unchanged, and should usually be removed
+/// before comparing plexes. Plexes on log2 scale are scaled as linear
+/// intensities, and stay on log2 scale. Returns the scaling factor of each
+/// plex for every scaled protein, by accession
+///
+/// # Panics
+///
+/// Panics if `references` and `plexes` differ in length
+pub fn irs(plexes: &mut [Dataset], references: &[Vec<usize>]) -> HashMap<String, Vec<f64>> {
+    assert_eq!(
+        plexes.len(),
+        references.len(),
+        "one set of reference channels is required per plex"
+    );
+
+    let scales = plexes.iter().map(|data| data.scale).collect::<Vec<_>>();
+    plexes.iter_mut().for_each(Dataset::expm2);
+
+    // Reference intensity of each protein in each plex
+    let mut reference: HashMap<String, Vec<f64>> = HashMap::new();
+    for (plex, (data, channels)) in plexes.iter().zip(references).enumerate() {
+        for prot in &data.proteins {
+            let total = prot.total();
+            let values = channels
+                .iter()
+                .map(|&c| total.get(c).copied().unwrap_or(f64::NAN))
+                .collect::<Vec<_>>();
+            let entry = reference
+                .entry(prot.accession.clone())
+                .or_insert_with(|| vec![f64::NAN; plexes.len()]);
+            entry[plex] = util::mean(&values);
+        }
+    }
+
+    let factors = reference
+        .into_iter()
+        .filter(|(_, refs)| refs.iter().all(|r| *r > 0.0))
+        .map(|(accession, refs)| {
+            let geomean = (refs.iter().map(|r| r.ln()).sum::<f64>() / refs.len() as f64).exp();
+            let factors = refs.iter().map(|r| geomean / r).collect::<Vec<_>>();
+            (accession, factors)
+        })
+        .collect::<HashMap<_, _>>();
+
+    for (plex, data) in plexes.iter_mut().enumerate() {
+        for prot in data.proteins.iter_mut() {
+            if let Some(f) = factors.get(&prot.accession) {
+                for c in 0..prot.channels as usize {
+                    scale_channel(prot, c, f[plex]);
+                }
+            }
+        }
+    }
+    for (data, scale) in plexes.iter_mut().zip(scales) {
+        if let Scale::Log2(pseudocount) = scale {
+            data.log2_transform(pseudocount);
+        }
+    }
+    factors
+}
+
+/// Channel normalization method, see `Dataset::normalize`
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Method {
+    /// Scale each channel so that its median peptide intensity equals the
+    /// mean of the nonzero channel medians
+    Median,
+    /// Replace each peptide intensity with the mean, across channels, of
+    /// the intensity at the same quantile, so that every channel has the
+    /// same distribution
+    Quantile,
+    /// Scale each channel so that its summed peptide intensity equals the
+    /// mean of the channel sums, ignoring channels that sum to zero
+    TotalIntensity,
+}
+
+/// Return the value at quantile `q` (0 to 1) of sorted `values`, linearly
+/// interpolating between neighbouring values
+fn quantile(values: &[f64], q: f64) -> f64 {
+    match values.len() {
+        0 => f64::NAN,
+        1 => values[0],
+        n => {
+            let pos = q * (n - 1) as f64;
+            let (lo, hi) = (pos.floor() as usize, pos.ceil() as usize);
+            values[lo] + (values[hi] - values[lo]) * (pos - lo as f64)
+        }
+    }
+}
+
+impl Dataset {
+    /// Return the intensities of every peptide in `channel`
+    fn channel_values(&self, channel: usize) -> Vec<f64> {
+        self.proteins
+            .iter()
+            .flat_map(|prot| prot.peptides.iter())
+            .map(|pep| pep.values.get(channel).copied().unwrap_or(f64::NAN))
+            .collect()
+    }
+
+    /// Equalize the distributions of peptide intensities across channels in
+    /// place, returning the scaling factor applied to each channel.
+    ///
+    /// Quantile normalization does not scale channels uniformly, so the
+    /// factor reported for it is the ratio of the channel's summed
+    /// intensity after normalization to that before. Channels without any
+    /// intensities are left unchanged, with a factor of 1.
+    ///
+    /// A dataset on log2 scale is normalized as linear intensities, and
+    /// stays on log2 scale
+    pub fn normalize(&mut self, method: Method) -> Vec<f64> {
+        if let Scale::Log2(pseudocount) = self.scale {
+            self.expm2();
+            let factors = self.normalize(method);
+            self.log2_transform(pseudocount);
+            return factors;
+        }
+        let channels = self.channels as usize;
+        let statistic = match method {
+            Method::Median => util::median,
+            Method::TotalIntensity | Method::Quantile => util::sum,
+        };
+        let before = (0..channels)
+            .map(|c| statistic(&self.channel_values(c)))
+            .collect::<Vec<_>>();
+
+        if method == Method::Quantile {
+            let mut sorted = (0..channels)
+                .map(|c| {
+                    let mut values = self.channel_values(c);
+                    values.retain(|v| !v.is_nan());
+                    values.sort_by(|a, b| a.total_cmp(b));
+                    values
+                })
+                .collect::<Vec<_>>();
+            sorted.retain(|values| !values.is_empty());
+            let target =
+                |q: f64| util::mean(&sorted.iter().map(|v| quantile(v, q)).collect::<Vec<_>>());
+
+            for c in 0..channels {
+                let values = self.channel_values(c);
+                let mut order = (0..values.len())
+                    .filter(|&i| !values[i].is_nan())
+                    .collect::<Vec<_>>();
+                order.sort_by(|&a, &b| values[a].total_cmp(&values[b]));
+                let mut normalized = values.clone();
+                let n = order.len();
+                for (rank, &i) in order.iter().enumerate() {
+                    let q = if n > 1 {
+                        rank as f64 / (n - 1) as f64
+                    } else {
+                        0.5
+                    };
+                    normalized[i] = target(q);
+                }
+                let mut normalized = normalized.into_iter();
+                for pep in self.proteins.iter_mut().flat_map(|p| p.peptides.iter_mut()) {
+                    let value = normalized.next().unwrap_or(f64::NAN);
+                    if let Some(v) = pep.values.get_mut(c) {
+                        *v = value;
+                    }
+                }
+            }
+            return (0..channels)
+                .map(|c| util::sum(&self.channel_values(c)) / before[c])
+                .map(|f| if f.is_finite() { f } else { 1.0 })
+                .collect();
+        }
+
+        // Empty channels (a sum or median of zero, or missing) have no
+        // meaningful scale, and would drag the target towards zero
+        let target = util::mean(
+            &before
+                .iter()
+                .copied()
+                .filter(|&b| b.is_finite() && b != 0.0)
+                .collect::<Vec<_>>(),
+        );
+        let factors = before
+            .iter()
+            .map(|b| target / b)
+            .map(|f| if f.is_finite() { f } else { 1.0 })
+            .collect::<Vec<_>>();
+        for prot in self.proteins.iter_mut() {
+            for (c, &f) in factors.iter().enumerate() {
+                scale_channel(prot, c, f);
+            }
+        }
+        factors
+    }
+
+    /// Return a copy of the dataset normalized with `method`, along with the
+    /// scaling factor of each channel, see `Dataset::normalize`
+    pub fn normalized(mut self, method: Method) -> (Dataset, Vec<f64>) {
+        let factors = self.normalize(method);
+        (self, factors)
+    }
+}
+
+/// Handling of peptides whose reference channel intensity is zero or
+/// missing, see `Dataset::to_ratios`
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum ZeroPolicy {
+    /// Every ratio of the peptide is missing (`NaN`)
+    Missing,
+    /// Remove the peptide, and any protein left without peptides
+    Drop,
+    /// Add a pseudocount to every intensity before taking ratios
+    Pseudocount(f64),
+}
+
+impl Dataset {
+    /// Return a copy of the dataset in which the intensities of every
+    /// peptide are replaced by ratios versus `reference` (0 indexed), so
+    /// that the reference channel itself has a ratio of 1. This is the
+    /// usual representation of bridge channel designs.
+    ///
+    /// Census-normalized values are discarded, since they no longer
+    /// correspond to the intensities. A dataset on log2 scale is converted
+    /// back to linear intensities first, so ratios are always linear. With
+    /// `ZeroPolicy::Drop`, the spectral and sequence counts of proteins that
+    /// lose peptides are recounted from the remaining peptides.
+    ///
+    /// Fails with `DatasetError::ChannelOutOfRange` if `reference` is not a
+    /// channel of the dataset
+    pub fn to_ratios(&self, reference: usize, policy: ZeroPolicy) -> Result<Dataset, DatasetError> {
+        if reference >= self.channels as usize {
+            return Err(DatasetError::ChannelOutOfRange(reference, self.channels));
+        }
+        if self.scale != Scale::Linear {
+            let mut linear = self.clone();
+            linear.expm2();
+            return linear.to_ratios(reference, policy);
+        }
+        let offset = match policy {
+            ZeroPolicy::Pseudocount(n) => n,
+            _ => 0.0,
+        };
+        let mut data = self.clone();
+        for prot in data.proteins.iter_mut() {
+            let before = prot.peptides.len();
+            prot.peptides.retain_mut(|pep| {
+                pep.norm_values.clear();
+                let denom = pep.values.get(reference).copied().unwrap_or(f64::NAN) + offset;
+                if denom == 0.0 || denom.is_nan() {
+                    if policy == ZeroPolicy::Drop {
+                        return false;
+                    }
+                    pep.values.iter_mut().for_each(|v| *v = f64::NAN);
+                } else {
+                    pep.values
+                        .iter_mut()
+                        .for_each(|v| *v = (*v + offset) / denom);
+                }
+                true
+            });
+            if prot.peptides.len() != before {
+                filter::recount(prot);
+            }
+        }
+        if policy == ZeroPolicy::Drop {
+            data.proteins.retain(|prot| !prot.peptides.is_empty());
+        }
+        Ok(data)
+    }
+}
+
+impl Dataset {
+    /// Remove batch effects from a merged multi-plex dataset by median
+    /// centering each protein within each batch, in place.
+    ///
+    /// Batches are the `ChannelInfo::plex` annotations of the channels (set
+    /// by `Dataset::merge` when every plex is annotated). For each protein,
+    /// the median log2 protein intensity (see the `stats` module) of every
+    /// batch is shifted to the mean of the batch medians, by scaling the
+    /// peptide intensities of that batch's channels. Batches in which the
+    /// protein is not quantified are left unchanged.
+    ///
+    /// Returns the log2 shift applied to each batch (in ascending plex
+    /// order) for every protein, by accession, or `None` if any channel has
+    /// no plex annotation
+    pub fn correct_batches(&mut self) -> Option<HashMap<String, Vec<f64>>> {
+        let channels = self.channels as usize;
+        if self.channel_info.len() != channels {
+            return None;
+        }
+        let plexes = self
+            .channel_info
+            .iter()
+            .map(|info| info.plex)
+            .collect::<Option<Vec<_>>>()?;
+        let mut batches = plexes.clone();
+        batches.sort_unstable();
+        batches.dedup();
+        let members = batches
+            .iter()
+            .map(|b| {
+                (0..channels)
+                    .filter(|&c| plexes[c] == *b)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut shifts = HashMap::new();
+        for idx in 0..self.proteins.len() {
+            let values = self.log2_protein(&self.proteins[idx]);
+            let medians = members
+                .iter()
+                .map(|m| {
+                    let v = m
+                        .iter()
+                        .map(|&c| values.get(c).copied().unwrap_or(f64::NAN))
+                        .filter(|v| v.is_finite())
+                        .collect::<Vec<_>>();
+                    util::median(&v)
+                })
+                .collect::<Vec<_>>();
+            let target = util::mean(&medians);
+            let shift = medians
+                .iter()
+                .map(|m| if m.is_nan() { 0.0 } else { target - m })
+                .collect::<Vec<_>>();
+
+            let scale = self.scale;
+            let prot = &mut self.proteins[idx];
+            for (m, &s) in members.iter().zip(&shift) {
+                for &c in m {
+                    match scale {
+                        Scale::Linear => scale_channel(prot, c, s.exp2()),
+                        Scale::Log2(_) => {
+                            for pep in prot.peptides.iter_mut() {
+                                if let Some(v) = pep.values.get_mut(c) {
+                                    *v += s;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            shifts.insert(prot.accession.clone(), shift);
+        }
+        Some(shifts)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn plex(proteins: &[(&str, Vec<f64>)]) -> Dataset {
+        let channels = proteins.first().map_or(3, |(_, values)| values.len() as u8);
+        Dataset {
+            channels,
+            proteins: proteins
+                .iter()
+                .map(|(acc, values)| Protein {
+                    accession: acc.to_string(),
+                    channels,
+                    peptides: vec![Peptide {
+                        values: values.clone(),
+                        ..Peptide::default()
+                    }],
+                    ..Protein::default()
+                })
+                .collect(),
+            header: Header::default(),
+            channel_info: Vec::new(),
+            scale: Scale::Linear,
+            ..Dataset::default()
+        }
+    }
+
+    #[test]
+    fn internal_reference_scaling() {
+        let mut plexes = vec![
+            plex(&[
+                ("P1", vec![100.0, 200.0, 300.0]),
+                ("P2", vec![1.0, 1.0, 1.0]),
+            ]),
+            plex(&[("P1", vec![400.0, 800.0, 1200.0])]),
+        ];
+        let factors = irs(&mut plexes, &[vec![0], vec![0]]);
+        assert_eq!(factors.len(), 1);
+        assert!((factors["P1"][0] - 2.0).abs() < 1e-9);
+        assert!((factors["P1"][1] - 0.5).abs() < 1e-9);
+
+        let a = plexes[0].proteins[0].total();
+        let b = plexes[1].proteins[0].total();
+        for (x, y) in a.iter().zip(&b) {
+            assert!((x - y).abs() < 1e-9);
+        }
+        // Not present in every plex, so left unscaled
+        assert_eq!(plexes[0].proteins[1].total(), vec![1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn median_normalization() {
+        let mut data = plex(&[
+            ("P1", vec![100.0, 200.0, f64::NAN]),
+            ("P2", vec![300.0, 600.0, 50.0]),
+            ("P3", vec![500.0, 1000.0, 50.0]),
+        ]);
+        let factors = data.normalize(Method::Median);
+        // Channel medians are 300, 600 and 50, with a mean of 316.67
+        assert!((factors[0] - 950.0 / 900.0).abs() < 1e-9);
+        assert!((factors[1] - 950.0 / 1800.0).abs() < 1e-9);
+        assert!(data.proteins[0].peptides[0].is_missing(2));
+        let medians = (0..3)
+            .map(|c| util::median(&data.channel_values(c)))
+            .collect::<Vec<_>>();
+        assert!((medians[0] - medians[1]).abs() < 1e-9);
+        assert!((medians[0] - medians[2]).abs() < 1e-9);
+
+        // Log2 intensities are normalized as linear intensities
+        let mut log = plex(&[("P1", vec![100.0, 200.0, 50.0])]);
+        log.log2_transform(0.0);
+        let factors = log.normalize(Method::TotalIntensity);
+        assert!((factors[0] - 350.0 / 300.0).abs() < 1e-9);
+        assert_eq!(log.scale, Scale::Log2(0.0));
+        let values = &log.proteins[0].peptides[0].values;
+        assert!((values[0] - values[1]).abs() < 1e-9);
+
+        // Empty channels are left unchanged, and do not count towards the
+        // target
+        let mut data = plex(&[
+            ("P1", vec![100.0, 300.0, 0.0, f64::NAN]),
+            ("P2", vec![100.0, 100.0, 0.0, f64::NAN]),
+        ]);
+        let factors = data.normalize(Method::TotalIntensity);
+        assert!((factors[0] - 300.0 / 200.0).abs() < 1e-9);
+        assert!((factors[1] - 300.0 / 400.0).abs() < 1e-9);
+        assert_eq!(&factors[2..], &[1.0, 1.0]);
+        assert_eq!(data.proteins[0].peptides[0].values[2], 0.0);
+    }
+
+    #[test]
+    fn quantile_normalization() {
+        let mut data = plex(&[
+            ("P1", vec![1.0, 40.0, 300.0]),
+            ("P2", vec![2.0, 10.0, 100.0]),
+            ("P3", vec![3.0, 20.0, 200.0]),
+        ]);
+        data.normalize(Method::Quantile);
+        let values = data.channel_values(0);
+        assert_eq!(
+            data.channel_values(1),
+            vec![values[2], values[0], values[1]]
+        );
+        assert_eq!(
+            values,
+            vec![
+                (1.0 + 10.0 + 100.0) / 3.0,
+                (2.0 + 20.0 + 200.0) / 3.0,
+                (3.0 + 40.0 + 300.0) / 3.0
+            ]
+        );
+    }
+
+    #[test]
+    fn batch_correction() {
+        let annotate = |mut data: Dataset| {
+            data.channel_info = vec![ChannelInfo::default(); 3];
+            data
+        };
+        let mut merged = Dataset::merge(vec![
+            annotate(plex(&[("P1", vec![100.0, 200.0, 400.0])])),
+            annotate(plex(&[
+                ("P1", vec![400.0, 800.0, 1600.0]),
+                ("P2", vec![1.0, 1.0, 1.0]),
+            ])),
+        ])
+        .unwrap();
+        let shifts = merged.correct_batches().unwrap();
+        assert!((shifts["P1"][0] - 1.0).abs() < 1e-9);
+        assert!((shifts["P1"][1] + 1.0).abs() < 1e-9);
+        assert_eq!(shifts["P2"], vec![0.0, 0.0]);
+
+        let total = merged.proteins[0].total();
+        for c in 0..3 {
+            assert!((total[c] - total[c + 3]).abs() < 1e-9);
+        }
+        assert!((total[1] - 400.0).abs() < 1e-9);
+        assert!(plex(&[]).correct_batches().is_none());
+    }
+
+    #[test]
+    fn reference_ratios() {
+        let mut data = plex(&[
+            ("P1", vec![100.0, 200.0, 50.0]),
+            ("P2", vec![1.0, 0.0, 1.0]),
+        ]);
+        let ratios = data.to_ratios(1, ZeroPolicy::Missing).unwrap();
+        assert_eq!(ratios.proteins[0].peptides[0].values, vec![0.5, 1.0, 0.25]);
+        assert!(ratios.proteins[1].peptides[0]
+            .values
+            .iter()
+            .all(|v| v.is_nan()));
+
+        let ratios = data.to_ratios(1, ZeroPolicy::Drop).unwrap();
+        assert_eq!(ratios.proteins.len(), 1);
+
+        let ratios = data.to_ratios(1, ZeroPolicy::Pseudocount(1.0)).unwrap();
+        assert_eq!(ratios.proteins[1].peptides[0].values, vec![2.0, 1.0, 2.0]);
+
+        let mut log = data.clone();
+        log.log2_transform(0.0);
+        let ratios = log.to_ratios(1, ZeroPolicy::Missing).unwrap();
+        assert_eq!(ratios.scale, Scale::Linear);
+        assert_eq!(ratios.proteins[0].peptides[0].values, vec![0.5, 1.0, 0.25]);
+
+        assert_eq!(
+            data.to_ratios(3, ZeroPolicy::Missing),
+            Err(DatasetError::ChannelOutOfRange(3, 3))
+        );
+
+        // Proteins that lose peptides are recounted
+        let extra = Peptide {
+            sequence: "K.MMK.L".into(),
+            values: vec![1.0, f64::NAN, 1.0],
+            ..Peptide::default()
+        };
+        data.proteins[0].peptides.push(extra);
+        data.proteins[0].spectral_count = 2;
+        data.proteins[0].sequence_count = 2;
+        let ratios = data.to_ratios(1, ZeroPolicy::Drop).unwrap();
+        assert_eq!(ratios.proteins[0].peptides.len(), 1);
+        assert_eq!(ratios.proteins[0].spectral_count, 1);
+        assert_eq!(ratios.proteins[0].sequence_count, 1);
+    }
+}
@@ -0,0 +1,301 @@
+//! Import OpenMS consensusXML files produced by isobaric quantification
+//! workflows (`IsobaricAnalyzer`, `IDMapper`)
+//!
+//! Each map of the consensus map becomes a channel, in order of map index.
+//! Consensus features carrying a peptide identification become peptides,
+//! grouped by the protein accession of their best peptide hit. Features
+//! without an identification cannot be assigned to a protein and are
+//! skipped. The map labels are recorded in the dataset header as
+//! `channel[n]` parameters, and are used as the channel labels.
+//!
+//! OpenMS modification names are kept in parentheses, so
+//! `.(Acetyl)PEPM(Oxidation)K` becomes `(Acetyl)PEPM(Oxidation)K`, with the
+//! flanking residues taken from the peptide hit.
+use super::*;
+use crate::xml::{elements, finish, number};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+/// A consensus feature with an identification
+struct Feature {
+    /// Protein hit references of the best peptide hit
+    refs: Vec<String>,
+    /// Intensity of each grouped element, by map index
+    intensities: Vec<(usize, f64)>,
+    peptide: Peptide,
+}
+
+/// Parse an OpenMS consensusXML file into a `Dataset`. Files with more than
+/// 255 maps fail with `ErrorKind::ChannelCount`
+pub fn from_consensusxml(input: &str) -> Result<Dataset, Error> {
+    // Map index -> (file name, label)
+    let mut maps: Vec<(usize, String, String)> = Vec::new();
+    // Protein hit id -> (accession, description)
+    let mut hits: HashMap<String, (String, String)> = HashMap::new();
+    let mut hit_id: Option<String> = None;
+    let mut features: Vec<Feature> = Vec::new();
+    let mut current: Option<Feature> = None;
+    // Is the current element's best peptide hit still to be read?
+    let mut first_hit = false;
+
+    elements(input, |line, name, attrs| {
+        let attrs = match attrs {
+            Some(attrs) => attrs,
+            None => {
+                match name {
+                    b"consensusElement" => {
+                        if let Some(feature) = current.take() {
+                            if !feature.refs.is_empty() {
+                                features.push(feature);
+                            }
+                        }
+                    }
+                    b"ProteinHit" => hit_id = None,
+                    _ => {}
+                }
+                return Ok(());
+            }
+        };
+        let get = |key: &str| attrs.get(key).cloned().unwrap_or_default();
+        match name {
+            b"map" => {
+                let id = number(&attrs, "id", line)?.unwrap_or(maps.len());
+                maps.push((id, get("name"), get("label")));
+                // Channels are indexed by `u8`, so `channels as u8` below is exact
+                if u8::try_from(maps.len()).is_err() {
+                    return Err(Error::new(ErrorKind::ChannelCount(maps.len()), line));
+                }
+            }
+            b"ProteinHit" => {
+                let id = get("id");
+                hits.insert(id.clone(), (get("accession"), String::new()));
+                hit_id = Some(id);
+            }
+            b"UserParam" | b"userParam" => {
+                if let Some(id) = &hit_id {
+                    if attrs.get("name").map(String::as_str) == Some("Description") {
+                        if let Some(hit) = hits.get_mut(id) {
+                            hit.1 = get("value");
+                        }
+                    }
+                }
+            }
+            b"consensusElement" => {
+                current = Some(Feature {
+                    refs: Vec::new(),
+                    intensities: Vec::new(),
+                    peptide: Peptide {
+                        charge: number(&attrs, "charge", line)?.unwrap_or(0),
+                        purity: 1.0,
+                        ..Peptide::default()
+                    },
+                });
+                first_hit = true;
+            }
+            _ => {}
+        }
+        let feature = match current.as_mut() {
+            Some(feature) => feature,
+            None => return Ok(()),
+        };
+        match name {
+            b"centroid" => {
+                feature.peptide.retention_time =
+                    number::<f32>(&attrs, "rt", line)?.map(|rt| rt / 60.0);
+            }
+            b"element" => {
+                let map = number::<usize>(&attrs, "map", line)?.unwrap_or(0);
+                let it = number::<f64>(&attrs, "it", line)?.unwrap_or(f64::NAN);
+                feature.intensities.push((map, it));
+            }
+            b"PeptideHit" if first_hit => {
+                first_hit = false;
+                let seq = get("sequence").replace(".(", "(");
+                let flank = |key: &str| match attrs.get(key).map(String::as_str) {
+                    None | Some("") | Some("[") | Some("]") => "-".to_string(),
+                    Some(aa) => aa.to_string(),
+                };
+                feature.peptide.sequence =
+                    format!("{}.{}.{}", flank("aa_before"), seq, flank("aa_after"));
+                feature.refs = get("protein_refs")
+                    .split_whitespace()
+                    .map(String::from)
+                    .collect();
+                feature.peptide.unique = feature.refs.len() == 1;
+                if let Some(z) = number(&attrs, "charge", line)? {
+                    feature.peptide.charge = z;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    })?;
+
+    maps.sort_by_key(|m| m.0);
+    let channels = maps.len();
+
+    let mut proteins: Vec<Protein> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for Feature {
+        refs,
+        intensities,
+        mut peptide,
+    } in features
+    {
+        peptide.values = vec![f64::NAN; channels];
+        for (map, it) in intensities {
+            if let Some(c) = maps.iter().position(|m| m.0 == map) {
+                peptide.values[c] = it;
+            }
+        }
+
+        let (accession, description) = hits
+            .get(&refs[0])
+            .cloned()
+            .unwrap_or_else(|| (refs[0].clone(), String::new()));
+        let alternatives = refs[1..]
+            .iter()
+            .map(|r| {
+                hits.get(r)
+                    .map(|h| h.0.clone())
+                    .unwrap_or_else(|| r.clone())
+            })
+            .collect::<Vec<_>>();
+        let idx = *index.entry(accession.clone()).or_insert_with(|| {
+            proteins.push(Protein {
+                accession,
+                description,
+                alternatives,
+                channels: channels as u8,
+                ..Protein::default()
+            });
+            proteins.len() - 1
+        });
+        proteins[idx].peptides.push(peptide);
+    }
+    finish(&mut proteins);
+
+    let file_name = maps.first().map(|m| m.1.clone()).unwrap_or_default();
+    for prot in proteins.iter_mut() {
+        for pep in prot.peptides.iter_mut() {
+            pep.file_name = file_name.clone();
+        }
+    }
+
+    let header = Header {
+        parameters: maps
+            .iter()
+            .enumerate()
+            .map(|(i, m)| (format!("channel[{}]", i + 1), m.2.clone()))
+            .collect(),
+        ..Header::default()
+    };
+    let channel_info = maps
+        .iter()
+        .map(|m| ChannelInfo {
+            label: m.2.clone(),
+            ..ChannelInfo::default()
+        })
+        .collect();
+
+    Ok(Dataset {
+        proteins,
+        channels: channels as u8,
+        header,
+        channel_info,
+        scale: Scale::Linear,
+        ..Dataset::default()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CONSENSUS: &str = r#"<?xml version="1.0" encoding="ISO-8859-1"?>
+<consensusXML version="1.7" id="cm_1">
+	<IdentificationRun id="PI_0" search_engine="Comet" search_engine_version="2019" date="2020-01-01T00:00:00">
+		<SearchParameters db="uniprot.fasta" charges="2,3" mass_type="monoisotopic"/>
+		<ProteinIdentification score_type="q-value" higher_score_better="false" significance_threshold="0">
+			<ProteinHit id="PH_0" accession="sp|P02768|ALBU_HUMAN" score="0" sequence="">
+				<UserParam type="string" name="Description" value="Serum albumin OS=Homo sapiens GN=ALB"/>
+			</ProteinHit>
+			<ProteinHit id="PH_1" accession="sp|P02769|ALBU_BOVIN" score="0" sequence=""/>
+		</ProteinIdentification>
+	</IdentificationRun>
+	<mapList count="2">
+		<map id="0" name="/data/run1.mzML" label="tmt6plex_126" size="2"/>
+		<map id="1" name="/data/run1.mzML" label="tmt6plex_127" size="2"/>
+	</mapList>
+	<consensusElementList>
+		<consensusElement id="e_1" quality="0" charge="2">
+			<centroid rt="2700" mz="575.31" it="4000"/>
+			<groupedElementList>
+				<element map="1" id="1" rt="2700" mz="127.125" it="3000" charge="2"/>
+				<element map="0" id="2" rt="2700" mz="126.128" it="1000" charge="2"/>
+			</groupedElementList>
+			<PeptideIdentification identification_run_ref="PI_0" score_type="q-value" higher_score_better="false" significance_threshold="0">
+				<PeptideHit score="0.001" sequence="LVNEVTEFAK" charge="2" aa_before="K" aa_after="T" protein_refs="PH_0"/>
+				<PeptideHit score="0.5" sequence="AAAAAK" charge="2" protein_refs="PH_1"/>
+			</PeptideIdentification>
+		</consensusElement>
+		<consensusElement id="e_2" quality="0" charge="2">
+			<centroid rt="2800" mz="420.2" it="900"/>
+			<groupedElementList>
+				<element map="0" id="3" rt="2800" mz="126.128" it="900" charge="2"/>
+			</groupedElementList>
+			<PeptideIdentification identification_run_ref="PI_0" score_type="q-value" higher_score_better="false" significance_threshold="0">
+				<PeptideHit score="0.01" sequence=".(Acetyl)M(Oxidation)ELFEK" charge="3" aa_before="[" aa_after="L" protein_refs="PH_0 PH_1"/>
+			</PeptideIdentification>
+		</consensusElement>
+		<consensusElement id="e_3" quality="0" charge="2">
+			<centroid rt="2900" mz="300.1" it="100"/>
+			<groupedElementList>
+				<element map="0" id="4" rt="2900" mz="126.128" it="100" charge="2"/>
+			</groupedElementList>
+		</consensusElement>
+	</consensusElementList>
+</consensusXML>
+"#;
+
+    #[test]
+    fn consensusxml() {
+        let data = from_consensusxml(CONSENSUS).unwrap();
+        assert_eq!(data.channels, 2);
+        assert_eq!(data.header.parameter("channel[2]"), Some("tmt6plex_127"));
+        assert_eq!(data.proteins.len(), 1);
+
+        let prot = &data.proteins[0];
+        assert_eq!(prot.accession, "sp|P02768|ALBU_HUMAN");
+        assert_eq!(prot.gene.as_deref(), Some("ALB"));
+        assert_eq!(prot.spectral_count, 2);
+
+        let pep = &prot.peptides[0];
+        assert_eq!(pep.sequence, "K.LVNEVTEFAK.T");
+        assert_eq!(pep.values, vec![1000.0, 3000.0]);
+        assert_eq!(pep.retention_time, Some(45.0));
+        assert_eq!(pep.file_name, "/data/run1.mzML");
+        assert!(pep.unique);
+
+        let pep = &prot.peptides[1];
+        assert_eq!(pep.sequence, "-.(Acetyl)M(Oxidation)ELFEK.L");
+        assert_eq!(pep.stripped_sequence(), "MELFEK");
+        assert_eq!(pep.charge, 3);
+        assert!(pep.is_missing(1));
+        assert!(!pep.unique);
+    }
+
+    #[test]
+    fn too_many_maps() {
+        let maps = (0..300)
+            .map(|i| format!("\t\t<map id=\"{}\" name=\"run.mzML\" label=\"\"/>\n", i))
+            .collect::<String>();
+        let input = CONSENSUS.replacen(
+            "\t<mapList count=\"2\">\n",
+            &format!("\t<mapList count=\"300\">\n{}", maps),
+            1,
+        );
+        let err = from_consensusxml(&input).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::ChannelCount(256));
+    }
+}
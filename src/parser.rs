@@ -2,12 +2,19 @@
 //!
 
 use super::*;
+use crate::columns::{PeptideColumns, ProteinColumns, RatioPeptideColumns, RatioProteinColumns};
+use crate::lines::{ByteLines, Lines};
+use crate::validate::{Validation, ValidationIssue};
 
+use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::fmt;
 use std::iter::Peekable;
-use std::str::Lines;
+use std::str::FromStr;
+use std::sync::Arc;
 
-#[derive(PartialEq, PartialOrd, Debug)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(PartialEq, PartialOrd, Clone, Debug)]
 pub enum ErrorKind {
     /// Invalid beginning of line
     Invalid(char),
@@ -15,6 +22,22 @@ pub enum ErrorKind {
     Conversion,
     /// Unexpected end-of-file
     EOF,
+    /// Line does not begin with the tag expected for its record type
+    UnexpectedTag(String),
+    /// Peptide uniqueness flag is neither empty nor "U"
+    InvalidUniqueFlag(String),
+    /// A required column is not declared in the header
+    MissingColumn(String),
+    /// Malformed XML input
+    Xml(String),
+    /// A protein accession appears more than once, and the parser is
+    /// configured with `DuplicatePolicy::Error`
+    DuplicateAccession(String),
+    /// The number of annotated channels (given) does not match the number
+    /// of quantified channels, or exceeds the 255 channels a `Dataset` holds
+    ChannelCount(usize),
+    /// A filter rule could not be parsed, see `Filter::parse`
+    InvalidFilter(String),
 }
 
 /// Error that may occur during parsing of a Census file
@@ -22,6 +45,21 @@ pub enum ErrorKind {
 pub struct Error {
     kind: ErrorKind,
     line: usize,
+    context: Option<Box<ErrorContext>>,
+}
+
+/// The input that caused an `Error`, for printing diagnostics
+#[derive(PartialEq, PartialOrd, Clone, Debug, Default)]
+pub struct ErrorContext {
+    /// Position of the offending field on the line, starting from 1 for the
+    /// line tag
+    pub column: Option<usize>,
+    /// Name of the offending column, if it is declared in the header
+    pub field: Option<String>,
+    /// Text of the offending field
+    pub token: Option<String>,
+    /// Raw text of the line
+    pub text: String,
 }
 
 impl fmt::Display for Error {
@@ -30,35 +68,320 @@ impl fmt::Display for Error {
             f,
             "Error parsing file at line {}: {:?}",
             self.line, self.kind
-        )
+        )?;
+        if let Some(ctx) = &self.context {
+            if let Some(column) = ctx.column {
+                write!(f, " in column {}", column)?;
+            }
+            if let Some(field) = &ctx.field {
+                write!(f, " ({})", field)?;
+            }
+            if let Some(token) = &ctx.token {
+                write!(f, ", found {:?}", token)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Error {
+    pub(crate) fn new(kind: ErrorKind, line: usize) -> Error {
+        Error {
+            kind,
+            line,
+            context: None,
+        }
+    }
+
+    pub(crate) fn with_context(mut self, context: ErrorContext) -> Error {
+        self.context = Some(Box::new(context));
+        self
+    }
+
+    /// Return the kind of error that occurred
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    /// Return the (1-based) line number at which the error occurred
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Return the column, token and line text that caused the error, if
+    /// known
+    pub fn context(&self) -> Option<&ErrorContext> {
+        self.context.as_deref()
     }
 }
 
 impl std::error::Error for Error {}
 
+/// How the parser handles a protein accession that appears more than once,
+/// e.g. when the output of fractionated runs has been concatenated
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Default)]
+pub enum DuplicatePolicy {
+    /// Keep every entry as a separate protein. This is the default
+    #[default]
+    KeepBoth,
+    /// Fail with `ErrorKind::DuplicateAccession`. In lenient mode, the
+    /// duplicate entry is instead skipped and recorded as a `Warning`
+    Error,
+    /// Append the peptides of the duplicate entry to the first entry with
+    /// that accession, and recompute its counts
+    Merge,
+}
+
+/// A line that was skipped while parsing in lenient mode
+#[derive(PartialEq, Clone, Debug)]
+pub struct Warning {
+    /// Line number of the skipped line
+    pub line: usize,
+    /// Reason the line could not be parsed
+    pub reason: ErrorKind,
+    /// Raw text of the skipped line
+    pub text: String,
+}
+
+/// Progress of a parse, reported to the callback registered with
+/// `Parser::on_progress`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ProgressEvent {
+    /// Number of bytes of input consumed so far
+    pub bytes_read: usize,
+    /// Total number of bytes of input
+    pub total_bytes: usize,
+    /// Number of protein entries parsed so far
+    pub proteins_parsed: usize,
+}
+
+/// Configuration options for a `Parser`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ParserBuilder {
+    keep_normalized: bool,
+    lenient: bool,
+    channels: Option<u8>,
+    delimiter: char,
+    quote: Option<char>,
+    duplicates: DuplicatePolicy,
+}
+
+impl Default for ParserBuilder {
+    fn default() -> Self {
+        ParserBuilder {
+            keep_normalized: false,
+            lenient: false,
+            channels: None,
+            delimiter: '\t',
+            quote: None,
+            duplicates: DuplicatePolicy::KeepBoth,
+        }
+    }
+}
+
+impl ParserBuilder {
+    /// Retain the Census-normalized intensity columns in
+    /// `Peptide::norm_values`, rather than discarding them.
+    ///
+    /// This follows the Builder pattern
+    pub fn keep_normalized(mut self, keep: bool) -> Self {
+        self.keep_normalized = keep;
+        self
+    }
+
+    /// Skip lines that cannot be parsed, recording them as `Warning`s,
+    /// rather than aborting the parse
+    ///
+    /// This follows the Builder pattern
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    /// Force the number of quantified channels, ignoring the number of m/z
+    /// columns declared in the header. Intensity columns are assumed to be
+    /// alternating raw and normalized values, starting at the first
+    /// declared m/z column.
+    ///
+    /// This follows the Builder pattern
+    pub fn channels(mut self, channels: u8) -> Self {
+        self.channels = Some(channels);
+        self
+    }
+
+    /// Set the field delimiter. Defaults to tab
+    ///
+    /// This follows the Builder pattern
+    pub fn delimiter(mut self, delimiter: char) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Allow fields to be enclosed in `quote` characters, so that they may
+    /// contain the delimiter, as in comma-separated exports from Excel. A
+    /// quote character within a quoted field is escaped by doubling it.
+    /// Quoting is disabled by default
+    ///
+    /// This follows the Builder pattern
+    pub fn quote(mut self, quote: char) -> Self {
+        self.quote = Some(quote);
+        self
+    }
+
+    /// Set how protein accessions that appear more than once are handled
+    /// when parsing into a `Dataset` or `RatioDataset`. Defaults to
+    /// `DuplicatePolicy::KeepBoth`. The `Proteins` iterator yields entries
+    /// as they are parsed, and is not affected.
+    ///
+    /// This follows the Builder pattern
+    pub fn duplicates(mut self, policy: DuplicatePolicy) -> Self {
+        self.duplicates = policy;
+        self
+    }
+
+    /// Create a new parser operating on input data with the configured
+    /// options
+    pub fn build(self, input: &str) -> Parser<'_> {
+        self.configure(Parser::new(input))
+    }
+
+    /// Create a new parser operating on ASCII input data with the
+    /// configured options, see `Parser::from_bytes`
+    pub fn build_bytes(self, input: &[u8]) -> Parser<'_> {
+        self.configure(Parser::from_bytes(input))
+    }
+
+    fn configure(self, parser: Parser<'_>) -> Parser<'_> {
+        let channels = self.channels.unwrap_or(0);
+        Parser {
+            config: self,
+            channels,
+            peptide_columns: PeptideColumns::positional(channels),
+            ..parser
+        }
+    }
+}
+
 pub struct Parser<'s> {
+    config: ParserBuilder,
     iter: Peekable<Lines<'s>>,
     /// Number of TMT channels to parse
     channels: u8,
+    /// Number of lines consumed so far
     line: usize,
+    /// Text of the line currently being parsed
+    current: &'s str,
+    header: Arc<Header>,
+    warnings: Vec<Warning>,
+    protein_columns: ProteinColumns,
+    peptide_columns: PeptideColumns,
+    ratio_protein_columns: RatioProteinColumns,
+    ratio_peptide_columns: RatioPeptideColumns,
+    /// Number of bytes consumed so far, including line endings
+    bytes_read: usize,
+    total_bytes: usize,
+    progress: Option<Box<dyn FnMut(ProgressEvent) + 's>>,
+    /// Issues found so far, when validating
+    validation: Option<Validation>,
+    /// Line number and text of the most recent protein line
+    entry: (usize, &'s str),
+    /// Field buffer reused from line to line, see `Parser::split`
+    fields: Vec<&'s str>,
 }
 
 impl<'s> Parser<'s> {
+    /// Return a `ParserBuilder` for configuring a new parser
+    pub fn builder() -> ParserBuilder {
+        ParserBuilder::default()
+    }
+
     /// Create a new parser operating on input data
     pub fn new(input: &'s str) -> Parser<'s> {
+        Parser::with_lines(Lines::Str(input.lines()), input.len())
+    }
+
+    /// Create a new parser operating on raw bytes, without validating the
+    /// whole input as UTF-8 up front. This is faster for very large files,
+    /// which are almost always entirely ASCII.
+    ///
+    /// Lines that are not valid UTF-8 produce an `ErrorKind::Invalid` error
+    pub fn from_bytes(input: &'s [u8]) -> Parser<'s> {
+        Parser::with_lines(Lines::Bytes(ByteLines::new(input)), input.len())
+    }
+
+    fn with_lines(lines: Lines<'s>, total_bytes: usize) -> Parser<'s> {
         Parser {
-            iter: input.lines().peekable(),
+            config: ParserBuilder::default(),
+            iter: lines.peekable(),
             channels: 0,
-            line: 1,
+            line: 0,
+            current: "",
+            header: Arc::default(),
+            warnings: Vec::new(),
+            protein_columns: ProteinColumns::default(),
+            peptide_columns: PeptideColumns::positional(0),
+            ratio_protein_columns: RatioProteinColumns::new(&[]),
+            ratio_peptide_columns: RatioPeptideColumns::new(&[]),
+            bytes_read: 0,
+            total_bytes,
+            progress: None,
+            validation: None,
+            entry: (0, ""),
+            fields: Vec::with_capacity(32),
         }
     }
 
+    /// Register a callback that is invoked with a `ProgressEvent` after each
+    /// protein entry is parsed, and once more when parsing finishes, e.g. to
+    /// drive a progress bar while parsing very large files
+    ///
+    /// This follows the Builder pattern
+    pub fn on_progress<F: FnMut(ProgressEvent) + 's>(mut self, callback: F) -> Self {
+        self.progress = Some(Box::new(callback));
+        self
+    }
+
+    fn report_progress(&mut self, proteins_parsed: usize) {
+        if let Some(callback) = self.progress.as_mut() {
+            callback(ProgressEvent {
+                bytes_read: self.bytes_read.min(self.total_bytes),
+                total_bytes: self.total_bytes,
+                proteins_parsed,
+            });
+        }
+    }
+
+    /// Skip lines that cannot be parsed, rather than aborting. Skipped
+    /// lines are recorded as `Warning`s, which are returned by
+    /// `parse_with_warnings`
+    ///
+    /// This follows the Builder pattern
+    pub fn lenient(mut self) -> Self {
+        self.config.lenient = true;
+        self
+    }
+
     /// Convenience function for creating Error struct
     fn err(&self, kind: ErrorKind) -> Error {
-        Error {
-            kind,
-            line: self.line,
-        }
+        Error::new(kind, self.line).with_context(ErrorContext {
+            text: self.current.into(),
+            ..ErrorContext::default()
+        })
+    }
+
+    /// Create an error caused by the field at position `idx` of the current
+    /// line
+    fn field_err(&self, kind: ErrorKind, fields: &[&str], idx: usize) -> Error {
+        let columns = match fields.first() {
+            Some(&"P") => &self.header.protein_columns,
+            _ => &self.header.peptide_columns,
+        };
+        Error::new(kind, self.line).with_context(ErrorContext {
+            column: Some(idx + 1),
+            field: idx.checked_sub(1).and_then(|i| columns.get(i)).cloned(),
+            token: fields.get(idx).map(|s| s.to_string()),
+            text: self.current.into(),
+        })
     }
 
     fn peek(&mut self) -> Option<&&'s str> {
@@ -67,101 +390,267 @@ impl<'s> Parser<'s> {
 
     fn next(&mut self) -> Option<&'s str> {
         let n = self.iter.next();
-        if n.is_some() {
+        if let Some(line) = n {
+            self.current = line;
             self.line += 1;
+            self.bytes_read += line.len() + 1;
         }
         n
     }
 
-    fn parse_peptide(&mut self) -> Result<Peptide, Error> {
-        let line = self.iter.next().ok_or_else(|| self.err(ErrorKind::EOF))?;
-        // Using split_whitespace obfuscates missing 'U' values, and messes up
-        // parsing
-        let mut data = line.split('\t');
-        assert_eq!(data.next(), Some("S"));
+    /// Split a data line into its fields, reusing the buffer of a previous
+    /// line. Hand the buffer back with `reuse` once the fields are no longer
+    /// needed
+    fn split(&mut self, line: &'s str) -> Vec<&'s str> {
+        let mut fields = std::mem::take(&mut self.fields);
+        match self.config.quote {
+            Some(quote) if line.contains(quote) => {
+                fast::split_quoted(line, self.config.delimiter, quote, &mut fields)
+            }
+            _ => fast::split_fields(line, self.config.delimiter, &mut fields),
+        }
+        fields
+    }
 
-        let n = data.next().ok_or_else(|| self.err(ErrorKind::EOF))?;
-        assert!(n.len() <= 1);
-        let unique: bool = n == "U";
-        let sequence = data.next().ok_or_else(|| self.err(ErrorKind::EOF))?.into();
+    /// Keep the buffer returned by `split` for the next line
+    fn reuse(&mut self, fields: Vec<&'s str>) {
+        self.fields = fields;
+    }
 
-        let mut values = Vec::with_capacity(self.channels as usize);
+    /// Convert a text field of the current line into an owned string,
+    /// unescaping quotes if it was quoted
+    fn text(&self, field: &str) -> String {
+        match self.config.quote {
+            Some(quote) => fast::unescape(self.current, field, quote),
+            None => field.into(),
+        }
+    }
 
-        for _ in 0..self.channels {
-            let mz = data
-                .next()
-                .ok_or_else(|| self.err(ErrorKind::EOF))?
-                .parse::<u32>()
-                .map_err(|_| self.err(ErrorKind::Conversion))?;
-            // discard normalized data
-            let _ = data.next().ok_or_else(|| self.err(ErrorKind::EOF))?;
-            values.push(mz);
+    /// Return the field at position `idx`, or an EOF error if the line is
+    /// too short
+    fn field<'a>(&self, fields: &[&'a str], idx: usize) -> Result<&'a str, Error> {
+        fields
+            .get(idx)
+            .copied()
+            .ok_or_else(|| self.field_err(ErrorKind::EOF, fields, idx))
+    }
+
+    /// Parse the field at position `idx` into a number
+    fn number<T: FromStr>(&self, fields: &[&str], idx: usize) -> Result<T, Error> {
+        self.field(fields, idx)?
+            .parse::<T>()
+            .map_err(|_| self.field_err(ErrorKind::Conversion, fields, idx))
+    }
+
+    /// Parse the intensity at position `idx`. Empty, "NA" and "NaN" fields
+    /// are missing values, which are represented as `NaN`
+    fn intensity(&self, fields: &[&str], idx: usize) -> Result<f64, Error> {
+        match self.field(fields, idx)?.trim() {
+            "" | "NA" | "N/A" | "NaN" | "nan" => Ok(f64::NAN),
+            s => {
+                fast::parse_f64(s).ok_or_else(|| self.field_err(ErrorKind::Conversion, fields, idx))
+            }
         }
+    }
 
-        let _ = data.next();
-        let purity = data.next().unwrap_or("1.0").parse::<f32>().unwrap_or(1.0);
+    /// In lenient mode, record `error` as a warning for the line `text`.
+    /// Otherwise, return the error
+    fn recover(&mut self, error: Error, text: &str) -> Result<(), Error> {
+        if !self.config.lenient {
+            return Err(error);
+        }
+        self.warnings.push(Warning {
+            line: error.line,
+            reason: error.kind,
+            text: text.into(),
+        });
+        Ok(())
+    }
 
-        let scan = data
-            .skip(3)
-            .next()
-            .unwrap_or("")
-            .parse::<usize>()
-            .unwrap_or(0);
+    /// Parse the line tag and uniqueness flag of a peptide line, returning
+    /// `(redundant, unique)`
+    fn peptide_flags(&self, fields: &[&str], unique: usize) -> Result<(bool, bool), Error> {
+        let redundant = match fields[0] {
+            "S" => false,
+            "&S" => true,
+            tag => {
+                let kind = ErrorKind::UnexpectedTag(tag.into());
+                return Err(self.field_err(kind, fields, 0));
+            }
+        };
+        let unique = match self.field(fields, unique)? {
+            "U" => true,
+            "" => false,
+            flag => {
+                let kind = ErrorKind::InvalidUniqueFlag(flag.into());
+                return Err(self.field_err(kind, fields, unique));
+            }
+        };
+        Ok((redundant, unique))
+    }
+
+    /// Parse all of the peptide lines immediately following a protein line
+    fn parse_peptides<T>(
+        &mut self,
+        parse_peptide: fn(&mut Self) -> Result<T, Error>,
+    ) -> Result<Vec<T>, Error> {
+        let mut peptides = Vec::new();
+        while let Some(&next) = self.peek() {
+            if is_peptide(next) {
+                match parse_peptide(self) {
+                    Ok(peptide) => peptides.push(peptide),
+                    Err(e) => self.recover(e, next)?,
+                }
+            } else {
+                // Next line should be a protein entry
+                break;
+            }
+        }
+        Ok(peptides)
+    }
+
+    fn parse_peptide(&mut self) -> Result<Peptide, Error> {
+        let line = self.next().ok_or_else(|| self.err(ErrorKind::EOF))?;
+        // Using split_whitespace obfuscates missing 'U' values, and messes up
+        // parsing
+        let fields = self.split(line);
+        if let Some(validation) = self.validation.as_mut() {
+            let expected = self.header.peptide_columns.len() + 1;
+            if expected > 1 && fields.len() != expected {
+                validation.issues.push(ValidationIssue::FieldCount {
+                    line: self.line,
+                    expected,
+                    found: fields.len(),
+                });
+            }
+        }
+        let cols = &self.peptide_columns;
+        let (redundant, unique) = self.peptide_flags(&fields, cols.unique)?;
+        let sequence = self.field(&fields, cols.sequence)?.into();
+
+        let values = cols
+            .values
+            .iter()
+            .map(|&i| self.intensity(&fields, i))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let norm_values = if self.config.keep_normalized {
+            cols.norm_values
+                .iter()
+                .map(|&i| self.intensity(&fields, i))
+                .collect::<Result<Vec<_>, _>>()?
+        } else {
+            Vec::new()
+        };
+
+        // Optional trailing columns are parsed leniently
+        let purity = optional(&fields, cols.purity).unwrap_or(1.0);
+        let scan = optional_uint(&fields, cols.scan).unwrap_or(0);
+        let charge = optional_uint(&fields, cols.charge).unwrap_or(0);
+        let xcorr = optional(&fields, cols.xcorr).unwrap_or(0.0);
+        let delta_cn = optional(&fields, cols.delta_cn).unwrap_or(0.0);
+        let file_name = optional_str(&fields, cols.file_name).into();
+        let retention_time = optional(&fields, cols.retention_time);
+        let injection_time = optional(&fields, cols.injection_time);
+        self.reuse(fields);
 
         Ok(Peptide {
             sequence,
             unique,
+            redundant,
             values,
+            norm_values,
             scan,
             purity,
+            charge,
+            xcorr,
+            delta_cn,
+            file_name,
+            retention_time,
+            injection_time,
+            shared_with: Vec::new(),
         })
     }
 
-    fn parse_protein(&mut self) -> Result<Protein, Error> {
-        let line = self.iter.next().ok_or_else(|| self.err(ErrorKind::EOF))?;
-        let mut data = line.split('\t');
-        assert_eq!(data.next(), Some("P"));
-        let accession = data.next().ok_or_else(|| self.err(ErrorKind::EOF))?.into();
-        let spectral_count = data
-            .next()
-            .ok_or_else(|| self.err(ErrorKind::EOF))?
-            .parse::<u16>()
-            .map_err(|_| self.err(ErrorKind::Conversion))?;
-        let sequence_count = data
-            .next()
-            .ok_or_else(|| self.err(ErrorKind::EOF))?
-            .parse::<u16>()
-            .map_err(|_| self.err(ErrorKind::Conversion))?;
-        let sequence_coverage = data
-            .next()
-            .ok_or_else(|| self.err(ErrorKind::EOF))?
-            .trim_end_matches('%')
-            .parse::<f32>()
-            .map_err(|_| self.err(ErrorKind::Conversion))?;
-        let molecular_weight = data
-            .next()
-            .ok_or_else(|| self.err(ErrorKind::EOF))?
-            .parse::<u32>()
-            .map_err(|_| self.err(ErrorKind::Conversion))?;
-
-        // let mut description = String::new();
-        // for n in data {
-        //     description = n.into();
-        // }
-        let description = data.last().ok_or_else(|| self.err(ErrorKind::EOF))?.into();
+    fn protein_tag(&self, fields: &[&str]) -> Result<(), Error> {
+        match fields[0] {
+            "P" => Ok(()),
+            tag => Err(self.field_err(ErrorKind::UnexpectedTag(tag.into()), fields, 0)),
+        }
+    }
 
-        let mut peptides = Vec::new();
-        while let Some(next) = self.iter.peek() {
-            if next.starts_with('S') {
-                peptides.push(self.parse_peptide()?);
-            } else {
-                // Next line should be a protein entry
+    /// Parse the accessions of any protein lines immediately following a
+    /// protein line, which list the other members of its protein group
+    fn group_members(&mut self, accession: usize) -> Result<Vec<String>, Error> {
+        let mut members = Vec::new();
+        while let Some(&next) = self.peek() {
+            if !is_protein(next, self.config.delimiter) {
                 break;
             }
+            self.next();
+            let fields = self.split(next);
+            let member: String = self.field(&fields, accession)?.into();
+            self.reuse(fields);
+            if let Some(validation) = self.validation.as_mut() {
+                validation.accessions.push((member.clone(), self.line));
+            }
+            members.push(member);
+        }
+        Ok(members)
+    }
+
+    /// Return the description field, which is the last field on the line
+    /// unless the header specifies otherwise
+    fn description(&self, fields: &[&str], idx: Option<usize>) -> Result<String, Error> {
+        match idx {
+            Some(i) => self.field(fields, i),
+            None => fields
+                .last()
+                .copied()
+                .ok_or_else(|| self.err(ErrorKind::EOF)),
         }
+        .map(|s| self.text(s))
+    }
+
+    fn parse_protein(&mut self) -> Result<Protein, Error> {
+        let line = self.next().ok_or_else(|| self.err(ErrorKind::EOF))?;
+        let fields = self.split(line);
+        self.protein_tag(&fields)?;
+
+        let cols = &self.protein_columns;
+        let accession: String = self.field(&fields, cols.accession)?.into();
+        if let Some(validation) = self.validation.as_mut() {
+            validation.accessions.push((accession.clone(), self.line));
+        }
+        let spectral_count = match cols.spectral_count {
+            Some(i) => self.number::<u16>(&fields, i)?,
+            None => 0,
+        };
+        let sequence_count = match cols.sequence_count {
+            Some(i) => self.number::<u16>(&fields, i)?,
+            None => 0,
+        };
+        let sequence_coverage = match cols.sequence_coverage {
+            Some(i) => self
+                .field(&fields, i)?
+                .trim_end_matches('%')
+                .parse::<f32>()
+                .map_err(|_| self.field_err(ErrorKind::Conversion, &fields, i))?,
+            None => 0.0,
+        };
+        let molecular_weight = match cols.molecular_weight {
+            Some(i) => self.number::<u32>(&fields, i)?,
+            None => 0,
+        };
+        let description = self.description(&fields, cols.description)?;
+        self.reuse(fields);
 
-        Ok(Protein {
+        let alternatives = self.group_members(self.protein_columns.accession)?;
+        let peptides = self.parse_peptides(Self::parse_peptide)?;
+
+        let mut protein = Protein {
             accession,
+            alternatives,
             spectral_count,
             sequence_count,
             sequence_coverage,
@@ -169,16 +658,92 @@ impl<'s> Parser<'s> {
             description,
             peptides,
             channels: self.channels,
-        })
+            ..Protein::default()
+        };
+        protein.annotate();
+        Ok(protein)
+    }
+
+    fn parse_ratio_peptide(&mut self) -> Result<RatioPeptide, Error> {
+        let line = self.next().ok_or_else(|| self.err(ErrorKind::EOF))?;
+        let fields = self.split(line);
+
+        let cols = &self.ratio_peptide_columns;
+        let (redundant, unique) = self.peptide_flags(&fields, cols.unique)?;
+        let ratio = |idx| optional(&fields, idx).unwrap_or(f64::NAN);
+
+        let peptide = RatioPeptide {
+            sequence: self.field(&fields, cols.sequence)?.into(),
+            unique,
+            redundant,
+            ratio: ratio(cols.ratio),
+            rev_slope_ratio: ratio(cols.rev_slope_ratio),
+            regression_factor: ratio(cols.regression_factor),
+            determinant_factor: ratio(cols.determinant_factor),
+            sample_intensity: ratio(cols.sample_intensity),
+            reference_intensity: ratio(cols.reference_intensity),
+            area_ratio: ratio(cols.area_ratio),
+            profile_score: ratio(cols.profile_score),
+            xcorr: optional(&fields, cols.xcorr).unwrap_or(0.0),
+            delta_cn: optional(&fields, cols.delta_cn).unwrap_or(0.0),
+            scan: optional(&fields, cols.scan).unwrap_or(0),
+            charge: optional(&fields, cols.charge).unwrap_or(0),
+            file_name: optional_str(&fields, cols.file_name).into(),
+        };
+        self.reuse(fields);
+        Ok(peptide)
+    }
+
+    fn parse_ratio_protein(&mut self) -> Result<RatioProtein, Error> {
+        let line = self.next().ok_or_else(|| self.err(ErrorKind::EOF))?;
+        let fields = self.split(line);
+        self.protein_tag(&fields)?;
+
+        let cols = &self.ratio_protein_columns;
+        let ratio = |idx| optional(&fields, idx).unwrap_or(f64::NAN);
+        let count = |idx: Option<usize>| match idx {
+            Some(i) => self.number::<u16>(&fields, i),
+            None => Ok(0),
+        };
+
+        let mut protein = RatioProtein {
+            accession: self.field(&fields, cols.accession)?.into(),
+            description: self.description(&fields, cols.description)?,
+            average_ratio: ratio(cols.average_ratio),
+            standard_deviation: ratio(cols.standard_deviation),
+            composite_ratio: ratio(cols.composite_ratio),
+            composite_ratio_standard_deviation: ratio(cols.composite_ratio_standard_deviation),
+            peptide_count: count(cols.peptide_count)?,
+            spectral_count: count(cols.spectral_count)?,
+            area_ratio: ratio(cols.area_ratio),
+            alternatives: Vec::new(),
+            peptides: Vec::new(),
+        };
+        let accession = cols.accession;
+        self.reuse(fields);
+        protein.alternatives = self.group_members(accession)?;
+        protein.peptides = self.parse_peptides(Self::parse_ratio_peptide)?;
+        Ok(protein)
     }
 
     fn parse_headers(&mut self) -> Option<()> {
         while let Some(line) = self.peek() {
             if line.starts_with('H') {
                 let line = self.next()?;
-                if line.contains("m/z") {
-                    self.channels = (line.matches("m/z_").count() / 2) as u8;
+                Arc::make_mut(&mut self.header).push_line(line, self.config.delimiter);
+                if !self.header.peptide_columns.is_empty() {
+                    self.peptide_columns = PeptideColumns::new(&self.header.peptide_columns);
+                } else if line.contains("m/z") {
+                    let channels = (line.matches("m/z_").count() / 2) as u8;
+                    self.peptide_columns = PeptideColumns::positional(channels);
+                }
+                if let Some(channels) = self.config.channels {
+                    self.peptide_columns.force_channels(channels);
                 }
+                self.channels = self.peptide_columns.channels();
+                self.protein_columns = ProteinColumns::new(&self.header.protein_columns);
+                self.ratio_protein_columns = RatioProteinColumns::new(&self.header.protein_columns);
+                self.ratio_peptide_columns = RatioPeptideColumns::new(&self.header.peptide_columns);
             } else {
                 return Some(());
             }
@@ -186,26 +751,783 @@ impl<'s> Parser<'s> {
         None
     }
 
-    pub fn parse(mut self) -> Result<Dataset, Error> {
-        let mut data = Vec::new();
+    pub fn parse(self) -> Result<Dataset, Error> {
+        self.parse_with_warnings().map(|(dataset, _)| dataset)
+    }
 
-        while let Some(line) = self.peek() {
-            let init = line
-                .chars()
-                .next()
-                .ok_or_else(|| self.err(ErrorKind::EOF))?;
-            match init {
-                'H' => self
-                    .parse_headers()
-                    .ok_or_else(|| self.err(ErrorKind::EOF))?,
-                'P' => data.push(self.parse_protein()?),
-                _ => return Err(self.err(ErrorKind::Invalid(init))),
+    /// Parse the input, returning the `Dataset` along with any lines that
+    /// were skipped. Warnings are only generated in lenient mode
+    pub fn parse_with_warnings(mut self) -> Result<(Dataset, Vec<Warning>), Error> {
+        let proteins = self.parse_entries(Self::parse_protein)?;
+        let dataset = Dataset {
+            proteins,
+            channels: self.channels,
+            header: Arc::unwrap_or_clone(self.header),
+            channel_info: Vec::new(),
+            scale: Scale::Linear,
+            ..Dataset::default()
+        };
+        Ok((dataset, self.warnings))
+    }
+
+    /// Parse the input, additionally checking that the declared Census
+    /// version is supported, that every peptide line has the number of
+    /// fields declared in the header, and that protein accessions are not
+    /// duplicated. Inconsistencies are returned as a `ValidationReport`
+    /// rather than aborting the parse
+    pub fn parse_validated(mut self) -> Result<(Dataset, ValidationReport), Error> {
+        self.validation = Some(Validation::default());
+        let proteins = self.parse_entries(Self::parse_protein)?;
+        let report = self
+            .validation
+            .take()
+            .unwrap_or_default()
+            .finish(&self.header, self.channels);
+        let dataset = Dataset {
+            proteins,
+            channels: self.channels,
+            header: Arc::unwrap_or_clone(self.header),
+            channel_info: Vec::new(),
+            scale: Scale::Linear,
+            ..Dataset::default()
+        };
+        Ok((dataset, report))
+    }
+
+    /// Parse ratio-format (e.g. SILAC or 15N) Census output, where peptides
+    /// are quantified as sample/reference ratios
+    pub fn parse_ratios(self) -> Result<RatioDataset, Error> {
+        self.parse_ratios_with_warnings()
+            .map(|(dataset, _)| dataset)
+    }
+
+    /// Parse ratio-format Census output, returning the `RatioDataset` along
+    /// with any lines that were skipped. Warnings are only generated in
+    /// lenient mode
+    pub fn parse_ratios_with_warnings(mut self) -> Result<(RatioDataset, Vec<Warning>), Error> {
+        let proteins = self.parse_entries(Self::parse_ratio_protein)?;
+        let dataset = RatioDataset {
+            proteins,
+            header: Arc::unwrap_or_clone(self.header),
+        };
+        Ok((dataset, self.warnings))
+    }
+
+    /// Parse header and protein entries until the end of the input
+    fn parse_entries<T: Entry>(
+        &mut self,
+        parse_protein: fn(&mut Self) -> Result<T, Error>,
+    ) -> Result<Vec<T>, Error> {
+        let mut data: Vec<T> = Vec::new();
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        while let Some(entry) = self.next_entry(parse_protein) {
+            let entry = entry?;
+            match (self.config.duplicates, seen.get(entry.accession())) {
+                (DuplicatePolicy::Error, Some(_)) => {
+                    let (line, text) = self.entry;
+                    let kind = ErrorKind::DuplicateAccession(entry.accession().into());
+                    self.recover(Error::new(kind, line), text)?;
+                }
+                (DuplicatePolicy::Merge, Some(&idx)) => data[idx].absorb(entry),
+                _ => {
+                    seen.insert(entry.accession().into(), data.len());
+                    data.push(entry);
+                }
             }
+            self.report_progress(data.len());
         }
+        self.bytes_read = self.total_bytes;
+        self.report_progress(data.len());
+        Ok(data)
+    }
 
-        Ok(Dataset {
-            proteins: data,
+    /// Parse header lines until the next protein entry, and then parse it.
+    /// Returns `None` at the end of the input
+    fn next_entry<T>(
+        &mut self,
+        parse_protein: fn(&mut Self) -> Result<T, Error>,
+    ) -> Option<Result<T, Error>> {
+        while let Some(&line) = self.peek() {
+            let init = match line.chars().next() {
+                Some(c) => c,
+                None => {
+                    // An empty line ends the input in strict mode
+                    self.next();
+                    let e = self.err(ErrorKind::EOF);
+                    match self.recover(e, line) {
+                        Ok(()) => continue,
+                        Err(e) => return Some(Err(e)),
+                    }
+                }
+            };
+            let result = match init {
+                'H' => match self.parse_headers() {
+                    Some(()) => Ok(()),
+                    None => Err(self.err(ErrorKind::EOF)),
+                },
+                'P' => {
+                    self.entry = (self.line + 1, line);
+                    match parse_protein(self) {
+                        Ok(protein) => return Some(Ok(protein)),
+                        Err(e) => {
+                            let result = self.recover(e, line);
+                            // Peptides cannot be attributed without their protein
+                            while self.peek().map(|l| is_peptide(l)).unwrap_or(false) {
+                                self.next();
+                            }
+                            result
+                        }
+                    }
+                }
+                _ => {
+                    self.next();
+                    let e = self.err(ErrorKind::Invalid(init));
+                    self.recover(e, line)
+                }
+            };
+            if let Err(e) = result {
+                return Some(Err(e));
+            }
+        }
+        None
+    }
+
+    /// Return an iterator that parses protein entries one at a time, rather
+    /// than collecting the entire file into a `Dataset`
+    pub fn proteins(self) -> Proteins<'s> {
+        Proteins {
+            parser: self,
+            parsed: 0,
+            done: false,
+        }
+    }
+}
+
+/// Configuration and header state of a Census file that is parsed one
+/// chunk of lines at a time, e.g. by `stream::proteins`. The header is
+/// shared with the parser of each chunk rather than copied
+#[cfg(feature = "async")]
+pub(crate) struct Template {
+    config: ParserBuilder,
+    channels: u8,
+    header: Arc<Header>,
+    protein_columns: ProteinColumns,
+    peptide_columns: PeptideColumns,
+    ratio_protein_columns: RatioProteinColumns,
+    ratio_peptide_columns: RatioPeptideColumns,
+}
+
+#[cfg(feature = "async")]
+impl Template {
+    /// Parse the header lines of a file
+    pub(crate) fn new(config: ParserBuilder, headers: &str) -> Template {
+        let mut parser = config.build(headers);
+        parser.parse_headers();
+        Template {
+            config: parser.config,
+            channels: parser.channels,
+            header: parser.header,
+            protein_columns: parser.protein_columns,
+            peptide_columns: parser.peptide_columns,
+            ratio_protein_columns: parser.ratio_protein_columns,
+            ratio_peptide_columns: parser.ratio_peptide_columns,
+        }
+    }
+
+    /// Parse a chunk of protein and peptide lines following the headers,
+    /// where `line` is the number of lines preceding the chunk. Lines
+    /// skipped in lenient mode are appended to `warnings`
+    pub(crate) fn parse_chunk(
+        &self,
+        chunk: &str,
+        line: usize,
+        warnings: &mut Vec<Warning>,
+    ) -> Result<Vec<Protein>, Error> {
+        let mut parser = Parser {
+            config: self.config,
             channels: self.channels,
-        })
+            line,
+            header: Arc::clone(&self.header),
+            protein_columns: self.protein_columns.clone(),
+            peptide_columns: self.peptide_columns.clone(),
+            ratio_protein_columns: self.ratio_protein_columns.clone(),
+            ratio_peptide_columns: self.ratio_peptide_columns.clone(),
+            ..Parser::new(chunk)
+        };
+        let proteins = parser.parse_entries(Parser::parse_protein);
+        warnings.append(&mut parser.warnings);
+        proteins
+    }
+}
+
+/// Iterator over the protein entries of a Census file, created by
+/// `Parser::proteins`.
+///
+/// Iteration stops after the first error. In lenient mode, lines that
+/// cannot be parsed are skipped and recorded as warnings instead
+pub struct Proteins<'s> {
+    parser: Parser<'s>,
+    parsed: usize,
+    done: bool,
+}
+
+impl<'s> Proteins<'s> {
+    /// Return the header lines parsed so far
+    pub fn header(&self) -> &Header {
+        &self.parser.header
+    }
+
+    /// Return the lines skipped so far in lenient mode
+    pub fn warnings(&self) -> &[Warning] {
+        &self.parser.warnings
+    }
+}
+
+impl<'s> Iterator for Proteins<'s> {
+    type Item = Result<Protein, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let entry = self.parser.next_entry(Parser::parse_protein);
+        match &entry {
+            Some(Ok(_)) => {
+                self.parsed += 1;
+                self.parser.report_progress(self.parsed);
+            }
+            Some(Err(_)) => self.done = true,
+            None => {
+                self.done = true;
+                self.parser.bytes_read = self.parser.total_bytes;
+                self.parser.report_progress(self.parsed);
+            }
+        }
+        entry
+    }
+}
+
+/// A protein entry that can be combined with a duplicate entry of the same
+/// accession, see `DuplicatePolicy::Merge`
+trait Entry {
+    fn accession(&self) -> &str;
+    fn absorb(&mut self, other: Self);
+}
+
+impl Entry for Protein {
+    fn accession(&self) -> &str {
+        &self.accession
+    }
+
+    fn absorb(&mut self, other: Protein) {
+        for member in other.alternatives {
+            if !self.alternatives.contains(&member) {
+                self.alternatives.push(member);
+            }
+        }
+        self.spectral_count = self.spectral_count.saturating_add(other.spectral_count);
+        self.sequence_coverage = self.sequence_coverage.max(other.sequence_coverage);
+        self.peptides.extend(other.peptides);
+        let mut seqs = self
+            .peptides
+            .iter()
+            .map(|p| p.stripped_sequence())
+            .collect::<Vec<_>>();
+        seqs.sort_unstable();
+        seqs.dedup();
+        self.sequence_count = u16::try_from(seqs.len()).unwrap_or(u16::MAX);
+    }
+}
+
+impl Entry for RatioProtein {
+    fn accession(&self) -> &str {
+        &self.accession
+    }
+
+    fn absorb(&mut self, other: RatioProtein) {
+        for member in other.alternatives {
+            if !self.alternatives.contains(&member) {
+                self.alternatives.push(member);
+            }
+        }
+        self.spectral_count = self.spectral_count.saturating_add(other.spectral_count);
+        self.peptide_count = self.peptide_count.saturating_add(other.peptide_count);
+        self.peptides.extend(other.peptides);
+    }
+}
+
+/// Leniently parse an optional field, returning `None` if the column is
+/// absent or the value cannot be parsed
+fn optional<T: FromStr>(fields: &[&str], idx: Option<usize>) -> Option<T> {
+    idx.and_then(|i| fields.get(i))
+        .and_then(|s| s.parse::<T>().ok())
+}
+
+/// Leniently parse an optional unsigned integer field
+fn optional_uint<T: TryFrom<u64>>(fields: &[&str], idx: Option<usize>) -> Option<T> {
+    idx.and_then(|i| fields.get(i))
+        .and_then(|s| fast::parse_uint(s))
+}
+
+/// Return an optional text field, or an empty string if the column is absent
+fn optional_str<'a>(fields: &[&'a str], idx: Option<usize>) -> &'a str {
+    idx.and_then(|i| fields.get(i)).copied().unwrap_or("")
+}
+
+/// Is this a protein ("P") line?
+fn is_protein(line: &str, delimiter: char) -> bool {
+    line.strip_prefix('P')
+        .is_some_and(|rest| rest.starts_with(delimiter))
+}
+
+/// Is this a peptide ("S" or "&S") line?
+fn is_peptide(line: &str) -> bool {
+    line.starts_with('S') || line.starts_with("&S")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SAMPLE: &str = "H\tCensus version 2.3.3
+H\tCreated Date\t03/01/2019
+H\tPLINE\tLOCUS\tSPEC_COUNT\tSEQ_COUNT\tSEQ_COVERAGE\tMOLWT\tDESCRIPTION
+H\tSLINE\tUNIQUE\tSEQUENCE\tm/z_126.127726_int\tnorm_m/z_126.127726_int\tm/z_127.124761_int\tnorm_m/z_127.124761_int\tm/z_128.134436_int\tnorm_m/z_128.134436_int\tSIGNAL_NOISE\tPURITY\tCSTATE\tXCORR\tDELTACN\tSCAN\tFILE_NAME
+P\tP12345\t2\t2\t12.5%\t45000\tSerum albumin OS=Homo sapiens GN=ALB
+S\tU\tK.LVNEVTEFAK.T\t1000\t0.25\t2000\t0.5\t1000\t0.25\t12.1\t0.95\t2\t3.12\t0.41\t1234\tsample_01.ms2
+S\t\tR.QNCELFEK.L\t500\t0.2\t1000\t0.4\t1000\t0.4\t8.3\t0.80\t3\t2.54\t0.33\t1301\tsample_01.ms2
+P\tReverse_Q99999\t1\t1\t3.0%\t22000\tDecoy protein
+S\tU\tK.AAAAAK.L\t10\t0.1\t20\t0.2\t70\t0.7\t1.1\t0.50\t2\t1.02\t0.05\t2001\tsample_02.ms2
+&S\t\tR.QNCELFEK.L\t500\t0.2\t1000\t0.4\t1000\t0.4\t8.3\t0.80\t3\t2.54\t0.33\t1301\tsample_01.ms2
+";
+
+    #[test]
+    fn parse_sample() {
+        let data = Parser::new(SAMPLE).parse().unwrap();
+        assert_eq!(data.channels, 3);
+        assert_eq!(data.proteins.len(), 2);
+        assert_eq!(data.proteins[0].peptides.len(), 2);
+        assert_eq!(
+            data.proteins[0].peptides[0].values,
+            vec![1000.0, 2000.0, 1000.0]
+        );
+        assert_eq!(data.proteins[0].peptides[1].scan, 1301);
+        assert!(!data.proteins[0].peptides[1].unique);
+    }
+
+    #[test]
+    fn parse_fractional_intensities() {
+        let input = SAMPLE.replacen("\t1000\t0.25\t2000", "\t1000.5\t0.25\t2000", 1);
+        let data = Parser::new(&input).parse().unwrap();
+        assert_eq!(data.proteins[0].peptides[0].values[0], 1000.5);
+    }
+
+    #[test]
+    fn parse_reordered_columns() {
+        let input = "H\tPLINE\tLOCUS\tDESCRIPTION\tSPEC_COUNT\tSEQ_COUNT\tSEQ_COVERAGE\tLENGTH\tMOLWT
+H\tSLINE\tUNIQUE\tSEQUENCE\tRETENTION_TIME\tm/z_126.127726_int\tm/z_127.124761_int\tnorm_m/z_126.127726_int\tnorm_m/z_127.124761_int\tSCAN\tPURITY
+P\tP12345\tSerum albumin\t1\t1\t12.5%\t609\t69367
+S\tU\tK.LVNEVTEFAK.T\t45.2\t1000\t3000\t0.25\t0.75\t1234\t0.9
+";
+        let data = Parser::new(input).parse().unwrap();
+        assert_eq!(data.channels, 2);
+        let prot = &data.proteins[0];
+        assert_eq!(prot.description, "Serum albumin");
+        assert_eq!(prot.molecular_weight, 69367);
+        assert_eq!(prot.peptides[0].values, vec![1000.0, 3000.0]);
+        assert_eq!(prot.peptides[0].scan, 1234);
+        assert_eq!(prot.peptides[0].purity, 0.9);
+        assert_eq!(prot.peptides[0].retention_time, Some(45.2));
+        assert_eq!(prot.peptides[0].injection_time, None);
+    }
+
+    #[test]
+    fn parse_protein_groups() {
+        let input = SAMPLE.replacen(
+            "P\tP12345",
+            "P\tP12345-2\t2\t2\t12.5%\t45000\tIsoform 2\nP\tP12345-3\t2\t2\t12.5%\t45000\tIsoform 3\nP\tP12345",
+            1,
+        );
+        let data = Parser::new(&input).parse().unwrap();
+        assert_eq!(data.proteins.len(), 2);
+        let prot = &data.proteins[0];
+        assert_eq!(prot.accession, "P12345-2");
+        assert_eq!(prot.alternatives, vec!["P12345-3", "P12345"]);
+        assert_eq!(prot.description, "Isoform 2");
+        assert_eq!(prot.peptides.len(), 2);
+        assert!(data.proteins[1].alternatives.is_empty());
+    }
+
+    #[test]
+    fn duplicate_accessions() {
+        let input = format!(
+            "{}P\tP12345\t1\t1\t20.0%\t45000\tSerum albumin\nS\tU\tK.YLYEIAR.R\t1\t0.3\t1\t0.3\t1\t0.3\t1.0\t0.9\t2\t2.0\t0.2\t4001\tsample_03.ms2\n",
+            SAMPLE
+        );
+        let data = Parser::new(&input).parse().unwrap();
+        assert_eq!(data.proteins.len(), 3);
+
+        let err = ParserBuilder::default()
+            .duplicates(DuplicatePolicy::Error)
+            .build(&input)
+            .parse()
+            .unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::DuplicateAccession("P12345".into()));
+        assert_eq!(err.line(), 11);
+
+        let (data, warnings) = ParserBuilder::default()
+            .duplicates(DuplicatePolicy::Error)
+            .lenient(true)
+            .build(&input)
+            .parse_with_warnings()
+            .unwrap();
+        assert_eq!(data.proteins.len(), 2);
+        assert_eq!(warnings[0].line, 11);
+
+        let data = ParserBuilder::default()
+            .duplicates(DuplicatePolicy::Merge)
+            .build(&input)
+            .parse()
+            .unwrap();
+        assert_eq!(data.proteins.len(), 2);
+        let prot = &data.proteins[0];
+        assert_eq!(prot.peptides.len(), 3);
+        assert_eq!(prot.spectral_count, 3);
+        assert_eq!(prot.sequence_count, 3);
+        assert_eq!(prot.sequence_coverage, 20.0);
+
+        let input = input.replace("P\tP12345\t1\t1\t20.0%", "P\tP12345\t65535\t1\t20.0%");
+        let data = ParserBuilder::default()
+            .duplicates(DuplicatePolicy::Merge)
+            .build(&input)
+            .parse()
+            .unwrap();
+        assert_eq!(data.proteins[0].spectral_count, u16::MAX);
+    }
+
+    #[test]
+    fn parse_description() {
+        let data = Parser::new(SAMPLE).parse().unwrap();
+        assert_eq!(
+            data.proteins[0].protein_name.as_deref(),
+            Some("Serum albumin")
+        );
+        assert_eq!(data.proteins[0].organism.as_deref(), Some("Homo sapiens"));
+        assert_eq!(data.proteins[0].gene.as_deref(), Some("ALB"));
+    }
+
+    #[test]
+    fn parse_search_columns() {
+        let data = Parser::new(SAMPLE).parse().unwrap();
+        let pep = &data.proteins[0].peptides[1];
+        assert_eq!(pep.scan, 1301);
+        assert_eq!(pep.charge, 3);
+        assert_eq!(pep.xcorr, 2.54);
+        assert_eq!(pep.delta_cn, 0.33);
+        assert_eq!(pep.file_name, "sample_01.ms2");
+        assert_eq!(data.proteins[1].peptides[0].file_name, "sample_02.ms2");
+    }
+
+    #[test]
+    fn keep_normalized() {
+        let data = Parser::new(SAMPLE).parse().unwrap();
+        assert!(data.proteins[0].peptides[0].norm_values.is_empty());
+
+        let data = ParserBuilder::default()
+            .keep_normalized(true)
+            .build(SAMPLE)
+            .parse()
+            .unwrap();
+        assert_eq!(
+            data.proteins[0].peptides[0].norm_values,
+            vec![0.25, 0.5, 0.25]
+        );
+    }
+
+    #[test]
+    fn missing_values() {
+        let input = SAMPLE
+            .replacen("\t1000\t0.25\t2000", "\tNA\t0.25\t2000", 1)
+            .replacen("\t20\t", "\t\t", 1);
+        let data = Parser::new(&input).parse().unwrap();
+        let pep = &data.proteins[0].peptides[0];
+        assert!(pep.is_missing(0));
+        assert_eq!(pep.values[1], 2000.0);
+        assert!(data.proteins[1].peptides[0].is_missing(1));
+        assert_eq!(data.proteins[0].total()[0], 500.0);
+    }
+
+    #[test]
+    fn parse_bytes() {
+        let data = Parser::from_bytes(SAMPLE.as_bytes()).parse().unwrap();
+        assert_eq!(data, Parser::new(SAMPLE).parse().unwrap());
+
+        let mut input = SAMPLE.as_bytes().to_vec();
+        let pos = SAMPLE.find("S\t\tR.QNCELFEK").unwrap();
+        input[pos] = 0xff;
+        let err = Parser::from_bytes(&input).parse().unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::Invalid('\u{FFFD}'));
+        assert_eq!(err.line, 7);
+    }
+
+    #[test]
+    fn progress() {
+        let mut events = Vec::new();
+        Parser::new(SAMPLE)
+            .on_progress(|e| events.push(e))
+            .parse()
+            .unwrap();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0].proteins_parsed, 1);
+        assert!(events[0].bytes_read < events[1].bytes_read);
+        assert_eq!(events[2].proteins_parsed, 2);
+        assert_eq!(events[2].bytes_read, SAMPLE.len());
+        assert_eq!(events[2].total_bytes, SAMPLE.len());
+
+        // Callbacks need not be `Send`
+        let count = std::rc::Rc::new(std::cell::Cell::new(0));
+        let counter = count.clone();
+        Parser::new(SAMPLE)
+            .on_progress(move |_| counter.set(counter.get() + 1))
+            .parse()
+            .unwrap();
+        assert_eq!(count.get(), 3);
+    }
+
+    #[test]
+    fn protein_iterator() {
+        let mut iter = Parser::new(SAMPLE).proteins();
+        let first = iter.next().unwrap().unwrap();
+        assert_eq!(first.accession, "P12345");
+        assert_eq!(iter.header().peptide_columns.len(), 15);
+        let rest = iter.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(rest.len(), 1);
+
+        let input = SAMPLE.replace("P\tReverse_Q99999\t1", "P\tReverse_Q99999\tone");
+        let results = Parser::new(&input).proteins().collect::<Vec<_>>();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[1].as_ref().unwrap_err().line, 8);
+    }
+
+    #[test]
+    fn lenient() {
+        let input = SAMPLE
+            .replacen("\t1000\t0.25\t2000", "\tabc\t0.25\t2000", 1)
+            .replace("P\tReverse_Q99999\t1", "P\tReverse_Q99999\tone");
+        let err = Parser::new(&input).parse().unwrap_err();
+        assert_eq!(err.line, 6);
+
+        let (data, warnings) = Parser::new(&input).lenient().parse_with_warnings().unwrap();
+        assert_eq!(data.proteins.len(), 1);
+        assert_eq!(data.proteins[0].peptides.len(), 1);
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(warnings[0].line, 6);
+        assert_eq!(warnings[0].reason, ErrorKind::Conversion);
+        assert!(warnings[0].text.starts_with("S\tU\tK.LVNEVTEFAK.T\tabc"));
+        assert_eq!(warnings[1].line, 8);
+        assert!(warnings[1].text.starts_with("P\tReverse_Q99999"));
+
+        let input = SAMPLE.replace("P\tReverse_Q99999", "\nP\tReverse_Q99999");
+        assert_eq!(
+            Parser::new(&input).parse().unwrap_err().kind,
+            ErrorKind::EOF
+        );
+        let (data, warnings) = Parser::new(&input).lenient().parse_with_warnings().unwrap();
+        assert_eq!(data.proteins.len(), 2);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].line, 8);
+        assert_eq!(warnings[0].reason, ErrorKind::EOF);
+        assert!(warnings[0].text.is_empty());
+    }
+
+    #[test]
+    fn error_context() {
+        let input = SAMPLE.replacen("\t1000\t0.25\t2000", "\tabc\t0.25\t2000", 1);
+        let err = Parser::new(&input).parse().unwrap_err();
+        assert_eq!(err.line(), 6);
+        let ctx = err.context().unwrap();
+        assert_eq!(ctx.column, Some(4));
+        assert_eq!(ctx.field.as_deref(), Some("m/z_126.127726_int"));
+        assert_eq!(ctx.token.as_deref(), Some("abc"));
+        assert!(ctx.text.starts_with("S\tU\tK.LVNEVTEFAK.T\tabc"));
+        assert_eq!(
+            err.to_string(),
+            "Error parsing file at line 6: Conversion in column 4 (m/z_126.127726_int), found \"abc\""
+        );
+    }
+
+    #[test]
+    fn validation() {
+        let (_, report) = Parser::new(SAMPLE).parse_validated().unwrap();
+        assert!(report.is_valid());
+        assert_eq!(report.version.as_deref(), Some("2.3.3"));
+        assert_eq!(report.channels, 3);
+
+        let input = SAMPLE
+            .replace("Census version 2.3.3", "Census version 9.0")
+            .replace("\tsample_02.ms2", "\tsample_02.ms2\textra")
+            .replace("P\tReverse_Q99999", "P\tP12345");
+        let (data, report) = Parser::new(&input).parse_validated().unwrap();
+        assert_eq!(data.proteins.len(), 2);
+        assert_eq!(
+            report.issues,
+            vec![
+                ValidationIssue::UnsupportedVersion("9.0".into()),
+                ValidationIssue::FieldCount {
+                    line: 9,
+                    expected: 16,
+                    found: 17
+                },
+                ValidationIssue::DuplicateAccession {
+                    accession: "P12345".into(),
+                    lines: vec![5, 8]
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_lines() {
+        let input = SAMPLE.replacen("S\tU\t", "S\tX\t", 1);
+        let err = Parser::new(&input).parse().unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::InvalidUniqueFlag("X".into()));
+
+        let input = SAMPLE.replacen("S\tU\t", "SX\tU\t", 1);
+        let err = Parser::new(&input).parse().unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::UnexpectedTag("SX".into()));
+
+        let input = SAMPLE.replacen("P\tP12345", "PLINE\tP12345", 1);
+        let err = Parser::new(&input).parse().unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::UnexpectedTag("PLINE".into()));
+    }
+
+    #[test]
+    fn builder_options() {
+        let input = SAMPLE.replace('\t', ",");
+        let data = Parser::builder()
+            .delimiter(',')
+            .channels(2)
+            .build(&input)
+            .parse()
+            .unwrap();
+        assert_eq!(data.channels, 2);
+        assert_eq!(data.proteins[0].peptides[0].values, vec![1000.0, 2000.0]);
+        assert_eq!(data.header.protein_columns[0], "LOCUS");
+
+        let headerless = SAMPLE
+            .lines()
+            .filter(|l| !l.starts_with('H'))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let data = Parser::builder()
+            .channels(3)
+            .build(&headerless)
+            .parse()
+            .unwrap();
+        assert_eq!(data.proteins[1].peptides[0].values, vec![10.0, 20.0, 70.0]);
+
+        let (_, warnings) = Parser::builder()
+            .lenient(true)
+            .build("X\tbad line")
+            .parse_with_warnings()
+            .unwrap();
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn quoted_fields() {
+        let input = SAMPLE.replace('\t', ",").replace(
+            "Serum albumin OS=Homo sapiens GN=ALB",
+            r#""Serum albumin, ""isoform"" 1 OS=Homo sapiens GN=ALB""#,
+        );
+        // Doubled quotes are only escapes within quoted fields
+        let input = input.replace("Decoy protein", r#"Decoy ""protein"""#);
+        let data = Parser::builder()
+            .delimiter(',')
+            .quote('"')
+            .build(&input)
+            .parse()
+            .unwrap();
+        let prot = &data.proteins[0];
+        assert_eq!(
+            prot.description,
+            r#"Serum albumin, "isoform" 1 OS=Homo sapiens GN=ALB"#
+        );
+        assert_eq!(prot.gene.as_deref(), Some("ALB"));
+        assert_eq!(prot.peptides.len(), 2);
+        assert_eq!(data.proteins[1].description, r#"Decoy ""protein"""#);
+    }
+
+    #[test]
+    fn label_free() {
+        let input =
+            "H\tPLINE\tLOCUS\tSPEC_COUNT\tSEQ_COUNT\tSEQ_COVERAGE\tLENGTH\tMOLWT\tpI\tDESCRIPTION
+H\tSLINE\tUNIQUE\tSEQUENCE\tXCorr\tdeltaCN\tScanNum\tCState\tFileName
+P\tP12345\t2\t1\t10.2%\t609\t69367\t5.9\tSerum albumin
+S\tU\tK.LVNEVTEFAK.T\t3.2\t0.4\t1234\t2\tsample.ms2
+S\tU\tK.LVNEVTEFAK.T\t3.0\t0.3\t1240\t2\tsample.ms2
+";
+        let data = Parser::new(input).parse().unwrap();
+        assert!(data.is_label_free());
+        let prot = &data.proteins[0];
+        assert_eq!(prot.molecular_weight, 69367);
+        assert_eq!(prot.peptides.len(), 2);
+        assert!(prot.peptides[0].values.is_empty());
+        assert_eq!(prot.peptides[1].scan, 1240);
+        assert!(prot.total().is_empty());
+
+        let headerless = input
+            .lines()
+            .skip(2)
+            .map(|l| l.replace("\t609", ""))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let data = Parser::new(&headerless).parse().unwrap();
+        assert!(data.is_label_free());
+        assert_eq!(data.proteins[0].peptides[0].purity, 1.0);
+        assert_eq!(data.proteins[0].peptides[0].scan, 0);
+    }
+
+    #[test]
+    fn parse_ratios() {
+        let input = "H\tPLINE\tLOCUS\tAVERAGE_RATIO\tSTANDARD_DEVIATION\tCOMPOSITE_RATIO\tCOMPOSITE_RATIO_STANDARD_DEVIATION\tPEPTIDE_NUM\tSPEC_COUNT\tLSPEC_COUNT\tHSPEC_COUNT\tAREA_RATIO\tDESCRIPTION
+H\tSLINE\tUNIQUE\tSEQUENCE\tRATIO\tREV_SLOPE_RATIO\tREGRESSION_FACTOR\tDETERMINANT_FACTOR\tXCorr\tdeltaCN\tSAM_INT\tREF_INT\tAREA_RATIO\tPROFILE_SCORE\tFILE_NAME\tSCAN\tCS
+P\tP12345\t1.52\t0.21\t1.48\t0.1\t2\t3\t2\t1\t1.5\tSerum albumin
+S\tU\tK.LVNEVTEFAK.T\t1.37\t0.72\t0.98\t0.01\t3.2\t0.41\t150000\t109000\t1.38\t0.95\tsample.ms1\t1234\t2
+&S\t\tR.QNCELFEK.L\tNA\tNA\t0.51\t0.2\t2.5\t0.3\t1200\t900\t1.33\t0.4\tsample.ms1\t1301\t3
+";
+        let data = Parser::new(input).parse_ratios().unwrap();
+        assert_eq!(data.proteins.len(), 1);
+        let prot = &data.proteins[0];
+        assert_eq!(prot.average_ratio, 1.52);
+        assert_eq!(prot.peptide_count, 2);
+        assert_eq!(prot.spectral_count, 3);
+        assert_eq!(prot.description, "Serum albumin");
+        assert_eq!(prot.peptides[0].ratio, 1.37);
+        assert_eq!(prot.peptides[0].sample_intensity, 150000.0);
+        assert_eq!(prot.peptides[0].charge, 2);
+        assert!(prot.peptides[1].ratio.is_nan());
+        assert!(prot.peptides[1].redundant);
+        assert_eq!(prot.peptides[1].scan, 1301);
+    }
+
+    #[test]
+    fn parse_redundant() {
+        let data = Parser::new(SAMPLE).parse().unwrap();
+        let decoy = &data.proteins[1];
+        assert_eq!(decoy.peptides.len(), 2);
+        assert!(!decoy.peptides[0].redundant);
+        assert!(decoy.peptides[1].redundant);
+        assert_eq!(decoy.peptides[1].sequence, "R.QNCELFEK.L");
+    }
+
+    #[test]
+    fn parse_header() {
+        let header = Parser::new(SAMPLE).parse().unwrap().header;
+        assert_eq!(header.version.as_deref(), Some("2.3.3"));
+        assert_eq!(header.parameter("Created Date"), Some("03/01/2019"));
+        assert_eq!(header.protein_columns[0], "LOCUS");
+        assert_eq!(header.peptide_columns.len(), 15);
+        assert_eq!(header.lines.len(), 4);
     }
 }
@@ -0,0 +1,110 @@
+//! Principal component analysis of the channel intensity matrix
+//!
+//! Each channel is an observation and each protein a variable. Proteins are
+//! summarized as in `Dataset::differential_expression` (log2 intensities),
+//! and only proteins quantified in every channel are used.
+use super::*;
+use nalgebra::DMatrix;
+
+#[derive(PartialEq, Clone, Debug)]
+/// Result of `Dataset::pca`
+pub struct Pca {
+    /// Accessions of the proteins used, in loading order
+    pub accessions: Vec<String>,
+    /// Score of each channel on each component: `scores[channel][component]`
+    pub scores: Vec<Vec<f64>>,
+    /// Loading of each protein on each component:
+    /// `loadings[protein][component]`
+    pub loadings: Vec<Vec<f64>>,
+    /// Fraction of the total variance explained by each component
+    pub explained_variance: Vec<f64>,
+}
+
+impl Dataset {
+    /// Calculate the first `k` principal components of the channels.
+    ///
+    /// Returns `None` if fewer than two channels or no complete proteins are
+    /// available. Fewer than `k` components are returned if the matrix has
+    /// lower rank
+    pub fn pca(&self, k: usize) -> Option<Pca> {
+        let channels = self.channels as usize;
+        let (accessions, columns): (Vec<String>, Vec<Vec<f64>>) = self
+            .proteins
+            .iter()
+            .map(|prot| (prot.accession.clone(), self.log2_protein(prot)))
+            .filter(|(_, v)| v.len() == channels && v.iter().all(|x| x.is_finite()))
+            .unzip();
+        if channels < 2 || columns.is_empty() {
+            return None;
+        }
+
+        let mut matrix = DMatrix::from_fn(channels, columns.len(), |r, c| columns[c][r]);
+        for mut col in matrix.column_iter_mut() {
+            let mean = col.mean();
+            col.add_scalar_mut(-mean);
+        }
+
+        let svd = matrix.svd(true, true);
+        let (u, v_t) = (svd.u?, svd.v_t?);
+        let total = svd.singular_values.iter().map(|s| s * s).sum::<f64>();
+        let k = k.min(svd.singular_values.len());
+        let singular = &svd.singular_values;
+
+        Some(Pca {
+            accessions,
+            scores: (0..channels)
+                .map(|r| (0..k).map(|c| u[(r, c)] * singular[c]).collect())
+                .collect(),
+            loadings: (0..columns.len())
+                .map(|p| (0..k).map(|c| v_t[(c, p)]).collect())
+                .collect(),
+            explained_variance: (0..k)
+                .map(|c| {
+                    if total > 0.0 {
+                        singular[c] * singular[c] / total
+                    } else {
+                        0.0
+                    }
+                })
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn outlier_channel() {
+        let protein = |acc: &str, values: Vec<f64>| Protein {
+            accession: acc.into(),
+            channels: 4,
+            peptides: vec![Peptide {
+                values,
+                ..Peptide::default()
+            }],
+            ..Protein::default()
+        };
+        let data = Dataset {
+            channels: 4,
+            proteins: vec![
+                protein("P1", vec![100.0, 100.0, 100.0, 1600.0]),
+                protein("P2", vec![200.0, 200.0, 200.0, 50.0]),
+                protein("P3", vec![300.0, f64::NAN, 300.0, 300.0]),
+            ],
+            ..Dataset::default()
+        };
+        let pca = data.pca(3).unwrap();
+        assert_eq!(pca.accessions, vec!["P1", "P2"]);
+        assert_eq!(pca.scores.len(), 4);
+        assert_eq!(pca.loadings.len(), 2);
+
+        // All variance is due to the fourth channel
+        assert!((pca.explained_variance[0] - 1.0).abs() < 1e-9);
+        let pc1 = pca.scores.iter().map(|s| s[0]).collect::<Vec<_>>();
+        assert!((pc1[0] - pc1[1]).abs() < 1e-9);
+        assert!((pc1[3] - pc1[0]).abs() > 4.0);
+        assert!(Dataset::default().pca(2).is_none());
+    }
+}
@@ -1,8 +1,13 @@
+use super::util;
+use crate::dataset::Scale;
+use crate::enzyme::Enzyme;
+use crate::modification::{self, Modification};
+use crate::uniprot;
 #[cfg(feature = "serialization")]
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-#[cfg_attr(feature = "serde", derive(Serialize))]
-#[derive(PartialEq, PartialOrd, Clone, Default)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+#[derive(PartialEq, PartialOrd, Clone, Debug, Default)]
 /// Protein-level TMT quantification data, as well as additional
 /// metadata about the protein that is output in the Census file
 pub struct Protein {
@@ -10,6 +15,17 @@ pub struct Protein {
     pub accession: String,
     /// Long-form description
     pub description: String,
+    /// Accessions of the other members of the protein group, which Census
+    /// lists on consecutive protein lines sharing the same peptides
+    pub alternatives: Vec<String>,
+    /// UniProt entry name, e.g. "ALBU_HUMAN". See `Protein::annotate`
+    pub entry_name: Option<String>,
+    /// Protein name from the description, e.g. "Serum albumin"
+    pub protein_name: Option<String>,
+    /// Source organism, from the "OS=" field of the description
+    pub organism: Option<String>,
+    /// Gene name, from the "GN=" field of the description
+    pub gene: Option<String>,
     /// Number of spectral counts
     pub spectral_count: u16,
     /// Number of unique sequence counts
@@ -25,54 +41,137 @@ pub struct Protein {
 }
 
 impl Protein {
-    /// Return the summed intensities for all peptides
-    pub fn total(&self) -> Vec<u32> {
+    /// Populate the entry name, protein name, organism and gene fields from
+    /// a UniProt FASTA-style accession and description, e.g.
+    /// "Serum albumin OS=Homo sapiens GN=ALB PE=1 SV=2".
+    ///
+    /// This is called by the parsers, and only needs to be called again if
+    /// the description is modified
+    pub fn annotate(&mut self) {
+        let desc = uniprot::Description::parse(&self.accession, &self.description);
+        self.entry_name = desc.entry_name;
+        self.protein_name = desc.protein_name;
+        self.organism = desc.organism;
+        self.gene = desc.gene;
+    }
+
+    /// Return the summed intensities for all peptides.
+    ///
+    /// Missing peptide values are skipped, and a channel is only missing
+    /// (`NaN`) in the total if it is missing from every peptide
+    pub fn total(&self) -> Vec<f64> {
+        self.linear_total(Scale::Linear)
+    }
+
+    /// Return the summed intensity of each channel, as by `Protein::total`,
+    /// after converting peptide intensities on `scale` back to linear
+    pub(crate) fn linear_total(&self, scale: Scale) -> Vec<f64> {
         let mut v = Vec::with_capacity(self.channels as usize);
-        for c in 0..self.channels {
-            let sum = self.peptides.iter().map(|pep| pep.values[c as usize]).sum();
-            v.push(sum);
+        for c in 0..self.channels as usize {
+            let values = self
+                .peptides
+                .iter()
+                .filter(|pep| !pep.is_missing(c))
+                .map(|pep| scale.linear(pep.values[c]))
+                .collect::<Vec<_>>();
+            v.push(if values.is_empty() {
+                f64::NAN
+            } else {
+                util::sum(&values)
+            });
         }
         v
     }
 
+    /// Clone the protein's metadata, with `peptides` in place of its own
+    pub(crate) fn with_peptides(&self, peptides: Vec<Peptide>) -> Protein {
+        Protein {
+            accession: self.accession.clone(),
+            description: self.description.clone(),
+            alternatives: self.alternatives.clone(),
+            entry_name: self.entry_name.clone(),
+            protein_name: self.protein_name.clone(),
+            organism: self.organism.clone(),
+            gene: self.gene.clone(),
+            spectral_count: self.spectral_count,
+            sequence_count: self.sequence_count,
+            sequence_coverage: self.sequence_coverage,
+            molecular_weight: self.molecular_weight,
+            peptides,
+            channels: self.channels,
+        }
+    }
+
     /// Return a vector of normalized ratios, where the signal intensity
-    /// for each channel is divided by the sum of all channels
+    /// for each channel is divided by the sum of all channels. Missing
+    /// channels are excluded from the sum, and remain missing
     pub fn ratios(&self) -> Vec<f64> {
         let values = self.total();
-        let total = values.iter().sum::<u32>() as f64;
-        values.iter().map(|v| *v as f64 / total).collect()
+        let total = util::sum(&values);
+        values.iter().map(|v| v / total).collect()
+    }
+
+    /// Sort peptides by scan number, then by spectrum file name
+    pub fn sort_peptides_by_scan(&mut self) {
+        self.peptides.sort_by(|a, b| {
+            a.scan
+                .cmp(&b.scan)
+                .then_with(|| a.file_name.cmp(&b.file_name))
+        });
     }
 }
 
-#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
 #[derive(PartialEq, PartialOrd, Clone, Debug, Default)]
 /// Peptide-level TMT quantification data
 pub struct Peptide {
     /// Peptide sequence
     pub sequence: String,
-    /// Raw isobaric ion intensity values
-    pub values: Vec<u32>,
+    /// Raw isobaric ion intensity values. Missing values are `NaN`, see
+    /// `Peptide::is_missing`
+    #[cfg_attr(feature = "serialization", serde(with = "crate::nan::vec"))]
+    pub values: Vec<f64>,
+    /// Census-normalized intensity values. Only populated when the parser
+    /// is configured to keep them, see `ParserBuilder::keep_normalized`
+    #[cfg_attr(feature = "serialization", serde(with = "crate::nan::vec"))]
+    pub norm_values: Vec<f64>,
     /// Is this a unique peptide?
     pub unique: bool,
+    /// Was this peptide listed on a redundant ("&S") line, i.e. shared with
+    /// another protein entry?
+    pub redundant: bool,
 
     pub purity: f32,
 
     pub scan: usize,
+    /// Precursor charge state
+    pub charge: u8,
+    /// SEQUEST XCorr score
+    pub xcorr: f32,
+    /// SEQUEST DeltaCN score
+    pub delta_cn: f32,
+    /// Name of the spectrum file the peptide was identified in
+    pub file_name: String,
+    /// Retention time (minutes), if present in the file
+    pub retention_time: Option<f32>,
+    /// Ion injection time (milliseconds), if present in the file
+    pub injection_time: Option<f32>,
+    /// Accessions of the other proteins in the dataset containing this
+    /// peptide. Only populated by `Dataset::recompute_uniqueness`
+    #[cfg_attr(feature = "serialization", serde(default))]
+    pub shared_with: Vec<String>,
 }
 
 impl Peptide {
-    /// Return a boolean indicating whether the peptide has 2 tryptic sites
+    /// Return a boolean indicating whether the peptide has 2 tryptic sites.
+    /// See `Enzyme::termini` for other enzymes
     pub fn tryptic(&self) -> bool {
         let cterm = self.sequence.ends_with('-');
-        let front = self.sequence.starts_with(|c| match c {
-            'K' | 'R' | '-' => true,
-            _ => false,
-        });
+        let front = self.sequence.starts_with(['K', 'R', '-']);
         let end = self
             .sequence
             .split('.')
-            .skip(1)
-            .next()
+            .nth(1)
             .map(|s| {
                 s.ends_with(|c| match c {
                     'K' | 'R' => true,
@@ -87,10 +186,43 @@ impl Peptide {
     }
 
     /// Return a vector of normalized ratios, where the signal intensity
-    /// for each channel is divided by the sum of all channels
+    /// for each channel is divided by the sum of all channels. Missing
+    /// channels are excluded from the sum, and remain missing
     pub fn ratios(&self) -> Vec<f64> {
-        let total: f64 = self.values.iter().sum::<u32>() as f64;
-        self.values.iter().map(|v| *v as f64 / total).collect()
+        let total = util::sum(&self.values);
+        self.values.iter().map(|v| v / total).collect()
+    }
+
+    /// Return the amino acid sequence of the peptide, without flanking
+    /// residues or modification annotations
+    pub fn stripped_sequence(&self) -> String {
+        let (_, core, _) = modification::split_flanks(&self.sequence);
+        modification::parse(core).0
+    }
+
+    /// Return the number of missed tryptic cleavages: internal K or R
+    /// residues of the stripped sequence that are not followed by P. See
+    /// `Enzyme::missed_cleavages` for other enzymes
+    pub fn missed_cleavages(&self) -> usize {
+        Enzyme::Trypsin.missed_cleavages(self)
+    }
+
+    /// Return the modifications annotated on the peptide sequence, e.g.
+    /// the phosphorylation in "K.S(79.9663)EMPK.R"
+    pub fn modifications(&self) -> Vec<Modification> {
+        let (_, core, _) = modification::split_flanks(&self.sequence);
+        modification::parse(core).1
+    }
+
+    /// Is the intensity of `channel` (0 indexed) missing? Channels beyond
+    /// the end of the values vector are also considered missing
+    pub fn is_missing(&self, channel: usize) -> bool {
+        self.values.get(channel).is_none_or(|v| v.is_nan())
+    }
+
+    /// Return a mask indicating which channels have missing intensities
+    pub fn missing(&self) -> Vec<bool> {
+        self.values.iter().map(|v| v.is_nan()).collect()
     }
 
     /// Swap channels A and B, which are 0 indexed into the peptide values
@@ -121,4 +253,68 @@ mod test {
         assert!(gen_peptide("R.TLDGFK*K.F").tryptic());
         assert!(!gen_peptide("K.KMDKDT.A").tryptic());
     }
+
+    #[test]
+    fn modifications() {
+        let pep = gen_peptide("K.S(79.9663)EM(15.9949)PK.R");
+        assert_eq!(pep.stripped_sequence(), "SEMPK");
+        let mods = pep.modifications();
+        assert_eq!(mods.len(), 2);
+        assert_eq!(mods[1].position, 3);
+        assert_eq!(mods[1].residue, Some('M'));
+        assert_eq!(mods[1].delta, 15.9949);
+    }
+
+    #[test]
+    fn missed_cleavages() {
+        assert_eq!(gen_peptide("K.LVNEVTEFAK.T").missed_cleavages(), 0);
+        assert_eq!(gen_peptide("K.AKPLVRK(42.0106)EK.T").missed_cleavages(), 2);
+        assert_eq!(gen_peptide("R.KRAAK.-").missed_cleavages(), 2);
+    }
+
+    #[test]
+    fn missing_values() {
+        let peptides = vec![
+            Peptide {
+                values: vec![10.0, f64::NAN, f64::NAN],
+                ..Peptide::default()
+            },
+            Peptide {
+                values: vec![30.0, 60.0, f64::NAN],
+                ..Peptide::default()
+            },
+        ];
+        assert_eq!(peptides[0].missing(), vec![false, true, true]);
+        assert!(peptides[0].is_missing(5));
+        assert_eq!(peptides[0].ratios()[0], 1.0);
+        assert!(peptides[0].ratios()[1].is_nan());
+
+        let prot = Protein {
+            peptides,
+            channels: 3,
+            ..Protein::default()
+        };
+        let total = prot.total();
+        assert_eq!(&total[..2], &[40.0, 60.0]);
+        assert!(total[2].is_nan());
+        assert_eq!(prot.ratios()[0], 0.4);
+    }
+
+    #[test]
+    fn peptide_order() {
+        let pep = |scan, file: &str| Peptide {
+            scan,
+            file_name: file.into(),
+            ..Peptide::default()
+        };
+        let mut prot = Protein {
+            peptides: vec![pep(20, "a"), pep(10, "b"), pep(10, "a")],
+            ..Protein::default()
+        };
+        prot.sort_peptides_by_scan();
+        assert_eq!(
+            prot.peptides,
+            vec![pep(10, "a"), pep(10, "b"), pep(20, "a")]
+        );
+    }
 }
@@ -0,0 +1,107 @@
+//! Ratio-based (SILAC, 15N) Census quantification data
+use super::*;
+#[cfg(feature = "serialization")]
+use serde::{Deserialize, Serialize};
+
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Clone, Debug, Default)]
+/// Container for proteomics data read from a ratio-format Census file, where
+/// each peptide is quantified as a sample/reference ratio rather than as a
+/// set of isobaric channel intensities
+pub struct RatioDataset {
+    /// Ratio data for each protein in the dataset
+    pub proteins: Vec<RatioProtein>,
+    /// Header information from the Census file the dataset was read from
+    pub header: Header,
+}
+
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+#[derive(PartialEq, PartialOrd, Clone, Debug, Default)]
+/// Protein-level ratio quantification data.
+///
+/// Ratios that are missing or reported as "NA" are stored as `NaN`
+pub struct RatioProtein {
+    /// Uniprot accession identifier
+    pub accession: String,
+    /// Long-form description
+    pub description: String,
+    /// Accessions of the other members of the protein group
+    pub alternatives: Vec<String>,
+    /// Average of the peptide ratios
+    #[cfg_attr(feature = "serialization", serde(with = "crate::nan"))]
+    pub average_ratio: f64,
+    /// Standard deviation of the peptide ratios
+    #[cfg_attr(feature = "serialization", serde(with = "crate::nan"))]
+    pub standard_deviation: f64,
+    /// Ratio calculated from the combined peptide chromatograms
+    #[cfg_attr(feature = "serialization", serde(with = "crate::nan"))]
+    pub composite_ratio: f64,
+    /// Standard deviation of the composite ratio
+    #[cfg_attr(feature = "serialization", serde(with = "crate::nan"))]
+    pub composite_ratio_standard_deviation: f64,
+    /// Number of quantified peptides
+    pub peptide_count: u16,
+    /// Number of spectral counts
+    pub spectral_count: u16,
+    /// Ratio of the summed sample and reference peak areas
+    #[cfg_attr(feature = "serialization", serde(with = "crate::nan"))]
+    pub area_ratio: f64,
+    /// Peptide-level ratio data
+    pub peptides: Vec<RatioPeptide>,
+}
+
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+#[derive(PartialEq, PartialOrd, Clone, Debug, Default)]
+/// Peptide-level ratio quantification data
+pub struct RatioPeptide {
+    /// Peptide sequence
+    pub sequence: String,
+    /// Is this a unique peptide?
+    pub unique: bool,
+    /// Was this peptide listed on a redundant ("&S") line?
+    pub redundant: bool,
+    /// Sample/reference ratio from linear regression
+    #[cfg_attr(feature = "serialization", serde(with = "crate::nan"))]
+    pub ratio: f64,
+    /// Reference/sample ratio from linear regression
+    #[cfg_attr(feature = "serialization", serde(with = "crate::nan"))]
+    pub rev_slope_ratio: f64,
+    /// Correlation coefficient of the sample and reference chromatograms
+    #[cfg_attr(feature = "serialization", serde(with = "crate::nan"))]
+    pub regression_factor: f64,
+    /// Determinant factor of the regression
+    #[cfg_attr(feature = "serialization", serde(with = "crate::nan"))]
+    pub determinant_factor: f64,
+    /// Integrated sample (light) intensity
+    #[cfg_attr(feature = "serialization", serde(with = "crate::nan"))]
+    pub sample_intensity: f64,
+    /// Integrated reference (heavy) intensity
+    #[cfg_attr(feature = "serialization", serde(with = "crate::nan"))]
+    pub reference_intensity: f64,
+    /// Ratio of the sample and reference peak areas
+    #[cfg_attr(feature = "serialization", serde(with = "crate::nan"))]
+    pub area_ratio: f64,
+    /// Chromatographic profile score
+    #[cfg_attr(feature = "serialization", serde(with = "crate::nan"))]
+    pub profile_score: f64,
+    /// SEQUEST XCorr score
+    pub xcorr: f32,
+    /// SEQUEST DeltaCN score
+    pub delta_cn: f32,
+    pub scan: usize,
+    /// Precursor charge state
+    pub charge: u8,
+    /// Name of the spectrum file the peptide was identified in
+    pub file_name: String,
+}
+
+impl RatioDataset {
+    /// Return a set of all UniProt KB accession ID's present in the
+    /// `RatioDataset`
+    pub fn accessions(&self) -> std::collections::HashSet<&'_ str> {
+        self.proteins
+            .iter()
+            .map(|pr| pr.accession.as_ref())
+            .collect()
+    }
+}
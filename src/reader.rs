@@ -0,0 +1,161 @@
+//! Open Census files from disk, transparently decompressing gzip or zstd
+//! compressed input when the corresponding feature is enabled
+use super::*;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Open the file at `path` for reading. Compressed files are detected by
+/// their magic bytes (not their extension) and decompressed on the fly.
+///
+/// Returns an error of kind `Unsupported` if the file is compressed but the
+/// feature required to decompress it ("gzip" or "zstd") is disabled
+pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Box<dyn Read>> {
+    let mut file = BufReader::new(File::open(path)?);
+    let magic = file.fill_buf()?;
+    if magic.starts_with(&GZIP_MAGIC) {
+        gzip(file)
+    } else if magic.starts_with(&ZSTD_MAGIC) {
+        zstd(file)
+    } else {
+        Ok(Box::new(file))
+    }
+}
+
+/// Read the complete contents of a (possibly compressed) file into a string
+pub fn read_to_string<P: AsRef<Path>>(path: P) -> io::Result<String> {
+    let mut buf = String::new();
+    open(path)?.read_to_string(&mut buf)?;
+    Ok(buf)
+}
+
+#[cfg(feature = "gzip")]
+fn gzip(file: BufReader<File>) -> io::Result<Box<dyn Read>> {
+    // Multi-member decoding handles concatenated and bgzip'd files
+    Ok(Box::new(flate2::bufread::MultiGzDecoder::new(file)))
+}
+
+#[cfg(not(feature = "gzip"))]
+fn gzip(_: BufReader<File>) -> io::Result<Box<dyn Read>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "gzip compressed input requires the \"gzip\" feature",
+    ))
+}
+
+#[cfg(feature = "zstd")]
+fn zstd(file: BufReader<File>) -> io::Result<Box<dyn Read>> {
+    Ok(Box::new(zstd::stream::read::Decoder::with_buffer(file)?))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zstd(_: BufReader<File>) -> io::Result<Box<dyn Read>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "zstd compressed input requires the \"zstd\" feature",
+    ))
+}
+
+/// Error that may occur while reading a Census file from disk
+#[derive(Debug)]
+pub enum ReadError {
+    /// The file could not be opened, read, or decompressed
+    Io(io::Error),
+    /// The file contents could not be parsed
+    Parse(Error),
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReadError::Io(e) => write!(f, "Error reading file: {}", e),
+            ReadError::Parse(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ReadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReadError::Io(e) => Some(e),
+            ReadError::Parse(e) => Some(e),
+        }
+    }
+}
+
+impl From<io::Error> for ReadError {
+    fn from(e: io::Error) -> Self {
+        ReadError::Io(e)
+    }
+}
+
+impl From<Error> for ReadError {
+    fn from(e: Error) -> Self {
+        ReadError::Parse(e)
+    }
+}
+
+/// Read and parse the (possibly compressed) census file at `path`
+pub fn read_census_path<P: AsRef<Path>>(path: P) -> Result<Dataset, ReadError> {
+    let input = read_to_string(path)?;
+    Ok(read_census(&input)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+
+    const SAMPLE: &str = "H\tPLINE\tLOCUS\tSPEC_COUNT\tSEQ_COUNT\tSEQ_COVERAGE\tMOLWT\tDESCRIPTION
+H\tSLINE\tUNIQUE\tSEQUENCE\tm/z_126.127726_int\tnorm_m/z_126.127726_int\tm/z_127.124761_int\tnorm_m/z_127.124761_int
+P\tP12345\t1\t1\t12.5%\t45000\tSerum albumin
+S\tU\tK.LVNEVTEFAK.T\t1000\t0.25\t3000\t0.75
+";
+
+    fn write_temp(name: &str, data: &[u8]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("census-{}-{}", std::process::id(), name));
+        File::create(&path).unwrap().write_all(data).unwrap();
+        path
+    }
+
+    #[test]
+    fn read_plain() {
+        let path = write_temp("plain.txt", SAMPLE.as_bytes());
+        let data = read_census_path(&path).unwrap();
+        assert_eq!(data.proteins[0].peptides[0].values, vec![1000.0, 3000.0]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn read_errors() {
+        let missing = std::env::temp_dir().join("census-does-not-exist.txt");
+        assert!(matches!(read_census_path(missing), Err(ReadError::Io(_))));
+
+        let path = write_temp("invalid.txt", b"X\tnot a census file");
+        assert!(matches!(read_census_path(&path), Err(ReadError::Parse(_))));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn read_gzip() {
+        let mut enc = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        enc.write_all(SAMPLE.as_bytes()).unwrap();
+        let path = write_temp("sample.txt.gz", &enc.finish().unwrap());
+        assert_eq!(read_to_string(&path).unwrap(), SAMPLE);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn read_zstd() {
+        let compressed = zstd::stream::encode_all(SAMPLE.as_bytes(), 0).unwrap();
+        let path = write_temp("sample.txt.zst", &compressed);
+        assert_eq!(read_to_string(&path).unwrap(), SAMPLE);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,276 @@
+//! Summarize peptide intensities into protein-level quantities
+use super::*;
+#[cfg(feature = "serialization")]
+use serde::{Deserialize, Serialize};
+
+/// Strategy for combining the peptide intensities of a protein into a
+/// single intensity per channel. Missing peptide intensities are ignored
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Rollup {
+    /// Sum of the peptide intensities, as in `Protein::total`
+    Sum,
+    /// Median of the peptide intensities
+    Median,
+    /// Mean of the peptide intensities
+    Mean,
+    /// Mean intensity of the N peptides with the highest summed intensity
+    /// across all channels
+    TopN(usize),
+    /// Mean of the peptide intensities, weighted by each peptide's summed
+    /// intensity across all channels
+    IntensityWeighted,
+    /// Tukey's median polish of the log2 peptide-by-channel intensity
+    /// matrix, as used by MSstatsTMT. The protein abundance of a channel is
+    /// the overall effect plus the channel effect, transformed back from
+    /// log2 scale. Zero intensities are treated as missing
+    MedianPolish,
+}
+
+/// Maximum number of median polish iterations
+const POLISH_ITERATIONS: usize = 10;
+
+/// Median polish stops once the sum of absolute residuals changes by less
+/// than this fraction between iterations
+const POLISH_TOLERANCE: f64 = 0.01;
+
+/// Fit `matrix[row][column] = overall + row effect + column effect +
+/// residual` by Tukey's median polish, ignoring missing (`NaN`) cells.
+/// Returns `overall + column effect` of each column
+fn median_polish(mut matrix: Vec<Vec<f64>>, columns: usize) -> Vec<f64> {
+    let mut overall = 0.0;
+    let mut row_effects = vec![0.0; matrix.len()];
+    let mut col_effects = vec![0.0; columns];
+    let mut last = 0.0;
+
+    for _ in 0..POLISH_ITERATIONS {
+        for (row, effect) in matrix.iter_mut().zip(row_effects.iter_mut()) {
+            let m = util::median(row);
+            if !m.is_nan() {
+                row.iter_mut().for_each(|x| *x -= m);
+                *effect += m;
+            }
+        }
+        let m = util::median(&col_effects);
+        col_effects.iter_mut().for_each(|x| *x -= m);
+        overall += m;
+
+        for (c, effect) in col_effects.iter_mut().enumerate() {
+            let column = matrix.iter().map(|row| row[c]).collect::<Vec<_>>();
+            let m = util::median(&column);
+            if !m.is_nan() {
+                matrix.iter_mut().for_each(|row| row[c] -= m);
+                *effect += m;
+            }
+        }
+        let m = util::median(&row_effects);
+        row_effects.iter_mut().for_each(|x| *x -= m);
+        overall += m;
+
+        let residual = matrix
+            .iter()
+            .flatten()
+            .filter(|x| !x.is_nan())
+            .map(|x| x.abs())
+            .sum::<f64>();
+        if residual == 0.0 || (residual - last).abs() < POLISH_TOLERANCE * residual {
+            break;
+        }
+        last = residual;
+    }
+
+    (0..columns)
+        .map(|c| {
+            if matrix.iter().all(|row| row[c].is_nan()) {
+                f64::NAN
+            } else {
+                overall + col_effects[c]
+            }
+        })
+        .collect()
+}
+
+/// Return the intensity of each peptide in `channel`
+fn channel_values(peptides: &[&Peptide], channel: usize) -> Vec<f64> {
+    peptides
+        .iter()
+        .map(|pep| pep.values.get(channel).copied().unwrap_or(f64::NAN))
+        .collect()
+}
+
+impl Protein {
+    /// Return the protein-level intensity of each channel, combining
+    /// peptide intensities with the given `Rollup` strategy. A channel is
+    /// missing (`NaN`) if it is missing from every peptide used
+    pub fn quantify(&self, rollup: Rollup) -> Vec<f64> {
+        self.quantify_scaled(rollup, Scale::Linear)
+    }
+
+    /// Return the protein-level intensity of each channel, as by
+    /// `Protein::quantify`, of peptide intensities on `scale`. The result
+    /// is on the same scale: on log2 scale, `MedianPolish` is fit to the
+    /// intensities as they are, `Sum` adds up the linear intensities, and
+    /// peptides are ranked and weighted by their linear intensities
+    pub fn quantify_scaled(&self, rollup: Rollup, scale: Scale) -> Vec<f64> {
+        let channels = self.channels as usize;
+        let linear_sum = |pep: &Peptide| {
+            let values = pep
+                .values
+                .iter()
+                .map(|&v| scale.linear(v))
+                .collect::<Vec<_>>();
+            util::sum(&values)
+        };
+        let peptides: Vec<&Peptide> = match rollup {
+            Rollup::TopN(n) => {
+                let mut ranked = self
+                    .peptides
+                    .iter()
+                    .map(|pep| (linear_sum(pep), pep))
+                    .collect::<Vec<_>>();
+                ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+                ranked.into_iter().take(n).map(|(_, pep)| pep).collect()
+            }
+            _ => self.peptides.iter().collect(),
+        };
+
+        if rollup == Rollup::MedianPolish {
+            let matrix = peptides
+                .iter()
+                .map(|pep| {
+                    (0..channels)
+                        .map(|c| match (pep.values.get(c), scale) {
+                            (Some(&v), Scale::Log2(_)) => v,
+                            (Some(&v), Scale::Linear) if v > 0.0 => v.log2(),
+                            _ => f64::NAN,
+                        })
+                        .collect()
+                })
+                .collect();
+            let polished = median_polish(matrix, channels);
+            return match scale {
+                Scale::Linear => polished.into_iter().map(f64::exp2).collect(),
+                Scale::Log2(_) => polished,
+            };
+        }
+
+        (0..channels)
+            .map(|c| {
+                let values = channel_values(&peptides, c);
+                if util::count(&values) == 0 {
+                    return f64::NAN;
+                }
+                match (rollup, scale) {
+                    (Rollup::Sum, Scale::Linear) => util::sum(&values),
+                    (Rollup::Sum, Scale::Log2(pseudocount)) => {
+                        let linear = values.iter().map(|&v| scale.linear(v)).collect::<Vec<_>>();
+                        (util::sum(&linear) + pseudocount).log2()
+                    }
+                    (Rollup::Median, _) => util::median(&values),
+                    (Rollup::Mean, _) | (Rollup::TopN(_), _) => util::mean(&values),
+                    (Rollup::IntensityWeighted, _) => {
+                        let (mut num, mut den) = (0.0, 0.0);
+                        for (value, pep) in values.iter().zip(&peptides) {
+                            if !value.is_nan() {
+                                let w = linear_sum(pep);
+                                num += w * value;
+                                den += w;
+                            }
+                        }
+                        num / den
+                    }
+                    (Rollup::MedianPolish, _) => unreachable!(),
+                }
+            })
+            .collect()
+    }
+}
+
+impl Dataset {
+    /// Return the protein-by-channel intensity matrix, with one row per
+    /// protein in dataset order, on the scale of the dataset, see
+    /// `Protein::quantify_scaled`
+    pub fn rollup_matrix(&self, rollup: Rollup) -> Vec<Vec<f64>> {
+        self.proteins
+            .iter()
+            .map(|prot| prot.quantify_scaled(rollup, self.scale))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn protein() -> Protein {
+        let peptide = |values: Vec<f64>| Peptide {
+            values,
+            ..Peptide::default()
+        };
+        Protein {
+            channels: 2,
+            peptides: vec![
+                peptide(vec![10.0, 30.0]),
+                peptide(vec![20.0, f64::NAN]),
+                peptide(vec![1000.0, 3000.0]),
+                peptide(vec![40.0, 50.0]),
+            ],
+            ..Protein::default()
+        }
+    }
+
+    #[test]
+    fn rollups() {
+        let prot = protein();
+        assert_eq!(prot.quantify(Rollup::Sum), prot.total());
+        assert_eq!(prot.quantify(Rollup::Median), vec![30.0, 50.0]);
+        assert_eq!(prot.quantify(Rollup::Mean), vec![267.5, 3080.0 / 3.0]);
+        assert_eq!(prot.quantify(Rollup::TopN(2)), vec![520.0, 1525.0]);
+
+        let weighted = prot.quantify(Rollup::IntensityWeighted);
+        let expected = (10.0 * 40.0 + 20.0 * 20.0 + 1000.0 * 4000.0 + 40.0 * 90.0) / 4150.0;
+        assert!((weighted[0] - expected).abs() < 1e-9);
+
+        let data = Dataset {
+            channels: 2,
+            proteins: vec![prot],
+            ..Dataset::default()
+        };
+        assert_eq!(data.rollup_matrix(Rollup::Median), vec![vec![30.0, 50.0]]);
+    }
+
+    #[test]
+    fn median_polish() {
+        // Additive on log2 scale: peptide effects of 0, 1 and 3, and
+        // channel effects of 0 and 2
+        let peptide = |values: Vec<f64>| Peptide {
+            values,
+            ..Peptide::default()
+        };
+        let mut prot = Protein {
+            channels: 2,
+            peptides: vec![
+                peptide(vec![4.0, 16.0]),
+                peptide(vec![8.0, 32.0]),
+                peptide(vec![32.0, f64::NAN]),
+            ],
+            ..Protein::default()
+        };
+        let abundance = prot.quantify(Rollup::MedianPolish);
+        assert!((abundance[1] / abundance[0] - 4.0).abs() < 1e-9);
+
+        // A single outlier peptide barely moves the estimate
+        prot.peptides.push(peptide(vec![8.0, 1e6]));
+        let abundance = prot.quantify(Rollup::MedianPolish);
+        assert!((abundance[1] / abundance[0] - 4.0).abs() < 1e-9);
+
+        // Log2 intensities are not log transformed again
+        let mut data = Dataset::default().with_proteins(vec![prot]);
+        data.channels = 2;
+        data.log2_transform(0.0);
+        let log = &data.rollup_matrix(Rollup::MedianPolish)[0];
+        assert!((log[1] - log[0] - 2.0).abs() < 1e-9);
+        let sum = data.proteins[0].quantify_scaled(Rollup::Sum, data.scale);
+        assert!((sum[0] - 52.0f64.log2()).abs() < 1e-9);
+    }
+}
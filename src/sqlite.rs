@@ -0,0 +1,414 @@
+//! Export of a `Dataset` to an SQLite database, and loading it back
+//!
+//! The database has a `proteins` table, a `peptides` table keyed to its
+//! protein by `protein_id`, and an `intensities` table with one row per
+//! peptide and channel, keyed by `peptide_id`. Proteins are indexed by
+//! accession and peptides by sequence. Missing intensities (`NaN`) are
+//! stored as `NULL`. Lists, such as the alternative accessions of a
+//! protein, are stored as ";"-separated text.
+//!
+//! The `dataset`, `header` and `channels` tables hold the number of
+//! channels and intensity scale, the raw header lines, and the channel
+//! annotations, so that a dataset can be loaded back unchanged.
+use super::*;
+use rusqlite::{params, Connection, Result};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::path::Path;
+
+const SCHEMA: &str = "
+CREATE TABLE dataset (
+    channels INTEGER NOT NULL,
+    scale TEXT NOT NULL,
+    pseudocount REAL
+);
+CREATE TABLE header (line TEXT NOT NULL);
+CREATE TABLE channels (
+    channel INTEGER PRIMARY KEY,
+    label TEXT NOT NULL,
+    sample TEXT,
+    condition TEXT,
+    replicate INTEGER,
+    plex INTEGER
+);
+CREATE TABLE proteins (
+    id INTEGER PRIMARY KEY,
+    accession TEXT NOT NULL,
+    description TEXT NOT NULL,
+    alternatives TEXT NOT NULL,
+    gene TEXT,
+    spectral_count INTEGER NOT NULL,
+    sequence_count INTEGER NOT NULL,
+    sequence_coverage REAL NOT NULL,
+    molecular_weight INTEGER NOT NULL
+);
+CREATE TABLE peptides (
+    id INTEGER PRIMARY KEY,
+    protein_id INTEGER NOT NULL REFERENCES proteins(id),
+    sequence TEXT NOT NULL,
+    is_unique INTEGER NOT NULL,
+    is_redundant INTEGER NOT NULL,
+    purity REAL NOT NULL,
+    scan INTEGER NOT NULL,
+    charge INTEGER NOT NULL,
+    xcorr REAL NOT NULL,
+    delta_cn REAL NOT NULL,
+    file_name TEXT NOT NULL,
+    retention_time REAL,
+    injection_time REAL,
+    shared_with TEXT NOT NULL
+);
+CREATE TABLE intensities (
+    peptide_id INTEGER NOT NULL REFERENCES peptides(id),
+    channel INTEGER NOT NULL,
+    value REAL,
+    norm_value REAL,
+    PRIMARY KEY (peptide_id, channel)
+);
+CREATE INDEX proteins_accession ON proteins(accession);
+CREATE INDEX peptides_sequence ON peptides(sequence);
+CREATE INDEX peptides_protein ON peptides(protein_id);
+";
+
+/// Map `NaN` to `NULL`
+fn nullable(value: f64) -> Option<f64> {
+    if value.is_nan() {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+/// Split ";"-separated text into a list
+fn split(list: String) -> Vec<String> {
+    list.split(';')
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+impl Dataset {
+    /// Write the dataset to an SQLite database at `path`, which is created
+    /// if it does not exist. The database must not already contain the
+    /// census tables
+    pub fn to_sqlite<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut conn = Connection::open(path)?;
+        let tx = conn.transaction()?;
+        tx.execute_batch(SCHEMA)?;
+
+        let (scale, pseudocount) = match self.scale {
+            Scale::Linear => ("linear", None),
+            Scale::Log2(pseudocount) => ("log2", Some(pseudocount)),
+        };
+        tx.execute(
+            "INSERT INTO dataset VALUES (?1, ?2, ?3)",
+            params![self.channels, scale, pseudocount],
+        )?;
+        {
+            let mut stmt = tx.prepare("INSERT INTO header VALUES (?1)")?;
+            for line in &self.header.lines {
+                stmt.execute([line])?;
+            }
+            let mut stmt = tx.prepare("INSERT INTO channels VALUES (?1, ?2, ?3, ?4, ?5, ?6)")?;
+            for (c, info) in self.channel_info.iter().enumerate() {
+                stmt.execute(params![
+                    c as i64,
+                    info.label,
+                    info.sample,
+                    info.condition,
+                    info.replicate,
+                    info.plex
+                ])?;
+            }
+
+            let mut proteins =
+                tx.prepare("INSERT INTO proteins VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)")?;
+            let mut peptides = tx.prepare(
+                "INSERT INTO peptides VALUES
+                 (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            )?;
+            let mut intensities = tx.prepare("INSERT INTO intensities VALUES (?1, ?2, ?3, ?4)")?;
+            let mut peptide_id = 0i64;
+            for (protein_id, prot) in self.proteins.iter().enumerate() {
+                proteins.execute(params![
+                    protein_id as i64,
+                    prot.accession,
+                    prot.description,
+                    prot.alternatives.join(";"),
+                    prot.gene,
+                    prot.spectral_count,
+                    prot.sequence_count,
+                    prot.sequence_coverage,
+                    prot.molecular_weight
+                ])?;
+                for pep in &prot.peptides {
+                    peptides.execute(params![
+                        peptide_id,
+                        protein_id as i64,
+                        pep.sequence,
+                        pep.unique,
+                        pep.redundant,
+                        pep.purity,
+                        pep.scan as i64,
+                        pep.charge,
+                        pep.xcorr,
+                        pep.delta_cn,
+                        pep.file_name,
+                        pep.retention_time,
+                        pep.injection_time,
+                        pep.shared_with.join(";")
+                    ])?;
+                    for (c, &value) in pep.values.iter().enumerate() {
+                        let norm = pep.norm_values.get(c).copied().and_then(nullable);
+                        intensities.execute(params![
+                            peptide_id,
+                            c as i64,
+                            nullable(value),
+                            norm
+                        ])?;
+                    }
+                    peptide_id += 1;
+                }
+            }
+        }
+        tx.commit()
+    }
+
+    /// Load a dataset written by `Dataset::to_sqlite`
+    pub fn from_sqlite<P: AsRef<Path>>(path: P) -> Result<Dataset> {
+        Dataset::from_sqlite_where(path, "1")
+    }
+
+    /// Load the proteins matching an SQL `condition` on the `proteins`
+    /// table, e.g. `"gene = 'ALB'"` or `"spectral_count >= 5"`, from a
+    /// database written by `Dataset::to_sqlite`. Only the matching rows are
+    /// read from the database.
+    ///
+    /// The condition is inserted into a `WHERE` clause on the `proteins`
+    /// table verbatim, and must come from a trusted source. Intensities with
+    /// a channel outside of the dataset's channels fail with
+    /// `rusqlite::Error::IntegralValueOutOfRange`
+    pub fn from_sqlite_where<P: AsRef<Path>>(path: P, condition: &str) -> Result<Dataset> {
+        let conn = Connection::open(path)?;
+
+        let (channels, scale, pseudocount) = conn.query_row(
+            "SELECT channels, scale, pseudocount FROM dataset",
+            [],
+            |row| {
+                Ok((
+                    row.get::<_, u8>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<f64>>(2)?,
+                ))
+            },
+        )?;
+        let scale = match (scale.as_str(), pseudocount) {
+            ("log2", Some(pseudocount)) => Scale::Log2(pseudocount),
+            _ => Scale::Linear,
+        };
+
+        let mut header = Header::default();
+        let mut stmt = conn.prepare("SELECT line FROM header ORDER BY rowid")?;
+        for line in stmt.query_map([], |row| row.get::<_, String>(0))? {
+            header.push_line(&line?, '\t');
+        }
+
+        let mut stmt = conn.prepare(
+            "SELECT label, sample, condition, replicate, plex FROM channels ORDER BY channel",
+        )?;
+        let channel_info = stmt
+            .query_map([], |row| {
+                Ok(ChannelInfo {
+                    label: row.get(0)?,
+                    sample: row.get(1)?,
+                    condition: row.get(2)?,
+                    replicate: row.get(3)?,
+                    plex: row.get(4)?,
+                })
+            })?
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut proteins = Vec::new();
+        let mut protein_ids = HashMap::new();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT proteins.id, proteins.accession, proteins.description,
+             proteins.alternatives, proteins.gene, proteins.spectral_count,
+             proteins.sequence_count, proteins.sequence_coverage, proteins.molecular_weight
+             FROM proteins WHERE {} ORDER BY proteins.id",
+            condition
+        ))?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let mut prot = Protein {
+                accession: row.get(1)?,
+                description: row.get(2)?,
+                alternatives: split(row.get(3)?),
+                spectral_count: row.get(5)?,
+                sequence_count: row.get(6)?,
+                sequence_coverage: row.get(7)?,
+                molecular_weight: row.get(8)?,
+                channels,
+                ..Protein::default()
+            };
+            prot.annotate();
+            // The gene may have been assigned separately from the
+            // description, see `Dataset::assign_genes`
+            prot.gene = row.get(4)?;
+            protein_ids.insert(row.get::<_, i64>(0)?, proteins.len());
+            proteins.push(prot);
+        }
+
+        // The condition only ever applies to the proteins table, so that
+        // its column names are not ambiguous
+        let selected = format!(
+            "peptides.protein_id IN (SELECT proteins.id FROM proteins WHERE {})",
+            condition
+        );
+        let mut peptide_ids = HashMap::new();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT peptides.id, peptides.protein_id, peptides.sequence, peptides.is_unique,
+             peptides.is_redundant, peptides.purity, peptides.scan, peptides.charge,
+             peptides.xcorr, peptides.delta_cn, peptides.file_name, peptides.retention_time,
+             peptides.injection_time, peptides.shared_with
+             FROM peptides WHERE {} ORDER BY peptides.id",
+            selected
+        ))?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let prot = protein_ids[&row.get::<_, i64>(1)?];
+            let pep = Peptide {
+                sequence: row.get(2)?,
+                values: vec![f64::NAN; channels as usize],
+                unique: row.get(3)?,
+                redundant: row.get(4)?,
+                purity: row.get(5)?,
+                scan: row.get::<_, i64>(6)? as usize,
+                charge: row.get(7)?,
+                xcorr: row.get(8)?,
+                delta_cn: row.get(9)?,
+                file_name: row.get(10)?,
+                retention_time: row.get(11)?,
+                injection_time: row.get(12)?,
+                shared_with: split(row.get(13)?),
+                ..Peptide::default()
+            };
+            peptide_ids.insert(row.get::<_, i64>(0)?, (prot, proteins[prot].peptides.len()));
+            proteins[prot].peptides.push(pep);
+        }
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT intensities.peptide_id, intensities.channel, intensities.value,
+             intensities.norm_value
+             FROM intensities JOIN peptides ON peptides.id = intensities.peptide_id
+             WHERE {}",
+            selected
+        ))?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let (prot, idx) = peptide_ids[&row.get::<_, i64>(0)?];
+            let pep = &mut proteins[prot].peptides[idx];
+            let c = row.get::<_, i64>(1)?;
+            let c = usize::try_from(c)
+                .ok()
+                .filter(|&c| c < channels as usize)
+                .ok_or(rusqlite::Error::IntegralValueOutOfRange(1, c))?;
+            if let Some(value) = row.get::<_, Option<f64>>(2)? {
+                pep.values[c] = value;
+            }
+            // Normalized values are only stored if they were kept by the
+            // parser
+            if let Some(norm) = row.get::<_, Option<f64>>(3)? {
+                if pep.norm_values.is_empty() {
+                    pep.norm_values = vec![f64::NAN; channels as usize];
+                }
+                pep.norm_values[c] = norm;
+            }
+        }
+
+        Ok(Dataset {
+            proteins,
+            channels,
+            header,
+            channel_info,
+            scale,
+            ..Dataset::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sqlite_round_trip() {
+        let mut dataset = Dataset::builder()
+            .protein(
+                Protein::builder("P02768")
+                    .description("Serum albumin OS=Homo sapiens GN=ALB PE=1 SV=2")
+                    .peptide(
+                        Peptide::builder("K.LVNEVTEFAK.T")
+                            .values(vec![1000.0, f64::NAN])
+                            .scan(1234)
+                            .charge(2)
+                            .retention_time(31.5)
+                            .build(),
+                    )
+                    .build()
+                    .unwrap(),
+            )
+            .protein(
+                Protein::builder("P60709")
+                    .peptide(
+                        Peptide::builder("R.AGFAGDDAPR.A")
+                            .values(vec![5.0, 6.0])
+                            .build(),
+                    )
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap();
+        dataset.proteins[1].alternatives = vec!["P63261".into(), "Q562R1".into()];
+        dataset
+            .annotate_channels("channel\tcondition\n126\tctrl\n127\ttreated\n")
+            .unwrap();
+
+        let path = std::env::temp_dir().join(format!("census-{}.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        dataset.to_sqlite(&path).unwrap();
+
+        let loaded = Dataset::from_sqlite(&path).unwrap();
+        let albumin = Dataset::from_sqlite_where(&path, "gene = 'ALB'").unwrap();
+        let first = Dataset::from_sqlite_where(&path, "id = 0").unwrap();
+
+        let conn = Connection::open(&path).unwrap();
+        conn.execute("UPDATE intensities SET channel = 2 WHERE channel = 1", [])
+            .unwrap();
+        drop(conn);
+        let err = Dataset::from_sqlite(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            err,
+            rusqlite::Error::IntegralValueOutOfRange(1, 2)
+        ));
+
+        assert_eq!(loaded.proteins.len(), 2);
+        assert_eq!(loaded.channel_info, dataset.channel_info);
+        assert_eq!(
+            loaded.proteins[1].alternatives,
+            dataset.proteins[1].alternatives
+        );
+        let pep = &loaded.proteins[0].peptides[0];
+        assert_eq!(pep.values[0], 1000.0);
+        assert!(pep.is_missing(1));
+        assert_eq!(pep.retention_time, Some(31.5));
+        assert_eq!(pep.scan, 1234);
+
+        assert_eq!(albumin.proteins.len(), 1);
+        assert_eq!(albumin.proteins[0].accession, "P02768");
+        assert_eq!(albumin.proteins[0].peptides.len(), 1);
+        assert_eq!(first.proteins.len(), 1);
+        assert_eq!(first.proteins[0].peptides[0].values[0], 1000.0);
+    }
+}
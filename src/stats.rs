@@ -0,0 +1,704 @@
+//! Statistical testing of protein intensities between groups of channels
+//!
+//! Tests are performed on log2 protein intensities. On linear scale (see
+//! `Dataset::scale`), protein intensities are the summed peptide
+//! intensities, which are then log2 transformed; on log2 scale, they are the
+//! mean log2 peptide intensities. Missing values are ignored.
+use super::*;
+#[cfg(feature = "serialization")]
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+
+/// Statistical test used by `Dataset::differential_expression_with`
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Copy, Clone, Debug, Default)]
+pub enum TestKind {
+    /// Welch's unequal variances t-test
+    #[default]
+    Welch,
+    /// Mann-Whitney U (Wilcoxon rank-sum) test, which makes no assumption
+    /// of normality. The statistic is the U of group A
+    MannWhitney,
+}
+
+/// Correlation coefficient used by `Dataset::correlation_matrix`
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Copy, Clone, Debug, Default)]
+pub enum CorrelationMethod {
+    /// Pearson's product-moment correlation
+    #[default]
+    Pearson,
+    /// Spearman's rank correlation
+    Spearman,
+}
+
+#[cfg_attr(feature = "serialization", derive(Serialize))]
+#[derive(PartialEq, Clone, Debug)]
+/// Symmetric channel-by-channel correlation matrix
+pub struct CorrelationMatrix {
+    /// Channel names, see `Dataset::channel_names`
+    pub labels: Vec<String>,
+    /// Correlation coefficients: `values[i][j]` is the correlation of
+    /// channels `i` and `j`
+    pub values: Vec<Vec<f64>>,
+}
+
+impl CorrelationMatrix {
+    /// Return the correlation of two channels by name
+    pub fn get(&self, a: &str, b: &str) -> Option<f64> {
+        let i = self.labels.iter().position(|l| l == a)?;
+        let j = self.labels.iter().position(|l| l == b)?;
+        Some(self.values[i][j])
+    }
+}
+
+#[cfg_attr(feature = "serialization", derive(Serialize))]
+#[derive(PartialEq, Clone, Debug)]
+/// Result of testing a single protein for differential expression
+pub struct DifferentialResult {
+    /// Uniprot accession identifier
+    pub accession: String,
+    /// Difference of the mean log2 intensities of group A and group B
+    pub log2_fold_change: f64,
+    /// Test statistic
+    pub statistic: f64,
+    /// Two-sided p-value, or `NaN` if either group has too few values
+    pub p_value: f64,
+    /// Benjamini-Hochberg adjusted p-value
+    pub q_value: f64,
+}
+
+/// Significance cutoffs for `Dataset::volcano`
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct Thresholds {
+    /// Minimum absolute log2 fold change
+    pub log2_fold_change: f64,
+    /// Maximum adjusted p-value
+    pub q_value: f64,
+}
+
+impl Default for Thresholds {
+    /// Two-fold change at 5% FDR
+    fn default() -> Self {
+        Thresholds {
+            log2_fold_change: 1.0,
+            q_value: 0.05,
+        }
+    }
+}
+
+#[cfg_attr(feature = "serialization", derive(Serialize))]
+#[derive(PartialEq, Clone, Debug)]
+/// One row of a volcano plot table
+pub struct VolcanoRow {
+    pub accession: String,
+    /// Gene name, if annotated (see `Protein::annotate`)
+    pub gene: Option<String>,
+    pub log2_fold_change: f64,
+    pub p_value: f64,
+    pub q_value: f64,
+    /// Does the protein pass both thresholds?
+    pub significant: bool,
+}
+
+/// Replace the tabs and line breaks in a text field, which would break the
+/// table layout
+fn clean(field: &str) -> String {
+    field.replace(['\t', '\n', '\r'], " ")
+}
+
+/// Write volcano plot rows as a tab-separated table with a header line.
+/// Missing values are written as "NA", and tabs and line breaks in
+/// accessions and gene names are replaced with spaces
+pub fn write_volcano_tsv<W: Write>(rows: &[VolcanoRow], mut w: W) -> io::Result<()> {
+    let num = |x: f64| {
+        if x.is_nan() {
+            "NA".to_string()
+        } else {
+            x.to_string()
+        }
+    };
+    writeln!(
+        w,
+        "accession\tgene\tlog2_fold_change\tp_value\tq_value\tsignificant"
+    )?;
+    for row in rows {
+        writeln!(
+            w,
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            clean(&row.accession),
+            clean(row.gene.as_deref().unwrap_or("")),
+            num(row.log2_fold_change),
+            num(row.p_value),
+            num(row.q_value),
+            row.significant
+        )?;
+    }
+    Ok(())
+}
+
+/// Write volcano plot rows as a JSON array of objects. Missing values are
+/// written as `null`
+#[cfg(feature = "serialization")]
+pub fn write_volcano_json<W: Write>(rows: &[VolcanoRow], mut w: W) -> io::Result<()> {
+    serde_json::to_writer(&mut w, rows)?;
+    writeln!(w)
+}
+
+/// Natural logarithm of the gamma function (Lanczos approximation)
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.18009172947146,
+        -86.50532032941677,
+        24.01409824083091,
+        -1.231739572450155,
+        0.1208650973866179e-2,
+        -0.5395239384953e-5,
+    ];
+    let tmp = x + 5.5;
+    let tmp = tmp - (x + 0.5) * tmp.ln();
+    let mut ser = 1.000000000190015;
+    for (i, c) in COEFFICIENTS.iter().enumerate() {
+        ser += c / (x + 1.0 + i as f64);
+    }
+    -tmp + (2.5066282746310005 * ser / x).ln()
+}
+
+/// Continued fraction for the incomplete beta function
+fn beta_cf(a: f64, b: f64, x: f64) -> f64 {
+    const MAX_ITERATIONS: usize = 200;
+    const EPS: f64 = 3e-14;
+    const FPMIN: f64 = 1e-300;
+    let (qab, qap, qam) = (a + b, a + 1.0, a - 1.0);
+    let mut c = 1.0;
+    let mut d = 1.0 - qab * x / qap;
+    if d.abs() < FPMIN {
+        d = FPMIN;
+    }
+    d = 1.0 / d;
+    let mut h = d;
+    for m in 1..=MAX_ITERATIONS {
+        let m = m as f64;
+        let m2 = 2.0 * m;
+        let aa = m * (b - m) * x / ((qam + m2) * (a + m2));
+        d = 1.0 + aa * d;
+        if d.abs() < FPMIN {
+            d = FPMIN;
+        }
+        c = 1.0 + aa / c;
+        if c.abs() < FPMIN {
+            c = FPMIN;
+        }
+        d = 1.0 / d;
+        h *= d * c;
+        let aa = -(a + m) * (qab + m) * x / ((a + m2) * (qap + m2));
+        d = 1.0 + aa * d;
+        if d.abs() < FPMIN {
+            d = FPMIN;
+        }
+        c = 1.0 + aa / c;
+        if c.abs() < FPMIN {
+            c = FPMIN;
+        }
+        d = 1.0 / d;
+        let del = d * c;
+        h *= del;
+        if (del - 1.0).abs() < EPS {
+            break;
+        }
+    }
+    h
+}
+
+/// Regularized incomplete beta function I_x(a, b)
+fn beta_inc(a: f64, b: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+    let front =
+        (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_cf(a, b, x) / a
+    } else {
+        1.0 - front * beta_cf(b, a, 1.0 - x) / b
+    }
+}
+
+/// Complementary error function (Chebyshev approximation, with a fractional
+/// error below 1.2e-7)
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let poly = -z * z - 1.26551223
+        + t * (1.00002368
+            + t * (0.37409196
+                + t * (0.09678418
+                    + t * (-0.18628806
+                        + t * (0.27886807
+                            + t * (-1.13520398
+                                + t * (1.48851587 + t * (-0.82215223 + t * 0.17087277))))))));
+    let ans = t * poly.exp();
+    if x >= 0.0 {
+        ans
+    } else {
+        2.0 - ans
+    }
+}
+
+/// Two-sided p-value of Student's t distribution with `df` degrees of
+/// freedom
+fn t_test_p(t: f64, df: f64) -> f64 {
+    beta_inc(df / 2.0, 0.5, df / (df + t * t))
+}
+
+/// Sample variance, ignoring missing values
+pub(crate) fn variance(values: &[f64]) -> f64 {
+    let mean = util::mean(values);
+    let n = util::count(values) as f64;
+    values
+        .iter()
+        .filter(|x| !x.is_nan())
+        .map(|x| (x - mean).powi(2))
+        .sum::<f64>()
+        / (n - 1.0)
+}
+
+/// Welch's unequal variances t-test, returning `(t, p)`
+pub fn welch_t_test(a: &[f64], b: &[f64]) -> (f64, f64) {
+    let (na, nb) = (util::count(a) as f64, util::count(b) as f64);
+    if na < 2.0 || nb < 2.0 {
+        return (f64::NAN, f64::NAN);
+    }
+    let (va, vb) = (variance(a) / na, variance(b) / nb);
+    let se = (va + vb).sqrt();
+    if se == 0.0 {
+        return (f64::NAN, f64::NAN);
+    }
+    let t = (util::mean(a) - util::mean(b)) / se;
+    let df = (va + vb).powi(2) / (va.powi(2) / (na - 1.0) + vb.powi(2) / (nb - 1.0));
+    (t, t_test_p(t, df))
+}
+
+/// Benjamini-Hochberg adjustment of p-values. Missing (`NaN`) p-values are
+/// not counted as tests, and remain missing
+pub fn benjamini_hochberg(p_values: &[f64]) -> Vec<f64> {
+    let mut order = (0..p_values.len())
+        .filter(|&i| !p_values[i].is_nan())
+        .collect::<Vec<_>>();
+    order.sort_by(|&a, &b| p_values[a].total_cmp(&p_values[b]));
+    let m = order.len() as f64;
+    let mut q = vec![f64::NAN; p_values.len()];
+    let mut min = 1.0f64;
+    for (rank, &i) in order.iter().enumerate().rev() {
+        min = min.min(p_values[i] * m / (rank + 1) as f64);
+        q[i] = min;
+    }
+    q
+}
+
+/// Pearson correlation of the pairs of values where both are present
+pub fn pearson(a: &[f64], b: &[f64]) -> f64 {
+    let (a, b): (Vec<f64>, Vec<f64>) = a
+        .iter()
+        .zip(b)
+        .filter(|(x, y)| !x.is_nan() && !y.is_nan())
+        .unzip();
+    if a.len() < 2 {
+        return f64::NAN;
+    }
+    let (ma, mb) = (util::mean(&a), util::mean(&b));
+    let (mut cov, mut va, mut vb) = (0.0, 0.0, 0.0);
+    for (x, y) in a.iter().zip(&b) {
+        cov += (x - ma) * (y - mb);
+        va += (x - ma).powi(2);
+        vb += (y - mb).powi(2);
+    }
+    cov / (va * vb).sqrt()
+}
+
+/// Spearman rank correlation of the pairs of values where both are present.
+/// Tied values receive their mean rank
+pub fn spearman(a: &[f64], b: &[f64]) -> f64 {
+    let (a, b): (Vec<f64>, Vec<f64>) = a
+        .iter()
+        .zip(b)
+        .filter(|(x, y)| !x.is_nan() && !y.is_nan())
+        .unzip();
+    pearson(&ranks(&a), &ranks(&b))
+}
+
+/// Rank of each value (1-based), assigning tied values their mean rank
+fn ranks(values: &[f64]) -> Vec<f64> {
+    let mut order = (0..values.len()).collect::<Vec<_>>();
+    order.sort_by(|&a, &b| values[a].total_cmp(&values[b]));
+    let mut ranks = vec![0.0; values.len()];
+    let mut i = 0;
+    while i < order.len() {
+        let j = (i..order.len())
+            .find(|&j| values[order[j]] != values[order[i]])
+            .unwrap_or(order.len());
+        for &idx in &order[i..j] {
+            ranks[idx] = (i + j + 1) as f64 / 2.0;
+        }
+        i = j;
+    }
+    ranks
+}
+
+/// Largest combined sample size for which exact Mann-Whitney p-values are
+/// calculated
+const EXACT_LIMIT: usize = 50;
+
+/// Mann-Whitney U test, returning `(U, p)` where U is the statistic of `a`.
+///
+/// Without ties, p-values are exact for small samples; otherwise the normal
+/// approximation with tie and continuity corrections is used
+pub fn mann_whitney(a: &[f64], b: &[f64]) -> (f64, f64) {
+    let a = a
+        .iter()
+        .copied()
+        .filter(|x| !x.is_nan())
+        .collect::<Vec<_>>();
+    let b = b
+        .iter()
+        .copied()
+        .filter(|x| !x.is_nan())
+        .collect::<Vec<_>>();
+    let (na, nb) = (a.len(), b.len());
+    if na == 0 || nb == 0 {
+        return (f64::NAN, f64::NAN);
+    }
+    let n = na + nb;
+
+    // Midranks of the pooled values, with the sizes of any tied groups
+    let mut pooled = a.iter().map(|&x| (x, true)).collect::<Vec<_>>();
+    pooled.extend(b.iter().map(|&x| (x, false)));
+    pooled.sort_by(|x, y| x.0.total_cmp(&y.0));
+    let (mut rank_sum, mut ties) = (0.0, Vec::new());
+    let mut i = 0;
+    while i < n {
+        let j = (i..n).find(|&j| pooled[j].0 != pooled[i].0).unwrap_or(n);
+        let rank = (i + j + 1) as f64 / 2.0;
+        rank_sum += rank * pooled[i..j].iter().filter(|x| x.1).count() as f64;
+        if j - i > 1 {
+            ties.push((j - i) as f64);
+        }
+        i = j;
+    }
+    let u = rank_sum - (na * (na + 1)) as f64 / 2.0;
+
+    let p = if ties.is_empty() && n <= EXACT_LIMIT {
+        let counts = rank_sum_counts(na, n);
+        let total = counts.iter().sum::<f64>();
+        let u = u as usize;
+        let lower = counts[..=u].iter().sum::<f64>() / total;
+        let upper = counts[u..].iter().sum::<f64>() / total;
+        (2.0 * lower.min(upper)).min(1.0)
+    } else {
+        let (na, nb, n) = (na as f64, nb as f64, n as f64);
+        let tie = ties.iter().map(|t| t.powi(3) - t).sum::<f64>();
+        let sigma = (na * nb / 12.0 * ((n + 1.0) - tie / (n * (n - 1.0)))).sqrt();
+        if sigma == 0.0 {
+            return (u, f64::NAN);
+        }
+        let delta = (u - na * nb / 2.0).abs();
+        let z = (delta - 0.5).max(0.0) / sigma;
+        erfc(z / std::f64::consts::SQRT_2).min(1.0)
+    };
+    (u, p)
+}
+
+/// Number of ways to choose `k` of the ranks `1..=n`, indexed by the
+/// resulting U statistic
+fn rank_sum_counts(k: usize, n: usize) -> Vec<f64> {
+    let max = k * (n - k);
+    // ways[j][u]: ways to choose j ranks from those seen so far, where `u`
+    // is the rank sum less its minimum j(j+1)/2
+    let mut ways = vec![vec![0.0; max + 1]; k + 1];
+    ways[0][0] = 1.0;
+    for rank in 0..n {
+        for j in (1..=k.min(rank + 1)).rev() {
+            // Adding rank `rank + 1` as the j-th pick adds `rank - (j - 1)`
+            let shift = rank + 1 - j;
+            for u in (shift..=max).rev() {
+                ways[j][u] += ways[j - 1][u - shift];
+            }
+        }
+    }
+    ways.swap_remove(k)
+}
+
+impl Dataset {
+    /// Return the log2 intensity of each channel of `prot`
+    pub(crate) fn log2_protein(&self, prot: &Protein) -> Vec<f64> {
+        match self.scale {
+            Scale::Linear => prot
+                .total()
+                .into_iter()
+                .map(|x| if x > 0.0 { x.log2() } else { f64::NAN })
+                .collect(),
+            Scale::Log2(_) => prot.quantify(Rollup::Mean),
+        }
+    }
+
+    /// Correlate each pair of channels over the log2 protein intensities
+    /// (see the module documentation), using proteins quantified in both
+    /// channels
+    pub fn correlation_matrix(&self, method: CorrelationMethod) -> CorrelationMatrix {
+        let channels = self.channels as usize;
+        let proteins = self
+            .proteins
+            .iter()
+            .map(|prot| self.log2_protein(prot))
+            .collect::<Vec<_>>();
+        let columns = (0..channels)
+            .map(|c| {
+                proteins
+                    .iter()
+                    .map(|v| v.get(c).copied().unwrap_or(f64::NAN))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let correlate = match method {
+            CorrelationMethod::Pearson => pearson,
+            CorrelationMethod::Spearman => spearman,
+        };
+        let mut values = vec![vec![f64::NAN; channels]; channels];
+        for i in 0..channels {
+            for j in i..channels {
+                let r = correlate(&columns[i], &columns[j]);
+                values[i][j] = r;
+                values[j][i] = r;
+            }
+        }
+        CorrelationMatrix {
+            labels: self.channel_names(),
+            values,
+        }
+    }
+
+    /// Combine differential expression results of this dataset into volcano
+    /// plot rows, flagging proteins that pass `thresholds`
+    pub fn volcano(
+        &self,
+        results: &[DifferentialResult],
+        thresholds: Thresholds,
+    ) -> Vec<VolcanoRow> {
+        let genes = self
+            .proteins
+            .iter()
+            .map(|prot| (prot.accession.as_str(), prot.gene.as_ref()))
+            .collect::<std::collections::HashMap<_, _>>();
+        results
+            .iter()
+            .map(|r| VolcanoRow {
+                accession: r.accession.clone(),
+                gene: genes.get(r.accession.as_str()).copied().flatten().cloned(),
+                log2_fold_change: r.log2_fold_change,
+                p_value: r.p_value,
+                q_value: r.q_value,
+                significant: r.log2_fold_change.abs() >= thresholds.log2_fold_change
+                    && r.q_value <= thresholds.q_value,
+            })
+            .collect()
+    }
+
+    /// Test each protein for differential expression between two groups of
+    /// channels (0 indexed) with Welch's t-test, adjusting p-values for
+    /// multiple testing with the Benjamini-Hochberg procedure. Results are
+    /// in protein order
+    pub fn differential_expression(
+        &self,
+        group_a: &[usize],
+        group_b: &[usize],
+    ) -> Vec<DifferentialResult> {
+        self.differential_expression_with(group_a, group_b, TestKind::Welch)
+    }
+
+    /// As `differential_expression`, using the given statistical test
+    pub fn differential_expression_with(
+        &self,
+        group_a: &[usize],
+        group_b: &[usize],
+        test: TestKind,
+    ) -> Vec<DifferentialResult> {
+        let pick = |values: &[f64], group: &[usize]| {
+            group
+                .iter()
+                .map(|&c| values.get(c).copied().unwrap_or(f64::NAN))
+                .collect::<Vec<_>>()
+        };
+        let mut results = self
+            .proteins
+            .iter()
+            .map(|prot| {
+                let values = self.log2_protein(prot);
+                let (a, b) = (pick(&values, group_a), pick(&values, group_b));
+                let (statistic, p_value) = match test {
+                    TestKind::Welch => welch_t_test(&a, &b),
+                    TestKind::MannWhitney => mann_whitney(&a, &b),
+                };
+                DifferentialResult {
+                    accession: prot.accession.clone(),
+                    log2_fold_change: util::mean(&a) - util::mean(&b),
+                    statistic,
+                    p_value,
+                    q_value: f64::NAN,
+                }
+            })
+            .collect::<Vec<_>>();
+        let p = results.iter().map(|r| r.p_value).collect::<Vec<_>>();
+        for (r, q) in results.iter_mut().zip(benjamini_hochberg(&p)) {
+            r.q_value = q;
+        }
+        results
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn welch() {
+        // t = -2.074, df = 10.21
+        let a = [19.8, 20.4, 19.6, 17.8, 18.5, 18.9, 18.3, 18.9, 19.5, 22.0];
+        let b = [28.2, 26.6, 20.1, 23.3, 25.2, 22.1, 17.7, 27.6, 20.6, 13.7];
+        let (t, p) = welch_t_test(&a, &b);
+        assert!((t - -2.07401).abs() < 1e-4);
+        assert!((p - 0.06428).abs() < 1e-4);
+        assert!(welch_t_test(&[1.0], &b).1.is_nan());
+    }
+
+    #[test]
+    fn mann_whitney_u() {
+        // Exact: U = 0 is the most extreme of C(7, 3) = 35 arrangements
+        let (u, p) = mann_whitney(&[1.0, 2.0, 3.0], &[4.0, 5.0, 6.0, 7.0]);
+        assert_eq!(u, 0.0);
+        assert!((p - 2.0 / 35.0).abs() < 1e-12);
+
+        let (u, p) = mann_whitney(&[1.0, 5.0, 3.0], &[4.0, 2.0, 6.0, f64::NAN]);
+        assert_eq!(u, 3.0);
+        assert!((p - 0.7).abs() < 1e-12);
+
+        // Ties fall back to the normal approximation: z = 3.5 / sqrt(4.8)
+        let (u, p) = mann_whitney(&[1.0, 1.0, 2.0], &[2.0, 3.0, 3.0]);
+        assert_eq!(u, 0.5);
+        assert!((p - 0.11015).abs() < 1e-4);
+    }
+
+    #[test]
+    fn correlation() {
+        let a = [1.0, 2.0, 3.0, 4.0, f64::NAN];
+        let b = [2.0, 4.0, 6.0, 8.0, 1.0];
+        let c = [1.0, 8.0, 27.0, 64.0, 1.0];
+        assert!((pearson(&a, &b) - 1.0).abs() < 1e-12);
+        assert!(pearson(&a, &c) < 1.0);
+        assert!((spearman(&a, &c) - 1.0).abs() < 1e-12);
+        assert_eq!(ranks(&[3.0, 1.0, 3.0, 2.0]), vec![3.5, 1.0, 3.5, 2.0]);
+    }
+
+    #[test]
+    fn volcano_export() {
+        let data = Dataset {
+            proteins: vec![Protein {
+                accession: "P1".into(),
+                gene: Some("ALB".into()),
+                ..Protein::default()
+            }],
+            ..Dataset::default()
+        };
+        let result = |acc: &str, fc: f64, q: f64| DifferentialResult {
+            accession: acc.into(),
+            log2_fold_change: fc,
+            statistic: f64::NAN,
+            p_value: q / 2.0,
+            q_value: q,
+        };
+        let rows = data.volcano(
+            &[result("P1", -1.5, 0.01), result("P\"2", 0.5, f64::NAN)],
+            Thresholds::default(),
+        );
+        assert!(rows[0].significant);
+        assert!(!rows[1].significant);
+
+        let mut buf = Vec::new();
+        write_volcano_tsv(&rows, &mut buf).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        assert_eq!(
+            text.lines().nth(1),
+            Some("P1\tALB\t-1.5\t0.005\t0.01\ttrue")
+        );
+        assert_eq!(text.lines().nth(2), Some("P\"2\t\t0.5\tNA\tNA\tfalse"));
+
+        let mut tabbed = rows[0].clone();
+        tabbed.gene = Some("ALB\tHSA\n".into());
+        let mut buf = Vec::new();
+        write_volcano_tsv(&[tabbed], &mut buf).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        assert_eq!(
+            text.lines().nth(1),
+            Some("P1\tALB HSA \t-1.5\t0.005\t0.01\ttrue")
+        );
+
+        #[cfg(feature = "serialization")]
+        {
+            let mut buf = Vec::new();
+            write_volcano_json(&rows, &mut buf).unwrap();
+            let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+            assert_eq!(json[0]["gene"], "ALB");
+            assert_eq!(json[1]["accession"], "P\"2");
+            assert!(json[1]["q_value"].is_null());
+        }
+    }
+
+    #[test]
+    fn bh_adjustment() {
+        let q = benjamini_hochberg(&[0.01, 0.04, f64::NAN, 0.03, 0.5]);
+        let expected = [0.04, 0.16 / 3.0, f64::NAN, 0.16 / 3.0, 0.5];
+        for (q, e) in q.iter().zip(&expected) {
+            assert!((q - e).abs() < 1e-12 || (q.is_nan() && e.is_nan()));
+        }
+    }
+
+    #[test]
+    fn differential_expression() {
+        let protein = |acc: &str, values: Vec<f64>| Protein {
+            accession: acc.into(),
+            channels: 6,
+            peptides: vec![Peptide {
+                values,
+                ..Peptide::default()
+            }],
+            ..Protein::default()
+        };
+        let data = Dataset {
+            channels: 6,
+            proteins: vec![
+                protein("P1", vec![100.0, 110.0, 90.0, 400.0, 440.0, 360.0]),
+                protein("P2", vec![100.0, 110.0, 90.0, 100.0, 90.0, 110.0]),
+            ],
+            ..Dataset::default()
+        };
+        let results = data.differential_expression(&[3, 4, 5], &[0, 1, 2]);
+        assert!((results[0].log2_fold_change - 2.0).abs() < 1e-9);
+        assert!(results[0].p_value < 0.001);
+        assert!(results[1].p_value > 0.9);
+        assert!(results[0].q_value >= results[0].p_value);
+
+        let results =
+            data.differential_expression_with(&[3, 4, 5], &[0, 1, 2], TestKind::MannWhitney);
+        assert_eq!(results[0].statistic, 9.0);
+        assert!((results[0].p_value - 0.1).abs() < 1e-12);
+
+        let matrix = data.correlation_matrix(CorrelationMethod::Spearman);
+        assert_eq!(matrix.values.len(), 6);
+        assert_eq!(matrix.get("channel_4", "channel_6"), Some(1.0));
+        // Channel 1 has the same intensity in both proteins
+        assert!(matrix.get("channel_1", "channel_4").unwrap().is_nan());
+    }
+}
@@ -0,0 +1,272 @@
+//! Asynchronously parse Census files as they are read, e.g. while they are
+//! being downloaded
+//!
+//! This mirrors `Parser::proteins`: proteins are yielded one at a time from
+//! an `AsyncBufRead` source, without first reading the whole file into
+//! memory.
+use super::*;
+use crate::parser::{Template, Warning};
+use futures_util::stream::Stream;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::AsyncBufRead;
+
+/// Stream of the proteins of a Census file, created by `proteins` or
+/// `proteins_with`.
+///
+/// The stream ends after the first error. In lenient mode, entries that
+/// cannot be parsed are skipped and recorded as warnings instead
+pub struct Proteins<R> {
+    reader: R,
+    config: ParserBuilder,
+    /// Header lines read so far
+    headers: String,
+    /// Header state parsed from `headers`, rebuilt when new header lines
+    /// are read
+    template: Option<Template>,
+    /// Bytes of a line that has only been partially read
+    partial: Vec<u8>,
+    /// Protein lines (one per group member) and peptide lines of the entry
+    /// being collected, and the number of lines preceding it
+    entry: Option<(String, usize)>,
+    /// First line of the next entry, read while collecting peptide lines
+    pending: Option<String>,
+    /// Number of lines read so far
+    line: usize,
+    warnings: Vec<Warning>,
+    done: bool,
+}
+
+impl<R> Proteins<R> {
+    /// Return the lines skipped so far in lenient mode
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+}
+
+impl<R: AsyncBufRead + Unpin> Proteins<R> {
+    fn poll_read_line(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<Option<String>>> {
+        loop {
+            let buf = match Pin::new(&mut self.reader).poll_fill_buf(cx) {
+                Poll::Ready(Ok(buf)) => buf,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            };
+            if buf.is_empty() {
+                if self.partial.is_empty() {
+                    return Poll::Ready(Ok(None));
+                }
+                break;
+            }
+            match memchr::memchr(b'\n', buf) {
+                Some(i) => {
+                    self.partial.extend_from_slice(&buf[..i]);
+                    Pin::new(&mut self.reader).consume(i + 1);
+                    break;
+                }
+                None => {
+                    let n = buf.len();
+                    self.partial.extend_from_slice(buf);
+                    Pin::new(&mut self.reader).consume(n);
+                }
+            }
+        }
+        self.line += 1;
+        let mut line = String::from_utf8(std::mem::take(&mut self.partial))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let len = line.trim_end_matches(['\n', '\r']).len();
+        line.truncate(len);
+        Poll::Ready(Ok(Some(line)))
+    }
+
+    fn poll_protein(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<Protein, ReadError>>> {
+        while !self.done {
+            // Collect a protein line, the lines of the other members of its
+            // group, and its peptide lines
+            if let Some((chunk, _)) = &self.entry {
+                // Group members directly follow the first protein line
+                let grouping = chunk
+                    .rsplit('\n')
+                    .next()
+                    .is_some_and(|l| l.starts_with('P'));
+                match self.poll_read_line(cx) {
+                    Poll::Ready(Ok(Some(line)))
+                        if line.starts_with('S')
+                            || line.starts_with("&S")
+                            || (grouping && line.starts_with('P')) =>
+                    {
+                        if let Some((chunk, _)) = self.entry.as_mut() {
+                            chunk.push('\n');
+                            chunk.push_str(&line);
+                        }
+                        continue;
+                    }
+                    Poll::Ready(Ok(Some(line))) => self.pending = Some(line),
+                    Poll::Ready(Ok(None)) => {}
+                    Poll::Ready(Err(e)) => return self.fail(e.into()),
+                    Poll::Pending => return Poll::Pending,
+                }
+            } else {
+                let first = match self.pending.take() {
+                    Some(line) => line,
+                    None => match self.poll_read_line(cx) {
+                        Poll::Ready(Ok(Some(line))) => line,
+                        Poll::Ready(Ok(None)) => break,
+                        Poll::Ready(Err(e)) => return self.fail(e.into()),
+                        Poll::Pending => return Poll::Pending,
+                    },
+                };
+                if first.starts_with('H') {
+                    self.headers.push_str(&first);
+                    self.headers.push('\n');
+                    self.template = None;
+                    continue;
+                }
+                let is_protein = first.starts_with('P');
+                self.entry = Some((first, self.line - 1));
+                if is_protein {
+                    continue;
+                }
+            }
+
+            let (chunk, start) = self.entry.take().unwrap_or_default();
+            let (config, headers) = (self.config, &self.headers);
+            let template = self
+                .template
+                .get_or_insert_with(|| Template::new(config, headers));
+            match template.parse_chunk(&chunk, start, &mut self.warnings) {
+                Ok(mut proteins) => {
+                    // Entries skipped in lenient mode produce no protein
+                    if let Some(protein) = proteins.pop() {
+                        return Poll::Ready(Some(Ok(protein)));
+                    }
+                }
+                Err(e) => return self.fail(e.into()),
+            }
+        }
+        self.done = true;
+        Poll::Ready(None)
+    }
+
+    fn fail(&mut self, error: ReadError) -> Poll<Option<Result<Protein, ReadError>>> {
+        self.done = true;
+        Poll::Ready(Some(Err(error)))
+    }
+}
+
+impl<R: AsyncBufRead + Unpin> Stream for Proteins<R> {
+    type Item = Result<Protein, ReadError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_protein(cx)
+    }
+}
+
+/// Parse the Census file read from `reader`, yielding each protein as soon
+/// as all of its lines have been read. The stream ends after the first error
+pub fn proteins<R>(reader: R) -> Proteins<R>
+where
+    R: AsyncBufRead + Unpin,
+{
+    proteins_with(ParserBuilder::default(), reader)
+}
+
+/// Parse the Census file read from `reader` using the configured parser
+/// options, see `proteins`.
+///
+/// In lenient mode, entries that cannot be parsed are skipped, see
+/// `Proteins::warnings`
+pub fn proteins_with<R>(config: ParserBuilder, reader: R) -> Proteins<R>
+where
+    R: AsyncBufRead + Unpin,
+{
+    Proteins {
+        reader,
+        config,
+        headers: String::new(),
+        template: None,
+        partial: Vec::new(),
+        entry: None,
+        pending: None,
+        line: 0,
+        warnings: Vec::new(),
+        done: false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures_executor::block_on;
+    use futures_util::StreamExt;
+
+    const SAMPLE: &str = "H\tCensus version 2.3.3
+H\tPLINE\tLOCUS\tSPEC_COUNT\tSEQ_COUNT\tSEQ_COVERAGE\tMOLWT\tDESCRIPTION
+H\tSLINE\tUNIQUE\tSEQUENCE\tm/z_126_int\tnorm_m/z_126_int\tm/z_127_int\tnorm_m/z_127_int\tSCAN
+P\tP12345\t2\t2\t12.5%\t69367\tSerum albumin
+S\tU\tK.LVNEVTEFAK.T\t1000\t0.25\t3000\t0.75\t1234
+&S\t\tR.QNCELFEK.L\t500\t0.5\t500\t0.5\t1301
+P\tQ99999\t1\t1\t5.0%\t12000\tOther protein
+S\tU\tK.AAAAAK.L\t10\t0.1\t90\t0.9\t2001
+";
+
+    #[test]
+    fn stream_proteins() {
+        let read = |input: &str| {
+            block_on(proteins(input.as_bytes()).collect::<Vec<_>>())
+                .into_iter()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap()
+        };
+        assert_eq!(read(SAMPLE), read_census(SAMPLE).unwrap().proteins);
+
+        // Consecutive protein lines form a group
+        let grouped = SAMPLE.replace(
+            "P\tP12345\t2\t2\t12.5%\t69367\tSerum albumin\n",
+            "P\tP12345\t2\t2\t12.5%\t69367\tSerum albumin\n\
+             P\tP12345-2\t2\t2\t12.5%\t69367\tSerum albumin isoform 2\n",
+        );
+        let groups = read(&grouped);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].accession, "P12345");
+        assert_eq!(groups[0].alternatives, vec!["P12345-2"]);
+        assert_eq!(groups[0].peptides.len(), 2);
+        assert_eq!(groups, read_census(&grouped).unwrap().proteins);
+    }
+
+    #[test]
+    fn stream_errors() {
+        let input = SAMPLE.replace("\t10\t0.1", "\tabc\t0.1");
+        let results = block_on(proteins(input.as_bytes()).collect::<Vec<_>>());
+        assert_eq!(results.len(), 2);
+        match &results[1] {
+            Err(ReadError::Parse(e)) => {
+                assert_eq!(e.kind(), &ErrorKind::Conversion);
+                assert_eq!(e.line(), 8);
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        let lenient = ParserBuilder::default().lenient(true);
+        let results = block_on(proteins_with(lenient, input.as_bytes()).collect::<Vec<_>>());
+        assert_eq!(results.len(), 2);
+        assert!(results[1].as_ref().unwrap().peptides.is_empty());
+    }
+
+    #[test]
+    fn stream_warnings() {
+        fn assert_send<T: Send>(_: &T) {}
+
+        let input = SAMPLE
+            .replace("\t500\t0.5", "\tabc\t0.5")
+            .replace("\t10\t0.1", "\tabc\t0.1");
+        let lenient = ParserBuilder::default().lenient(true);
+        let mut stream = proteins_with(lenient, input.as_bytes());
+        assert_send(&stream);
+        let proteins = block_on(stream.by_ref().collect::<Vec<_>>());
+        assert_eq!(proteins.len(), 2);
+        let lines = stream.warnings().iter().map(|w| w.line).collect::<Vec<_>>();
+        assert_eq!(lines, vec![6, 8]);
+    }
+}
@@ -0,0 +1,156 @@
+//! Summary statistics of a `Dataset`
+use super::*;
+#[cfg(feature = "serialization")]
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fmt;
+
+#[cfg_attr(feature = "serialization", derive(Serialize))]
+#[derive(PartialEq, Clone, Debug)]
+/// Intensity summary of a single channel
+pub struct ChannelSummary {
+    /// Channel name, see `Dataset::channel_names`
+    pub name: String,
+    /// Summed peptide intensity
+    pub total: f64,
+    /// Median peptide intensity
+    pub median: f64,
+    /// Fraction of peptides with a missing intensity
+    pub missing_fraction: f64,
+}
+
+#[cfg_attr(feature = "serialization", derive(Serialize))]
+#[derive(PartialEq, Clone, Debug)]
+/// Summary statistics of a `Dataset`, see `Dataset::summary`
+pub struct Summary {
+    pub proteins: usize,
+    /// Number of peptide entries (PSMs)
+    pub psms: usize,
+    /// Number of distinct peptide sequences, ignoring modifications
+    pub sequences: usize,
+    /// Fraction of PSMs flagged as unique
+    pub unique_fraction: f64,
+    /// Fraction of all peptide intensities that are missing
+    pub missing_fraction: f64,
+    pub channels: Vec<ChannelSummary>,
+}
+
+impl Dataset {
+    /// Calculate summary statistics of the dataset
+    pub fn summary(&self) -> Summary {
+        let peptides = self
+            .proteins
+            .iter()
+            .flat_map(|prot| prot.peptides.iter())
+            .collect::<Vec<_>>();
+        let psms = peptides.len();
+        let fraction = |n: usize, of: usize| {
+            if of == 0 {
+                0.0
+            } else {
+                n as f64 / of as f64
+            }
+        };
+
+        let mut missing = 0;
+        let channels = self
+            .channel_names()
+            .into_iter()
+            .enumerate()
+            .map(|(c, name)| {
+                let values = peptides
+                    .iter()
+                    .map(|pep| pep.values.get(c).copied().unwrap_or(f64::NAN))
+                    .collect::<Vec<_>>();
+                let absent = psms - util::count(&values);
+                missing += absent;
+                ChannelSummary {
+                    name,
+                    total: util::sum(&values),
+                    median: util::median(&values),
+                    missing_fraction: fraction(absent, psms),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        Summary {
+            proteins: self.proteins.len(),
+            psms,
+            sequences: peptides
+                .iter()
+                .map(|pep| pep.stripped_sequence())
+                .collect::<HashSet<_>>()
+                .len(),
+            unique_fraction: fraction(peptides.iter().filter(|p| p.unique).count(), psms),
+            missing_fraction: fraction(missing, psms * channels.len()),
+            channels,
+        }
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Proteins:          {}", self.proteins)?;
+        writeln!(f, "PSMs:              {}", self.psms)?;
+        writeln!(f, "Peptide sequences: {}", self.sequences)?;
+        writeln!(f, "Unique PSMs:       {:.1}%", self.unique_fraction * 100.0)?;
+        writeln!(
+            f,
+            "Missing values:    {:.1}%",
+            self.missing_fraction * 100.0
+        )?;
+        if !self.channels.is_empty() {
+            writeln!(f, "Channel\tTotal\tMedian\tMissing")?;
+            for c in &self.channels {
+                writeln!(
+                    f,
+                    "{}\t{:.4e}\t{:.4e}\t{:.1}%",
+                    c.name,
+                    c.total,
+                    c.median,
+                    c.missing_fraction * 100.0
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn dataset_summary() {
+        let peptide =
+            |seq: &str, unique, values| Peptide::builder(seq).unique(unique).values(values).build();
+        let data = Dataset::builder()
+            .protein(
+                Protein::builder("P1")
+                    .peptide(peptide("K.AAK.L", true, vec![100.0, f64::NAN]))
+                    .peptide(peptide("K.AAK.L", true, vec![300.0, 50.0]))
+                    .build()
+                    .unwrap(),
+            )
+            .protein(
+                Protein::builder("P2")
+                    .peptide(peptide("R.CCK.D", false, vec![200.0, 150.0]))
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap();
+        let summary = data.summary();
+        assert_eq!(summary.proteins, 2);
+        assert_eq!(summary.psms, 3);
+        assert_eq!(summary.sequences, 2);
+        assert!((summary.unique_fraction - 2.0 / 3.0).abs() < 1e-12);
+        assert!((summary.missing_fraction - 1.0 / 6.0).abs() < 1e-12);
+        assert_eq!(summary.channels[0].total, 600.0);
+        assert_eq!(summary.channels[1].median, 100.0);
+
+        let text = summary.to_string();
+        assert!(text.contains("PSMs:              3\n"));
+        assert!(text.contains("channel_2\t2.0000e2\t1.0000e2\t33.3%\n"));
+    }
+}
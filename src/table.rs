@@ -0,0 +1,357 @@
+//! Export a `Dataset` as a delimited (TSV or CSV) table
+use super::*;
+use std::io::{self, Write};
+use std::sync::Arc;
+
+/// A single peptide intensity in long format, see `Dataset::iter_long`
+pub type LongRow<'a> = (&'a str, &'a str, usize, usize, Arc<str>, f64);
+
+/// Row granularity of an exported table
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Layout {
+    /// One row per peptide, with its reporter ion intensities
+    PeptideLevel,
+    /// One row per protein, with the summed reporter ion intensities of its
+    /// peptides, on the same scale as the dataset (see `Rollup::Sum`)
+    ProteinLevel,
+}
+
+/// A column of an exported table. Peptide columns are left empty when
+/// writing a protein-level table
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Column {
+    Accession,
+    Description,
+    Gene,
+    SpectralCount,
+    SequenceCount,
+    SequenceCoverage,
+    MolecularWeight,
+    Sequence,
+    Unique,
+    Scan,
+    Charge,
+    Purity,
+    RetentionTime,
+    FileName,
+    /// One column per quantified channel
+    Channels,
+}
+
+impl Column {
+    /// Name of the column in the table header
+    pub fn name(&self) -> &'static str {
+        match self {
+            Column::Accession => "accession",
+            Column::Description => "description",
+            Column::Gene => "gene",
+            Column::SpectralCount => "spectral_count",
+            Column::SequenceCount => "sequence_count",
+            Column::SequenceCoverage => "sequence_coverage",
+            Column::MolecularWeight => "molecular_weight",
+            Column::Sequence => "sequence",
+            Column::Unique => "unique",
+            Column::Scan => "scan",
+            Column::Charge => "charge",
+            Column::Purity => "purity",
+            Column::RetentionTime => "retention_time",
+            Column::FileName => "file_name",
+            Column::Channels => "channels",
+        }
+    }
+}
+
+/// Layout, columns and delimiter of an exported table
+#[derive(PartialEq, Clone, Debug)]
+pub struct TableFormat {
+    layout: Layout,
+    columns: Vec<Column>,
+    channel_names: Option<Vec<String>>,
+    delimiter: char,
+}
+
+impl TableFormat {
+    /// Create a tab-delimited format with the default columns for `layout`
+    pub fn new(layout: Layout) -> TableFormat {
+        let columns = match layout {
+            Layout::PeptideLevel => vec![
+                Column::Accession,
+                Column::Description,
+                Column::SpectralCount,
+                Column::SequenceCount,
+                Column::Sequence,
+                Column::Scan,
+                Column::Purity,
+                Column::Channels,
+            ],
+            Layout::ProteinLevel => vec![
+                Column::Accession,
+                Column::Description,
+                Column::Gene,
+                Column::SpectralCount,
+                Column::SequenceCount,
+                Column::SequenceCoverage,
+                Column::MolecularWeight,
+                Column::Channels,
+            ],
+        };
+        TableFormat {
+            layout,
+            columns,
+            channel_names: None,
+            delimiter: '\t',
+        }
+    }
+
+    /// Set the columns to write, in order
+    ///
+    /// This follows the Builder pattern
+    pub fn columns(mut self, columns: Vec<Column>) -> Self {
+        self.columns = columns;
+        self
+    }
+
+    /// Set the header names of the channel columns. Channels are named
+    /// `channel_1`, `channel_2`, ... by default
+    ///
+    /// This follows the Builder pattern
+    pub fn channel_names(mut self, names: Vec<String>) -> Self {
+        self.channel_names = Some(names);
+        self
+    }
+
+    /// Set the field delimiter. Defaults to tab
+    ///
+    /// This follows the Builder pattern
+    pub fn delimiter(mut self, delimiter: char) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Quote `field` if it contains the delimiter, a quote or a line break,
+    /// doubling any quotes within it
+    fn escape<'a>(&self, field: &'a str) -> std::borrow::Cow<'a, str> {
+        if field.contains([self.delimiter, '"', '\n', '\r']) {
+            format!("\"{}\"", field.replace('"', "\"\"")).into()
+        } else {
+            field.into()
+        }
+    }
+
+    pub(crate) fn write_row<W: Write>(&self, w: &mut W, fields: &[String]) -> io::Result<()> {
+        for (i, field) in fields.iter().enumerate() {
+            if i > 0 {
+                write!(w, "{}", self.delimiter)?;
+            }
+            write!(w, "{}", self.escape(field))?;
+        }
+        writeln!(w)
+    }
+}
+
+/// Format an optional value, leaving the field empty if it is absent
+fn opt<T: ToString>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+impl Dataset {
+    /// Write the dataset as a tab-delimited table with the default columns
+    /// for `layout`
+    pub fn write_tsv<W: Write>(&self, w: W, layout: Layout) -> io::Result<()> {
+        self.write_table(w, &TableFormat::new(layout))
+    }
+
+    /// Write the dataset as a comma-delimited table with the default columns
+    /// for `layout`. Fields containing commas or quotes are quoted
+    pub fn write_csv<W: Write>(&self, w: W, layout: Layout) -> io::Result<()> {
+        self.write_table(w, &TableFormat::new(layout).delimiter(','))
+    }
+
+    /// Write the dataset as a delimited table in the given format, with a
+    /// header row of column names
+    pub fn write_table<W: Write>(&self, mut w: W, format: &TableFormat) -> io::Result<()> {
+        let channel_names = match &format.channel_names {
+            Some(names) => names.clone(),
+            None => self.channel_names(),
+        };
+        let mut header = Vec::new();
+        for col in &format.columns {
+            match col {
+                Column::Channels => header.extend(channel_names.iter().cloned()),
+                col => header.push(col.name().to_string()),
+            }
+        }
+        format.write_row(&mut w, &header)?;
+
+        let mut row = Vec::with_capacity(header.len());
+        for prot in &self.proteins {
+            let peptides: Vec<Option<&Peptide>> = match format.layout {
+                Layout::PeptideLevel => prot.peptides.iter().map(Some).collect(),
+                Layout::ProteinLevel => vec![None],
+            };
+            for pep in peptides {
+                row.clear();
+                for col in &format.columns {
+                    let field = match col {
+                        Column::Accession => prot.accession.clone(),
+                        Column::Description => prot.description.clone(),
+                        Column::Gene => prot.gene.clone().unwrap_or_default(),
+                        Column::SpectralCount => prot.spectral_count.to_string(),
+                        Column::SequenceCount => prot.sequence_count.to_string(),
+                        Column::SequenceCoverage => prot.sequence_coverage.to_string(),
+                        Column::MolecularWeight => prot.molecular_weight.to_string(),
+                        Column::Sequence => opt(pep.map(|p| &p.sequence)),
+                        Column::Unique => opt(pep.map(|p| p.unique)),
+                        Column::Scan => opt(pep.map(|p| p.scan)),
+                        Column::Charge => opt(pep.map(|p| p.charge)),
+                        Column::Purity => opt(pep.map(|p| p.purity)),
+                        Column::RetentionTime => opt(pep.and_then(|p| p.retention_time)),
+                        Column::FileName => opt(pep.map(|p| &p.file_name)),
+                        Column::Channels => {
+                            let values = match pep {
+                                Some(p) => p.values.clone(),
+                                None => prot.quantify_scaled(Rollup::Sum, self.scale),
+                            };
+                            row.extend(values.iter().map(|v| v.to_string()));
+                            continue;
+                        }
+                    };
+                    row.push(field);
+                }
+                format.write_row(&mut w, &row)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Iterate over the dataset in long ("tidy") format, yielding an
+    /// `(accession, sequence, scan, channel, label, intensity)` tuple for
+    /// each peptide and channel, where `channel` is 0 indexed and `label` is
+    /// the channel name, see `Dataset::channel_names`. Missing intensities
+    /// are yielded as `NaN`
+    pub fn iter_long(&self) -> impl Iterator<Item = LongRow<'_>> + '_ {
+        let labels = self
+            .channel_names()
+            .into_iter()
+            .map(Arc::from)
+            .collect::<Vec<Arc<str>>>();
+        self.proteins.iter().flat_map(move |prot| {
+            let labels = labels.clone();
+            prot.peptides.iter().flat_map(move |pep| {
+                let labels = labels.clone();
+                pep.values.iter().enumerate().map(move |(c, &value)| {
+                    let label = labels.get(c).cloned().unwrap_or_else(|| Arc::from(""));
+                    (
+                        prot.accession.as_str(),
+                        pep.sequence.as_str(),
+                        pep.scan,
+                        c,
+                        label,
+                        value,
+                    )
+                })
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn dataset() -> Dataset {
+        Dataset {
+            channels: 2,
+            proteins: vec![Protein {
+                accession: "P12345".into(),
+                description: "Serum albumin, \"ALB\"".into(),
+                spectral_count: 2,
+                sequence_count: 2,
+                channels: 2,
+                peptides: vec![
+                    Peptide {
+                        sequence: "K.LVNEVTEFAK.T".into(),
+                        values: vec![1000.0, 3000.0],
+                        scan: 1234,
+                        ..Peptide::default()
+                    },
+                    Peptide {
+                        sequence: "R.QNCELFEK.L".into(),
+                        values: vec![500.0, f64::NAN],
+                        scan: 1301,
+                        ..Peptide::default()
+                    },
+                ],
+                ..Protein::default()
+            }],
+            header: Header::default(),
+            channel_info: Vec::new(),
+            scale: Scale::Linear,
+            ..Dataset::default()
+        }
+    }
+
+    #[test]
+    fn peptide_tsv() {
+        let mut buf = Vec::new();
+        dataset().write_tsv(&mut buf, Layout::PeptideLevel).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        let lines = text.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[0],
+            "accession\tdescription\tspectral_count\tsequence_count\tsequence\tscan\tpurity\tchannel_1\tchannel_2"
+        );
+        assert!(lines[1]
+            .starts_with("P12345\t\"Serum albumin, \"\"ALB\"\"\"\t2\t2\tK.LVNEVTEFAK.T\t1234\t"));
+        assert!(lines[2].ends_with("\t500\tNaN"));
+    }
+
+    #[test]
+    fn long_format() {
+        let data = dataset();
+        let rows = data.iter_long().collect::<Vec<_>>();
+        assert_eq!(rows.len(), 4);
+        assert_eq!(
+            rows[1],
+            (
+                "P12345",
+                "K.LVNEVTEFAK.T",
+                1234,
+                1,
+                Arc::from("channel_2"),
+                3000.0
+            )
+        );
+        assert_eq!(rows[2].2, 1301);
+        assert!(rows[3].5.is_nan());
+
+        // Rows can be handed to other threads
+        let sent = std::thread::scope(|s| s.spawn(move || rows.len()).join().unwrap());
+        assert_eq!(sent, 4);
+    }
+
+    #[test]
+    fn protein_csv() {
+        let format = TableFormat::new(Layout::ProteinLevel)
+            .delimiter(',')
+            .columns(vec![Column::Accession, Column::Channels, Column::Scan])
+            .channel_names(vec!["126".into(), "127".into()]);
+        let mut buf = Vec::new();
+        dataset().write_table(&mut buf, &format).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "accession,126,127,scan\nP12345,1500,3000,\n"
+        );
+
+        let mut data = dataset();
+        data.log2_transform(1.0);
+        let mut buf = Vec::new();
+        data.write_table(&mut buf, &format).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        let row = text.lines().nth(1).unwrap().split(',').collect::<Vec<_>>();
+        assert_eq!(row[0], "P12345");
+        assert!((row[1].parse::<f64>().unwrap() - 1501f64.log2()).abs() < 1e-9);
+        assert!((row[2].parse::<f64>().unwrap() - 3001f64.log2()).abs() < 1e-9);
+    }
+}
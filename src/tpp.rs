@@ -0,0 +1,420 @@
+//! Import Trans-Proteomic Pipeline (TPP) pepXML and protXML files
+//!
+//! Peptide-spectrum matches are read from pepXML, using the top-ranked
+//! search hit of each spectrum query. Reporter ion intensities are taken
+//! from Libra results, with each Libra channel becoming a channel of the
+//! `Dataset`. Protein inference can optionally be taken from a protXML file,
+//! otherwise peptides are grouped by the protein of their search hit.
+use super::*;
+use crate::xml::{elements, finish, number};
+use std::collections::HashMap;
+
+/// Monoisotopic residue masses, used to convert the modified residue masses
+/// of older pepXML files into mass shifts
+fn residue_mass(residue: char) -> Option<f64> {
+    Some(match residue {
+        'G' => 57.02146,
+        'A' => 71.03711,
+        'S' => 87.03203,
+        'P' => 97.05276,
+        'V' => 99.06841,
+        'T' => 101.04768,
+        'C' => 103.00919,
+        'L' | 'I' => 113.08406,
+        'N' => 114.04293,
+        'D' => 115.02694,
+        'Q' => 128.05858,
+        'K' => 128.09496,
+        'E' => 129.04259,
+        'M' => 131.04049,
+        'H' => 137.05891,
+        'F' => 147.06841,
+        'R' => 156.10111,
+        'Y' => 163.06333,
+        'W' => 186.07931,
+        _ => return None,
+    })
+}
+
+/// Mass of the N-terminal hydrogen, included in pepXML N-terminal masses
+const NTERM_MASS: f64 = 1.007825;
+
+/// A peptide-spectrum match read from a pepXML file
+struct Psm {
+    protein: String,
+    description: String,
+    alternatives: Vec<String>,
+    /// Reporter ion intensities, indexed by Libra channel (1-based)
+    intensities: Vec<(usize, f64)>,
+    peptide: Peptide,
+}
+
+/// Read the top-ranked search hit of each spectrum query in a pepXML file
+fn read_psms(input: &str) -> Result<Vec<Psm>, Error> {
+    let mut psms = Vec::new();
+    let mut file_name = String::new();
+    // Spectrum query attributes: scan, charge and retention time
+    let mut query = (0, 0, None);
+    let mut hit: Option<Psm> = None;
+    // Modifications as (position, mass shift), with position 0 for N-term
+    let mut mods: Vec<(usize, f64)> = Vec::new();
+    let mut bare = String::new();
+    let mut flanks = (String::new(), String::new());
+    let mut in_libra = false;
+
+    elements(input, |line, name, attrs| {
+        let attrs = match attrs {
+            Some(attrs) => attrs,
+            None => {
+                match name {
+                    b"search_hit" => {
+                        if let Some(mut psm) = hit.take() {
+                            let mut core = String::new();
+                            for (_, delta) in mods.iter().filter(|(pos, _)| *pos == 0) {
+                                core.push_str(&format!("({})", delta));
+                            }
+                            for (i, c) in bare.chars().enumerate() {
+                                core.push(c);
+                                for (_, delta) in mods.iter().filter(|(pos, _)| *pos == i + 1) {
+                                    core.push_str(&format!("({})", delta));
+                                }
+                            }
+                            psm.peptide.sequence = format!("{}.{}.{}", flanks.0, core, flanks.1);
+                            psms.push(psm);
+                        }
+                    }
+                    b"analysis_result" => in_libra = false,
+                    _ => {}
+                }
+                return Ok(());
+            }
+        };
+        match name {
+            b"msms_run_summary" => {
+                let base = attrs.get("base_name").map(String::as_str).unwrap_or("");
+                file_name = base.rsplit(['/', '\\']).next().unwrap_or("").into();
+            }
+            b"spectrum_query" => {
+                query = (
+                    number(&attrs, "start_scan", line)?.unwrap_or(0),
+                    number(&attrs, "assumed_charge", line)?.unwrap_or(0),
+                    number::<f32>(&attrs, "retention_time_sec", line)?.map(|rt| rt / 60.0),
+                );
+            }
+            b"search_hit" => {
+                if attrs.get("hit_rank").map(String::as_str) != Some("1") {
+                    return Ok(());
+                }
+                bare = attrs.get("peptide").cloned().unwrap_or_default();
+                flanks = (
+                    attrs
+                        .get("peptide_prev_aa")
+                        .cloned()
+                        .unwrap_or_else(|| "-".into()),
+                    attrs
+                        .get("peptide_next_aa")
+                        .cloned()
+                        .unwrap_or_else(|| "-".into()),
+                );
+                mods.clear();
+                let proteins = number::<usize>(&attrs, "num_tot_proteins", line)?.unwrap_or(1);
+                hit = Some(Psm {
+                    protein: attrs.get("protein").cloned().unwrap_or_default(),
+                    description: attrs.get("protein_descr").cloned().unwrap_or_default(),
+                    alternatives: Vec::new(),
+                    intensities: Vec::new(),
+                    peptide: Peptide {
+                        unique: proteins == 1,
+                        purity: 1.0,
+                        scan: query.0,
+                        charge: query.1,
+                        retention_time: query.2,
+                        file_name: file_name.clone(),
+                        ..Peptide::default()
+                    },
+                });
+            }
+            _ => {}
+        }
+        let psm = match hit.as_mut() {
+            Some(psm) => psm,
+            None => return Ok(()),
+        };
+        match name {
+            b"alternative_protein" => {
+                if let Some(protein) = attrs.get("protein") {
+                    psm.alternatives.push(protein.clone());
+                }
+            }
+            b"modification_info" => {
+                if let Some(mass) = number::<f64>(&attrs, "mod_nterm_mass", line)? {
+                    mods.push((0, mass - NTERM_MASS));
+                }
+            }
+            b"mod_aminoacid_mass" => {
+                let position = number::<usize>(&attrs, "position", line)?.unwrap_or(0);
+                let delta = match number::<f64>(&attrs, "variable", line)? {
+                    Some(delta) => Some(delta),
+                    None => number::<f64>(&attrs, "static", line)?,
+                };
+                let delta = match delta {
+                    Some(delta) => delta,
+                    None => {
+                        let mass = number::<f64>(&attrs, "mass", line)?.unwrap_or(0.0);
+                        let residue = bare.chars().nth(position.saturating_sub(1));
+                        mass - residue.and_then(residue_mass).unwrap_or(mass)
+                    }
+                };
+                mods.push((position, (delta * 10000.0).round() / 10000.0));
+            }
+            b"search_score" => {
+                let value = number::<f32>(&attrs, "value", line)?.unwrap_or(0.0);
+                match attrs.get("name").map(String::as_str) {
+                    Some("xcorr") => psm.peptide.xcorr = value,
+                    Some("deltacn") => psm.peptide.delta_cn = value,
+                    _ => {}
+                }
+            }
+            b"analysis_result" => {
+                in_libra = attrs.get("analysis").map(String::as_str) == Some("libra");
+            }
+            b"intensity" if in_libra => {
+                let channel = number::<usize>(&attrs, "channel", line)?.unwrap_or(0);
+                let absolute = number::<f64>(&attrs, "absolute", line)?.unwrap_or(f64::NAN);
+                if channel > 0 {
+                    psm.intensities.push((channel, absolute));
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    })?;
+    Ok(psms)
+}
+
+/// Return the number of Libra channels, and fill in the intensities of each
+/// peptide. Channels without an intensity are missing (`NaN`)
+fn assign_intensities(psms: &mut [Psm]) -> u8 {
+    let channels = psms
+        .iter()
+        .flat_map(|psm| psm.intensities.iter().map(|(c, _)| *c))
+        .max()
+        .unwrap_or(0);
+    for psm in psms.iter_mut() {
+        psm.peptide.values = vec![f64::NAN; channels];
+        for &(c, v) in &psm.intensities {
+            psm.peptide.values[c - 1] = v;
+        }
+    }
+    channels as u8
+}
+
+/// Parse a pepXML file into a `Dataset`, grouping peptides by the protein of
+/// their search hit
+pub fn from_pepxml(input: &str) -> Result<Dataset, Error> {
+    let mut psms = read_psms(input)?;
+    let channels = assign_intensities(&mut psms);
+
+    let mut proteins: Vec<Protein> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for psm in psms {
+        let idx = *index.entry(psm.protein.clone()).or_insert_with(|| {
+            proteins.push(Protein {
+                accession: psm.protein.clone(),
+                description: psm.description.clone(),
+                alternatives: psm.alternatives.clone(),
+                channels,
+                ..Protein::default()
+            });
+            proteins.len() - 1
+        });
+        proteins[idx].peptides.push(psm.peptide);
+    }
+    finish(&mut proteins);
+
+    Ok(Dataset {
+        proteins,
+        channels,
+        header: Header::default(),
+        channel_info: Vec::new(),
+        scale: Scale::Linear,
+        ..Dataset::default()
+    })
+}
+
+/// Parse a pepXML file into a `Dataset`, using the protein inference of the
+/// corresponding protXML file. Each protein receives the peptide-spectrum
+/// matches of the peptides listed for it in the protXML file
+pub fn from_pepxml_protxml(pepxml: &str, protxml: &str) -> Result<Dataset, Error> {
+    let mut psms = read_psms(pepxml)?;
+    let channels = assign_intensities(&mut psms);
+
+    let mut by_sequence: HashMap<String, Vec<Peptide>> = HashMap::new();
+    for psm in psms {
+        by_sequence
+            .entry(psm.peptide.stripped_sequence())
+            .or_default()
+            .push(psm.peptide);
+    }
+
+    let mut proteins: Vec<Protein> = Vec::new();
+    let mut current: Option<Protein> = None;
+    elements(protxml, |line, name, attrs| {
+        let attrs = match attrs {
+            Some(attrs) => attrs,
+            None => {
+                if name == b"protein" {
+                    proteins.extend(current.take());
+                }
+                return Ok(());
+            }
+        };
+        match (name, current.as_mut()) {
+            (b"protein", _) => {
+                current = Some(Protein {
+                    accession: attrs.get("protein_name").cloned().unwrap_or_default(),
+                    sequence_coverage: number(&attrs, "percent_coverage", line)?.unwrap_or(0.0),
+                    channels,
+                    ..Protein::default()
+                });
+            }
+            (b"annotation", Some(prot)) => {
+                if let Some(desc) = attrs.get("protein_description") {
+                    prot.description = desc.clone();
+                }
+            }
+            (b"indistinguishable_protein", Some(prot)) => {
+                if let Some(name) = attrs.get("protein_name") {
+                    prot.alternatives.push(name.clone());
+                }
+            }
+            (b"peptide", Some(prot)) => {
+                let seq = attrs
+                    .get("peptide_sequence")
+                    .map(String::as_str)
+                    .unwrap_or("");
+                let charge = number::<u8>(&attrs, "charge", line)?;
+                if let Some(peptides) = by_sequence.get(seq) {
+                    prot.peptides.extend(
+                        peptides
+                            .iter()
+                            .filter(|p| charge.is_none_or(|z| p.charge == z))
+                            .cloned(),
+                    );
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    })?;
+    finish(&mut proteins);
+
+    Ok(Dataset {
+        proteins,
+        channels,
+        header: Header::default(),
+        channel_info: Vec::new(),
+        scale: Scale::Linear,
+        ..Dataset::default()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PEPXML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<msms_pipeline_analysis>
+<msms_run_summary base_name="/data/run1">
+<spectrum_query spectrum="run1.01234.01234.2" start_scan="1234" assumed_charge="2" retention_time_sec="2700.0" index="1">
+<search_result>
+<search_hit hit_rank="1" peptide="LVNEVTEFAK" peptide_prev_aa="K" peptide_next_aa="T" protein="sp|P02768|ALBU_HUMAN" protein_descr="Serum albumin OS=Homo sapiens GN=ALB" num_tot_proteins="1">
+<search_score name="xcorr" value="3.12"/>
+<search_score name="deltacn" value="0.41"/>
+<analysis_result analysis="libra">
+<libra_result>
+<intensity channel="1" target_mass="126.128" absolute="1000" normalized="0.25"/>
+<intensity channel="2" target_mass="127.125" absolute="3000" normalized="0.75"/>
+</libra_result>
+</analysis_result>
+</search_hit>
+<search_hit hit_rank="2" peptide="AAAAAK" protein="decoy" num_tot_proteins="1"/>
+</search_result>
+</spectrum_query>
+<spectrum_query spectrum="run1.01301.01301.3" start_scan="1301" assumed_charge="3" index="2">
+<search_result>
+<search_hit hit_rank="1" peptide="MELFEK" peptide_prev_aa="R" peptide_next_aa="L" protein="sp|P02768|ALBU_HUMAN" num_tot_proteins="2">
+<alternative_protein protein="sp|P02769|ALBU_BOVIN"/>
+<modification_info modified_peptide="M[147]ELFEK">
+<mod_aminoacid_mass position="1" mass="147.0354"/>
+</modification_info>
+<analysis_result analysis="libra">
+<libra_result>
+<intensity channel="1" target_mass="126.128" absolute="500" normalized="0.5"/>
+</libra_result>
+</analysis_result>
+</search_hit>
+</search_result>
+</spectrum_query>
+</msms_run_summary>
+</msms_pipeline_analysis>
+"#;
+
+    #[test]
+    fn pepxml() {
+        let data = from_pepxml(PEPXML).unwrap();
+        assert_eq!(data.channels, 2);
+        assert_eq!(data.proteins.len(), 1);
+        let prot = &data.proteins[0];
+        assert_eq!(prot.accession, "sp|P02768|ALBU_HUMAN");
+        assert_eq!(prot.gene.as_deref(), Some("ALB"));
+        assert_eq!(prot.spectral_count, 2);
+
+        let pep = &prot.peptides[0];
+        assert_eq!(pep.sequence, "K.LVNEVTEFAK.T");
+        assert_eq!(pep.values, vec![1000.0, 3000.0]);
+        assert_eq!(pep.scan, 1234);
+        assert_eq!(pep.retention_time, Some(45.0));
+        assert_eq!(pep.xcorr, 3.12);
+        assert_eq!(pep.file_name, "run1");
+        assert!(pep.unique);
+
+        let pep = &prot.peptides[1];
+        assert_eq!(pep.sequence, "R.M(15.9949)ELFEK.L");
+        assert!(pep.is_missing(1));
+        assert!(!pep.unique);
+    }
+
+    #[test]
+    fn protxml() {
+        let protxml = r#"<?xml version="1.0"?>
+<protein_summary>
+<protein_group group_number="1" probability="1.0">
+<protein protein_name="sp|P02768|ALBU_HUMAN" n_indistinguishable_proteins="2" probability="1.0" percent_coverage="12.5">
+<annotation protein_description="Serum albumin OS=Homo sapiens GN=ALB"/>
+<indistinguishable_protein protein_name="sp|P02769|ALBU_BOVIN"/>
+<peptide peptide_sequence="MELFEK" charge="3"/>
+</protein>
+</protein_group>
+</protein_summary>
+"#;
+        let data = from_pepxml_protxml(PEPXML, protxml).unwrap();
+        let prot = &data.proteins[0];
+        assert_eq!(prot.alternatives, vec!["sp|P02769|ALBU_BOVIN"]);
+        assert_eq!(prot.sequence_coverage, 12.5);
+        assert_eq!(prot.description, "Serum albumin OS=Homo sapiens GN=ALB");
+        assert_eq!(prot.peptides.len(), 1);
+        assert_eq!(prot.peptides[0].scan, 1301);
+    }
+
+    #[test]
+    fn malformed() {
+        let err = from_pepxml("<a>\n<b></a>").unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::Xml(_)));
+        assert_eq!(err.line(), 2);
+
+        let err = from_pepxml(&PEPXML.replace("assumed_charge=\"3\"", "assumed_charge=\"x\""))
+            .unwrap_err();
+        assert_eq!(err.line(), 19);
+    }
+}
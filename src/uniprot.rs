@@ -0,0 +1,92 @@
+//! Parse UniProt FASTA-style protein descriptions, e.g.
+//! "sp|P02768|ALBU_HUMAN Serum albumin OS=Homo sapiens OX=9606 GN=ALB PE=1 SV=2"
+
+/// Fields of a UniProt FASTA-style description
+#[derive(PartialEq, Clone, Debug, Default)]
+pub(crate) struct Description {
+    pub entry_name: Option<String>,
+    pub protein_name: Option<String>,
+    pub organism: Option<String>,
+    pub gene: Option<String>,
+}
+
+/// Return the entry name from a "db|accession|ENTRY_NAME" identifier
+fn entry_name(id: &str) -> Option<&str> {
+    let mut parts = id.trim_start_matches('>').split('|');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(_), Some(_), Some(name)) if !name.is_empty() => Some(name),
+        _ => None,
+    }
+}
+
+/// Return the byte offsets of " XX=" keys in a description
+fn keys(description: &str) -> Vec<usize> {
+    let b = description.as_bytes();
+    (0..b.len().saturating_sub(3))
+        .filter(|&i| {
+            b[i] == b' '
+                && b[i + 1].is_ascii_uppercase()
+                && b[i + 2].is_ascii_uppercase()
+                && b[i + 3] == b'='
+        })
+        .collect()
+}
+
+impl Description {
+    /// Parse the description of the protein identified by `accession`.
+    /// Returns all `None` fields for descriptions that do not follow the
+    /// UniProt convention
+    pub fn parse(accession: &str, description: &str) -> Description {
+        let mut text = description.trim();
+        let mut entry = entry_name(accession);
+        // Descriptions may also start with the full FASTA identifier
+        if let Some((id, rest)) = text.split_once(' ') {
+            if let Some(name) = entry_name(id) {
+                entry = Some(name);
+                text = rest.trim_start();
+            }
+        }
+
+        let keys = keys(text);
+        let value = |key: &str| {
+            keys.iter().enumerate().find_map(|(n, &i)| {
+                if &text[i + 1..i + 3] != key {
+                    return None;
+                }
+                let end = keys.get(n + 1).copied().unwrap_or(text.len());
+                Some(text[i + 4..end].trim().to_string())
+            })
+        };
+        let name = &text[..keys.first().copied().unwrap_or(text.len())];
+
+        Description {
+            entry_name: entry.map(String::from),
+            protein_name: Some(name.trim().to_string()).filter(|s| !s.is_empty()),
+            organism: value("OS"),
+            gene: value("GN"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_description() {
+        let desc = Description::parse(
+            "P02768",
+            "sp|P02768|ALBU_HUMAN Serum albumin OS=Homo sapiens OX=9606 GN=ALB PE=1 SV=2",
+        );
+        assert_eq!(desc.entry_name.as_deref(), Some("ALBU_HUMAN"));
+        assert_eq!(desc.protein_name.as_deref(), Some("Serum albumin"));
+        assert_eq!(desc.organism.as_deref(), Some("Homo sapiens"));
+        assert_eq!(desc.gene.as_deref(), Some("ALB"));
+
+        let desc = Description::parse("sp|P12345|AATM_RABIT", "Aspartate aminotransferase");
+        assert_eq!(desc.entry_name.as_deref(), Some("AATM_RABIT"));
+        assert_eq!(desc.gene, None);
+
+        assert_eq!(Description::parse("P1", ""), Description::default());
+    }
+}
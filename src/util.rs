@@ -1,59 +1,81 @@
-use std::u32;
+//! Summary statistics over intensity values
+//!
+//! Missing values, which are represented as `NaN`, are ignored by all of the
+//! functions in this module
 
 /// Calaculate the sum of a slice
 #[inline]
 pub fn sum(slice: &[f64]) -> f64 {
-    slice.iter().fold(0.0f64, |acc, x| acc + x)
+    present(slice).fold(0.0f64, |acc, x| acc + x)
+}
+
+/// Iterate over the values of a slice that are not missing
+#[inline]
+fn present(slice: &[f64]) -> impl Iterator<Item = f64> + '_ {
+    slice.iter().copied().filter(|x| !x.is_nan())
+}
+
+/// Return the number of values in a slice that are not missing
+#[inline]
+pub fn count(slice: &[f64]) -> usize {
+    present(slice).count()
 }
 
 /// Calculate the mean value of a slice
 #[inline]
-pub fn mean(slice: &[u32]) -> f64 {
-    slice.iter().sum::<u32>() as f64 / slice.len() as f64
+pub fn mean(slice: &[f64]) -> f64 {
+    sum(slice) / count(slice) as f64
 }
 
 /// Calculate the mean value of a slice
+#[deprecated(note = "`mean` now operates on `f64` slices")]
 #[inline]
 pub fn meanf(slice: &[f64]) -> f64 {
-    slice.iter().sum::<f64>() as f64 / slice.len() as f64
+    mean(slice)
 }
 
 /// Return the maximum value of a slice
 #[inline]
-pub fn max(slice: &[u32]) -> u32 {
-    slice
-        .iter()
-        .fold(u32::MIN, |acc, &x| if x > acc { x } else { acc })
+pub fn max(slice: &[f64]) -> f64 {
+    slice.iter().cloned().fold(f64::MIN, f64::max)
 }
 
 /// Calculate the standard deviation (population) of a slice
 #[inline]
-pub fn stddev(slice: &[u32]) -> f64 {
+pub fn stddev(slice: &[f64]) -> f64 {
     let mean = mean(slice);
-    (slice
-        .iter()
-        .fold(0.0f64, |acc, x| acc + (*x as f64 - mean).powi(2))
-        / slice.len() as f64)
-        .sqrt()
+    (present(slice).fold(0.0f64, |acc, x| acc + (x - mean).powi(2)) / count(slice) as f64).sqrt()
 }
 
 /// Calculate the standard deviation (population) of a slice
+#[deprecated(note = "`stddev` now operates on `f64` slices")]
 #[inline]
 pub fn stddevf(slice: &[f64]) -> f64 {
-    let mean = meanf(slice);
-    (slice
-        .iter()
-        .fold(0.0f64, |acc, x| acc + (*x - mean).powi(2))
-        / slice.len() as f64)
-        .sqrt()
+    stddev(slice)
 }
 
 /// Calculate the standard error (population) of a slice
 #[inline]
-pub fn stderr(slice: &[u32]) -> f64 {
-    stddev(slice) / (slice.len() as f64).sqrt()
+pub fn stderr(slice: &[f64]) -> f64 {
+    stddev(slice) / (count(slice) as f64).sqrt()
 }
 
-pub fn cv(slice: &[u32]) -> f64 {
+/// Calculate the coefficient of variation of a slice
+pub fn cv(slice: &[f64]) -> f64 {
     stddev(slice) / mean(slice)
 }
+
+/// Calculate the median value of a slice
+pub fn median(slice: &[f64]) -> f64 {
+    let mut values = present(slice).collect::<Vec<_>>();
+    if values.is_empty() {
+        return f64::NAN;
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+    let mid = values.len() / 2;
+    if values.len() % 2 == 0 {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    }
+}
@@ -0,0 +1,107 @@
+//! Consistency checks performed by `Parser::parse_validated`
+#[cfg(feature = "serialization")]
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Major versions of Census whose output is known to be supported
+const SUPPORTED_VERSIONS: std::ops::RangeInclusive<u32> = 1..=3;
+
+/// A problem found while validating a Census file
+#[cfg_attr(feature = "serialization", derive(Serialize))]
+#[derive(PartialEq, Clone, Debug)]
+pub enum ValidationIssue {
+    /// No "Census version" header line is present
+    MissingVersion,
+    /// The declared Census version is not known to be supported
+    UnsupportedVersion(String),
+    /// The `PLINE` or `SLINE` column header is not present, so fields were
+    /// located by their conventional positions
+    MissingColumnHeader(&'static str),
+    /// A peptide line has a different number of fields than the columns
+    /// declared on the `SLINE` header, so its channel intensities may be
+    /// misaligned
+    FieldCount {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    /// A protein accession is listed more than once
+    DuplicateAccession {
+        accession: String,
+        lines: Vec<usize>,
+    },
+}
+
+/// Result of validating a Census file, see `Parser::parse_validated`
+#[cfg_attr(feature = "serialization", derive(Serialize))]
+#[derive(PartialEq, Clone, Debug, Default)]
+pub struct ValidationReport {
+    /// Declared Census version, if any
+    pub version: Option<String>,
+    /// Number of quantified channels
+    pub channels: u8,
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    /// Return true if no issues were found
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Issues collected by the parser while validating
+#[derive(Default)]
+pub(crate) struct Validation {
+    pub issues: Vec<ValidationIssue>,
+    /// Line numbers of each protein accession, in order of appearance
+    pub accessions: Vec<(String, usize)>,
+}
+
+impl Validation {
+    /// Complete validation of a parsed file with the given header
+    pub fn finish(self, header: &super::Header, channels: u8) -> ValidationReport {
+        let mut issues = Vec::new();
+        match &header.version {
+            None => issues.push(ValidationIssue::MissingVersion),
+            Some(v) => {
+                let major = v
+                    .split('.')
+                    .next()
+                    .and_then(|m| m.trim().parse::<u32>().ok());
+                if !major.is_some_and(|m| SUPPORTED_VERSIONS.contains(&m)) {
+                    issues.push(ValidationIssue::UnsupportedVersion(v.clone()));
+                }
+            }
+        }
+        if header.protein_columns.is_empty() {
+            issues.push(ValidationIssue::MissingColumnHeader("PLINE"));
+        }
+        if header.peptide_columns.is_empty() {
+            issues.push(ValidationIssue::MissingColumnHeader("SLINE"));
+        }
+        issues.extend(self.issues);
+
+        let mut lines: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (accession, line) in &self.accessions {
+            lines.entry(accession).or_default().push(*line);
+        }
+        let mut duplicates = lines
+            .into_iter()
+            .filter(|(_, lines)| lines.len() > 1)
+            .collect::<Vec<_>>();
+        duplicates.sort_by_key(|(_, lines)| lines[0]);
+        issues.extend(duplicates.into_iter().map(|(accession, lines)| {
+            ValidationIssue::DuplicateAccession {
+                accession: accession.into(),
+                lines,
+            }
+        }));
+
+        ValidationReport {
+            version: header.version.clone(),
+            channels,
+            issues,
+        }
+    }
+}
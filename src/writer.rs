@@ -0,0 +1,256 @@
+//! Write a `Dataset` back out in the Census file format
+use super::*;
+use std::borrow::Cow;
+use std::io::{self, Write};
+
+/// Column names of the protein lines written by `write_census`
+const PROTEIN_COLUMNS: [&str; 6] = [
+    "LOCUS",
+    "SPEC_COUNT",
+    "SEQ_COUNT",
+    "SEQ_COVERAGE",
+    "MOLWT",
+    "DESCRIPTION",
+];
+
+/// Column names of the peptide lines written by `write_census`, following
+/// the intensity columns
+const PEPTIDE_COLUMNS: [&str; 8] = [
+    "PURITY",
+    "SCAN",
+    "CSTATE",
+    "XCORR",
+    "DELTACN",
+    "FILE_NAME",
+    "RETENTION_TIME",
+    "ION_INJECTION_TIME",
+];
+
+/// Enclose `field` in double quotes if `quote` and it contains the
+/// `delimiter` or a quote, doubling any quotes within it
+fn escape(field: &str, delimiter: char, quote: bool) -> Cow<'_, str> {
+    if quote && field.contains([delimiter, '"']) {
+        format!("\"{}\"", field.replace('"', "\"\"")).into()
+    } else {
+        field.into()
+    }
+}
+
+impl Dataset {
+    /// Return the names of the raw intensity columns, reusing those declared
+    /// in the original header where possible
+    fn channel_columns(&self) -> Vec<String> {
+        let declared = self
+            .header
+            .peptide_columns
+            .iter()
+            .filter(|c| c.contains("m/z") && !c.to_ascii_lowercase().starts_with("norm"))
+            .cloned()
+            .collect::<Vec<_>>();
+        if declared.len() == self.channels as usize {
+            declared
+        } else {
+            (1..=self.channels)
+                .map(|i| format!("m/z_{}_int", i))
+                .collect()
+        }
+    }
+
+    /// Write the dataset as a Census file, which can be read back in using
+    /// `read_census`.
+    ///
+    /// Header lines from the original file are reproduced, except for the
+    /// `PLINE` and `SLINE` column headers, which are replaced to describe the
+    /// columns that are written. Census-normalized intensities are written
+    /// from `Peptide::norm_values` if they were retained during parsing, and
+    /// are otherwise calculated as the fraction of the peptide's total
+    /// intensity.
+    ///
+    /// Only the leading protein of each protein group is written, as the
+    /// descriptions of the other members (`Protein::alternatives`) are not
+    /// retained during parsing
+    pub fn write_census<W: Write>(&self, w: W) -> io::Result<()> {
+        self.write_census_with(w, '\t', false)
+    }
+
+    /// Write the dataset as a Census file with fields separated by
+    /// `delimiter`, as by `write_census`. Use the delimiter the dataset was
+    /// parsed with (see `ParserBuilder::delimiter`), as the original header
+    /// lines are reproduced verbatim.
+    ///
+    /// Fields containing the delimiter or a double quote are enclosed in
+    /// double quotes, with any quotes within them doubled, so the file should
+    /// be read with `ParserBuilder::quote('"')`
+    pub fn write_census_delimited<W: Write>(&self, w: W, delimiter: char) -> io::Result<()> {
+        self.write_census_with(w, delimiter, true)
+    }
+
+    /// Write the dataset as a Census file, quoting text fields if `quote`
+    fn write_census_with<W: Write>(
+        &self,
+        mut w: W,
+        delimiter: char,
+        quote: bool,
+    ) -> io::Result<()> {
+        let d = delimiter;
+        let mut wrote_columns = false;
+        let mut write_columns = |w: &mut W| -> io::Result<()> {
+            if !wrote_columns {
+                write!(w, "H{}PLINE", d)?;
+                for col in PROTEIN_COLUMNS {
+                    write!(w, "{}{}", d, col)?;
+                }
+                write!(w, "\nH{}SLINE{}UNIQUE{}SEQUENCE", d, d, d)?;
+                for col in self.channel_columns() {
+                    write!(w, "{}{}{}norm_{}", d, col, d, col)?;
+                }
+                for col in PEPTIDE_COLUMNS {
+                    write!(w, "{}{}", d, col)?;
+                }
+                writeln!(w)?;
+                wrote_columns = true;
+            }
+            Ok(())
+        };
+
+        for line in &self.header.lines {
+            let tag = line.split(d).nth(1).map(str::trim);
+            if tag == Some("PLINE") || tag == Some("SLINE") {
+                write_columns(&mut w)?;
+            } else {
+                writeln!(w, "{}", line)?;
+            }
+        }
+        write_columns(&mut w)?;
+
+        for prot in &self.proteins {
+            writeln!(
+                w,
+                "P{d}{}{d}{}{d}{}{d}{}%{d}{}{d}{}",
+                escape(&prot.accession, d, quote),
+                prot.spectral_count,
+                prot.sequence_count,
+                prot.sequence_coverage,
+                prot.molecular_weight,
+                escape(&prot.description, d, quote),
+                d = d
+            )?;
+            for pep in &prot.peptides {
+                let norm = if pep.norm_values.len() == pep.values.len() {
+                    pep.norm_values.clone()
+                } else {
+                    pep.ratios()
+                };
+                write!(
+                    w,
+                    "{}{d}{}{d}{}",
+                    if pep.redundant { "&S" } else { "S" },
+                    if pep.unique { "U" } else { "" },
+                    escape(&pep.sequence, d, quote),
+                    d = d
+                )?;
+                for (value, norm) in pep.values.iter().zip(norm.iter()) {
+                    write!(w, "{d}{}{d}{}", value, norm, d = d)?;
+                }
+                write!(
+                    w,
+                    "{d}{}{d}{}{d}{}{d}{}{d}{}{d}{}",
+                    pep.purity,
+                    pep.scan,
+                    pep.charge,
+                    pep.xcorr,
+                    pep.delta_cn,
+                    escape(&pep.file_name, d, quote),
+                    d = d
+                )?;
+                for time in [pep.retention_time, pep.injection_time] {
+                    match time {
+                        Some(t) => write!(w, "{}{}", d, t)?,
+                        None => write!(w, "{}", d)?,
+                    }
+                }
+                writeln!(w)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SAMPLE: &str = "H\tCensus version 2.3.3
+H\tPLINE\tLOCUS\tSPEC_COUNT\tSEQ_COUNT\tSEQ_COVERAGE\tLENGTH\tMOLWT\tDESCRIPTION
+H\tSLINE\tUNIQUE\tSEQUENCE\tm/z_126.127726_int\tnorm_m/z_126.127726_int\tm/z_127.124761_int\tnorm_m/z_127.124761_int\tRETENTION_TIME\tPURITY\tSCAN\tCSTATE\tXCORR\tDELTACN\tFILE_NAME
+P\tP12345\t2\t2\t12.5%\t609\t69367\tSerum albumin
+S\tU\tK.LVNEVTEFAK.T\t1000.5\t0.25\t3000\t0.75\t45.1\t0.95\t1234\t2\t3.12\t0.41\tsample_01.ms2
+&S\t\tR.QNCELFEK.L\t500\t0.5\t500\t0.5\t46.0\t0.8\t1301\t3\t2.54\t0.33\tsample_01.ms2
+";
+
+    #[test]
+    fn round_trip() {
+        let data = ParserBuilder::default()
+            .keep_normalized(true)
+            .build(SAMPLE)
+            .parse()
+            .unwrap();
+        let mut buf = Vec::new();
+        data.write_census(&mut buf).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+
+        assert!(text.starts_with("H\tCensus version 2.3.3\nH\tPLINE\tLOCUS"));
+        assert!(text.contains("\tm/z_126.127726_int\tnorm_m/z_126.127726_int\t"));
+
+        let parsed = ParserBuilder::default()
+            .keep_normalized(true)
+            .build(&text)
+            .parse()
+            .unwrap();
+        assert_eq!(parsed.channels, data.channels);
+        assert_eq!(parsed.proteins, data.proteins);
+        assert_eq!(parsed.header.version, data.header.version);
+    }
+
+    #[test]
+    fn protein_groups() {
+        let mut data = ParserBuilder::default().build(SAMPLE).parse().unwrap();
+        data.proteins[0].alternatives = vec!["P12345-2".into()];
+        let mut buf = Vec::new();
+        data.write_census(&mut buf).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        assert_eq!(text.lines().filter(|l| l.starts_with("P\t")).count(), 1);
+        assert!(!text.contains("P12345-2"));
+    }
+
+    #[test]
+    fn round_trip_delimited() {
+        let csv = SAMPLE
+            .replace('\t', ",")
+            .replace("Serum albumin", "\"Serum albumin, ALB\"");
+        let data = ParserBuilder::default()
+            .delimiter(',')
+            .quote('"')
+            .build(&csv)
+            .parse()
+            .unwrap();
+        let mut buf = Vec::new();
+        data.write_census_delimited(&mut buf, ',').unwrap();
+        let text = String::from_utf8(buf).unwrap();
+
+        // The original column headers are replaced, not repeated
+        assert_eq!(text.matches("PLINE").count(), 1);
+        assert_eq!(text.matches("SLINE").count(), 1);
+        assert!(!text.contains('\t'));
+        assert_eq!(data.proteins[0].description, "Serum albumin, ALB");
+        assert!(text.contains(",\"Serum albumin, ALB\"\n"));
+
+        let parsed = ParserBuilder::default()
+            .delimiter(',')
+            .quote('"')
+            .build(&text)
+            .parse()
+            .unwrap();
+        assert_eq!(parsed.proteins, data.proteins);
+    }
+}
@@ -0,0 +1,197 @@
+//! Export of a `Dataset` as an Excel workbook
+//!
+//! The workbook has a "Proteins" sheet, with one row per protein and the
+//! summed intensity of each channel on the dataset's scale, and a "Peptides"
+//! sheet with one row per peptide. Channel columns are named as by `Dataset::channel_names`, and
+//! the header row of each sheet is frozen. Missing intensities (`NaN`) and
+//! absent optional values are left blank.
+use super::*;
+use rust_xlsxwriter::{Format, Workbook, Worksheet, XlsxError};
+use std::io::Write;
+
+/// Write a bold header row, and freeze it
+fn header(sheet: &mut Worksheet, names: &[&str], channels: &[String]) -> Result<(), XlsxError> {
+    let bold = Format::new().set_bold();
+    let names = names
+        .iter()
+        .map(|s| s.to_string())
+        .chain(channels.iter().cloned());
+    for (col, name) in names.enumerate() {
+        sheet.write_string_with_format(0, col as u16, name, &bold)?;
+    }
+    sheet.set_freeze_panes(1, 0)?;
+    Ok(())
+}
+
+/// Write a number, leaving the cell blank if it is `NaN`
+fn number(sheet: &mut Worksheet, row: u32, col: u16, value: f64) -> Result<(), XlsxError> {
+    if !value.is_nan() {
+        sheet.write_number(row, col, value)?;
+    }
+    Ok(())
+}
+
+impl Dataset {
+    /// Build an Excel workbook with protein-level and peptide-level sheets
+    pub fn to_xlsx(&self) -> Result<Workbook, XlsxError> {
+        let channels = self.channel_names();
+        let mut workbook = Workbook::new();
+
+        let sheet = workbook.add_worksheet().set_name("Proteins")?;
+        header(
+            sheet,
+            &[
+                "accession",
+                "description",
+                "gene",
+                "spectral_count",
+                "sequence_count",
+                "sequence_coverage",
+                "molecular_weight",
+            ],
+            &channels,
+        )?;
+        for (i, prot) in self.proteins.iter().enumerate() {
+            let row = i as u32 + 1;
+            sheet.write_string(row, 0, &prot.accession)?;
+            sheet.write_string(row, 1, &prot.description)?;
+            if let Some(gene) = &prot.gene {
+                sheet.write_string(row, 2, gene)?;
+            }
+            sheet.write_number(row, 3, prot.spectral_count)?;
+            sheet.write_number(row, 4, prot.sequence_count)?;
+            number(sheet, row, 5, prot.sequence_coverage as f64)?;
+            sheet.write_number(row, 6, prot.molecular_weight)?;
+            let totals = prot.quantify_scaled(Rollup::Sum, self.scale);
+            for (c, value) in totals.into_iter().enumerate() {
+                number(sheet, row, 7 + c as u16, value)?;
+            }
+        }
+
+        let sheet = workbook.add_worksheet().set_name("Peptides")?;
+        header(
+            sheet,
+            &[
+                "accession",
+                "sequence",
+                "unique",
+                "scan",
+                "charge",
+                "purity",
+                "retention_time",
+                "file_name",
+            ],
+            &channels,
+        )?;
+        let peptides = self
+            .proteins
+            .iter()
+            .flat_map(|prot| prot.peptides.iter().map(move |pep| (prot, pep)));
+        for (i, (prot, pep)) in peptides.enumerate() {
+            let row = i as u32 + 1;
+            sheet.write_string(row, 0, &prot.accession)?;
+            sheet.write_string(row, 1, &pep.sequence)?;
+            sheet.write_boolean(row, 2, pep.unique)?;
+            sheet.write_number(row, 3, pep.scan as f64)?;
+            sheet.write_number(row, 4, pep.charge)?;
+            number(sheet, row, 5, pep.purity as f64)?;
+            if let Some(rt) = pep.retention_time {
+                number(sheet, row, 6, rt as f64)?;
+            }
+            sheet.write_string(row, 7, &pep.file_name)?;
+            for (c, &value) in pep.values.iter().enumerate() {
+                number(sheet, row, 8 + c as u16, value)?;
+            }
+        }
+        Ok(workbook)
+    }
+
+    /// Write the dataset as an Excel workbook, see `Dataset::to_xlsx`
+    pub fn write_xlsx<W: Write + Send>(&self, w: W) -> Result<(), XlsxError> {
+        self.to_xlsx()?.save_to_writer(w)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn xlsx_workbook() {
+        let dataset = Dataset::builder()
+            .protein(
+                Protein::builder("P1")
+                    .peptide(
+                        Peptide::builder("K.AAK.L")
+                            .values(vec![1.0, f64::NAN])
+                            .build(),
+                    )
+                    .peptide(Peptide::builder("K.CCK.L").values(vec![2.0, 3.0]).build())
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap();
+        let mut workbook = dataset.to_xlsx().unwrap();
+        let names = workbook
+            .worksheets()
+            .iter()
+            .map(|s| s.name())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["Proteins", "Peptides"]);
+
+        let mut buf = Vec::new();
+        dataset.write_xlsx(&mut buf).unwrap();
+        assert!(buf.starts_with(b"PK"));
+        assert!(workbook.save_to_buffer().is_ok());
+
+        // Read the sheets back from the archive
+        let read = |buf: &[u8], name: &str| {
+            let mut archive = zip::ZipArchive::new(std::io::Cursor::new(buf)).unwrap();
+            let mut xml = String::new();
+            std::io::Read::read_to_string(&mut archive.by_name(name).unwrap(), &mut xml).unwrap();
+            xml
+        };
+        let strings = read(&buf, "xl/sharedStrings.xml");
+        let proteins = read(&buf, "xl/worksheets/sheet1.xml");
+        let peptides = read(&buf, "xl/worksheets/sheet2.xml");
+        let strings = strings
+            .split("<t>")
+            .skip(1)
+            .map(|s| &s[..s.find("</t>").unwrap()])
+            .collect::<Vec<_>>();
+        // Value of a cell, e.g. "B2", or `None` if it is blank
+        let cell = |xml: &str, r: &str| {
+            let rest = &xml[xml.find(&format!("<c r=\"{}\"", r))?..];
+            let rest = &rest[..rest.find("</c>")?];
+            Some(rest[rest.find("<v>")? + 3..rest.find("</v>")?].to_string())
+        };
+        let text = |xml: &str, r: &str| strings[cell(xml, r).unwrap().parse::<usize>().unwrap()];
+
+        for sheet in [&proteins, &peptides] {
+            assert!(sheet.contains(r#"<pane ySplit="1" topLeftCell="A2""#));
+            assert!(sheet.contains(r#"state="frozen""#));
+        }
+        assert_eq!(text(&proteins, "A1"), "accession");
+        assert_eq!(text(&proteins, "H1"), "channel_1");
+        assert_eq!(text(&proteins, "I1"), "channel_2");
+        assert_eq!(text(&peptides, "I1"), "channel_1");
+        assert_eq!(text(&peptides, "J1"), "channel_2");
+
+        assert_eq!(cell(&proteins, "I2").as_deref(), Some("3"));
+        assert_eq!(cell(&peptides, "I2").as_deref(), Some("1"));
+        assert_eq!(cell(&peptides, "J2"), None);
+        assert_eq!(cell(&peptides, "J3").as_deref(), Some("3"));
+
+        // Protein totals stay on the dataset's scale
+        let mut dataset = dataset;
+        dataset.log2_transform(1.0);
+        let mut buf = Vec::new();
+        dataset.write_xlsx(&mut buf).unwrap();
+        let proteins = read(&buf, "xl/worksheets/sheet1.xml");
+        for r in ["H2", "I2"] {
+            let total = cell(&proteins, r).unwrap().parse::<f64>().unwrap();
+            assert!((total - 2.0).abs() < 1e-9, "{} = {}", r, total);
+        }
+    }
+}
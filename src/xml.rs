@@ -0,0 +1,120 @@
+//! Helpers shared by the XML-based importers
+use super::*;
+use quick_xml::events::BytesStart;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::collections::HashMap;
+
+/// Return the line number of a byte offset into `input`. This scans from
+/// the start of the input, so it is only used for errors, see `LineCursor`
+pub(crate) fn line_at(input: &str, pos: u64) -> usize {
+    let pos = (pos as usize).min(input.len());
+    memchr::memchr_iter(b'\n', &input.as_bytes()[..pos]).count() + 1
+}
+
+/// Line numbers of increasing byte offsets into an input, counting only the
+/// newlines since the previous offset
+#[derive(Default)]
+struct LineCursor {
+    pos: usize,
+    line: usize,
+}
+
+impl LineCursor {
+    fn line_at(&mut self, input: &str, pos: u64) -> usize {
+        let pos = (pos as usize).clamp(self.pos, input.len());
+        self.line += memchr::memchr_iter(b'\n', &input.as_bytes()[self.pos..pos]).count();
+        self.pos = pos;
+        self.line + 1
+    }
+}
+
+/// Collect the attributes of an element into a map
+pub(crate) fn attributes(
+    input: &str,
+    pos: u64,
+    e: &BytesStart,
+) -> Result<HashMap<String, String>, Error> {
+    let err = |e: String| Error::new(ErrorKind::Xml(e), line_at(input, pos));
+    let mut map = HashMap::new();
+    for attr in e.attributes() {
+        let attr = attr.map_err(|e| err(e.to_string()))?;
+        let value = attr.unescape_value().map_err(|e| err(e.to_string()))?;
+        map.insert(
+            String::from_utf8_lossy(attr.key.as_ref()).into_owned(),
+            value.into_owned(),
+        );
+    }
+    Ok(map)
+}
+
+/// Read XML elements from `input`, calling `f` with the line number, name and
+/// attributes of each start (or empty) element, and with `None` attributes
+/// for each end element
+pub(crate) fn elements<F>(input: &str, mut f: F) -> Result<(), Error>
+where
+    F: FnMut(usize, &[u8], Option<HashMap<String, String>>) -> Result<(), Error>,
+{
+    let mut reader = Reader::from_str(input);
+    let mut lines = LineCursor::default();
+    loop {
+        let pos = reader.buffer_position();
+        let event = reader.read_event().map_err(|e| {
+            Error::new(
+                ErrorKind::Xml(e.to_string()),
+                line_at(input, reader.error_position()),
+            )
+        })?;
+        match event {
+            Event::Start(e) => {
+                let attrs = attributes(input, pos, &e)?;
+                f(
+                    lines.line_at(input, pos),
+                    e.local_name().as_ref(),
+                    Some(attrs),
+                )?;
+            }
+            Event::Empty(e) => {
+                let attrs = attributes(input, pos, &e)?;
+                let name = e.local_name();
+                let line = lines.line_at(input, pos);
+                f(line, name.as_ref(), Some(attrs))?;
+                f(line, name.as_ref(), None)?;
+            }
+            Event::End(e) => f(lines.line_at(input, pos), e.local_name().as_ref(), None)?,
+            Event::Eof => return Ok(()),
+            _ => {}
+        }
+    }
+}
+
+/// Parse the attribute `key`, if present
+pub(crate) fn number<T: std::str::FromStr>(
+    attrs: &HashMap<String, String>,
+    key: &str,
+    line: usize,
+) -> Result<Option<T>, Error> {
+    attrs
+        .get(key)
+        .map(|v| {
+            v.parse::<T>()
+                .map_err(|_| Error::new(ErrorKind::Conversion, line))
+        })
+        .transpose()
+}
+
+/// Calculate spectral and sequence counts, and annotate each protein
+pub(crate) fn finish(proteins: &mut [Protein]) {
+    for prot in proteins.iter_mut() {
+        prot.annotate();
+        prot.spectral_count = prot.peptides.len() as u16;
+        let mut seqs = prot
+            .peptides
+            .iter()
+            .map(|p| p.stripped_sequence())
+            .collect::<Vec<_>>();
+        seqs.sort_unstable();
+        seqs.dedup();
+        prot.sequence_count = seqs.len() as u16;
+    }
+}